@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub(crate) struct Aabb {
+    minimum: Point3,
+    maximum: Point3,
+}
+
+impl Aabb {
+    pub(crate) fn new(minimum: Point3, maximum: Point3) -> Self {
+        Self { minimum, maximum }
+    }
+
+    pub(crate) fn min(&self) -> &Point3 {
+        &self.minimum
+    }
+
+    pub(crate) fn max(&self) -> &Point3 {
+        &self.maximum
+    }
+
+    pub(crate) fn centroid(&self) -> Point3 {
+        (self.minimum + self.maximum) / 2.0
+    }
+
+    /// Returns the area of the sides of the box.
+    pub(crate) fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Returns the eight corners of the box.
+    pub(crate) fn corners(&self) -> impl Iterator<Item = Point3> + '_ {
+        (0..8).map(move |i| {
+            let pick = |bit: usize, axis: usize| {
+                if i & bit == 0 {
+                    self.minimum[axis]
+                } else {
+                    self.maximum[axis]
+                }
+            };
+
+            Point3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+        })
+    }
+
+    /// Returns the smallest box containing all of `points`, if there are any.
+    pub(crate) fn from_points(points: impl IntoIterator<Item = Point3>) -> Option<Self> {
+        points
+            .into_iter()
+            .map(|p| Self::new(p, p))
+            .reduce(|a, b| surrounding_box(&a, &b))
+    }
+
+    pub(crate) fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction()[a];
+            let mut t0 = (self.minimum[a] - r.origin()[a]) * inv_d;
+            let mut t1 = (self.maximum[a] - r.origin()[a]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            // Boxes of triangles lying in an axis plane are flat, and rays cross them in a
+            // single instant.
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+pub(crate) fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+    Aabb::new(box0.min().inf(box1.min()), box0.max().sup(box1.max()))
+}
@@ -0,0 +1,43 @@
+use argh::FromArgs;
+
+use crate::scene_loader::StartEndPair;
+
+/// Render the frames of an animation of the scene, each over a slice of time of its own,
+/// which moving objects, transforms and mesh sequences follow.
+///
+/// Frame `n` opens the shutter at time `n / fps`, with the frame rate of `--fps`, for the
+/// `--shutter` of a frame. The scene is loaded again for every frame, so that sequences only
+/// read the frames they show.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "animate")]
+pub(crate) struct Animate {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// number of the first frame to render
+    #[argh(option, default = "0")]
+    pub(crate) first: u64,
+
+    /// number of frames to render
+    #[argh(option, default = "24")]
+    pub(crate) frames: u64,
+
+    /// fraction of a frame during which the shutter is open, for motion blur (0 for none)
+    #[argh(option, default = "0.5")]
+    pub(crate) shutter: f64,
+}
+
+impl Animate {
+    /// Returns the numbers of the frames to render, in order.
+    pub(crate) fn frames(&self) -> impl Iterator<Item = u64> {
+        self.first..self.first + self.frames
+    }
+
+    /// Returns when the shutter opens and closes for `frame`, at `fps` frames per unit of time.
+    pub(crate) fn time(&self, frame: u64, fps: u32) -> StartEndPair<f64> {
+        let start = frame as f64 / fps as f64;
+
+        StartEndPair::new(start, start + self.shutter.clamp(0.0, 1.0) / fps as f64)
+    }
+}
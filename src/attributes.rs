@@ -0,0 +1,164 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::sampler::{splitmix, to_unit};
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+/// Rays shot from each vertex over its hemisphere to measure how occluded it is.
+const OCCLUSION_RAYS: usize = 64;
+
+/// A property of the geometry at a hit, for driving a [`crate::material::Mix`] without painted
+/// masks.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Attribute {
+    /// The fraction of the hemisphere above the surface that the object itself leaves open,
+    /// from 0 deep in crevices to 1 out in the open.
+    Occlusion,
+    /// The mean curvature, in inverse scene units: positive on bumps, negative in hollows and
+    /// 0 on flat parts.
+    Curvature,
+    /// The world-space height of the hit.
+    Height,
+    /// A value in `0..1` drawn for each transform group, the same in every render, so that
+    /// instances of the same objects can vary; 0 outside of groups.
+    Random,
+}
+
+impl Attribute {
+    pub(crate) fn at(&self, rec: &HitRecord) -> f64 {
+        match self {
+            Self::Occlusion => rec.occlusion(),
+            Self::Curvature => rec.curvature(),
+            Self::Height => rec.p().y,
+            Self::Random => rec.instance().map_or(0.0, |seed| to_unit(splitmix(seed))),
+        }
+    }
+
+    /// Returns whether the attribute is measured when the scene loads, rather than when a ray
+    /// hits.
+    pub(crate) fn is_precomputed(&self) -> bool {
+        matches!(self, Self::Occlusion | Self::Curvature)
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Occlusion => write!(f, "occlusion"),
+            Self::Curvature => write!(f, "curvature"),
+            Self::Height => write!(f, "height"),
+            Self::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// The occlusion and curvature of every vertex of a mesh, interpolated over its faces.
+#[derive(Clone, Debug)]
+pub(crate) struct VertexAttributes {
+    occlusion: Vec<f64>,
+    curvature: Vec<f64>,
+}
+
+impl VertexAttributes {
+    /// Measures the attributes of `vertices`, where `occluded` tells whether a ray leaving the
+    /// surface runs into the mesh. Occlusion is sampled along fixed directions, so that the same
+    /// mesh always comes out the same.
+    pub(crate) fn new(
+        vertices: &[Point3],
+        faces: &[[usize; 3]],
+        occluded: impl Fn(&Ray) -> bool,
+    ) -> Self {
+        // Area-weighted vertex normals, and which vertices share an edge.
+        let mut normals = vec![Vec3::zeros(); vertices.len()];
+        let mut neighbours = vec![vec![]; vertices.len()];
+        for &[a, b, c] in faces {
+            let normal = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+            for (i, j) in [(a, b), (b, c), (c, a)] {
+                normals[i] += normal;
+                neighbours[i].push(j);
+                neighbours[j].push(i);
+            }
+        }
+        for normal in &mut normals {
+            if normal.norm_squared() > 0.0 {
+                *normal = unit_vector(normal);
+            }
+        }
+
+        let occlusion = vertices
+            .iter()
+            .zip(&normals)
+            .map(|(p, n)| {
+                if n.norm_squared() == 0.0 {
+                    return 1.0;
+                }
+
+                let open = hemisphere(n)
+                    .filter(|d| !occluded(&Ray::new(*p, *d, None)))
+                    .count();
+                open as f64 / OCCLUSION_RAYS as f64
+            })
+            .collect();
+
+        // How fast the normal turns along each edge, averaged over the edges of a vertex: on a
+        // sphere of radius r, `(n_j - n_i) · (p_j - p_i)` is `|p_j - p_i|² / r`.
+        let curvature = (0..vertices.len())
+            .map(|i| {
+                let (sum, count) = neighbours[i]
+                    .iter()
+                    .filter_map(|&j| {
+                        let edge = vertices[j] - vertices[i];
+                        let length_squared = edge.norm_squared();
+                        (length_squared > 0.0)
+                            .then(|| (normals[j] - normals[i]).dot(&edge) / length_squared)
+                    })
+                    .fold((0.0, 0), |(sum, count), k| (sum + k, count + 1));
+                if count > 0 {
+                    sum / count as f64
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        Self {
+            occlusion,
+            curvature,
+        }
+    }
+
+    /// Returns the occlusion and curvature at barycentric coordinates `(u, v)` of the face with
+    /// vertices `indices`.
+    pub(crate) fn at(&self, indices: [usize; 3], u: f64, v: f64) -> (f64, f64) {
+        let [a, b, c] = indices;
+        let blend = |values: &[f64]| (1.0 - u - v) * values[a] + u * values[b] + v * values[c];
+
+        (blend(&self.occlusion), blend(&self.curvature))
+    }
+}
+
+/// Directions spread over the hemisphere around `normal` by the cosine, along a Fibonacci spiral.
+fn hemisphere(normal: &Vec3) -> impl Iterator<Item = Vec3> {
+    let w = *normal;
+    let a = if w.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = unit_vector(&w.cross(&a));
+    let u = w.cross(&v);
+    let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+
+    (0..OCCLUSION_RAYS).map(move |k| {
+        let r2 = (k as f64 + 0.5) / OCCLUSION_RAYS as f64;
+        let phi = k as f64 * golden_angle;
+        let r = r2.sqrt();
+
+        u * (r * phi.cos()) + v * (r * phi.sin()) + w * (1.0 - r2).sqrt()
+    })
+}
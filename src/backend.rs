@@ -0,0 +1,27 @@
+use crate::bvh::Bvh;
+use crate::hittable::Hittable;
+
+/// A structure that finds what rays hit among the objects of a scene, as [`Bvh`] does. Renders
+/// work with any of them, as every part of the renderer only asks for [`Hittable`] objects.
+pub(crate) trait Backend<H>: Hittable + Sized {
+    /// What the backend is called on the command line and in reports.
+    const NAME: &'static str;
+
+    /// Builds the structure over `objects`, finding them at any time in `time0..time1`.
+    fn build(objects: Vec<H>, time0: f64, time1: f64) -> Self;
+
+    /// Gives the objects back, for another backend to be built over them.
+    fn into_objects(self) -> Vec<H>;
+}
+
+impl<H: Hittable> Backend<H> for Bvh<H> {
+    const NAME: &'static str = "bvh";
+
+    fn build(objects: Vec<H>, time0: f64, time1: f64) -> Self {
+        Bvh::new(objects, time0, time1)
+    }
+
+    fn into_objects(self) -> Vec<H> {
+        self.into_objects()
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::vec3::Color;
+
+/// An image seen behind the scene by camera rays that hit nothing, such as a photograph to
+/// render objects over. Everything else still sees the background, which lights the scene and
+/// shows in reflections.
+///
+/// The image is stretched over the whole frame, and comes out of a render as it went in.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Backplate {
+    width: usize,
+    height: usize,
+    /// Linear colors, from the top row down.
+    pixels: Vec<Color>,
+}
+
+impl Backplate {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let image = image::open(path)
+            .wrap_err_with(|| format!("reading backplate {}", path.display()))?
+            .to_rgb8();
+
+        // Undo the gamma of 2 that images are written with, from the middle of each step.
+        let linear = |c: u8| ((c as f64 + 0.5) / 256.0).powi(2);
+
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|p| Color::new(linear(p[0]), linear(p[1]), linear(p[2])))
+                .collect(),
+        })
+    }
+
+    /// Returns the bytes taken by the image.
+    pub(crate) fn memory(&self) -> usize {
+        self.pixels.capacity() * std::mem::size_of::<Color>()
+    }
+
+    /// Returns the color at `(u, v)` on the frame, from its bottom left corner to its top right.
+    pub(crate) fn at(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = (((1.0 - v) * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x]
+    }
+}
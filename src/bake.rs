@@ -0,0 +1,276 @@
+use std::str::FromStr;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread::spawn;
+
+use argh::FromArgs;
+use color_eyre::eyre::{eyre, Result};
+use indicatif::ProgressBar;
+
+use crate::color::clamp_color;
+use crate::hittable::Hittable;
+use crate::nice;
+use crate::ray::Ray;
+use crate::render::{ray_color, PathSampling, RenderSettings, World};
+use crate::scene_loader::TexturedTriangle;
+use crate::vec3::{near_zero, random_unit_vector, unit_vector, Color, Point3, Vec3};
+
+/// Render the lighting of a mesh into its texture space, as a lightmap.
+///
+/// Each texel that a triangle of the mesh covers, in the texture coordinates of its OBJ file,
+/// is lit by tracing from the point of the mesh it maps to, on the side its faces point to.
+/// The lightmap is written to `-o`, with `-s` samples per texel.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bake")]
+pub(crate) struct Bake {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// name of the mesh to bake, as given in the scene file
+    #[argh(positional)]
+    pub(crate) object: String,
+
+    /// width and height of the lightmap, in texels
+    #[argh(option, default = "1024")]
+    pub(crate) size: u32,
+
+    /// what to bake, either `lighting` (how bright a white diffuse surface would be) or `ao`
+    /// (how much of the hemisphere above the surface is open, written without gamma)
+    #[argh(option, default = "BakeMode::Lighting")]
+    pub(crate) mode: BakeMode,
+
+    /// how far occluders count for `ao`
+    #[argh(option, default = "1.0")]
+    pub(crate) distance: f64,
+
+    /// texels to grow each island of the lightmap by, so that filtering it doesn't bring in
+    /// the black around them
+    #[argh(option, default = "2")]
+    pub(crate) padding: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BakeMode {
+    Lighting,
+    Occlusion,
+}
+
+impl FromStr for BakeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lighting" => Ok(Self::Lighting),
+            "ao" => Ok(Self::Occlusion),
+            _ => Err(format!(
+                "unknown bake mode `{}` (expected `lighting` or `ao`)",
+                s
+            )),
+        }
+    }
+}
+
+/// A texel of the lightmap, and the point of the mesh it maps to.
+struct Texel {
+    index: usize,
+    p: Point3,
+    normal: Vec3,
+}
+
+impl Bake {
+    /// Renders the lightmap of `triangles`, placed in `world` as at `time`, and returns its
+    /// RGB8 data, from the top row down.
+    pub(crate) fn render<H>(
+        &self,
+        world: &Arc<World<H>>,
+        triangles: &[TexturedTriangle],
+        settings: RenderSettings,
+        time: f64,
+        pb: &ProgressBar,
+    ) -> Result<Vec<u8>>
+    where
+        H: Hittable + Send + Sync + 'static,
+    {
+        let texels = Arc::new(self.texels(triangles));
+        pb.set_length(texels.len() as u64);
+
+        let num_cpus = nice::threads();
+        let mode = self.mode;
+        let distance = self.distance;
+        let (sender, receiver) = channel();
+        let workers: Vec<_> = (0..num_cpus)
+            .map(|n| {
+                let world = world.clone();
+                let texels = texels.clone();
+                let sender = sender.clone();
+                spawn(move || {
+                    let occlusion = world.occlusion_cache(&settings);
+                    let sampling = PathSampling {
+                        regularization: settings.regularization,
+                        lights: settings.light_sampling,
+                        guide: None,
+                        training: false,
+                        occlusion: occlusion.as_ref(),
+                    };
+
+                    for texel in texels.iter().skip(n).step_by(num_cpus) {
+                        nice::wait_turn(n);
+                        let sum: Color = (0..settings.samples_per_pixel)
+                            .map(|_| {
+                                // Cosine-weighted, like the bounces off a diffuse surface.
+                                let direction = texel.normal + random_unit_vector();
+                                let direction = if near_zero(&direction) {
+                                    texel.normal
+                                } else {
+                                    unit_vector(&direction)
+                                };
+                                let r = Ray::new(texel.p, direction, Some(time));
+
+                                match mode {
+                                    BakeMode::Lighting => {
+                                        ray_color(&r, &world, settings.max_depth, &sampling)
+                                    }
+                                    BakeMode::Occlusion => {
+                                        match world.objects().hit(
+                                            &r,
+                                            world.interval().t_min,
+                                            distance,
+                                        ) {
+                                            Some(_) => Color::zeros(),
+                                            None => Color::new(1.0, 1.0, 1.0),
+                                        }
+                                    }
+                                }
+                            })
+                            .sum();
+
+                        sender.send((texel.index, sum)).ok();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let size = self.size as usize;
+        let mut colors = vec![None; size * size];
+        for _ in 0..texels.len() {
+            let (index, sum) = receiver.recv()?;
+            colors[index] = Some(sum);
+            pb.inc(1);
+        }
+
+        for handle in workers {
+            handle.join().map_err(|_| eyre!("a bake thread panicked"))?;
+        }
+
+        for _ in 0..self.padding {
+            colors = dilate(&colors, size);
+        }
+
+        Ok(colors
+            .iter()
+            .flat_map(|color| {
+                let color = color.unwrap_or_else(Color::zeros);
+                let samples = settings.samples_per_pixel;
+                let (r, g, b) = match self.mode {
+                    BakeMode::Lighting => clamp_color(&color, samples),
+                    BakeMode::Occlusion => {
+                        let open = (color / samples as f64).map(|c| (c * 255.999) as u8);
+                        (open.x, open.y, open.z)
+                    }
+                };
+                [r, g, b]
+            })
+            .collect())
+    }
+
+    /// Returns the texels whose centers the triangles cover, with the first triangle to cover
+    /// each winning.
+    fn texels(&self, triangles: &[TexturedTriangle]) -> Vec<Texel> {
+        let size = self.size as usize;
+        let mut covered = vec![false; size * size];
+        let mut texels = vec![];
+
+        for triangle in triangles {
+            let [p0, p1, p2] = triangle.positions;
+            let normal = (p1 - p0).cross(&(p2 - p0));
+            if near_zero(&normal) {
+                continue;
+            }
+            let normal = unit_vector(&normal);
+
+            // Rows go down while `v` goes up.
+            let corners = triangle
+                .texture_coordinates
+                .map(|(u, v)| (u * size as f64, (1.0 - v) * size as f64));
+            let area = edge(corners[0], corners[1], corners[2]);
+            if area.abs() < f64::EPSILON {
+                continue;
+            }
+
+            let min = |f: fn(&(f64, f64)) -> f64| {
+                (corners.iter().map(f).fold(f64::INFINITY, f64::min).floor() as isize).max(0)
+                    as usize
+            };
+            let max = |f: fn(&(f64, f64)) -> f64| {
+                (corners
+                    .iter()
+                    .map(f)
+                    .fold(f64::NEG_INFINITY, f64::max)
+                    .ceil() as isize)
+                    .clamp(0, size as isize) as usize
+            };
+
+            for y in min(|c| c.1)..max(|c| c.1) {
+                for x in min(|c| c.0)..max(|c| c.0) {
+                    let center = (x as f64 + 0.5, y as f64 + 0.5);
+                    let b0 = edge(corners[1], corners[2], center) / area;
+                    let b1 = edge(corners[2], corners[0], center) / area;
+                    let b2 = 1.0 - b0 - b1;
+                    let index = y * size + x;
+
+                    if b0 < 0.0 || b1 < 0.0 || b2 < 0.0 || covered[index] {
+                        continue;
+                    }
+                    covered[index] = true;
+                    texels.push(Texel {
+                        index,
+                        p: b0 * p0 + b1 * p1 + b2 * p2,
+                        normal,
+                    });
+                }
+            }
+        }
+
+        texels
+    }
+}
+
+/// Returns twice the signed area of the triangle `a`, `b`, `c`.
+fn edge(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Gives each empty texel next to baked ones the average of its baked neighbours.
+fn dilate(colors: &[Option<Color>], size: usize) -> Vec<Option<Color>> {
+    (0..colors.len())
+        .map(|index| {
+            if colors[index].is_some() {
+                return colors[index];
+            }
+
+            let (x, y) = ((index % size) as isize, (index / size) as isize);
+            let neighbours: Vec<Color> = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                .filter(|&(x, y)| x >= 0 && y >= 0 && x < size as isize && y < size as isize)
+                .filter_map(|(x, y)| colors[y as usize * size + x as usize])
+                .collect();
+
+            match neighbours.len() {
+                0 => None,
+                count => Some(neighbours.iter().sum::<Color>() / count as f64),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use argh::FromArgs;
+
+use crate::backend::Backend;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::ray::{Interval, Ray};
+use crate::rtweekend::random_double;
+use crate::scene_loader::{Object, Scene};
+
+/// Time how fast each intersection backend is built over a scene and traces rays through it.
+///
+/// The rays go from the camera through random points of the image, on one thread, first for
+/// the closest hit as camera rays and bounces need, then for any hit as shadow rays do. Every
+/// backend traces the same rays, and should hit with as many of them. Backends are built over
+/// the objects of the scene, while meshes keep the hierarchies they were loaded with.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bench")]
+pub(crate) struct Bench {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// number of rays traced for each kind of query
+    #[argh(option, default = "1_000_000")]
+    pub(crate) rays: usize,
+}
+
+impl Bench {
+    pub(crate) fn run(&self, scene: Scene, camera: &Camera) {
+        let time = (*scene.camera.time.start(), *scene.camera.time.end());
+        let rays: Vec<_> = (0..self.rays)
+            .map(|_| camera.get_ray(random_double(), random_double()))
+            .collect();
+
+        let objects = scene.world.into_objects();
+        time_backend::<Bvh<Object>>(objects, time, &rays, &scene.interval);
+    }
+}
+
+/// Builds `B` over `objects`, traces `rays` through it and prints how long both took, then
+/// gives the objects back.
+fn time_backend<B: Backend<Object>>(
+    objects: Vec<Object>,
+    (time0, time1): (f64, f64),
+    rays: &[Ray],
+    interval: &Interval,
+) -> Vec<Object> {
+    let start = Instant::now();
+    let backend = B::build(objects, time0, time1);
+    let build = start.elapsed();
+
+    let start = Instant::now();
+    let hits = rays
+        .iter()
+        .filter(|r| {
+            let (t_min, t_max) = interval.along(r);
+            backend.hit(r, t_min, t_max).is_some()
+        })
+        .count();
+    let closest = start.elapsed();
+
+    let start = Instant::now();
+    let occluded = rays
+        .iter()
+        .filter(|r| {
+            let (t_min, t_max) = interval.along(r);
+            backend.occluder(r, t_min, t_max, None).is_some()
+        })
+        .count();
+    let any = start.elapsed();
+
+    let rate = |elapsed: Duration| rays.len() as f64 / elapsed.as_secs_f64() / 1e6;
+    println!(
+        "{:<8} built in {:.2?}, closest hit {:.2} Mrays/s, any hit {:.2} Mrays/s, {} of {} rays hit",
+        B::NAME,
+        build,
+        rate(closest),
+        rate(any),
+        hits,
+        rays.len()
+    );
+    if occluded != hits {
+        println!(
+            "{:<8} found {} rays blocked but {} hits",
+            B::NAME,
+            occluded,
+            hits
+        );
+    }
+
+    backend.into_objects()
+}
@@ -0,0 +1,403 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+
+/// Deepest hierarchy [`Bvh::hit`] can walk. Trees are split at the median, so they're about
+/// `log2(objects)` deep and this is never reached.
+const MAX_DEPTH: usize = 64;
+
+/// How much costlier than when it was built [`Bvh::refit`] lets the hierarchy get before it
+/// builds it again instead.
+const MAX_DEGRADATION: f64 = 1.5;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        object: usize,
+    },
+    Branch {
+        bbox: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            Self::Leaf { bbox, .. } | Self::Branch { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// A bounding volume hierarchy over a list of objects.
+///
+/// Nodes are stored in a flat array with the root at index 0. Objects without a bounding box
+/// (if any) can't be placed in the tree and are tested against every ray instead.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Bvh<H> {
+    nodes: Vec<BvhNode>,
+    objects: Vec<H>,
+    unbounded: Vec<H>,
+    /// The [`Bvh::cost`] of the hierarchy when it was built.
+    #[serde(default)]
+    built_cost: f64,
+}
+
+/// What [`Bvh::refit`] did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Refit {
+    /// The boxes were fitted to where the objects are now.
+    Refitted,
+    /// The objects moved too far apart from their neighbors in the hierarchy for it to stay
+    /// fast, or lost their bounds, so it was built again from scratch.
+    Rebuilt,
+}
+
+impl<H: Hittable> Bvh<H> {
+    /// Builds the hierarchy, with bounding boxes covering the whole `time0..time1` interval so
+    /// that moving objects are found at any time a ray may carry.
+    pub(crate) fn new(objects: Vec<H>, time0: f64, time1: f64) -> Self {
+        let mut bounded = Vec::with_capacity(objects.len());
+        let mut unbounded = vec![];
+
+        for object in objects {
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => bounded.push((bbox, object)),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(bounded.len() * 2),
+            objects: Vec::with_capacity(bounded.len()),
+            unbounded,
+            built_cost: 0.0,
+        };
+
+        if !bounded.is_empty() {
+            bvh.build(bounded);
+        }
+        bvh.built_cost = bvh.cost();
+
+        bvh
+    }
+
+    /// Fits the boxes of the hierarchy to where its objects are over `time0..time1`, keeping its
+    /// structure, which is much faster than building it again when objects have only moved a
+    /// little, such as between frames of an animation or after nudging an object. Objects that
+    /// moved far make the boxes of their branches large and overlapping, so if that makes the
+    /// hierarchy much slower to walk than when it was built, it is built again instead.
+    pub(crate) fn refit(&mut self, time0: f64, time1: f64) -> Refit {
+        // Children always come after their parent, so going backward fits them first.
+        let mut bounded = true;
+        for index in (0..self.nodes.len()).rev() {
+            let fitted = match self.nodes[index] {
+                BvhNode::Leaf { object, .. } => self.objects[object].bounding_box(time0, time1),
+                BvhNode::Branch { left, right, .. } => Some(surrounding_box(
+                    self.nodes[left].bbox(),
+                    self.nodes[right].bbox(),
+                )),
+            };
+            match (&mut self.nodes[index], fitted) {
+                (BvhNode::Leaf { bbox, .. } | BvhNode::Branch { bbox, .. }, Some(fitted)) => {
+                    *bbox = fitted
+                }
+                (_, None) => bounded = false,
+            }
+        }
+
+        if bounded && self.cost() <= self.built_cost * MAX_DEGRADATION {
+            return Refit::Refitted;
+        }
+
+        let empty = Self {
+            nodes: vec![],
+            objects: vec![],
+            unbounded: vec![],
+            built_cost: 0.0,
+        };
+        let objects = std::mem::replace(self, empty).into_objects();
+        *self = Self::new(objects, time0, time1);
+
+        Refit::Rebuilt
+    }
+
+    /// Appends the subtree for `objects` and returns the index of its root node.
+    fn build(&mut self, mut objects: Vec<(Aabb, H)>) -> usize {
+        let index = self.nodes.len();
+
+        if objects.len() == 1 {
+            let (bbox, object) = objects.pop().unwrap();
+            self.nodes.push(BvhNode::Leaf {
+                bbox,
+                object: self.objects.len(),
+            });
+            self.objects.push(object);
+
+            return index;
+        }
+
+        // Split along the axis where the centroids are the most spread out.
+        let centroids = Aabb::from_points(objects.iter().map(|(bbox, _)| bbox.centroid())).unwrap();
+        let extent = centroids.max() - centroids.min();
+        let axis = extent.imax();
+
+        objects.sort_by(|(a, _), (b, _)| {
+            a.centroid()[axis]
+                .partial_cmp(&b.centroid()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let right_objects = objects.split_off(objects.len() / 2);
+
+        // Reserve this node's slot; the children are built after it.
+        self.nodes.push(BvhNode::Branch {
+            bbox: centroids,
+            left: 0,
+            right: 0,
+        });
+
+        let left = self.build(objects);
+        let right = self.build(right_objects);
+        let bbox = surrounding_box(self.nodes[left].bbox(), self.nodes[right].bbox());
+        self.nodes[index] = BvhNode::Branch { bbox, left, right };
+
+        index
+    }
+}
+
+impl<H> Bvh<H> {
+    /// Returns every object, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &H> {
+        self.objects.iter().chain(&self.unbounded)
+    }
+
+    /// Returns every object, in the same order as [`Bvh::iter`], to change what they are made of.
+    /// Changing their shape would leave the hierarchy out of date.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut H> {
+        self.objects.iter_mut().chain(&mut self.unbounded)
+    }
+
+    /// Returns every object, taking the hierarchy apart.
+    pub(crate) fn into_objects(self) -> Vec<H> {
+        let mut objects = self.objects;
+        objects.extend(self.unbounded);
+
+        objects
+    }
+
+    /// Returns object `index`, counted as in [`Bvh::iter`].
+    fn get(&self, index: usize) -> Option<&H> {
+        match index.checked_sub(self.objects.len()) {
+            Some(index) => self.unbounded.get(index),
+            None => self.objects.get(index),
+        }
+    }
+
+    /// Returns how costly the hierarchy is to walk: the surface areas of its branches, relative
+    /// to that of the root, which is about how many of them a ray through the root visits.
+    fn cost(&self) -> f64 {
+        let root = match self.nodes.first() {
+            Some(root) => root.bbox().surface_area(),
+            None => return 0.0,
+        };
+        if root <= 0.0 {
+            return 0.0;
+        }
+
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node, BvhNode::Branch { .. }))
+            .map(|node| node.bbox().surface_area() / root)
+            .sum()
+    }
+
+    /// Returns the number of nodes in the hierarchy.
+    pub(crate) fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns about how many bytes the hierarchy takes, with its objects but not what they
+    /// refer to.
+    pub(crate) fn memory(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<BvhNode>()
+            + (self.objects.capacity() + self.unbounded.capacity()) * std::mem::size_of::<H>()
+    }
+
+    /// Returns about how many bytes [`Bvh::memory`] comes to once `objects` are built into a
+    /// hierarchy, to tell before building it.
+    pub(crate) fn memory_for(objects: usize) -> usize {
+        objects * (2 * std::mem::size_of::<BvhNode>() + std::mem::size_of::<H>())
+    }
+
+    /// Returns a hierarchy with the same structure, holding `f` of each object.
+    pub(crate) fn map<U>(&self, f: impl Fn(&H) -> U) -> Bvh<U> {
+        Bvh {
+            nodes: self.nodes.clone(),
+            objects: self.objects.iter().map(&f).collect(),
+            unbounded: self.unbounded.iter().map(&f).collect(),
+            built_cost: self.built_cost,
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Bvh<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut temp_rec = None;
+        let mut closest_so_far = t_max;
+
+        for object in &self.unbounded {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = *rec.t();
+                temp_rec = Some(rec);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return temp_rec;
+        }
+
+        // A fixed stack rather than a `Vec`, to keep allocations out of every ray's traversal.
+        let mut stack = [0; MAX_DEPTH];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len]];
+
+            if !node.bbox().hit(r, t_min, closest_so_far) {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { object, .. } => {
+                    if let Some(rec) = self.objects[*object].hit(r, t_min, closest_so_far) {
+                        closest_so_far = *rec.t();
+                        temp_rec = Some(rec);
+                    }
+                }
+                BvhNode::Branch { left, right, .. } => {
+                    stack[len] = *right;
+                    stack[len + 1] = *left;
+                    len += 2;
+                }
+            }
+        }
+
+        temp_rec
+    }
+
+    /// Parts are objects, numbered as in [`Bvh::iter`]. The walk down the hierarchy stops at
+    /// the first object in the way.
+    fn occluder(&self, r: &Ray, t_min: f64, t_max: f64, hint: Option<usize>) -> Option<usize> {
+        if let Some(object) = hint.and_then(|hint| self.get(hint)) {
+            if object.hit(r, t_min, t_max).is_some() {
+                return hint;
+            }
+        }
+
+        for (i, object) in self.unbounded.iter().enumerate() {
+            if object.hit(r, t_min, t_max).is_some() {
+                return Some(self.objects.len() + i);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut stack = [0; MAX_DEPTH];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len]];
+
+            if !node.bbox().hit(r, t_min, t_max) {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { object, .. } => {
+                    if self.objects[*object].hit(r, t_min, t_max).is_some() {
+                        return Some(*object);
+                    }
+                }
+                BvhNode::Branch { left, right, .. } => {
+                    stack[len] = *right;
+                    stack[len + 1] = *left;
+                    len += 2;
+                }
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+
+        self.nodes.first().map(|node| *node.bbox())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::material;
+    use crate::rtweekend::INFINITY;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    fn spheres(xs: &[f64]) -> Bvh<Sphere> {
+        let spheres = xs
+            .iter()
+            .map(|&x| Sphere::new(Point3::new(x, 0.0, 0.0), 0.5, material()))
+            .collect();
+
+        Bvh::new(spheres, 0.0, 1.0)
+    }
+
+    /// Moves the sphere at `from` along X to `to`.
+    fn move_sphere(bvh: &mut Bvh<Sphere>, from: f64, to: f64) {
+        for sphere in bvh.iter_mut() {
+            if sphere.center().x == from {
+                *sphere = Sphere::new(Point3::new(to, 0.0, 0.0), 0.5, material());
+            }
+        }
+    }
+
+    fn hits_at(bvh: &Bvh<Sphere>, x: f64) -> bool {
+        let r = Ray::new(Point3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), None);
+
+        bvh.hit(&r, 0.001, INFINITY).is_some()
+    }
+
+    #[test]
+    fn refits_small_moves_and_rebuilds_after_large_ones() {
+        let xs: Vec<_> = (0..16).map(|i| i as f64 * 2.0).collect();
+
+        let mut nudged = spheres(&xs);
+        move_sphere(&mut nudged, 6.0, 6.5);
+        assert_eq!(nudged.refit(0.0, 1.0), Refit::Refitted);
+        assert!(hits_at(&nudged, 6.9));
+        assert!(!hits_at(&nudged, 5.6));
+
+        // Swapping the ends makes every branch span the whole row.
+        let mut swapped = spheres(&xs);
+        move_sphere(&mut swapped, 0.0, 100.0);
+        move_sphere(&mut swapped, 30.0, 0.0);
+        move_sphere(&mut swapped, 100.0, 30.0);
+        assert_eq!(swapped.refit(0.0, 1.0), Refit::Rebuilt);
+        assert!(hits_at(&swapped, 0.0) && hits_at(&swapped, 30.0));
+        assert_eq!(swapped.iter().count(), 16);
+    }
+}
@@ -1,21 +1,48 @@
+use serde::{Deserialize, Serialize};
+
 use crate::ray::Ray;
 use crate::rtweekend::{degrees_to_radians, random_double_between};
 use crate::scene_loader::StartEndPair;
 use crate::vec3::{random_in_unit_disk, unit_vector, Point3, Vec3};
 
-pub(crate) struct Camera {
+/// Where the camera is and which way it looks, at one instant.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+struct Frame {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
     u: Vec3,
-    w: Vec3,
     v: Vec3,
+}
+
+impl Frame {
+    fn lerp(&self, other: &Self, f: f64) -> Self {
+        Self {
+            origin: self.origin.lerp(&other.origin, f),
+            lower_left_corner: self.lower_left_corner.lerp(&other.lower_left_corner, f),
+            horizontal: self.horizontal.lerp(&other.horizontal, f),
+            vertical: self.vertical.lerp(&other.vertical, f),
+            u: self.u.lerp(&other.u, f),
+            v: self.v.lerp(&other.v, f),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Camera {
+    start: Frame,
+    end: Frame,
     lens_radius: f64,
+    focus_distance: f64,
     time: StartEndPair<f64>,
+    /// Distances along the view direction between which rays see the scene.
+    near: f64,
+    far: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         look_from: Point3,
         look_at: Point3,
@@ -40,27 +67,183 @@ impl Camera {
         let vertical = focus_distance * viewport_height * v;
         let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_distance * w;
 
-        Self {
+        let frame = Frame {
             origin,
             horizontal,
             vertical,
             lower_left_corner,
             u,
-            w,
             v,
+        };
+
+        Self {
+            start: frame,
+            end: frame,
             lens_radius: aperture / 2.0,
+            focus_distance,
             time,
+            near: 0.0,
+            far: f64::INFINITY,
+        }
+    }
+
+    /// Makes the camera see only what is between `near` and `far` along its view direction,
+    /// cutting away the rest as if it weren't there.
+    pub(crate) fn clipped(self, near: f64, far: f64) -> Self {
+        Self { near, far, ..self }
+    }
+
+    /// Widens the image by `x` of its width on the left and on the right, and by `y` of its
+    /// height at the bottom and at the top, keeping what was in frame where it was, for
+    /// overscan.
+    pub(crate) fn overscanned(self, x: f64, y: f64) -> Self {
+        let widen = |frame: Frame| Frame {
+            lower_left_corner: frame.lower_left_corner - x * frame.horizontal - y * frame.vertical,
+            horizontal: (1.0 + 2.0 * x) * frame.horizontal,
+            vertical: (1.0 + 2.0 * y) * frame.vertical,
+            ..frame
+        };
+
+        Self {
+            start: widen(self.start),
+            end: widen(self.end),
+            ..self
+        }
+    }
+
+    /// Makes the camera move from its current position to `end`'s over the shutter interval.
+    ///
+    /// Rays are generated from a linear blend of both positions, which is accurate as long as
+    /// the camera doesn't turn much while the shutter is open.
+    pub(crate) fn moving_to(self, end: &Camera) -> Self {
+        Self {
+            end: end.start,
+            ..self
         }
     }
 
+    /// Returns the camera for one eye of a stereo rig, `offset` units along the camera's
+    /// horizontal axis (negative for the left eye).
+    ///
+    /// Both eyes keep parallel view directions; the image plane is shifted instead so that
+    /// objects at `convergence` distance land on the same pixel in both images (zero parallax).
+    pub(crate) fn eye(&self, offset: f64, convergence: f64) -> Self {
+        let eye_frame = |frame: &Frame| {
+            let shift = offset * frame.u;
+            let toward_center = (self.focus_distance / convergence) * shift;
+
+            Frame {
+                origin: frame.origin + shift,
+                lower_left_corner: frame.lower_left_corner + shift - toward_center,
+                ..*frame
+            }
+        };
+
+        Self {
+            start: eye_frame(&self.start),
+            end: eye_frame(&self.end),
+            time: self.time.clone(),
+            ..*self
+        }
+    }
+
+    /// Returns the ray through the center of the lens and `(s, t)` on the image, when the shutter
+    /// opens.
+    pub(crate) fn center_ray(&self, s: f64, t: f64) -> Ray {
+        let frame = &self.start;
+
+        Ray::new(
+            frame.origin,
+            frame.lower_left_corner + s * frame.horizontal + t * frame.vertical - frame.origin,
+            Some(*self.time.start()),
+        )
+    }
+
+    /// Returns how far `p` is in front of the camera when the shutter opens, along the view
+    /// direction, which is what the focus distance measures.
+    pub(crate) fn depth(&self, p: &Point3) -> f64 {
+        let frame = &self.start;
+
+        (p - frame.origin).dot(&frame.v.cross(&frame.u))
+    }
+
+    /// Returns where `(s, t)` on the image the center of the lens sees `p` when the shutter
+    /// opens, the inverse of [`Camera::center_ray`], or nothing when `p` is behind the camera.
+    pub(crate) fn project(&self, p: &Point3) -> Option<(f64, f64)> {
+        let frame = &self.start;
+        let depth = self.depth(p);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // Follow the line of sight to the image plane, where the lower left corner is.
+        let on_plane =
+            frame.origin + (p - frame.origin) * (self.depth(&frame.lower_left_corner) / depth);
+        let offset = on_plane - frame.lower_left_corner;
+
+        Some((
+            offset.dot(&frame.horizontal) / frame.horizontal.norm_squared(),
+            offset.dot(&frame.vertical) / frame.vertical.norm_squared(),
+        ))
+    }
+
     pub(crate) fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let time = random_double_between(*self.time.start(), *self.time.end());
+        let duration = self.time.end() - self.time.start();
+        let frame = if self.start == self.end || duration <= 0.0 {
+            self.start
+        } else {
+            self.start
+                .lerp(&self.end, (time - self.time.start()) / duration)
+        };
+
         let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
+        let offset = frame.u * rd.x + frame.v * rd.y;
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            Some(random_double_between(*self.time.start(), *self.time.end())),
+        let ray = Ray::new(
+            frame.origin + offset,
+            frame.lower_left_corner + s * frame.horizontal + t * frame.vertical
+                - frame.origin
+                - offset,
+            Some(time),
         )
+        .on_screen(s, t);
+
+        if self.near <= 0.0 && self.far == f64::INFINITY {
+            return ray;
+        }
+
+        // How far along the view direction the ray goes for each unit of `t`.
+        let along = ray.direction().dot(&frame.v.cross(&frame.u));
+        ray.clipped(self.near / along, self.far / along)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overscan_keeps_the_frame_in_place() {
+        let camera = Camera::new(
+            Point3::new(0.0, 1.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            2.0,
+            0.0,
+            1.0,
+            StartEndPair::new(0.0, 1.0),
+        );
+        // 10 pixels of overscan around a frame 101 by 51 pixels.
+        let overscanned = camera.clone().overscanned(10.0 / 100.0, 10.0 / 50.0);
+
+        for (s, t) in [(0.0, 0.0), (0.25, 0.5), (1.0, 1.0)] {
+            let (i, j) = (s * 100.0 + 10.0, t * 50.0 + 10.0);
+            let inside = overscanned.center_ray(i / 120.0, j / 70.0);
+            let frame = camera.center_ray(s, t);
+
+            assert!((inside.direction() - frame.direction()).norm() < 1e-12);
+        }
     }
 }
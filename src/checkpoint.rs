@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::output::write_atomically;
+use crate::render::Accumulation;
+
+/// Bumped whenever the layout of checkpoints changes.
+const CHECKPOINT_VERSION: u32 = 2;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Header {
+    version: u32,
+    render: RenderIdentity,
+}
+
+/// What a render is of, which a render resuming its checkpoint must be of too, so that samples
+/// of another scene or view don't end up in the same image.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct RenderIdentity {
+    width: u32,
+    height: u32,
+    samples_per_pixel: usize,
+    camera: Camera,
+    /// A checksum of the contents of the scene file and of every file it refers to.
+    scene: u32,
+}
+
+impl RenderIdentity {
+    /// Returns the identity of a `width` × `height` render of the scene built from `sources`,
+    /// seen by `camera`, taking `samples_per_pixel` samples.
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        samples_per_pixel: usize,
+        camera: &Camera,
+        sources: &[PathBuf],
+    ) -> Result<Self> {
+        let mut hasher = crc32fast::Hasher::new();
+        for source in sources {
+            hasher.update(
+                &std::fs::read(source).wrap_err_with(|| format!("reading {}", source.display()))?,
+            );
+        }
+
+        Ok(Self {
+            width,
+            height,
+            samples_per_pixel,
+            camera: camera.clone(),
+            scene: hasher.finalize(),
+        })
+    }
+
+    /// Returns why a render of `self` can't resume the checkpoint of a render of `saved`, if
+    /// it can't.
+    fn mismatch(&self, saved: &Self) -> Option<String> {
+        if (saved.width, saved.height) != (self.width, self.height) {
+            Some(format!(
+                "is of a {}x{} image, not {}x{}",
+                saved.width, saved.height, self.width, self.height
+            ))
+        } else if saved.samples_per_pixel != self.samples_per_pixel {
+            Some(format!(
+                "is of a render taking {} samples per pixel, not {}",
+                saved.samples_per_pixel, self.samples_per_pixel
+            ))
+        } else if saved.camera != self.camera {
+            Some("is of a render seen from another camera".to_string())
+        } else if saved.scene != self.scene {
+            Some("is of a render of another scene, or the scene has changed since".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns where an interrupted render of `scene_path` saves its samples
+/// (`scene.yml.checkpoint` for `scene.yml`).
+pub(crate) fn checkpoint_path(scene_path: &str) -> String {
+    format!("{}.checkpoint", scene_path)
+}
+
+/// Saves the samples of an interrupted `render`, for `--resume` to continue.
+pub(crate) fn write_checkpoint(
+    path: &str,
+    render: RenderIdentity,
+    accumulation: &Accumulation,
+) -> Result<()> {
+    let header = Header {
+        version: CHECKPOINT_VERSION,
+        render,
+    };
+
+    write_atomically(Path::new(path), |partial| {
+        let mut writer = BufWriter::new(File::create(partial)?);
+        rmp_serde::encode::write_named(&mut writer, &header)?;
+        rmp_serde::encode::write_named(&mut writer, accumulation)?;
+        writer.flush()?;
+
+        Ok(())
+    })
+}
+
+/// Reads the samples [`write_checkpoint`] saved, which must be of the same `render`.
+pub(crate) fn read_checkpoint(path: &str, render: &RenderIdentity) -> Result<Accumulation> {
+    let mut reader =
+        BufReader::new(File::open(path).wrap_err_with(|| format!("opening {}", path))?);
+    let header: Header =
+        rmp_serde::decode::from_read(&mut reader).wrap_err_with(|| format!("reading {}", path))?;
+
+    if header.version != CHECKPOINT_VERSION {
+        bail!("{} was written by another version of the raytracer", path);
+    }
+    if let Some(mismatch) = render.mismatch(&header.render) {
+        bail!("{} {}", path, mismatch);
+    }
+
+    let accumulation: Accumulation =
+        rmp_serde::decode::from_read(&mut reader).wrap_err_with(|| format!("reading {}", path))?;
+    if accumulation.len() != (render.width * render.height) as usize {
+        bail!("{} is truncated", path);
+    }
+
+    Ok(accumulation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_loader::StartEndPair;
+    use crate::vec3::{Point3, Vec3};
+
+    fn camera(vfov: f64) -> Camera {
+        Camera::new(
+            Point3::new(0.0, 1.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            vfov,
+            2.0,
+            0.0,
+            1.0,
+            StartEndPair::new(0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn resumes_only_the_same_render() {
+        let id = std::process::id();
+        let scene = std::env::temp_dir().join(format!("checkpoint-{}.yml", id));
+        let checkpoint = checkpoint_path(&scene.to_string_lossy());
+        std::fs::write(&scene, "objects: []").unwrap();
+        let sources = [scene.clone()];
+
+        let render = || RenderIdentity::new(4, 2, 16, &camera(40.0), &sources).unwrap();
+        write_checkpoint(&checkpoint, render(), &Accumulation::empty(8)).unwrap();
+        assert_eq!(read_checkpoint(&checkpoint, &render()).unwrap().len(), 8);
+
+        let others = [
+            RenderIdentity::new(2, 4, 16, &camera(40.0), &sources).unwrap(),
+            RenderIdentity::new(4, 2, 64, &camera(40.0), &sources).unwrap(),
+            RenderIdentity::new(4, 2, 16, &camera(30.0), &sources).unwrap(),
+        ];
+        for other in &others {
+            assert!(read_checkpoint(&checkpoint, other).is_err());
+        }
+        std::fs::write(&scene, "objects: [] # edited").unwrap();
+        assert!(read_checkpoint(&checkpoint, &render()).is_err());
+
+        std::fs::remove_file(&scene).unwrap();
+        std::fs::remove_file(&checkpoint).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::scene_loader::ClipPlaneDef;
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+/// A plane cutting objects, which keeps the side its normal points to and cuts away the other.
+///
+/// Cut objects are left open, showing their inside, as in cutaway and sectional renders.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "ClipPlaneDef", into = "ClipPlaneDef")]
+pub(crate) struct ClipPlane {
+    point: Point3,
+    normal: Vec3,
+}
+
+impl ClipPlane {
+    pub(crate) fn new(point: Point3, normal: Vec3) -> Self {
+        Self {
+            point,
+            normal: unit_vector(&normal),
+        }
+    }
+
+    pub(crate) fn point(&self) -> &Point3 {
+        &self.point
+    }
+
+    pub(crate) fn normal(&self) -> &Vec3 {
+        &self.normal
+    }
+
+    /// Returns the plane moved along with objects scaled by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Self {
+        Self::new(self.point * factor, self.normal)
+    }
+
+    /// Narrows `t_min..t_max` to the part of `r` on the kept side of the plane, which is empty
+    /// when `t_max < t_min`.
+    fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> (f64, f64) {
+        let distance = self.normal.dot(&(r.origin() - self.point));
+        let speed = self.normal.dot(r.direction());
+
+        if speed > 0.0 {
+            (t_min.max(-distance / speed), t_max)
+        } else if speed < 0.0 {
+            (t_min, t_max.min(-distance / speed))
+        } else if distance < 0.0 {
+            (t_max, t_min)
+        } else {
+            (t_min, t_max)
+        }
+    }
+}
+
+/// Intersects `object` with the part of `r` that all of `planes` keep. The kept space is
+/// convex, so that part is a single interval and hits outside of it need no second look.
+pub(crate) fn hit<'a, H: Hittable>(
+    planes: &[ClipPlane],
+    object: &'a H,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let (t_min, t_max) = planes.iter().fold((t_min, t_max), |(t_min, t_max), plane| {
+        plane.clip(r, t_min, t_max)
+    });
+
+    if t_max < t_min {
+        return None;
+    }
+
+    object.hit(r, t_min, t_max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::rtweekend::INFINITY;
+    use crate::sphere::Sphere;
+
+    fn sphere() -> Sphere {
+        Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, material())
+    }
+
+    #[test]
+    fn shows_the_inside_of_a_cut_object() {
+        // Keeping the far half of the sphere, the ray goes in through the cut and hits the
+        // inside of the back.
+        let planes = [ClipPlane::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        )];
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        let sphere = sphere();
+        let rec = hit(&planes, &sphere, &r, 0.001, INFINITY);
+        assert_hit(rec, &r, 2.5, Vec3::new(0.0, 0.0, 1.0), false);
+    }
+
+    #[test]
+    fn keeps_the_near_side_it_faces() {
+        let planes = [ClipPlane::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        )];
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        let sphere = sphere();
+        let rec = hit(&planes, &sphere, &r, 0.001, INFINITY);
+        assert_hit(rec, &r, 1.5, Vec3::new(0.0, 0.0, 1.0), true);
+    }
+
+    #[test]
+    fn misses_everything_cut_away() {
+        let planes = [
+            ClipPlane::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0)),
+            ClipPlane::new(Point3::new(0.0, 0.0, -1.8), Vec3::new(0.0, 0.0, -1.0)),
+        ];
+        let along = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+        let parallel = Ray::new(
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Some(0.0),
+        );
+
+        assert!(hit(&planes, &sphere(), &along, 0.001, INFINITY).is_none());
+        assert!(hit(&planes[1..], &sphere(), &parallel, 0.001, INFINITY).is_none());
+    }
+}
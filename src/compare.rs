@@ -0,0 +1,466 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use nalgebra::Matrix3;
+
+use crate::vec3::Color;
+
+/// Pixels per degree of visual angle FLIP assumes by default: a 0.7 m wide 4K monitor seen
+/// from 0.7 m.
+const PIXELS_PER_DEGREE: f64 = 67.0;
+
+/// Measure how different two images are, and write where they differ to `-o` as a heat map.
+///
+/// Both images must have the same size. They are read as any format `image` reads, such as
+/// PNG.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "compare")]
+pub(crate) struct Compare {
+    /// reference image
+    #[argh(positional)]
+    pub(crate) reference: String,
+
+    /// image to compare against the reference
+    #[argh(positional)]
+    pub(crate) test: String,
+
+    /// how to measure the difference, either `mse` (the mean squared error of the linear
+    /// colors) or `flip` (NVIDIA's FLIP, which weighs it by how visible it is, from 0 to 1)
+    #[argh(option, default = "Metric::Mse")]
+    pub(crate) metric: Metric,
+
+    /// fail if the difference is larger than this
+    #[argh(option)]
+    pub(crate) fail_above: Option<f64>,
+}
+
+/// An image difference metric.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Metric {
+    Mse,
+    Flip,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mse" => Ok(Self::Mse),
+            "flip" => Ok(Self::Flip),
+            _ => Err(format!("unknown metric `{}` (expected `mse` or `flip`)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mse => write!(f, "mse"),
+            Self::Flip => write!(f, "flip"),
+        }
+    }
+}
+
+/// An image as displayed, with channels from 0 to 1, from the top row down.
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    fn load(path: &str) -> Result<Self> {
+        let image = image::open(path)
+            .wrap_err_with(|| format!("reading {}", path))?
+            .to_rgb8();
+        let channel = |c: u8| c as f64 / 255.0;
+
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|p| Color::new(channel(p[0]), channel(p[1]), channel(p[2])))
+                .collect(),
+        })
+    }
+}
+
+impl Compare {
+    /// Prints the difference, and hands the heat map to `write` as RGB8 data with its width and
+    /// height.
+    pub(crate) fn run(&self, write: impl FnOnce(&[u8], u32, u32) -> Result<()>) -> Result<()> {
+        let reference = Image::load(&self.reference)?;
+        let test = Image::load(&self.test)?;
+        if (reference.width, reference.height) != (test.width, test.height) {
+            bail!(
+                "{} is {}x{} but {} is {}x{}",
+                self.reference,
+                reference.width,
+                reference.height,
+                self.test,
+                test.width,
+                test.height
+            );
+        }
+
+        let errors = match self.metric {
+            Metric::Mse => squared_errors(&reference, &test),
+            Metric::Flip => flip(&reference, &test, PIXELS_PER_DEGREE),
+        };
+        let mean = errors.iter().sum::<f64>() / errors.len().max(1) as f64;
+        println!("{} {:.6}", self.metric, mean);
+
+        // FLIP errors go from 0 to 1 already; squared errors are shown relative to the largest.
+        let scale = match self.metric {
+            Metric::Mse => errors.iter().copied().fold(f64::EPSILON, f64::max),
+            Metric::Flip => 1.0,
+        };
+        let heat_map: Vec<_> = errors
+            .iter()
+            .flat_map(|error| magma(error / scale))
+            .collect();
+        write(&heat_map, reference.width as u32, reference.height as u32)?;
+
+        if let Some(threshold) = self.fail_above {
+            if mean > threshold {
+                bail!("the {} of {:.6} is above {}", self.metric, mean, threshold);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the squared error of each pixel, averaged over the channels of the linear colors,
+/// which images are written with a gamma of 2 from.
+fn squared_errors(reference: &Image, test: &Image) -> Vec<f64> {
+    let linear = |c: &Color| c.component_mul(c);
+
+    reference
+        .pixels
+        .iter()
+        .zip(&test.pixels)
+        .map(|(a, b)| (linear(a) - linear(b)).norm_squared() / 3.0)
+        .collect()
+}
+
+/// Maps `t` in `0..1` to the magma color map, from black through purple and orange to pale
+/// yellow.
+fn magma(t: f64) -> [u8; 3] {
+    const STOPS: [[f64; 3]; 5] = [
+        [0.0, 0.0, 4.0],
+        [81.0, 18.0, 124.0],
+        [183.0, 55.0, 121.0],
+        [252.0, 137.0, 97.0],
+        [252.0, 253.0, 191.0],
+    ];
+
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    let channel = |c: usize| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f).round() as u8;
+
+    [channel(0), channel(1), channel(2)]
+}
+
+/// A filter written as a sum of separable terms, each a horizontal and a vertical kernel of
+/// odd length.
+type Filter = Vec<(Vec<f64>, Vec<f64>)>;
+
+/// Filters one channel of an image of `width` by `height`, mirroring it at the borders.
+fn convolve(channel: &[f64], width: usize, height: usize, filter: &Filter) -> Vec<f64> {
+    let mirror = |i: isize, n: usize| {
+        let n = n as isize;
+        let period = 2 * n;
+        let i = i.rem_euclid(period);
+        (if i < n { i } else { period - 1 - i }) as usize
+    };
+
+    let mut result = vec![0.0; channel.len()];
+    for (horizontal, vertical) in filter {
+        let (rx, ry) = (horizontal.len() as isize / 2, vertical.len() as isize / 2);
+
+        let mut rows = vec![0.0; channel.len()];
+        for y in 0..height {
+            for x in 0..width {
+                rows[y * width + x] = horizontal
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        w * channel[y * width + mirror(x as isize + k as isize - rx, width)]
+                    })
+                    .sum();
+            }
+        }
+        for y in 0..height {
+            for x in 0..width {
+                result[y * width + x] += vertical
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        w * rows[mirror(y as isize + k as isize - ry, height) * width + x]
+                    })
+                    .sum::<f64>();
+            }
+        }
+    }
+
+    result
+}
+
+/// Converts linear sRGB to CIE XYZ.
+fn rgb_to_xyz() -> Matrix3<f64> {
+    Matrix3::new(
+        0.4124, 0.3576, 0.1805, 0.2126, 0.7152, 0.0722, 0.0193, 0.1192, 0.9505,
+    )
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// XYZ of linear white, which the opponent and Lab spaces are relative to.
+fn white_point() -> Color {
+    rgb_to_xyz() * Color::new(1.0, 1.0, 1.0)
+}
+
+/// Converts linear RGB to the YCxCz opponent space: achromatic, red-green and blue-yellow.
+fn linear_to_ycxcz(c: &Color) -> Color {
+    let xyz = (rgb_to_xyz() * c).component_div(&white_point());
+
+    Color::new(
+        116.0 * xyz.y - 16.0,
+        500.0 * (xyz.x - xyz.y),
+        200.0 * (xyz.y - xyz.z),
+    )
+}
+
+fn ycxcz_to_linear(c: &Color, xyz_to_rgb: &Matrix3<f64>) -> Color {
+    let y = (c.x + 16.0) / 116.0;
+    let xyz = Color::new(c.y / 500.0 + y, y, y - c.z / 200.0).component_mul(&white_point());
+
+    xyz_to_rgb * xyz
+}
+
+/// Converts linear RGB to CIELAB, with the chroma scaled down in the dark as the Hunt effect
+/// says it looks.
+fn linear_to_hunt_lab(c: &Color) -> Color {
+    let delta: f64 = 6.0 / 29.0;
+    let f = |t: f64| {
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let xyz = (rgb_to_xyz() * c).component_div(&white_point());
+    let (fx, fy, fz) = (f(xyz.x), f(xyz.y), f(xyz.z));
+    let l = 116.0 * fy - 16.0;
+
+    Color::new(
+        l,
+        0.01 * l * 500.0 * (fx - fy),
+        0.01 * l * 200.0 * (fy - fz),
+    )
+}
+
+/// The HyAB distance: lightness and chroma differences added, rather than put together as in
+/// Euclidean distance.
+fn hyab(a: &Color, b: &Color) -> f64 {
+    (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// The contrast sensitivity of the eye to one channel of YCxCz, as a filter over pixels.
+fn contrast_sensitivity(pixels_per_degree: f64, (a1, b1, a2, b2): (f64, f64, f64, f64)) -> Filter {
+    // Large enough for the widest of the three channels.
+    let radius = (3.0 * (0.04 / (2.0 * PI * PI)).sqrt() * pixels_per_degree).ceil() as isize;
+    let gaussian = |a: f64, b: f64| {
+        let kernel: Vec<_> = (-radius..=radius)
+            .map(|x| (-PI * PI * (x as f64 / pixels_per_degree).powi(2) / b).exp())
+            .collect();
+        (
+            kernel.iter().map(|g| a * (PI / b).sqrt() * g).collect(),
+            kernel,
+        )
+    };
+
+    let mut filter: Filter = vec![gaussian(a1, b1)];
+    if a2 != 0.0 {
+        filter.push(gaussian(a2, b2));
+    }
+    let total: f64 = filter
+        .iter()
+        .map(|(h, v)| h.iter().sum::<f64>() * v.iter().sum::<f64>())
+        .sum();
+    for (horizontal, _) in &mut filter {
+        for w in horizontal.iter_mut() {
+            *w /= total;
+        }
+    }
+
+    filter
+}
+
+/// Returns the filters finding edges (with `point` false) or points along X and along Y, with
+/// the positive and the negative weights each summing to 1.
+fn feature_filters(pixels_per_degree: f64, point: bool) -> [Filter; 2] {
+    let sd = 0.5 * 0.082 * pixels_per_degree;
+    let radius = (3.0 * sd).ceil() as isize;
+    let gaussian: Vec<_> = (-radius..=radius)
+        .map(|x| (-(x * x) as f64 / (2.0 * sd * sd)).exp())
+        .collect();
+    let derivative: Vec<_> = (-radius..=radius)
+        .zip(&gaussian)
+        .map(|(x, g)| {
+            let x = x as f64;
+            if point {
+                (x * x / (sd * sd) - 1.0) * g
+            } else {
+                -x * g
+            }
+        })
+        .collect();
+
+    let positive: f64 = derivative.iter().filter(|w| **w > 0.0).sum();
+    let negative: f64 = -derivative.iter().filter(|w| **w < 0.0).sum::<f64>();
+    let derivative: Vec<_> = derivative
+        .iter()
+        .map(|w| if *w < 0.0 { w / negative } else { w / positive })
+        .collect();
+    let sum: f64 = gaussian.iter().sum();
+    let gaussian: Vec<_> = gaussian.iter().map(|g| g / sum).collect();
+
+    [
+        vec![(derivative.clone(), gaussian.clone())],
+        vec![(gaussian, derivative)],
+    ]
+}
+
+/// Returns the FLIP error of each pixel of `test` against `reference`, both displayed as
+/// sRGB, as seen with `pixels_per_degree` (Andersson et al., "FLIP: A Difference Evaluator for
+/// Alternating Images", 2020).
+fn flip(reference: &Image, test: &Image, pixels_per_degree: f64) -> Vec<f64> {
+    let (width, height) = (reference.width, reference.height);
+    let xyz_to_rgb = rgb_to_xyz()
+        .try_inverse()
+        .expect("the sRGB primaries are independent");
+    let opponent = |image: &Image| -> Vec<Color> {
+        image
+            .pixels
+            .iter()
+            .map(|c| linear_to_ycxcz(&c.map(srgb_to_linear)))
+            .collect()
+    };
+    let (reference, test) = (opponent(reference), opponent(test));
+
+    // Colors, as blurred by the eye's contrast sensitivity to each channel.
+    let sensitivities = [
+        contrast_sensitivity(pixels_per_degree, (1.0, 0.0047, 0.0, 1e-5)),
+        contrast_sensitivity(pixels_per_degree, (1.0, 0.0053, 0.0, 1e-5)),
+        contrast_sensitivity(pixels_per_degree, (34.1, 0.04, 13.5, 0.025)),
+    ];
+    let filtered = |image: &[Color]| -> Vec<Color> {
+        let channels: Vec<_> = (0..3)
+            .map(|c| {
+                let channel: Vec<_> = image.iter().map(|p| p[c]).collect();
+                convolve(&channel, width, height, &sensitivities[c])
+            })
+            .collect();
+        (0..image.len())
+            .map(|i| {
+                let c = Color::new(channels[0][i], channels[1][i], channels[2][i]);
+                linear_to_hunt_lab(&ycxcz_to_linear(&c, &xyz_to_rgb).map(|v| v.clamp(0.0, 1.0)))
+            })
+            .collect()
+    };
+    let (reference_lab, test_lab) = (filtered(&reference), filtered(&test));
+
+    let qc = 0.7;
+    let green = linear_to_hunt_lab(&Color::new(0.0, 1.0, 0.0));
+    let blue = linear_to_hunt_lab(&Color::new(0.0, 0.0, 1.0));
+    let cmax = hyab(&green, &blue).powf(qc);
+    let (pc, pt) = (0.4, 0.95);
+    let color_errors = reference_lab.iter().zip(&test_lab).map(|(a, b)| {
+        let error = hyab(a, b).powf(qc);
+        if error < pc * cmax {
+            pt / (pc * cmax) * error
+        } else {
+            pt + (error - pc * cmax) / (cmax - pc * cmax) * (1.0 - pt)
+        }
+    });
+
+    // Edges and points, found in the achromatic channel.
+    let features = |image: &[Color], point: bool| -> Vec<f64> {
+        let luminance: Vec<_> = image.iter().map(|c| (c.x + 16.0) / 116.0).collect();
+        let [x, y] = feature_filters(pixels_per_degree, point);
+        let (x, y) = (
+            convolve(&luminance, width, height, &x),
+            convolve(&luminance, width, height, &y),
+        );
+        x.iter().zip(&y).map(|(x, y)| x.hypot(*y)).collect()
+    };
+    let (edges_reference, edges_test) = (features(&reference, false), features(&test, false));
+    let (points_reference, points_test) = (features(&reference, true), features(&test, true));
+    let feature_errors = (0..reference.len()).map(|i| {
+        let difference = (edges_reference[i] - edges_test[i])
+            .abs()
+            .max((points_reference[i] - points_test[i]).abs());
+        (difference / 2.0_f64.sqrt()).sqrt()
+    });
+
+    color_errors
+        .zip(feature_errors)
+        .map(|(color, feature)| color.powf(1.0 - feature))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, color: impl Fn(usize, usize) -> Color) -> Image {
+        Image {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| color(x, y))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_images_do_not_differ() {
+        let checker = image(24, 16, |x, y| {
+            Color::new(((x / 4 + y / 4) % 2) as f64, 0.5, 0.25)
+        });
+
+        assert!(squared_errors(&checker, &checker).iter().all(|e| *e == 0.0));
+        assert!(flip(&checker, &checker, PIXELS_PER_DEGREE)
+            .iter()
+            .all(|e| e.abs() < 1e-9));
+    }
+
+    #[test]
+    fn flip_sees_black_against_white_as_different() {
+        let (width, height) = (16, 16);
+        let black = image(width, height, |_, _| Color::zeros());
+        let white = image(width, height, |_, _| Color::new(1.0, 1.0, 1.0));
+        let grey = image(width, height, |_, _| Color::new(0.9, 0.9, 0.9));
+
+        let mean = |errors: Vec<f64>| errors.iter().sum::<f64>() / errors.len() as f64;
+        let far = mean(flip(&black, &white, PIXELS_PER_DEGREE));
+        let near = mean(flip(&grey, &white, PIXELS_PER_DEGREE));
+        assert!(far > 0.9 && far <= 1.0, "{}", far);
+        assert!(near > 0.0 && near < far, "{}", near);
+    }
+}
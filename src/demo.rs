@@ -0,0 +1,354 @@
+use std::fmt;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use crate::furnace::default_materials;
+use crate::material::{Dielectric, DiffuseLight, Fresnel, Lambertian, Material, Metal};
+use crate::sampler::{splitmix, to_unit};
+use crate::scene_loader::{Color, Point3};
+use crate::sphere::Sphere;
+
+/// Render one of the built-in scenes, or print its scene file as an example to start from.
+///
+/// The scenes are built from the same types as the renderer, so they keep up with it.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "demo")]
+pub(crate) struct Demo {
+    /// which scene: `cornell`, `furnace`, `spheres` or `glass-caustic`
+    #[argh(option)]
+    pub(crate) scene: DemoScene,
+
+    /// print the scene file instead of rendering it
+    #[argh(switch)]
+    pub(crate) print: bool,
+}
+
+/// The built-in scenes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DemoScene {
+    /// A box with a red wall on the left and a green one on the right, lit from the ceiling,
+    /// around two blocks.
+    Cornell,
+    /// Spheres of every kind of material in a uniformly white environment, where those that
+    /// neither create nor lose energy disappear.
+    Furnace,
+    /// The random spheres of the cover of _Ray Tracing in One Weekend_.
+    Spheres,
+    /// A glass sphere focusing a small light onto the floor.
+    GlassCaustic,
+}
+
+impl DemoScene {
+    pub(crate) const ALL: [Self; 4] = [
+        Self::Cornell,
+        Self::Furnace,
+        Self::Spheres,
+        Self::GlassCaustic,
+    ];
+
+    /// Returns the scene file of the scene.
+    pub(crate) fn scene_file(&self) -> Result<String> {
+        let document = match self {
+            Self::Cornell => cornell(),
+            Self::Furnace => furnace(),
+            Self::Spheres => spheres(),
+            Self::GlassCaustic => glass_caustic(),
+        };
+
+        Ok(serde_yaml::to_string(&document)?)
+    }
+}
+
+impl FromStr for DemoScene {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|scene| scene.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown demo scene `{}` (expected `cornell`, `furnace`, `spheres` or \
+                     `glass-caustic`)",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for DemoScene {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cornell => write!(f, "cornell"),
+            Self::Furnace => write!(f, "furnace"),
+            Self::Spheres => write!(f, "spheres"),
+            Self::GlassCaustic => write!(f, "glass-caustic"),
+        }
+    }
+}
+
+/// A scene file, with only the settings the demos need.
+#[derive(Serialize)]
+pub(crate) struct Document {
+    pub(crate) camera: Camera,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) background: Option<Color>,
+    pub(crate) objects: Vec<Object>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Camera {
+    look_from: Point3,
+    look_at: Point3,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum Object {
+    Sphere(Sphere),
+    Mesh {
+        vertices: Vec<Point3>,
+        faces: Vec<[usize; 3]>,
+        material: Material,
+    },
+    Transformed {
+        transform: Placement,
+        objects: Vec<Object>,
+    },
+}
+
+#[derive(Serialize)]
+pub(crate) struct Placement {
+    translate: Point3,
+    /// In degrees.
+    rotate_y: f64,
+}
+
+fn point(x: f64, y: f64, z: f64) -> Point3 {
+    (&crate::Point3::new(x, y, z)).into()
+}
+
+pub(crate) fn camera(look_from: (f64, f64, f64), look_at: (f64, f64, f64)) -> Camera {
+    Camera {
+        look_from: point(look_from.0, look_from.1, look_from.2),
+        look_at: point(look_at.0, look_at.1, look_at.2),
+    }
+}
+
+pub(crate) fn sphere(center: (f64, f64, f64), radius: f64, material: Material) -> Object {
+    let center = crate::Point3::new(center.0, center.1, center.2);
+
+    Object::Sphere(Sphere::new(center, radius, material))
+}
+
+pub(crate) fn lambertian(r: f64, g: f64, b: f64) -> Material {
+    Material::Lambertian(Lambertian::new(crate::Color::new(r, g, b)))
+}
+
+pub(crate) fn light(power: f64) -> Material {
+    Material::DiffuseLight(DiffuseLight::new(crate::Color::new(power, power, power)))
+}
+
+fn glass() -> Material {
+    Material::Dielectric(Dielectric::new(1.5, Fresnel::Schlick))
+}
+
+/// A rectangle from `corner` along `u` then `v`, with its front on the side they go around
+/// counterclockwise from.
+pub(crate) fn quad(
+    corner: crate::Point3,
+    u: crate::Vec3,
+    v: crate::Vec3,
+    material: Material,
+) -> Object {
+    Object::Mesh {
+        vertices: [corner, corner + u, corner + u + v, corner + v]
+            .iter()
+            .map(Point3::from)
+            .collect(),
+        faces: vec![[0, 1, 2], [0, 2, 3]],
+        material,
+    }
+}
+
+/// A box standing on the floor, `width` by `depth` and `height` high, centered on `(x, z)`
+/// and turned by `angle` degrees around the Y axis.
+fn block(x: f64, z: f64, size: (f64, f64, f64), angle: f64, material: Material) -> Object {
+    let (width, height, depth) = size;
+    let vertices = (0..8)
+        .map(|i| {
+            let pick = |bit, length: f64| {
+                if i & bit == 0 {
+                    -length / 2.0
+                } else {
+                    length / 2.0
+                }
+            };
+            point(
+                pick(1, width),
+                pick(2, height) + height / 2.0,
+                pick(4, depth),
+            )
+        })
+        .collect();
+    let faces = vec![
+        [0, 2, 6],
+        [0, 6, 4],
+        [1, 5, 7],
+        [1, 7, 3],
+        [0, 4, 5],
+        [0, 5, 1],
+        [2, 3, 7],
+        [2, 7, 6],
+        [0, 1, 3],
+        [0, 3, 2],
+        [4, 6, 7],
+        [4, 7, 5],
+    ];
+
+    Object::Transformed {
+        transform: Placement {
+            translate: point(x, 0.0, z),
+            rotate_y: angle,
+        },
+        objects: vec![Object::Mesh {
+            vertices,
+            faces,
+            material,
+        }],
+    }
+}
+
+fn cornell() -> Document {
+    let white = || lambertian(0.73, 0.73, 0.73);
+    let (x, y, z) = (
+        crate::Vec3::new(2.0, 0.0, 0.0),
+        crate::Vec3::new(0.0, 2.0, 0.0),
+        crate::Vec3::new(0.0, 0.0, 2.0),
+    );
+    let corner = crate::Point3::new(-1.0, 0.0, -1.0);
+    let far_corner = crate::Point3::new(1.0, 2.0, 1.0);
+
+    Document {
+        camera: camera((0.0, 1.0, 10.0), (0.0, 1.0, 0.0)),
+        background: Some((&crate::Color::zeros()).into()),
+        objects: vec![
+            quad(corner, z, x, white()),
+            quad(far_corner, -x, -z, white()),
+            quad(corner, x, y, white()),
+            quad(corner, y, z, lambertian(0.65, 0.05, 0.05)),
+            quad(far_corner, -z, -y, lambertian(0.12, 0.45, 0.15)),
+            sphere((0.0, 1.8, 0.0), 0.12, light(40.0)),
+            block(-0.35, -0.3, (0.6, 1.2, 0.6), 18.0, white()),
+            block(0.35, 0.35, (0.6, 0.6, 0.6), -18.0, white()),
+        ],
+    }
+}
+
+fn furnace() -> Document {
+    let materials = default_materials();
+    let spacing = 0.85;
+    let first = -spacing * (materials.len() - 1) as f64 / 2.0;
+
+    Document {
+        camera: camera((0.0, 0.0, 10.0), (0.0, 0.0, 0.0)),
+        background: Some((&crate::Color::new(1.0, 1.0, 1.0)).into()),
+        objects: materials
+            .into_iter()
+            .enumerate()
+            .map(|(i, material)| sphere((first + spacing * i as f64, 0.0, 0.0), 0.4, material))
+            .collect(),
+    }
+}
+
+fn spheres() -> Document {
+    // The same spheres in every build, rather than new ones on every run.
+    let mut state = 0;
+    let mut random = move || {
+        state += 1;
+        to_unit(splitmix(state))
+    };
+
+    let mut objects = vec![sphere(
+        (0.0, -1000.0, 0.0),
+        1000.0,
+        lambertian(0.5, 0.5, 0.5),
+    )];
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = (a as f64 + 0.9 * random(), 0.2, b as f64 + 0.9 * random());
+            let offset = crate::Point3::new(center.0 - 4.0, 0.0, center.2).norm();
+            if offset <= 0.9 {
+                continue;
+            }
+
+            let choice = random();
+            let material = if choice < 0.8 {
+                lambertian(
+                    random() * random(),
+                    random() * random(),
+                    random() * random(),
+                )
+            } else if choice < 0.95 {
+                let albedo = crate::Color::new(
+                    0.5 + random() / 2.0,
+                    0.5 + random() / 2.0,
+                    0.5 + random() / 2.0,
+                );
+                Material::Metal(Metal::new(albedo, random() / 2.0))
+            } else {
+                glass()
+            };
+            objects.push(sphere(center, 0.2, material));
+        }
+    }
+    objects.push(sphere((0.0, 1.0, 0.0), 1.0, glass()));
+    objects.push(sphere((-4.0, 1.0, 0.0), 1.0, lambertian(0.4, 0.2, 0.1)));
+    objects.push(sphere(
+        (4.0, 1.0, 0.0),
+        1.0,
+        Material::Metal(Metal::new(crate::Color::new(0.7, 0.6, 0.5), 0.0)),
+    ));
+
+    Document {
+        camera: camera((13.0, 2.0, 3.0), (0.0, 0.0, 0.0)),
+        background: None,
+        objects,
+    }
+}
+
+fn glass_caustic() -> Document {
+    Document {
+        camera: camera((0.0, 3.0, 9.0), (0.0, 0.7, 0.0)),
+        background: Some((&crate::Color::new(0.01, 0.01, 0.01)).into()),
+        objects: vec![
+            sphere((0.0, -1000.0, 0.0), 1000.0, lambertian(0.8, 0.8, 0.8)),
+            sphere((0.0, 1.0, 0.0), 1.0, glass()),
+            sphere((-2.0, 5.0, -1.5), 0.2, light(400.0)),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_loader::{load_scene, LoadOptions};
+
+    #[test]
+    fn every_demo_loads() {
+        for demo in DemoScene::ALL {
+            let options = LoadOptions {
+                source: Some(demo.scene_file().unwrap()),
+                ..LoadOptions::default()
+            };
+            let scene = load_scene(&demo.to_string(), &options).unwrap();
+
+            assert_eq!(demo.to_string().parse(), Ok(demo));
+            assert!(scene.world.iter().count() > 0);
+        }
+    }
+}
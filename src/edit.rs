@@ -0,0 +1,144 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result, WrapErr};
+
+use crate::material::Material;
+use crate::scene_document::SceneDocument;
+use crate::scene_loader::Animated;
+use crate::turntable::parse_point;
+use crate::vec3::{Point3, Vec3};
+
+/// Edit a scene file: move objects, give them other materials or move the camera, and write it
+/// back with the rest as it was, without comments.
+///
+/// Objects are picked by name, and each option may be given several times. Lengths are in the
+/// units of the scene file.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "edit")]
+pub(crate) struct Edit {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// move the objects named `<object>` by an offset, as `<object>=<x>,<y>,<z>`
+    #[argh(option, long = "move")]
+    pub(crate) moves: Vec<Move>,
+
+    /// give the objects named `<object>` the material of a YAML file, written as in scene
+    /// files, as `<object>=<file>`
+    #[argh(option)]
+    pub(crate) material: Vec<MaterialSwap>,
+
+    /// where the camera is, as `x,y,z`
+    #[argh(option, from_str_fn(parse_point))]
+    pub(crate) look_from: Option<Point3>,
+
+    /// what the camera looks at, as `x,y,z`
+    #[argh(option, from_str_fn(parse_point))]
+    pub(crate) look_at: Option<Point3>,
+
+    /// write the edited scene file there instead of over the scene file
+    #[argh(option)]
+    pub(crate) to: Option<String>,
+}
+
+/// Objects to move, written `<object>=<x>,<y>,<z>`.
+pub(crate) struct Move {
+    object: String,
+    offset: Vec3,
+}
+
+impl FromStr for Move {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (object, offset) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid move `{}`, expected `<object>=<x>,<y>,<z>`", s))?;
+
+        Ok(Self {
+            object: object.to_string(),
+            offset: parse_point(offset)?,
+        })
+    }
+}
+
+/// Objects to give another material, written `<object>=<file>`.
+pub(crate) struct MaterialSwap {
+    object: String,
+    path: String,
+}
+
+impl FromStr for MaterialSwap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((object, path)) => Ok(Self {
+                object: object.to_string(),
+                path: path.to_string(),
+            }),
+            None => Err(format!(
+                "invalid material `{}`, expected `<object>=<file>`",
+                s
+            )),
+        }
+    }
+}
+
+impl Edit {
+    pub(crate) fn run(&self) -> Result<()> {
+        let path = Path::new(&self.scene_file);
+        let mut document = SceneDocument::read(path)?;
+
+        for swap in &self.material {
+            let source = std::fs::read_to_string(&swap.path)
+                .wrap_err_with(|| format!("reading {}", swap.path))?;
+            let material: Material =
+                serde_yaml::from_str(&source).wrap_err_with(|| format!("parsing {}", swap.path))?;
+            edit_objects(&mut document, path, &swap.object, |object| {
+                object.set_material(&material)
+            })?;
+        }
+        for moving in &self.moves {
+            edit_objects(&mut document, path, &moving.object, |object| {
+                object.translate(&moving.offset)
+            })?;
+        }
+
+        if self.look_from.is_some() || self.look_at.is_some() {
+            let camera = document.camera.get_or_insert_with(Default::default);
+            if let Some(look_from) = &self.look_from {
+                camera.look_from = Some(Animated::Fixed(look_from.into()));
+            }
+            if let Some(look_at) = &self.look_at {
+                camera.look_at = Some(Animated::Fixed(look_at.into()));
+            }
+        }
+
+        document.write(Path::new(self.to.as_deref().unwrap_or(&self.scene_file)))
+    }
+}
+
+/// Lets `edit` change each object named `name` in `document`, read from `path`. Fails if there
+/// are none.
+fn edit_objects(
+    document: &mut SceneDocument,
+    path: &Path,
+    name: &str,
+    mut edit: impl FnMut(&mut crate::scene_loader::Object),
+) -> Result<()> {
+    let mut found = false;
+    for object in document.objects_named(name) {
+        edit(object);
+        found = true;
+    }
+
+    if !found {
+        bail!("{} has no object named `{}`", path.display(), name);
+    }
+
+    Ok(())
+}
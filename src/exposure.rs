@@ -0,0 +1,198 @@
+use std::fmt;
+
+use crate::light::luminance;
+use crate::vec3::Color;
+
+/// Linear value from which pixels are written as white, once gamma-corrected.
+const CLIPPED: f64 = (255.0 / 256.0) * (255.0 / 256.0);
+/// Linear value under which pixels are written as black, once gamma-corrected.
+const CRUSHED: f64 = 1.0 / (256.0 * 256.0);
+const MIDDLE_GREY: f64 = 0.18;
+
+/// Bins of the histogram per stop, and the stops it covers from where black ends.
+const BINS_PER_STOP: usize = 8;
+const STOPS: usize = 18;
+const HISTOGRAM_HEIGHT: u32 = 128;
+
+/// How bright a pixel is, as shown by the false-color map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Zone {
+    /// Written as black.
+    Crushed,
+    /// More than 4 stops under middle grey.
+    DeepShadows,
+    /// 2 to 4 stops under middle grey.
+    Shadows,
+    /// Half a stop to 2 stops under middle grey.
+    LowerMidtones,
+    /// Within half a stop of middle grey.
+    MiddleGrey,
+    /// Half a stop to 2 stops over middle grey.
+    UpperMidtones,
+    /// Over that, but still under white.
+    Highlights,
+    /// Written as white in at least one channel.
+    Clipped,
+}
+
+impl Zone {
+    /// Returns the zone of a pixel of linear `color`.
+    pub(crate) fn of(color: &Color) -> Self {
+        if color.max() >= CLIPPED {
+            return Self::Clipped;
+        }
+
+        Self::of_luminance(luminance(color))
+    }
+
+    fn of_luminance(luminance: f64) -> Self {
+        let stops = (luminance / MIDDLE_GREY).log2();
+
+        if luminance < CRUSHED {
+            Self::Crushed
+        } else if stops < -4.0 {
+            Self::DeepShadows
+        } else if stops < -2.0 {
+            Self::Shadows
+        } else if stops < -0.5 {
+            Self::LowerMidtones
+        } else if stops < 0.5 {
+            Self::MiddleGrey
+        } else if stops < 2.0 {
+            Self::UpperMidtones
+        } else if luminance < CLIPPED {
+            Self::Highlights
+        } else {
+            Self::Clipped
+        }
+    }
+
+    fn color(&self) -> [u8; 3] {
+        match self {
+            Self::Crushed => [128, 0, 160],
+            Self::DeepShadows => [0, 64, 255],
+            Self::Shadows => [0, 176, 192],
+            Self::LowerMidtones => [96, 96, 96],
+            Self::MiddleGrey => [0, 192, 0],
+            Self::UpperMidtones => [176, 176, 176],
+            Self::Highlights => [255, 216, 0],
+            Self::Clipped => [255, 0, 0],
+        }
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Crushed => "crushed",
+            Self::DeepShadows => "deep shadows",
+            Self::Shadows => "shadows",
+            Self::LowerMidtones => "lower midtones",
+            Self::MiddleGrey => "middle grey",
+            Self::UpperMidtones => "upper midtones",
+            Self::Highlights => "highlights",
+            Self::Clipped => "clipped",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns the RGB8 false-color map of the linear `colors` of an image.
+pub(crate) fn false_color(colors: &[Color]) -> Vec<u8> {
+    colors
+        .iter()
+        .flat_map(|color| Zone::of(color).color())
+        .collect()
+}
+
+/// Returns the fractions of the linear `colors` of an image that are clipped and crushed.
+pub(crate) fn clipped_and_crushed(colors: &[Color]) -> (f64, f64) {
+    let count = |zone| colors.iter().filter(|c| Zone::of(c) == zone).count();
+    let total = colors.len().max(1) as f64;
+
+    (
+        count(Zone::Clipped) as f64 / total,
+        count(Zone::Crushed) as f64 / total,
+    )
+}
+
+/// Returns the RGB8 data, width and height of a chart of how many of the linear `colors` of
+/// an image have each luminance, with crushed pixels on the left then eighths of a stop from
+/// there up to 2 stops over white, the bars colored as in the false-color map.
+///
+/// Bars are scaled to the tallest one between the ends, so that a black background or a
+/// blown-out sky doesn't flatten the rest; the ends are cut off at the top when taller.
+pub(crate) fn histogram(colors: &[Color]) -> (Vec<u8>, u32, u32) {
+    let bins = 1 + STOPS * BINS_PER_STOP;
+    let lowest = CRUSHED.log2();
+    let bin = |luminance: f64| {
+        if luminance < CRUSHED {
+            return 0;
+        }
+        let stops = luminance.log2() - lowest;
+        (1 + (stops * BINS_PER_STOP as f64) as usize).min(bins - 1)
+    };
+    let zone = |bin: usize| {
+        if bin == 0 {
+            Zone::Crushed
+        } else {
+            let stops = (bin as f64 - 0.5) / BINS_PER_STOP as f64;
+            Zone::of_luminance(2.0_f64.powf(lowest + stops))
+        }
+    };
+
+    let mut counts = vec![0; bins];
+    for color in colors {
+        counts[bin(luminance(color))] += 1;
+    }
+    let tallest = counts[1..bins - 1]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    // Two pixels per bin.
+    let (width, height) = (2 * bins as u32, HISTOGRAM_HEIGHT);
+    let mut data = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let i = x as usize / 2;
+            let bar = (counts[i] as f64 / tallest as f64 * height as f64).ceil();
+            let color = if (height - y) as f64 <= bar {
+                zone(i).color()
+            } else {
+                [32, 32, 32]
+            };
+            data.extend_from_slice(&color);
+        }
+    }
+
+    (data, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_follow_what_is_written() {
+        let grey = |value| Color::new(value, value, value);
+
+        assert_eq!(Zone::of(&grey(0.0)), Zone::Crushed);
+        assert_eq!(Zone::of(&grey(0.01)), Zone::DeepShadows);
+        assert_eq!(Zone::of(&grey(0.2)), Zone::MiddleGrey);
+        assert_eq!(Zone::of(&grey(1.5)), Zone::Clipped);
+        // A saturated color clips in one channel before its luminance gets anywhere near white.
+        assert_eq!(Zone::of(&Color::new(1.0, 0.0, 0.0)), Zone::Clipped);
+
+        let colors = vec![grey(0.0), grey(0.0), grey(0.2), grey(2.0)];
+        assert_eq!(clipped_and_crushed(&colors), (0.25, 0.5));
+
+        let (data, width, height) = histogram(&colors);
+        assert_eq!(data.len(), (width * height * 3) as usize);
+        // The crushed pixels fill the bar on the far left, bottom to top.
+        assert_eq!(data[0..3], Zone::Crushed.color());
+    }
+}
@@ -0,0 +1,62 @@
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result};
+
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::ray::Interval;
+
+/// Print the focus distance that brings a pixel of the image into focus.
+///
+/// A ray is traced through the center of the pixel, counted from the top left corner of an
+/// image as large as `-w` and `-a` make it, without the `--overscan` border, and the distance at
+/// which it hits the scene is printed, to pass as `-D`.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "focus")]
+pub(crate) struct Focus {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// pixel column, from the left
+    #[argh(positional)]
+    pub(crate) x: u32,
+
+    /// pixel row, from the top
+    #[argh(positional)]
+    pub(crate) y: u32,
+}
+
+impl Focus {
+    /// Returns the focus distance for the pixel, in a frame of `frame_width` by `frame_height`
+    /// pixels that `camera` sees with `overscan` more pixels on every side, where hits count
+    /// along `interval` of the ray.
+    pub(crate) fn distance(
+        &self,
+        world: &impl Hittable,
+        interval: &Interval,
+        camera: &Camera,
+        (frame_width, frame_height): (u32, u32),
+        overscan: u32,
+    ) -> Result<f64> {
+        if self.x >= frame_width || self.y >= frame_height {
+            bail!(
+                "pixel ({}, {}) is outside the {}x{} image",
+                self.x,
+                self.y,
+                frame_width,
+                frame_height
+            );
+        }
+
+        // Rows are traced bottom up, so flip the row.
+        let (x, y) = (self.x + overscan, self.y + overscan);
+        let (image_width, image_height) = (frame_width + 2 * overscan, frame_height + 2 * overscan);
+        let s = (x as f64 + 0.5) / (image_width - 1) as f64;
+        let t = ((image_height - 1 - y) as f64 + 0.5) / (image_height - 1) as f64;
+
+        match world.hit(&camera.center_ray(s, t), interval.t_min, interval.t_max) {
+            Some(rec) => Ok(camera.depth(rec.p())),
+            None => bail!("nothing is visible at pixel ({}, {})", self.x, self.y),
+        }
+    }
+}
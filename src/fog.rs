@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bvh::Bvh;
+use crate::hittable::Hittable;
+use crate::ray::Ray;
+use crate::rtweekend::INFINITY;
+use crate::scene_loader::Object;
+use crate::vec3::{length, Color};
+
+/// How far past a boundary of the container the next one is looked for, so that the same one
+/// isn't found again.
+const CROSSING_EPSILON: f64 = 1e-4;
+
+/// A uniform medium filling the scene, which dims what is seen through it and, lit by the
+/// lights, glows along camera rays with shafts of light where objects shadow it.
+///
+/// The fog only fills the first `distance` of every ray, so that rays escaping the scene still
+/// see some of the sky, and only the inside of its container when it has one.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Fog {
+    /// Fraction of light scattered or absorbed per unit of length.
+    density: f64,
+    /// Fraction of what the fog stops that it scatters rather than absorbs, by channel.
+    color: Color,
+    distance: f64,
+    /// Points along each camera ray at which the fog gathers light.
+    steps: usize,
+    /// The name of the objects that the fog fills, if it doesn't fill everything.
+    container: Option<String>,
+    /// Those objects, once loaded. Any closed surface will do, whichever way it faces: points
+    /// are inside when rays from them cross it an odd number of times.
+    bounds: Option<Bvh<Object>>,
+}
+
+impl Fog {
+    pub(crate) fn new(
+        density: f64,
+        color: Color,
+        distance: f64,
+        steps: usize,
+        container: Option<String>,
+    ) -> Self {
+        Self {
+            density,
+            color,
+            distance,
+            steps,
+            container,
+            bounds: None,
+        }
+    }
+
+    pub(crate) fn density(&self) -> f64 {
+        self.density
+    }
+
+    pub(crate) fn color(&self) -> &Color {
+        &self.color
+    }
+
+    pub(crate) fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub(crate) fn container(&self) -> Option<&str> {
+        self.container.as_deref()
+    }
+
+    /// Returns the fog filling only the inside of `bounds`, the objects of its container.
+    pub(crate) fn bounded_by(self, bounds: Bvh<Object>) -> Self {
+        Self {
+            bounds: Some(bounds),
+            ..self
+        }
+    }
+
+    /// Returns the fog with its lengths multiplied by `factor`, for a scene scaled by `factor`.
+    pub(crate) fn scaled(self, factor: f64) -> Self {
+        Self {
+            density: self.density / factor,
+            distance: self.distance * factor,
+            ..self
+        }
+    }
+
+    /// Calls `f` with every part of `near..far`, distances along `r`, that lies in the fog, from
+    /// the nearest to the furthest.
+    pub(crate) fn for_each_span(&self, r: &Ray, near: f64, far: f64, mut f: impl FnMut(f64, f64)) {
+        let (near, far) = (near.min(self.distance), far.min(self.distance));
+        if far <= near {
+            return;
+        }
+        let bounds = match &self.bounds {
+            Some(bounds) => bounds,
+            None => return f(near, far),
+        };
+
+        let speed = length(r.direction());
+        let mut inside = crossings(bounds, r, INFINITY, |_| ()) % 2 == 1;
+        let mut from: f64 = 0.0;
+        crossings(bounds, r, far / speed, |t| {
+            let distance = t * speed;
+            if inside && distance > near {
+                f(from.max(near), distance);
+            }
+            inside = !inside;
+            from = distance;
+        });
+        if inside {
+            f(from.max(near), far);
+        }
+    }
+
+    /// Returns the fraction of light that goes through the fog between distances `near` and
+    /// `far` along `r`.
+    pub(crate) fn transmittance(&self, r: &Ray, near: f64, far: f64) -> f64 {
+        let mut length = 0.0;
+        self.for_each_span(r, near, far, |near, far| length += far - near);
+
+        (-self.density * length).exp()
+    }
+}
+
+/// Calls `f` with where `r` crosses `bounds` up to `t_max`, in order, and returns how many times
+/// it does.
+fn crossings(bounds: &Bvh<Object>, r: &Ray, t_max: f64, mut f: impl FnMut(f64)) -> usize {
+    let step = CROSSING_EPSILON / length(r.direction());
+    let mut count = 0;
+    let mut t_min = step;
+    while let Some(rec) = bounds.hit(r, t_min, t_max) {
+        f(*rec.t());
+        count += 1;
+        t_min = rec.t() + step;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::material;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    fn spans(fog: &Fog, r: &Ray, near: f64, far: f64) -> Vec<(f64, f64)> {
+        let mut spans = vec![];
+        fog.for_each_span(r, near, far, |near, far| spans.push((near, far)));
+
+        spans
+    }
+
+    #[test]
+    fn container_bounds_the_spans() {
+        let sphere = |x| Object::Sphere(Sphere::new(Point3::new(x, 0.0, 0.0), 1.0, material()));
+        let fog = Fog::new(0.5, Color::new(1.0, 1.0, 1.0), INFINITY, 16, None)
+            .bounded_by(Bvh::new(vec![sphere(0.0), sphere(4.0)], 0.0, 1.0));
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+        // From outside, through both spheres, with a direction of length 2.
+        let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), None);
+        let found = spans(&fog, &r, 0.0, 20.0);
+        assert_eq!(found.len(), 2);
+        assert!(close(found[0].0, 4.0) && close(found[0].1, 6.0));
+        assert!(close(found[1].0, 8.0) && close(found[1].1, 10.0));
+        assert!(close(
+            fog.transmittance(&r, 0.0, 20.0),
+            (-0.5 * 4.0_f64).exp()
+        ));
+        // Cut short in the second sphere.
+        assert_eq!(spans(&fog, &r, 0.0, 9.0).len(), 2);
+
+        // From inside the first sphere, and stopping before the second.
+        let r = Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), None);
+        let found = spans(&fog, &r, 0.1, 2.0);
+        assert_eq!(found.len(), 1);
+        assert!(close(found[0].0, 0.1) && close(found[0].1, 0.5));
+    }
+}
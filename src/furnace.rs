@@ -0,0 +1,241 @@
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result, WrapErr};
+
+use crate::hittable::Hittable;
+use crate::material::{Dielectric, Fresnel, Lambertian, Material, Metal, Sheen};
+use crate::ray::Ray;
+use crate::rtweekend::INFINITY;
+use crate::sphere::Sphere;
+use crate::vec3::{random_in_unit_disk, random_unit_vector, unit_vector, Color, Point3, Vec3};
+
+/// Render materials in a uniformly white environment and report how much energy they gain or
+/// lose.
+///
+/// A sphere that reflects everything it receives looks exactly as bright as the environment
+/// around it, so any difference from the material's albedo is energy the material creates or
+/// loses on its own.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "furnace")]
+pub(crate) struct Furnace {
+    /// YAML list of materials to check, written as in scene files (defaults to a built-in set)
+    #[argh(positional)]
+    pub(crate) materials: Option<String>,
+
+    /// number of rays shot at each material
+    #[argh(option, default = "100_000")]
+    pub(crate) samples: usize,
+
+    /// fail if a material reflects more than this fraction above its albedo
+    #[argh(option, default = "0.01")]
+    pub(crate) tolerance: f64,
+}
+
+impl Furnace {
+    pub(crate) fn run(&self, max_depth: usize) -> Result<()> {
+        let materials = match &self.materials {
+            Some(path) => {
+                let source =
+                    std::fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path))?;
+                serde_yaml::from_str(&source).wrap_err_with(|| format!("parsing {}", path))?
+            }
+            None => default_materials(),
+        };
+
+        let mut gaining = 0;
+        for material in &materials {
+            let measured = measure(material, self.samples, max_depth);
+            let expected = expected(material);
+            let gain = relative_gain(&measured, &expected);
+
+            println!(
+                "{:<40} measured {:.4} {:.4} {:.4}  expected {:.4} {:.4} {:.4}  {:+.2}%",
+                material.to_string(),
+                measured.x,
+                measured.y,
+                measured.z,
+                expected.x,
+                expected.y,
+                expected.z,
+                gain * 100.0
+            );
+
+            if gain > self.tolerance {
+                gaining += 1;
+            }
+        }
+
+        if gaining > 0 {
+            bail!(
+                "{} material(s) reflect more energy than they receive",
+                gaining
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn default_materials() -> Vec<Material> {
+    let white = Color::new(1.0, 1.0, 1.0);
+    let grey = Color::new(0.5, 0.5, 0.5);
+
+    vec![
+        Material::Lambertian(Lambertian::new(white)),
+        Material::Lambertian(Lambertian::new(grey)),
+        Material::Metal(Metal::new(white, 0.0)),
+        Material::Metal(Metal::new(white, 0.5)),
+        Material::Metal(Metal::new(grey, 0.5)),
+        Material::Sheen(Sheen::new(white, white, 0.3)),
+        Material::Dielectric(Dielectric::new(1.5, Fresnel::Schlick)),
+        Material::Dielectric(Dielectric::new(1.5, Fresnel::Exact)),
+    ]
+}
+
+/// What a material would reflect in a white furnace if it only absorbed what its albedo says.
+pub(crate) fn expected(material: &Material) -> Color {
+    match material {
+        Material::Lambertian(lambertian) => *lambertian.albedo(),
+        Material::Metal(metal) => *metal.albedo(),
+        Material::Sheen(sheen) => sheen.albedo().sup(sheen.sheen()),
+        Material::Dielectric(_) | Material::Water(_) => Color::new(1.0, 1.0, 1.0),
+        Material::DiffuseLight(_) | Material::ShadowCatcher(_) => Color::zeros(),
+        // Previews and mixes change color over the surface, and reflect at most everything.
+        Material::Preview(_) | Material::Mix(_) => Color::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// Returns the largest relative excess of `measured` over `expected` across channels, which
+/// is negative when the material loses energy everywhere.
+pub(crate) fn relative_gain(measured: &Color, expected: &Color) -> f64 {
+    (0..3)
+        .map(|i| (measured[i] - expected[i]) / expected[i].max(f64::EPSILON))
+        .fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// Shoots `samples` rays at a unit sphere of `material` from every direction, with light of
+/// radiance 1 coming from everywhere else, and returns the average radiance seen.
+pub(crate) fn measure(material: &Material, samples: usize, max_depth: usize) -> Color {
+    let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material.clone());
+
+    let total: Color = (0..samples)
+        .map(|_| {
+            // Aim at a random point of the sphere's silhouette, seen from a random direction.
+            let direction = random_unit_vector();
+            let (u, v) = basis(&direction);
+            let offset = random_in_unit_disk();
+            let origin = -3.0 * direction + offset.x * u + offset.y * v;
+
+            radiance(&Ray::new(origin, direction, None), &sphere, max_depth)
+        })
+        .sum();
+
+    total / samples as f64
+}
+
+fn basis(w: &Vec3) -> (Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = unit_vector(&w.cross(&a));
+
+    (w.cross(&v), v)
+}
+
+fn radiance(r: &Ray, world: &Sphere, max_depth: usize) -> Color {
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut r = *r;
+
+    for _ in 0..max_depth {
+        let rec = match world.hit(&r, 0.001, INFINITY) {
+            Some(rec) => rec,
+            None => return throughput,
+        };
+
+        match rec.material().scatter(&r, &rec) {
+            Some((scattered, attenuation)) => {
+                throughput = throughput.component_mul(&attenuation);
+                r = scattered;
+            }
+            None => return Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    Color::new(0.0, 0.0, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 20_000;
+    const MAX_DEPTH: usize = 50;
+    const TOLERANCE: f64 = 0.02;
+
+    fn assert_does_not_gain_energy(material: Material) {
+        let measured = measure(&material, SAMPLES, MAX_DEPTH);
+        let gain = relative_gain(&measured, &expected(&material));
+
+        assert!(
+            gain < TOLERANCE,
+            "{} reflects {:?}, {:+.2}% above its albedo",
+            material,
+            measured,
+            gain * 100.0
+        );
+    }
+
+    fn assert_conserves_energy(material: Material) {
+        let measured = measure(&material, SAMPLES, MAX_DEPTH);
+        let gain = relative_gain(&measured, &expected(&material));
+
+        assert!(
+            gain.abs() < TOLERANCE,
+            "{} reflects {:?}, {:+.2}% off its albedo",
+            material,
+            measured,
+            gain * 100.0
+        );
+    }
+
+    #[test]
+    fn white_lambertian_reflects_everything() {
+        assert_conserves_energy(Material::Lambertian(Lambertian::new(Color::new(
+            1.0, 1.0, 1.0,
+        ))));
+    }
+
+    #[test]
+    fn grey_lambertian_reflects_its_albedo() {
+        assert_conserves_energy(Material::Lambertian(Lambertian::new(Color::new(
+            0.5, 0.5, 0.5,
+        ))));
+    }
+
+    #[test]
+    fn mirror_reflects_everything() {
+        assert_conserves_energy(Material::Metal(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0)));
+    }
+
+    #[test]
+    fn fuzzy_metal_does_not_gain_energy() {
+        // Fuzz scattered below the surface is absorbed, so only gains are an error.
+        assert_does_not_gain_energy(Material::Metal(Metal::new(Color::new(1.0, 1.0, 1.0), 0.5)));
+    }
+
+    #[test]
+    fn sheen_does_not_gain_energy() {
+        // The base makes up for the sheen from most angles, but not exactly.
+        for roughness in [0.1, 0.5, 1.0] {
+            let white = Color::new(1.0, 1.0, 1.0);
+            assert_does_not_gain_energy(Material::Sheen(Sheen::new(white, white, roughness)));
+        }
+    }
+
+    #[test]
+    fn glass_reflects_everything() {
+        assert_conserves_energy(Material::Dielectric(Dielectric::new(1.5, Fresnel::Schlick)));
+        assert_conserves_energy(Material::Dielectric(Dielectric::new(1.5, Fresnel::Exact)));
+    }
+}
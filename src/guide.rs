@@ -0,0 +1,391 @@
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::aabb::Aabb;
+use crate::rtweekend::random_double;
+use crate::vec3::{Point3, Vec3};
+
+/// A spatial leaf recording more samples than this in a pass is split in two.
+const SPATIAL_SPLIT_SAMPLES: usize = 4000;
+const MAX_SPATIAL_DEPTH: usize = 60;
+/// Directional nodes holding more than this fraction of a leaf's energy are split in four.
+const DIRECTIONAL_SPLIT_FRACTION: f64 = 0.01;
+const MAX_DIRECTIONAL_DEPTH: usize = 20;
+
+/// An `f64` that threads can add to concurrently.
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> Self {
+        Self(AtomicU64::new(value.to_bits()))
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            })
+            .ok();
+    }
+}
+
+/// Maps a direction to the unit square, preserving areas: `u` is the cosine of its angle to
+/// +Z, and `v` its angle around Z.
+fn to_square(direction: &Vec3) -> (f64, f64) {
+    let u = ((direction.z + 1.0) / 2.0).clamp(0.0, 1.0);
+    let v = direction.y.atan2(direction.x) / (2.0 * PI);
+
+    (u, if v < 0.0 { v + 1.0 } else { v.min(1.0) })
+}
+
+fn from_square(u: f64, v: f64) -> Vec3 {
+    let z = 2.0 * u - 1.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Quadrant `0..4` of a node's square holding `(u, v)`, in coordinates local to the node, and
+/// the point's local coordinates within that quadrant.
+fn quadrant(u: f64, v: f64) -> (usize, f64, f64) {
+    let (right, u) = if u < 0.5 {
+        (0, 2.0 * u)
+    } else {
+        (1, 2.0 * u - 1.0)
+    };
+    let (top, v) = if v < 0.5 {
+        (0, 2.0 * v)
+    } else {
+        (1, 2.0 * v - 1.0)
+    };
+
+    (right + 2 * top, u, v)
+}
+
+struct QuadNode {
+    /// Energy arriving from the node's directions.
+    energy: AtomicF64,
+    children: Option<[usize; 4]>,
+}
+
+/// Where the energy of a node comes from while refining a tree: an existing node, or an
+/// even share of a leaf that is being split.
+#[derive(Clone, Copy)]
+enum Source {
+    Node(usize),
+    Share(f64),
+}
+
+/// How much light arrives at a region of space from each direction, in a quadtree over the
+/// directions mapped to the unit square, which it can sample in proportion to.
+pub(crate) struct DirectionalTree {
+    nodes: Vec<QuadNode>,
+}
+
+impl DirectionalTree {
+    fn new() -> Self {
+        Self {
+            nodes: vec![QuadNode {
+                energy: AtomicF64::new(0.0),
+                children: None,
+            }],
+        }
+    }
+
+    fn energy(&self) -> f64 {
+        self.nodes[0].energy.get()
+    }
+
+    /// Returns a tree with the same nodes, holding the same energy or none at all.
+    fn copy(&self, keep_energy: bool) -> Self {
+        Self {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| QuadNode {
+                    energy: AtomicF64::new(if keep_energy { node.energy.get() } else { 0.0 }),
+                    children: node.children,
+                })
+                .collect(),
+        }
+    }
+
+    fn record(&self, direction: &Vec3, energy: f64) {
+        let (mut u, mut v) = to_square(direction);
+        let mut node = &self.nodes[0];
+
+        loop {
+            node.energy.add(energy);
+
+            match node.children {
+                Some(children) => {
+                    let (child, child_u, child_v) = quadrant(u, v);
+                    node = &self.nodes[children[child]];
+                    u = child_u;
+                    v = child_v;
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Picks a direction with a probability proportional to the energy arriving from it, and
+    /// returns it with its density over solid angle.
+    pub(crate) fn sample(&self) -> (Vec3, f64) {
+        let (mut u0, mut v0, mut size) = (0.0, 0.0, 1.0);
+        let mut density = 1.0;
+        let mut node = &self.nodes[0];
+
+        while let Some(children) = node.children {
+            let energies = children.map(|child| self.nodes[child].energy.get());
+            let total: f64 = energies.iter().sum();
+
+            let (chosen, probability) = if total > 0.0 {
+                let mut pick = random_double() * total;
+                // Falls back on the last child with any energy, against rounding.
+                let mut chosen = energies.iter().rposition(|&energy| energy > 0.0).unwrap();
+                for (i, &energy) in energies.iter().enumerate() {
+                    if pick < energy {
+                        chosen = i;
+                        break;
+                    }
+                    pick -= energy;
+                }
+
+                (chosen, energies[chosen] / total)
+            } else {
+                ((4.0 * random_double()) as usize % 4, 0.25)
+            };
+
+            // A child covers a quarter of its parent's area.
+            density *= 4.0 * probability;
+            size /= 2.0;
+            u0 += (chosen % 2) as f64 * size;
+            v0 += (chosen / 2) as f64 * size;
+            node = &self.nodes[children[chosen]];
+        }
+
+        let direction = from_square(u0 + random_double() * size, v0 + random_double() * size);
+
+        // The mapping preserves areas, and the sphere is 4π times as large as the square.
+        (direction, density / (4.0 * PI))
+    }
+
+    /// Returns the density over solid angle of [`DirectionalTree::sample`] picking `direction`.
+    pub(crate) fn pdf(&self, direction: &Vec3) -> f64 {
+        let (mut u, mut v) = to_square(direction);
+        let mut density = 1.0;
+        let mut node = &self.nodes[0];
+
+        while let Some(children) = node.children {
+            let energies = children.map(|child| self.nodes[child].energy.get());
+            let total: f64 = energies.iter().sum();
+            let (child, child_u, child_v) = quadrant(u, v);
+
+            density *= if total > 0.0 {
+                4.0 * energies[child] / total
+            } else {
+                1.0
+            };
+            node = &self.nodes[children[child]];
+            u = child_u;
+            v = child_v;
+        }
+
+        density / (4.0 * PI)
+    }
+
+    /// Builds the tree for the next pass from the energy recorded in this one, splitting the
+    /// directions that received a large share of it so that they can be told apart.
+    fn refined(&self) -> Self {
+        let mut tree = Self { nodes: vec![] };
+        self.refine(Source::Node(0), 0, self.energy(), &mut tree);
+
+        tree
+    }
+
+    fn refine(&self, source: Source, depth: usize, total: f64, tree: &mut Self) -> usize {
+        let energy = match source {
+            Source::Node(index) => self.nodes[index].energy.get(),
+            Source::Share(energy) => energy,
+        };
+
+        let index = tree.nodes.len();
+        tree.nodes.push(QuadNode {
+            energy: AtomicF64::new(energy),
+            children: None,
+        });
+
+        if energy > DIRECTIONAL_SPLIT_FRACTION * total && depth < MAX_DIRECTIONAL_DEPTH {
+            let children = [0, 1, 2, 3].map(|i| {
+                let source = match source {
+                    Source::Node(index) => match self.nodes[index].children {
+                        Some(children) => Source::Node(children[i]),
+                        None => Source::Share(energy / 4.0),
+                    },
+                    Source::Share(energy) => Source::Share(energy / 4.0),
+                };
+
+                self.refine(source, depth + 1, total, tree)
+            });
+            tree.nodes[index].children = Some(children);
+        }
+
+        index
+    }
+}
+
+struct GuideLeaf {
+    samples: AtomicUsize,
+    /// What the previous passes learned, for this pass to sample.
+    sampling: DirectionalTree,
+    /// What this pass learns, for the next one.
+    recording: DirectionalTree,
+}
+
+impl GuideLeaf {
+    fn new(sampling: DirectionalTree) -> Self {
+        Self {
+            samples: AtomicUsize::new(0),
+            recording: sampling.copy(false),
+            sampling,
+        }
+    }
+}
+
+/// A child of a split node covers one half of it, along the axis its depth picks in turn.
+enum SpatialNode {
+    Leaf(usize),
+    Split([usize; 2]),
+}
+
+/// Learns where the light reaching each region of the scene comes from, so that diffuse
+/// bounces can be steered towards it, after Müller, Gross and Novák's "Practical Path Guiding
+/// for Efficient Light-Transport Simulation".
+///
+/// The scene's bounds are split in halves wherever many paths go, and each region holds a
+/// [`DirectionalTree`]. A guide is trained over several passes, each sampling what the
+/// previous ones learned and recording what it finds for the next one, through
+/// [`Guide::refined`].
+pub(crate) struct Guide {
+    bbox: Aabb,
+    nodes: Vec<SpatialNode>,
+    leaves: Vec<GuideLeaf>,
+}
+
+impl Guide {
+    /// Creates a guide over `bbox` that hasn't learned anything yet.
+    pub(crate) fn new(bbox: Aabb) -> Self {
+        Self {
+            bbox,
+            nodes: vec![SpatialNode::Leaf(0)],
+            leaves: vec![GuideLeaf::new(DirectionalTree::new())],
+        }
+    }
+
+    fn leaf(&self, p: &Point3) -> &GuideLeaf {
+        let (mut min, mut max) = (*self.bbox.min(), *self.bbox.max());
+        let mut node = &self.nodes[0];
+        let mut depth = 0;
+
+        loop {
+            match node {
+                SpatialNode::Leaf(leaf) => return &self.leaves[*leaf],
+                SpatialNode::Split(children) => {
+                    let axis = depth % 3;
+                    let middle = (min[axis] + max[axis]) / 2.0;
+                    if p[axis] < middle {
+                        max[axis] = middle;
+                        node = &self.nodes[children[0]];
+                    } else {
+                        min[axis] = middle;
+                        node = &self.nodes[children[1]];
+                    }
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns what the guide learned about the light arriving at `p`, if anything.
+    pub(crate) fn distribution(&self, p: &Point3) -> Option<&DirectionalTree> {
+        let sampling = &self.leaf(p).sampling;
+
+        if sampling.energy() > 0.0 {
+            Some(sampling)
+        } else {
+            None
+        }
+    }
+
+    /// Records that a path sampling `direction` at `p` with density `pdf` found light of
+    /// brightness `radiance` there.
+    pub(crate) fn record(&self, p: &Point3, direction: &Vec3, radiance: f64, pdf: f64) {
+        let leaf = self.leaf(p);
+        leaf.samples.fetch_add(1, Ordering::Relaxed);
+
+        let energy = radiance / pdf;
+        if energy.is_finite() && energy > 0.0 {
+            leaf.recording.record(direction, energy);
+        }
+    }
+
+    /// Builds the guide for the next pass from what this one recorded.
+    pub(crate) fn refined(&self) -> Self {
+        let mut guide = Self {
+            bbox: self.bbox,
+            nodes: vec![],
+            leaves: vec![],
+        };
+        self.refine(0, 0, &mut guide);
+
+        guide
+    }
+
+    fn refine(&self, index: usize, depth: usize, guide: &mut Self) -> usize {
+        match &self.nodes[index] {
+            SpatialNode::Split(children) => {
+                let index = guide.nodes.len();
+                guide.nodes.push(SpatialNode::Split([0, 0]));
+                let children = children.map(|child| self.refine(child, depth + 1, guide));
+                guide.nodes[index] = SpatialNode::Split(children);
+
+                index
+            }
+            SpatialNode::Leaf(leaf) => {
+                let leaf = &self.leaves[*leaf];
+                // Regions no path reached in this pass keep what earlier passes learned.
+                let sampling = if leaf.recording.energy() > 0.0 {
+                    leaf.recording.refined()
+                } else {
+                    leaf.sampling.copy(true)
+                };
+
+                guide.split(depth, leaf.samples.load(Ordering::Relaxed), sampling)
+            }
+        }
+    }
+
+    /// Adds the nodes for a region that received `samples` in the last pass, split until
+    /// each part would have received few enough of them.
+    fn split(&mut self, depth: usize, samples: usize, sampling: DirectionalTree) -> usize {
+        let index = self.nodes.len();
+
+        if samples > SPATIAL_SPLIT_SAMPLES && depth < MAX_SPATIAL_DEPTH {
+            self.nodes.push(SpatialNode::Split([0, 0]));
+            let first = self.split(depth + 1, samples / 2, sampling.copy(true));
+            let second = self.split(depth + 1, samples / 2, sampling);
+            self.nodes[index] = SpatialNode::Split([first, second]);
+        } else {
+            self.nodes.push(SpatialNode::Leaf(self.leaves.len()));
+            self.leaves.push(GuideLeaf::new(sampling));
+        }
+
+        index
+    }
+}
@@ -1,23 +1,36 @@
+use crate::aabb::Aabb;
+use crate::light::LightLinks;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 
-pub(crate) struct HitRecord<M: Material> {
+#[derive(Clone)]
+pub(crate) struct HitRecord<'a> {
     p: Point3,
     normal: Vec3,
-    material: M,
+    material: &'a Material,
     t: f64,
     front_face: bool,
+    /// See [`crate::attributes::Attribute`].
+    occlusion: f64,
+    curvature: f64,
+    /// The seed of the innermost transform group the hit is in, if any.
+    instance: Option<u64>,
+    links: LightLinks,
 }
 
-impl<M: Material> HitRecord<M> {
-    pub(crate) fn new(p: Point3, normal: Vec3, material: M, t: f64) -> Self {
+impl<'a> HitRecord<'a> {
+    pub(crate) fn new(p: Point3, normal: Vec3, material: &'a Material, t: f64) -> Self {
         Self {
             p,
             normal,
             material,
             t,
             front_face: false,
+            occlusion: 1.0,
+            curvature: 0.0,
+            instance: None,
+            links: LightLinks::ALL,
         }
     }
 
@@ -29,8 +42,8 @@ impl<M: Material> HitRecord<M> {
         &self.normal
     }
 
-    pub(crate) fn material(&self) -> &M {
-        &self.material
+    pub(crate) fn material(&self) -> &'a Material {
+        self.material
     }
 
     pub(crate) fn t(&self) -> &f64 {
@@ -41,6 +54,28 @@ impl<M: Material> HitRecord<M> {
         &self.front_face
     }
 
+    pub(crate) fn occlusion(&self) -> f64 {
+        self.occlusion
+    }
+
+    pub(crate) fn curvature(&self) -> f64 {
+        self.curvature
+    }
+
+    pub(crate) fn instance(&self) -> Option<u64> {
+        self.instance
+    }
+
+    /// Returns which lights light the object hit, and which of their shadows it casts.
+    pub(crate) fn links(&self) -> &LightLinks {
+        &self.links
+    }
+
+    /// Returns the same hit, made of `material` instead.
+    pub(crate) fn with_material(self, material: &Material) -> HitRecord<'_> {
+        HitRecord { material, ..self }
+    }
+
     pub(crate) fn set_p(&mut self, p: Point3) {
         self.p = p;
     }
@@ -49,8 +84,19 @@ impl<M: Material> HitRecord<M> {
         self.normal = normal;
     }
 
-    pub(crate) fn set_t(&mut self, t: f64) {
-        self.t = t;
+    pub(crate) fn set_attributes(&mut self, occlusion: f64, curvature: f64) {
+        self.occlusion = occlusion;
+        self.curvature = curvature;
+    }
+
+    /// Marks the hit as in the instance seeded with `seed`, unless an instance inside of it
+    /// already claimed it.
+    pub(crate) fn set_instance(&mut self, seed: u64) {
+        self.instance.get_or_insert(seed);
+    }
+
+    pub(crate) fn set_links(&mut self, links: LightLinks) {
+        self.links = links;
     }
 
     pub(crate) fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
@@ -64,6 +110,83 @@ impl<M: Material> HitRecord<M> {
 }
 
 pub(crate) trait Hittable {
-    type Material: Material;
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<Self::Material>>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Returns a box containing the object at every instant of `time0..time1`, or `None` for
+    /// unbounded objects.
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// Returns the index of a part of the object lying along `r` between `t_min` and `t_max`,
+    /// not necessarily the closest, or `None` if nothing does. The part at `hint`, found by an
+    /// earlier call, is tested first. Objects that aren't made of parts are part 0.
+    fn occluder(&self, r: &Ray, t_min: f64, t_max: f64, _hint: Option<usize>) -> Option<usize> {
+        self.hit(r, t_min, t_max).map(|_| 0)
+    }
+}
+
+/// Helpers for checking the hits of primitives against values worked out by hand.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    const TOLERANCE: f64 = 1e-9;
+
+    pub(crate) fn material() -> Material {
+        Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    /// Checks that `rec` lies at `t` along `r`, with the shading `normal` and `front_face`
+    /// expected there.
+    pub(crate) fn assert_hit(
+        rec: Option<HitRecord>,
+        r: &Ray,
+        t: f64,
+        normal: Vec3,
+        front_face: bool,
+    ) {
+        let rec = rec.expect("the ray should hit");
+
+        assert!(
+            (rec.t() - t).abs() < TOLERANCE,
+            "t is {}, expected {}",
+            rec.t(),
+            t
+        );
+        assert!(
+            (rec.p() - r.at(t)).norm() < TOLERANCE,
+            "p is {:?}, expected {:?}",
+            rec.p(),
+            r.at(t)
+        );
+        assert!(
+            (rec.normal() - normal).norm() < TOLERANCE,
+            "normal is {:?}, expected {:?}",
+            rec.normal(),
+            normal
+        );
+        assert_eq!(*rec.front_face(), front_face, "front_face");
+    }
+
+    #[test]
+    fn face_normal_points_against_the_ray() {
+        let material = material();
+        let outward = Vec3::new(0.0, 0.0, 1.0);
+        let mut rec = HitRecord::new(Point3::zeros(), outward, &material, 1.0);
+
+        rec.set_face_normal(
+            &Ray::new(Point3::new(0.0, 0.0, 1.0), -outward, None),
+            &outward,
+        );
+        assert!(*rec.front_face());
+        assert_eq!(*rec.normal(), outward);
+
+        rec.set_face_normal(
+            &Ray::new(Point3::new(0.0, 0.0, -1.0), outward, None),
+            &outward,
+        );
+        assert!(!rec.front_face());
+        assert_eq!(*rec.normal(), -outward);
+    }
 }
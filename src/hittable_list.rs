@@ -1,25 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::{surrounding_box, Aabb};
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(transparent)]
 pub(crate) struct HittableList<H: Hittable> {
     objects: Vec<H>,
 }
 
 impl<H: Hittable> HittableList<H> {
-    pub(crate) fn new() -> Self {
-        Self { objects: vec![] }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &H> {
+        self.objects.iter()
     }
 
-    pub(crate) fn add(&mut self, object: H) {
-        self.objects.push(object);
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut H> {
+        self.objects.iter_mut()
     }
 }
 
-impl<H: Hittable> Hittable for HittableList<H> {
-    type Material = H::Material;
+impl<H: Hittable> From<Vec<H>> for HittableList<H> {
+    fn from(objects: Vec<H>) -> Self {
+        Self { objects }
+    }
+}
 
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<Self::Material>> {
+impl<H: Hittable> Hittable for HittableList<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut temp_rec = None;
         let mut closest_so_far = t_max;
 
@@ -32,4 +40,16 @@ impl<H: Hittable> Hittable for HittableList<H> {
 
         temp_rec
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let mut boxes = self
+            .objects
+            .iter()
+            .map(|object| object.bounding_box(time0, time1));
+        let first = boxes.next()??;
+
+        boxes.try_fold(first, |output_box, bbox| {
+            Some(surrounding_box(&output_box, &bbox?))
+        })
+    }
 }
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use argh::FromArgs;
+
+use crate::backplate::Backplate;
+use crate::bvh::Bvh;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::scene_loader::{Object, Scene};
+
+/// Print a summary of a scene: what it is made of, how large it is, and what it costs to hold
+/// in memory.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "info")]
+pub(crate) struct Info {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+}
+
+/// Counts of what a scene holds, gathered through its transforms.
+#[derive(Default)]
+struct Summary {
+    spheres: usize,
+    moving_spheres: usize,
+    transforms: usize,
+    clipped: usize,
+    meshes: usize,
+    triangles: usize,
+    point_clouds: usize,
+    points: usize,
+    /// How many objects use each material, by description.
+    materials: BTreeMap<String, usize>,
+    /// Bytes taken by the mesh vertices, the points of clouds and all the hierarchies.
+    memory: usize,
+    nodes: usize,
+    /// Bytes taken by the images of textures, counted for each object that holds one.
+    textures: usize,
+}
+
+impl Summary {
+    fn add_objects<'a>(&mut self, objects: impl Iterator<Item = &'a Object>) {
+        for object in objects {
+            self.add_object(object);
+        }
+    }
+
+    fn add_object(&mut self, object: &Object) {
+        match object {
+            Object::Sphere(sphere) => {
+                self.spheres += 1;
+                self.add_material(sphere.material());
+            }
+            Object::MovingSphere(sphere) => {
+                self.moving_spheres += 1;
+                self.add_material(sphere.material());
+            }
+            Object::Transformed { objects, .. } => {
+                self.transforms += 1;
+                self.add_objects(objects.iter());
+            }
+            Object::Clipped { objects, .. } => {
+                self.clipped += 1;
+                self.add_objects(objects.iter());
+            }
+            // Light links are counted with the lights.
+            Object::Linked { objects, .. } => self.add_objects(objects.iter()),
+            Object::PointCloud(cloud) => {
+                self.point_clouds += 1;
+                if let Some(material) = cloud.material() {
+                    self.add_material(material);
+                }
+
+                if let Some((points, memory, nodes)) = cloud.stats() {
+                    self.points += points;
+                    self.memory += memory;
+                    self.nodes += nodes;
+                }
+            }
+            Object::Mesh(mesh) => {
+                self.meshes += 1;
+                self.add_material(mesh.material());
+
+                if let Some((triangles, memory, nodes)) = mesh.stats() {
+                    self.triangles += triangles;
+                    self.memory += memory;
+                    self.nodes += nodes;
+                }
+            }
+        }
+    }
+
+    fn add_material(&mut self, material: &Material) {
+        *self.materials.entry(material.to_string()).or_insert(0) += 1;
+        self.textures += material.texture_memory();
+    }
+}
+
+/// Formats `bytes` with a binary unit.
+pub(crate) fn format_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, units[unit])
+}
+
+impl Info {
+    pub(crate) fn print(&self, scene: &Scene) {
+        let world: &Bvh<Object> = &scene.world;
+        let mut summary = Summary::default();
+        summary.add_objects(world.iter());
+        summary.memory += world.memory();
+        summary.nodes += world.node_count();
+
+        println!(
+            "objects     {} spheres, {} moving spheres, {} transforms, {} clipped groups, {} meshes, \
+             {} point clouds",
+            summary.spheres,
+            summary.moving_spheres,
+            summary.transforms,
+            summary.clipped,
+            summary.meshes,
+            summary.point_clouds
+        );
+        println!("triangles   {}", summary.triangles);
+        println!("points      {}", summary.points);
+        println!("lights      {} emitting spheres", scene.lights.len());
+
+        let camera = &scene.camera;
+        match world.bounding_box(*camera.time.start(), *camera.time.end()) {
+            Some(bbox) => {
+                let (min, max) = (bbox.min(), bbox.max());
+                println!(
+                    "bounds      ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3})",
+                    min.x, min.y, min.z, max.x, max.y, max.z
+                );
+            }
+            None => println!("bounds      unbounded"),
+        }
+
+        println!(
+            "memory      {} for mesh vertices, points and {} hierarchy nodes",
+            format_bytes(summary.memory),
+            summary.nodes
+        );
+        println!(
+            "            {} for textures, {} for the backplate",
+            format_bytes(summary.textures),
+            format_bytes(scene.backplate.as_ref().map_or(0, Backplate::memory))
+        );
+
+        println!("materials   {}", summary.materials.len());
+        for (material, count) in &summary.materials {
+            println!("  {:>6} x {}", count, material);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    REQUESTED.store(true, Ordering::Relaxed);
+
+    // A second Ctrl-C kills the process, in case saving what was rendered takes too long.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Catches Ctrl-C, so that renders stop taking samples and save what they have instead of
+/// being killed.
+pub(crate) fn catch() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Returns whether Ctrl-C was pressed since [`catch`].
+pub(crate) fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,502 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::random_double;
+use crate::scene_loader::StartEndPair;
+use crate::stats;
+use crate::vec3::{length_squared, unit_vector, Color, Point3, Vec3};
+
+const MAX_DEPTH: usize = 64;
+
+/// How a shading point picks which light to aim at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LightSampling {
+    /// Every light is equally likely.
+    Uniform,
+    /// Lights are picked through the [`LightTree`], by how much they're likely to contribute.
+    Tree,
+}
+
+impl FromStr for LightSampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Self::Uniform),
+            "tree" => Ok(Self::Tree),
+            _ => Err(format!(
+                "unknown light sampling `{}`, expected `uniform` or `tree`",
+                s
+            )),
+        }
+    }
+}
+
+/// The last object found in the way of shadow rays toward each light, which the next shadow ray
+/// toward that light tests first: rays from nearby points toward the same light are mostly
+/// blocked by the same thing, which then takes a single test instead of a walk down the
+/// hierarchy.
+///
+/// Each render thread has its own, and adds how often it helped to `--stats` when dropped.
+pub(crate) struct OcclusionCache {
+    last: Vec<Cell<Option<usize>>>,
+    tests: Cell<u64>,
+    hits: Cell<u64>,
+}
+
+impl OcclusionCache {
+    pub(crate) fn new(lights: &LightTree) -> Self {
+        Self {
+            last: vec![Cell::new(None); lights.len()],
+            tests: Cell::new(0),
+            hits: Cell::new(0),
+        }
+    }
+
+    /// Returns whether anything in `objects` lies along `r`, a shadow ray toward `light`,
+    /// between `t_min` and `t_max`.
+    pub(crate) fn occluded(
+        &self,
+        objects: &impl Hittable,
+        light: usize,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> bool {
+        let last = &self.last[light];
+        let hint = last.get();
+        let occluder = objects.occluder(r, t_min, t_max, hint);
+
+        self.tests.set(self.tests.get() + 1);
+        if occluder.is_some() && occluder == hint {
+            self.hits.set(self.hits.get() + 1);
+        }
+        last.set(occluder);
+
+        occluder.is_some()
+    }
+}
+
+impl Drop for OcclusionCache {
+    fn drop(&mut self) {
+        stats::count_shadow_rays(self.tests.get(), self.hits.get());
+    }
+}
+
+/// A light that only lights some objects, or whose shadows only some objects cast, as the
+/// `light_links` of a scene file say. Each has a bit of its own in [`LightLinks`].
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LightLink {
+    pub(crate) bit: u8,
+    /// Whether only some objects cast its shadows, which shadow rays then have to look past
+    /// the others for.
+    pub(crate) shadows: bool,
+}
+
+/// Which of the linked lights light an object and which of their shadows it casts, one bit per
+/// [`LightLink`]. Lights that aren't linked light everything and are blocked by everything.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LightLinks {
+    pub(crate) lit_by: u64,
+    pub(crate) casts: u64,
+    /// The link of the object itself, if it is a linked light.
+    pub(crate) light: Option<LightLink>,
+}
+
+impl LightLinks {
+    /// The links of objects that nothing singles out.
+    pub(crate) const ALL: Self = Self {
+        lit_by: u64::MAX,
+        casts: u64::MAX,
+        light: None,
+    };
+
+    /// Returns whether `link`, or any light that isn't linked, lights the object.
+    pub(crate) fn is_lit_by(&self, link: Option<&LightLink>) -> bool {
+        link.is_none_or(|link| self.lit_by & (1 << link.bit) != 0)
+    }
+
+    /// Returns whether the object blocks the light of `link`, or of any light that isn't linked.
+    pub(crate) fn casts(&self, link: Option<&LightLink>) -> bool {
+        link.is_none_or(|link| self.casts & (1 << link.bit) != 0)
+    }
+}
+
+/// A sphere made of an emitting material, which paths can aim at directly.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Light {
+    center: StartEndPair<Point3>,
+    time: StartEndPair<f64>,
+    radius: f64,
+    material: Material,
+    link: Option<LightLink>,
+}
+
+/// A direction towards a light, as seen from some point.
+pub(crate) struct LightSample<'a> {
+    pub(crate) direction: Vec3,
+    pub(crate) distance: f64,
+    /// Density of the direction over solid angle, including the chance of picking the light.
+    pub(crate) pdf: f64,
+    pub(crate) material: &'a Material,
+    /// Index of the light in its [`LightTree`].
+    pub(crate) light: usize,
+    pub(crate) link: Option<&'a LightLink>,
+}
+
+impl Light {
+    pub(crate) fn new(
+        center: StartEndPair<Point3>,
+        time: StartEndPair<f64>,
+        radius: f64,
+        material: Material,
+    ) -> Self {
+        Self {
+            center,
+            time,
+            radius: radius.abs(),
+            material,
+            link: None,
+        }
+    }
+
+    /// Makes the light only light and be blocked by what `link` says.
+    pub(crate) fn linked(self, link: LightLink) -> Self {
+        Self {
+            link: Some(link),
+            ..self
+        }
+    }
+
+    fn center(&self, time: f64) -> Point3 {
+        let duration = self.time.end() - self.time.start();
+        if duration <= 0.0 {
+            return *self.center.start();
+        }
+
+        self.center.start()
+            + ((time - self.time.start()) / duration) * (self.center.end() - self.center.start())
+    }
+
+    /// Returns the total power the light gives off, in terms of the luminance it emits.
+    fn power(&self) -> f64 {
+        luminance(&self.material.emitted()).max(0.0) * 4.0 * PI * self.radius * self.radius * PI
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let at = |center: &Point3| Aabb::new(center - radius, center + radius);
+
+        surrounding_box(&at(self.center.start()), &at(self.center.end()))
+    }
+
+    /// Returns the vector and distance to the light's center from `origin`, and `1 - cos θ` for
+    /// the cone of directions it covers from there, or `None` from inside the light.
+    fn cone(&self, origin: &Point3, time: f64) -> Option<(Vec3, f64, f64)> {
+        let to_center = self.center(time) - origin;
+        let distance_squared = length_squared(&to_center);
+        let sin_squared = self.radius * self.radius / distance_squared;
+        if sin_squared >= 1.0 {
+            return None;
+        }
+
+        // Written this way to keep precision for lights that are small or far away.
+        let cos_max = (1.0 - sin_squared).sqrt();
+        let one_minus_cos = sin_squared / (1.0 + cos_max);
+
+        Some((to_center, distance_squared.sqrt(), one_minus_cos))
+    }
+
+    /// Picks a direction towards the light from `origin`, uniformly within the cone it covers.
+    fn sample(&self, origin: &Point3, time: f64) -> Option<(Vec3, f64, f64)> {
+        let (to_center, distance, one_minus_cos) = self.cone(origin, time)?;
+        let w = to_center / distance;
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(&w.cross(&a));
+        let u = w.cross(&v);
+
+        let cos_theta = 1.0 - random_double() * one_minus_cos;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_double();
+        let direction = (phi.cos() * sin_theta) * u + (phi.sin() * sin_theta) * v + cos_theta * w;
+
+        // The nearest intersection with the sphere along `direction`.
+        let sin_squared = 1.0 - cos_theta * cos_theta;
+        let chord = (self.radius * self.radius - distance * distance * sin_squared)
+            .max(0.0)
+            .sqrt();
+        let hit_distance = distance * cos_theta - chord;
+
+        Some((direction, hit_distance, 1.0 / (2.0 * PI * one_minus_cos)))
+    }
+
+    /// Density over solid angle of [`Light::sample`] picking any direction towards the light.
+    fn pdf(&self, origin: &Point3, time: f64) -> f64 {
+        match self.cone(origin, time) {
+            Some((_, _, one_minus_cos)) => 1.0 / (2.0 * PI * one_minus_cos),
+            None => 0.0,
+        }
+    }
+
+    fn is_on_surface(&self, p: &Point3, time: f64) -> bool {
+        let distance = (p - self.center(time)).norm();
+
+        (distance - self.radius).abs() <= 1e-6 * self.radius.max(1.0)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct LightNode {
+    bbox: Aabb,
+    power: f64,
+    /// The lights under the node are `lights[first..first + count]`.
+    first: usize,
+    count: usize,
+    children: Option<(usize, usize)>,
+}
+
+impl LightNode {
+    /// Estimates how much the lights under the node light `p`: their power, falling off with
+    /// the squared distance to them, but no faster than from the edge of their bounds.
+    fn importance(&self, p: &Point3) -> f64 {
+        let half_diagonal_squared = length_squared(&(self.bbox.max() - self.bbox.min())) / 4.0;
+        let distance_squared = length_squared(&(self.bbox.centroid() - p));
+
+        self.power
+            / distance_squared
+                .max(half_diagonal_squared)
+                .max(f64::EPSILON)
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        (self.first..self.first + self.count).contains(&index)
+    }
+}
+
+/// The emitting spheres of a scene, in a bounding volume hierarchy that also sums up their
+/// power, so that each shading point can pick the lights most likely to matter to it.
+///
+/// Picking a light walks down from the root, choosing each child with a probability
+/// proportional to its estimated importance, so scenes with many lights mostly aim at the
+/// nearby and bright ones.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct LightTree {
+    lights: Vec<Light>,
+    nodes: Vec<LightNode>,
+}
+
+impl LightTree {
+    pub(crate) fn new(mut lights: Vec<Light>) -> Self {
+        lights.retain(|light| light.power() > 0.0);
+
+        let mut nodes = vec![];
+        if !lights.is_empty() {
+            build(&mut nodes, &mut lights, 0);
+        }
+
+        Self { lights, nodes }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Picks a light for `p`, returning its index and the probability of picking it.
+    fn choose(&self, p: &Point3, sampling: LightSampling) -> Option<(usize, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        if sampling == LightSampling::Uniform {
+            let index =
+                ((random_double() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+            return Some((index, 1.0 / self.lights.len() as f64));
+        }
+
+        let mut node = &self.nodes[0];
+        let mut probability = 1.0;
+
+        while let Some((left, right)) = node.children {
+            let left_importance = self.nodes[left].importance(p);
+            let right_importance = self.nodes[right].importance(p);
+            let total = left_importance + right_importance;
+            if total <= 0.0 {
+                return None;
+            }
+
+            let p_left = left_importance / total;
+            if random_double() < p_left {
+                node = &self.nodes[left];
+                probability *= p_left;
+            } else {
+                node = &self.nodes[right];
+                probability *= 1.0 - p_left;
+            }
+        }
+
+        Some((node.first, probability))
+    }
+
+    /// Returns the probability of [`LightTree::choose`] picking light `index` for `p`.
+    fn probability(&self, p: &Point3, index: usize, sampling: LightSampling) -> f64 {
+        if sampling == LightSampling::Uniform {
+            return 1.0 / self.lights.len() as f64;
+        }
+
+        let mut node = &self.nodes[0];
+        let mut probability = 1.0;
+
+        while let Some((left, right)) = node.children {
+            let left_importance = self.nodes[left].importance(p);
+            let right_importance = self.nodes[right].importance(p);
+            let total = left_importance + right_importance;
+            if total <= 0.0 {
+                return 0.0;
+            }
+
+            if self.nodes[left].contains(index) {
+                node = &self.nodes[left];
+                probability *= left_importance / total;
+            } else {
+                node = &self.nodes[right];
+                probability *= right_importance / total;
+            }
+        }
+
+        probability
+    }
+
+    /// Picks a light and a direction towards it from `p`.
+    pub(crate) fn sample(
+        &self,
+        p: &Point3,
+        time: f64,
+        sampling: LightSampling,
+    ) -> Option<LightSample<'_>> {
+        let (index, probability) = self.choose(p, sampling)?;
+        let light = &self.lights[index];
+        let (direction, distance, pdf) = light.sample(p, time)?;
+
+        Some(LightSample {
+            direction,
+            distance,
+            pdf: probability * pdf,
+            material: &light.material,
+            light: index,
+            link: light.link.as_ref(),
+        })
+    }
+
+    /// Returns the density over solid angle of [`LightTree::sample`] from `p` picking the
+    /// direction towards `hit`, a point on some light's surface, or 0 if `hit` isn't on one.
+    pub(crate) fn pdf(&self, p: &Point3, hit: &Point3, time: f64, sampling: LightSampling) -> f64 {
+        match self.find(hit, time) {
+            Some(index) => self.probability(p, index, sampling) * self.lights[index].pdf(p, time),
+            None => 0.0,
+        }
+    }
+
+    /// Finds the light whose surface passes through `p`.
+    fn find(&self, p: &Point3, time: f64) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        // Splitting at the median keeps the tree balanced, so a small fixed stack is enough.
+        let mut stack = [0; MAX_DEPTH];
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len]];
+            let margin = 1e-6 * length_squared(&(node.bbox.max() - node.bbox.min())).sqrt();
+            let inside = (0..3).all(|axis| {
+                node.bbox.min()[axis] - margin <= p[axis]
+                    && p[axis] <= node.bbox.max()[axis] + margin
+            });
+            if !inside {
+                continue;
+            }
+
+            match node.children {
+                Some((left, right)) => {
+                    stack[len] = right;
+                    stack[len + 1] = left;
+                    len += 2;
+                }
+                None if self.lights[node.first].is_on_surface(p, time) => return Some(node.first),
+                None => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Builds the node for `lights`, which start at index `first` of the tree's list, and
+/// returns its index.
+fn build(nodes: &mut Vec<LightNode>, lights: &mut [Light], first: usize) -> usize {
+    let bbox = lights
+        .iter()
+        .map(Light::bounding_box)
+        .reduce(|a, b| surrounding_box(&a, &b))
+        .unwrap();
+    let power = lights.iter().map(Light::power).sum();
+
+    let index = nodes.len();
+    nodes.push(LightNode {
+        bbox,
+        power,
+        first,
+        count: lights.len(),
+        children: None,
+    });
+
+    if lights.len() > 1 {
+        let centroids =
+            Aabb::from_points(lights.iter().map(|light| light.bounding_box().centroid())).unwrap();
+        let axis = (centroids.max() - centroids.min()).imax();
+        lights.sort_by(|a, b| {
+            a.bounding_box().centroid()[axis]
+                .partial_cmp(&b.bounding_box().centroid()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let middle = lights.len() / 2;
+        let (left, right) = lights.split_at_mut(middle);
+        let left = build(nodes, left, first);
+        let right = build(nodes, right, first + middle);
+        nodes[index].children = Some((left, right));
+    }
+
+    index
+}
+
+/// The power heuristic for combining a sample of density `pdf` with another strategy's
+/// `other_pdf`.
+pub(crate) fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Returns the luminance-weighted brightness of `color`.
+pub(crate) fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
@@ -0,0 +1,32 @@
+use tracing::Level;
+
+/// How much the raytracer says about what it is doing. Everything goes to stderr, so that it
+/// never mixes with images written to stdout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Verbosity {
+    /// Warnings and errors only, and no progress bars.
+    Quiet,
+    Normal,
+    /// Also how long each step takes and what the scene is made of.
+    Verbose,
+}
+
+impl Verbosity {
+    fn level(self) -> Level {
+        match self {
+            Self::Quiet => Level::WARN,
+            Self::Normal => Level::INFO,
+            Self::Verbose => Level::DEBUG,
+        }
+    }
+}
+
+/// Prints log messages of `verbosity` to stderr from now on.
+pub(crate) fn init(verbosity: Verbosity) {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(verbosity.level())
+        .without_time()
+        .with_target(false)
+        .init();
+}
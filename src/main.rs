@@ -1,52 +1,168 @@
+use std::io::Write;
 use std::sync::Arc;
-use std::sync::mpsc::channel;
-use std::thread::spawn;
+use std::time::{Duration, Instant};
 
 use argh::FromArgs;
-use color_eyre::eyre::Result;
-use image::ColorType;
+use color_eyre::eyre::{eyre, Result, WrapErr};
 use indicatif::{ProgressBar, ProgressStyle};
+use tracing::{debug, info, warn};
 
+use animate::Animate;
+use bake::Bake;
+use bench::Bench;
+use bvh::{Bvh, Refit};
 use camera::Camera;
+use checkpoint::{checkpoint_path, read_checkpoint, write_checkpoint, RenderIdentity};
 use color::clamp_color;
+use compare::Compare;
+use demo::Demo;
+use edit::Edit;
+use focus::Focus;
+use furnace::Furnace;
 use hittable::Hittable;
-use material::Material;
+use info::Info;
+use light::LightSampling;
+use logging::Verbosity;
+use matpreview::Matpreview;
+use memory_budget::MemoryBudget;
+use output::{FrameWriter, Metadata, Output, PixelAspect};
+use probe::Probe;
+use probes::Probes;
+use progressive::ProgressiveRenderer;
+use quality::Quality;
 use ray::Ray;
-use rtweekend::{random_double, INFINITY};
-use scene_loader::{load_scene, StartEndPair};
-use vec3::{unit_vector, Color, Point3, Vec3};
+use render::{
+    render, render_from, Accumulation, Refresh, Regularization, RenderSettings, TimeLimit, World,
+};
+use sampler::Sampler;
+use scene_cache::load_scene_cached;
+use scene_loader::{
+    camera_settings, load_scene, reload_materials, textured_triangles, CameraSettings, LoadOptions,
+    MaterialOverride, Object, Scene, StartEndPair,
+};
+use stats::{CountingAllocator, Stats};
+use stereo::{side_by_side, StereoMode};
+use streamed_mesh::GeometryCache;
+use tape::{Record, Replay};
+use temporal::GBuffer;
+use turntable::Turntable;
+use vec3::{Color, Point3, Vec3};
+use watch::{Change, Modified, Watch};
 
+mod aabb;
+mod animate;
+mod attributes;
+mod backend;
+mod backplate;
+mod bake;
+mod bench;
+mod bvh;
 mod camera;
+mod checkpoint;
+mod clip;
 mod color;
+mod compare;
+mod demo;
+mod edit;
+mod exposure;
+mod focus;
+mod fog;
+mod furnace;
+mod guide;
 mod hittable;
 mod hittable_list;
+mod info;
+mod interrupt;
+mod light;
+mod logging;
 mod material;
+mod matpreview;
+mod memory_budget;
+mod mesh;
+mod mesh_sequence;
 mod moving_sphere;
+mod nice;
+mod obj;
+mod output;
+mod ply;
+mod point_cloud;
+mod probe;
+mod probes;
+mod progressive;
+mod quality;
 mod ray;
+mod render;
 mod rtweekend;
+mod sampler;
+mod scene_cache;
+mod scene_document;
+mod scene_error;
 mod scene_loader;
+mod spectrum;
 mod sphere;
+mod stats;
+mod stereo;
+mod streamed_mesh;
+mod tape;
+mod temporal;
+mod texture;
+mod tiles;
+mod transform;
+mod turntable;
 mod vec3;
+mod watch;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 /// A ray tracer.
 #[derive(FromArgs)]
 struct Args {
     /// scene file
     #[argh(option, short = 'f')]
-    scene_file: String,
+    scene_file: Option<String>,
+
+    /// output file, `-` for a PNG image on stdout, or `pipe:y4m` / `pipe:ppm` to stream frames
+    /// to stdout
+    #[argh(
+        option,
+        short = 'o',
+        default = "Output::File(\"image.png\".to_string())"
+    )]
+    output: Output,
+
+    /// frames per unit of scene time of `animate`, also written into `pipe:y4m` streams
+    #[argh(option, default = "24")]
+    fps: u32,
+
+    /// rewrite the output with the image so far every `<n>s` seconds, or after every `<n>`
+    /// passes of an eighth of the samples, while a still image renders
+    #[argh(option)]
+    write_every: Option<Refresh>,
 
-    /// output file
-    #[argh(option, short = 'o', default = "\"image.png\".to_string()")]
-    output: String,
+    /// keep adding passes of the samples per pixel until this much time has passed, like `90s`,
+    /// `10m` or `1h`, then write the image
+    #[argh(option)]
+    time_limit: Option<TimeLimit>,
 
     /// aspect ratio
     #[argh(option, short = 'a', default = "16.0 / 9.0")]
     aspect_ratio: f64,
 
-    /// output image width
+    /// output image width, at least 2 pixels
     #[argh(option, short = 'w', default = "1200")]
     image_width: u32,
 
+    /// width of the pixels over their height, for anamorphic formats: the image has the aspect
+    /// ratio once they are stretched to it (defaults to 1)
+    #[argh(option, default = "PixelAspect(1.0)")]
+    pixel_aspect: PixelAspect,
+
+    /// pixels rendered on every side beyond the frame, for filters that need to see past its
+    /// edges
+    #[argh(option, default = "0")]
+    overscan: u32,
+
     /// samples per pixel
     #[argh(option, short = 's', default = "10")]
     samples_per_pixel: usize,
@@ -66,135 +182,1124 @@ struct Args {
     /// vertical field-of-view
     #[argh(option, short = 'v', default = "20.0")]
     vfov: f64,
+
+    /// render a stereo pair, either `side-by-side` or `separate`
+    #[argh(option)]
+    stereo: Option<StereoMode>,
+
+    /// distance between the two stereo eyes
+    #[argh(option, default = "0.065")]
+    interaxial: f64,
+
+    /// trace light by wavelength, with colors upsampled to smooth spectra
+    #[argh(switch)]
+    spectral: bool,
+
+    /// how diffuse surfaces pick which emitting sphere to aim at, either `tree` (by estimated
+    /// contribution) or `uniform`
+    #[argh(option, default = "LightSampling::Tree")]
+    light_sampling: LightSampling,
+
+    /// first spend this many short passes learning where indirect light comes from, and
+    /// steer diffuse bounces towards it
+    #[argh(option, default = "0")]
+    guide_passes: usize,
+
+    /// treat mirrors and glass as slightly rough once paths have bounced off this many
+    /// diffuse surfaces, to keep caustics from turning into fireflies, or `off` (defaults to 1)
+    #[argh(option, default = "Regularization::After(1)")]
+    regularize_after: Regularization,
+
+    /// how the samples of neighbouring pixels relate, either `random` (independently) or
+    /// `blue-noise` (dithered, for smoother low sample counts)
+    #[argh(option, default = "Sampler::Random")]
+    sampler: Sampler,
+
+    /// render with the settings of a preset, either `draft` (a sample per pixel and a single
+    /// bounce, smoothed along surfaces, in under a second), `medium` or `final` (as the other
+    /// options say), in place of -s, -d and --sampler (defaults to `final`)
+    #[argh(option, default = "Quality::Final")]
+    quality: Quality,
+
+    /// test every shadow ray against the whole scene, rather than against what blocked the
+    /// last shadow ray toward the same light first
+    #[argh(switch)]
+    no_occlusion_cache: bool,
+
+    /// also write a luminance histogram to `<output>-histogram.png` and a false-color map of
+    /// exposure zones to `<output>-exposure.png`, to spot blown highlights and crushed shadows
+    #[argh(switch)]
+    exposure: bool,
+
+    /// distance of the zero-parallax plane (defaults to the focus distance)
+    #[argh(option)]
+    convergence: Option<f64>,
+
+    /// render from the view of this name among the `cameras` of the scene file, rather than
+    /// from its `camera`
+    #[argh(option)]
+    camera: Option<String>,
+
+    /// keep the built scene in `<scene-file>.cache` and reuse it until the scene changes
+    #[argh(switch)]
+    cache: bool,
+
+    /// stream large meshes from disk, keeping at most this many megabytes of them in memory
+    /// (disables --cache)
+    #[argh(option)]
+    geometry_memory: Option<usize>,
+
+    /// make textures smaller, stream large meshes from disk and thin out point clouds rather
+    /// than load more than this many megabytes of them (disables --cache)
+    #[argh(option)]
+    memory_budget: Option<usize>,
+
+    /// render the objects named `<object>` in the scene file with `clay`, `normals` or
+    /// `checker` instead of their own material, as `<object>=<material>` (disables --cache)
+    #[argh(option)]
+    override_material: Vec<MaterialOverride>,
+
+    /// render at the lowest priority, leaving a core free and pausing render threads while
+    /// the rest of the system keeps cores busy
+    #[argh(switch)]
+    nice: bool,
+
+    /// continue the render that Ctrl-C interrupted, from `<scene-file>.checkpoint`
+    #[argh(switch)]
+    resume: bool,
+
+    /// only print warnings and errors, without progress bars
+    #[argh(switch, short = 'q')]
+    quiet: bool,
+
+    /// also print how long each step takes and what the scene is made of
+    #[argh(switch)]
+    verbose: bool,
+
+    /// print how long loading and rendering took, and how many allocations they made
+    #[argh(switch)]
+    stats: bool,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Animate(Animate),
+    Bake(Bake),
+    Bench(Bench),
+    Compare(Compare),
+    Demo(Demo),
+    Edit(Edit),
+    Focus(Focus),
+    Furnace(Furnace),
+    Info(Info),
+    Matpreview(Matpreview),
+    Probe(Probe),
+    Probes(Probes),
+    Record(Record),
+    Replay(Replay),
+    Turntable(Turntable),
+    Watch(Watch),
 }
 
-fn ray_color<H: Hittable>(r: &Ray, world: &H, depth: usize) -> Color {
-    // If we've exceeded the ray bounce limit, no more light is gathered.
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
+impl Args {
+    /// Returns the height of the frame, in pixels as tall as `--pixel-aspect` makes them.
+    fn image_height(&self) -> u32 {
+        (self.image_width as f64 * self.pixel_aspect.0 / self.aspect_ratio) as u32
+    }
+
+    /// Returns the width and the height of the images rendered, the frame with its overscan.
+    fn image_size(&self) -> (u32, u32) {
+        (
+            self.image_width + 2 * self.overscan,
+            self.image_height() + 2 * self.overscan,
+        )
+    }
+
+    fn render_settings(&self) -> RenderSettings {
+        let (image_width, image_height) = self.image_size();
+
+        self.quality.apply(RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            spectral: self.spectral,
+            light_sampling: self.light_sampling,
+            guide_passes: self.guide_passes,
+            regularization: self.regularize_after,
+            occlusion_cache: !self.no_occlusion_cache,
+            sampler: self.sampler,
+            time_limit: self.time_limit.map(|limit| limit.0),
+        })
+    }
+
+    /// Returns what `camera` sees of `world` through each pixel, for the draft quality to
+    /// smooth the image along, or nothing for the others.
+    fn draft_gbuffer<H>(&self, world: &Arc<World<H>>, camera: &Camera) -> Result<Option<GBuffer>>
+    where
+        H: Hittable + Send + Sync + 'static,
+    {
+        if !self.quality.smooths() {
+            return Ok(None);
+        }
+        let (width, height) = self.image_size();
+
+        Ok(Some(GBuffer::new(
+            world,
+            &Arc::new(camera.clone()),
+            width,
+            height,
+        )?))
+    }
+
+    fn load_options(&self) -> LoadOptions {
+        let source = match &self.command {
+            Some(Command::Demo(demo)) => demo.scene.scene_file().ok(),
+            _ => None,
+        };
+
+        LoadOptions {
+            geometry_cache: self
+                .geometry_memory
+                .map(|megabytes| Arc::new(GeometryCache::new(megabytes * 1024 * 1024))),
+            material_overrides: self.override_material.clone(),
+            source,
+            camera: self.camera.clone(),
+            time: None,
+            memory_budget: self
+                .memory_budget
+                .map(|megabytes| Arc::new(MemoryBudget::new(megabytes * 1024 * 1024))),
+        }
+    }
+
+    fn load_scene(&self, path: &str) -> Result<Scene> {
+        let options = self.load_options();
+        let start = Instant::now();
+
+        let scene = if self.cache
+            && options.geometry_cache.is_none()
+            && options.memory_budget.is_none()
+            && options.material_overrides.is_empty()
+            && options.source.is_none()
+        {
+            let mut scene = load_scene_cached(path)?;
+            // The cache is of the scene as written, seen from its own camera.
+            if options.camera.is_some() {
+                scene.camera = camera_settings(path, &options)?;
+            }
+            scene
+        } else {
+            load_scene(path, &options)?
+        };
+        debug!(
+            "loaded {} in {:.2?}: {} objects, {} lights",
+            path,
+            start.elapsed(),
+            scene.world.iter().count(),
+            scene.lights.len()
+        );
+
+        Ok(scene)
     }
 
-    return match world.hit(r, 0.001, INFINITY) {
-        Some(rec) => {
-            if let Some((scattered_ray, attenuation)) =
-                rec.material().scatter(r, &rec, &Ray::default())
-            {
-                let r = ray_color(&scattered_ray, world, depth - 1);
+    fn frame_writer(&self) -> FrameWriter {
+        FrameWriter::new(self.output.clone(), self.fps).with_pixel_aspect(self.pixel_aspect.0)
+    }
 
-                Color::new(
-                    r.x * attenuation.x,
-                    r.y * attenuation.y,
-                    r.z * attenuation.z,
-                )
+    /// Describes a render of `scene_file` well enough to make it again.
+    fn metadata(
+        &self,
+        scene_file: &str,
+        camera: &CameraSettings,
+        samples_per_pixel: usize,
+        render_time: Duration,
+    ) -> Metadata {
+        let point = |p: &Point3| format!("{:.3} {:.3} {:.3}", p.x, p.y, p.z);
+        let pair = |pair: &StartEndPair<Point3>| {
+            if pair.start() == pair.end() {
+                point(pair.start())
             } else {
-                Color::new(0.0, 0.0, 0.0)
+                format!("{} to {}", point(pair.start()), point(pair.end()))
             }
+        };
+
+        let mut metadata = Metadata::default();
+        metadata.add(
+            "Software",
+            format!("raytracer {}", env!("CARGO_PKG_VERSION")),
+        );
+        metadata.add("Source", scene_file);
+        metadata.add(
+            "Command Line",
+            std::env::args().collect::<Vec<_>>().join(" "),
+        );
+        metadata.add("Samples Per Pixel", samples_per_pixel);
+        metadata.add("Max Depth", self.render_settings().max_depth);
+        metadata.add(
+            "Camera",
+            format!(
+                "look from {}, look at {}, vup {}, vfov {}, aperture {}, focus distance {}, time {} to {}",
+                pair(&camera.look_from),
+                pair(&camera.look_at),
+                point(&camera.vup),
+                self.vfov,
+                self.aperture,
+                self.focus_distance,
+                camera.time.start(),
+                camera.time.end()
+            ),
+        );
+        if self.overscan > 0 {
+            metadata.add(
+                "Overscan",
+                format!("{} pixels on every side", self.overscan),
+            );
         }
-        None => {
-            let unit_direction = unit_vector(r.direction());
-            let t = 0.5 * (unit_direction.y + 1.0);
+        metadata.add("Render Time", format!("{:.2?}", render_time));
 
-            (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+        metadata
+    }
+
+    fn camera(&self, settings: &CameraSettings) -> Camera {
+        let camera_at = |look_from: &Point3, look_at: &Point3| {
+            Camera::new(
+                *look_from,
+                *look_at,
+                settings.vup,
+                self.vfov,
+                self.aspect_ratio,
+                self.aperture,
+                self.focus_distance,
+                settings.time.clone(),
+            )
+        };
+        let end = camera_at(settings.look_from.end(), settings.look_at.end());
+
+        let camera = camera_at(settings.look_from.start(), settings.look_at.start())
+            .moving_to(&end)
+            .clipped(settings.near, settings.far);
+        if self.overscan == 0 {
+            return camera;
         }
-    };
+
+        // Pixels are spaced a width and a height of the frame over one less than there are.
+        let overscan = self.overscan as f64;
+        camera.overscanned(
+            overscan / (self.image_width - 1) as f64,
+            overscan / (self.image_height() - 1) as f64,
+        )
+    }
+
+    fn verbosity(&self) -> Result<Verbosity> {
+        match (self.quiet, self.verbose) {
+            (true, true) => Err(eyre!("--quiet and --verbose can't go together")),
+            (true, false) => Ok(Verbosity::Quiet),
+            (false, true) => Ok(Verbosity::Verbose),
+            (false, false) => Ok(Verbosity::Normal),
+        }
+    }
+
+    fn progress_bar(&self, len: u64) -> ProgressBar {
+        if self.quiet {
+            return ProgressBar::hidden();
+        }
+
+        progress_bar(len)
+    }
+
+    /// Fails unless the output can take several frames.
+    fn check_several_frames(&self) -> Result<()> {
+        if self.output == Output::Stdout {
+            return Err(eyre!(
+                "`-o -` takes a single image, pipe several with `pipe:y4m` or `pipe:ppm`"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fails unless the frame is at least 2 pixels wide and tall, since rays are spread from
+    /// its first pixel to its last.
+    fn check_frame_size(&self) -> Result<()> {
+        if self.image_width < 2 || self.image_height() < 2 {
+            return Err(eyre!(
+                "the frame must be at least 2 pixels wide and tall, not {}x{}",
+                self.image_width,
+                self.image_height()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn progress_bar(len: u64) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] ({eta_precise}) {msg} [{wide_bar}]"),
+    );
+
+    pb
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
 
     let args: Args = argh::from_env();
+    logging::init(args.verbosity()?);
+    args.check_frame_size()?;
+    if args.nice {
+        nice::enable();
+    }
+    let mut stats = Stats::start();
 
-    // Image
+    match &args.command {
+        Some(Command::Animate(animate)) => render_animation(&args, animate, &mut stats)?,
+        Some(Command::Bake(bake)) => bake_lightmap(&args, bake, &mut stats)?,
+        Some(Command::Bench(bench)) => {
+            let scene = args.load_scene(&bench.scene_file)?;
+            let camera = args.camera(&scene.camera);
+            bench.run(scene, &camera);
+        }
+        Some(Command::Compare(compare)) => compare.run(|heat_map, width, height| {
+            args.frame_writer()
+                .write_frame(None, heat_map, width, height)
+        })?,
+        Some(Command::Demo(demo)) if demo.print => {
+            std::io::stdout().write_all(demo.scene.scene_file()?.as_bytes())?
+        }
+        Some(Command::Demo(_)) => render_still(&args, &mut stats)?,
+        Some(Command::Edit(edit)) => edit.run()?,
+        Some(Command::Focus(focus)) => pick_focus(&args, focus)?,
+        Some(Command::Furnace(furnace)) => furnace.run(args.max_depth)?,
+        Some(Command::Info(info)) => info.print(&args.load_scene(&info.scene_file)?),
+        Some(Command::Matpreview(matpreview)) => render_matpreview(&args, matpreview, &mut stats)?,
+        Some(Command::Probe(probe)) => probe.run()?,
+        Some(Command::Probes(probes)) => sample_probes(&args, probes)?,
+        Some(Command::Record(record)) => record_pixel(&args, record)?,
+        Some(Command::Replay(replay)) => replay_pixel(&args, replay)?,
+        Some(Command::Turntable(turntable)) => render_turntable(&args, turntable, &mut stats)?,
+        Some(Command::Watch(watch)) => render_watch(&args, watch)?,
+        None => render_still(&args, &mut stats)?,
+    }
 
-    let aspect_ratio = args.aspect_ratio;
-    let image_width = args.image_width;
-    let image_height: u32 = (image_width as f64 / aspect_ratio) as u32;
-    let samples_per_pixel = args.samples_per_pixel;
-    let max_depth = args.max_depth;
+    if args.stats {
+        stats.print();
+    }
 
-    // World
+    if interrupt::requested() {
+        std::process::exit(130);
+    }
 
-    let pb = ProgressBar::new(image_height as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] ({eta_precise}) {msg} [{wide_bar}]"),
+    Ok(())
+}
+
+fn pick_focus(args: &Args, focus: &Focus) -> Result<()> {
+    let scene = args.load_scene(&focus.scene_file)?;
+    let camera = args.camera(&scene.camera);
+    let distance = focus.distance(
+        &scene.world,
+        &scene.interval,
+        &camera,
+        (args.image_width, args.image_height()),
+        args.overscan,
+    )?;
+
+    println!("focus distance {:.4} (-D {:.4})", distance, distance);
+
+    Ok(())
+}
+
+fn sample_probes(args: &Args, probes: &Probes) -> Result<()> {
+    let scene = args.load_scene(&probes.scene_file)?;
+    let time = *scene.camera.time.start();
+    let world = Arc::new(World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    ));
+
+    let pb = args.progress_bar((probes.at.len() * probes.samples) as u64);
+    pb.set_message("sampling probes");
+    let coefficients = probes.sample(&world, args.render_settings(), time, &pb)?;
+    pb.finish_and_clear();
+
+    println!("{}", probes.to_json(&coefficients));
+
+    Ok(())
+}
+
+fn record_pixel(args: &Args, record: &Record) -> Result<()> {
+    let scene = args.load_scene(&record.scene_file)?;
+    let camera = args.camera(&scene.camera);
+    let world = World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
     );
 
-    let world = load_scene(&args.scene_file)?;
-    let world = Arc::new(world);
+    record.run(&world, &camera, args.render_settings())
+}
 
-    // Camera
+fn replay_pixel(args: &Args, replay: &Replay) -> Result<()> {
+    let scene = args.load_scene(&replay.scene_file()?)?;
+    let camera = args.camera(&scene.camera);
+    let world = World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    );
+
+    replay.run(&world, &camera, args.render_settings())
+}
 
-    let look_from = Point3::new(13.0, 2.0, 3.0);
-    let look_at = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = args.focus_distance;
-    let aperture = args.aperture;
-
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        vup,
-        args.vfov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        StartEndPair::new(0.0, 1.0),
+fn render_turntable(args: &Args, turntable: &Turntable, stats: &mut Stats) -> Result<()> {
+    args.check_several_frames()?;
+    if args.write_every.is_some() {
+        return Err(eyre!("--write-every only applies to still images"));
+    }
+    interrupt::catch();
+    let settings = args.render_settings();
+    let pb = args.progress_bar(
+        settings.image_height as u64 * settings.samples_per_pixel as u64 * turntable.frames as u64,
     );
-    let camera = Arc::new(camera);
 
-    // Render
-    let mut image_data = Vec::with_capacity((image_width * image_height * 3) as usize);
-    let num_cpus = num_cpus::get(); // get it once for all
-    let workers: Vec<_> = (0..num_cpus)
-        .map(|n| {
-            let world = world.clone();
-            let camera = camera.clone();
-            let (sender, receiver) = channel();
-            spawn(move || {
-                for j in (0..image_height)
-                    .filter(|j| *j as usize % num_cpus == n)
-                    .rev()
-                {
-                    for i in 0..image_width {
-                        let pixel_color = (0..samples_per_pixel)
-                            .map(|_| {
-                                let u = (i as f64 + random_double()) / (image_width - 1) as f64;
-                                let v = (j as f64 + random_double()) / (image_height - 1) as f64;
-                                camera.get_ray(u, v)
-                            })
-                            .fold(Color::new(0.0, 0.0, 0.0), |pixel_color, r| {
-                                pixel_color + ray_color(&r, world.as_ref(), max_depth)
-                            });
-
-                        let color = clamp_color(&pixel_color, samples_per_pixel);
-                        sender.send(color).ok();
-                    }
+    let scene = args.load_scene(&turntable.scene_file)?;
+    let world = Arc::new(World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    ));
+    let mut writer = args.frame_writer();
+    stats.end_phase("loading");
+
+    // The samples of the last frame, and what they saw, for the next frame to reuse.
+    let reused_samples =
+        (turntable.reuse.clamp(0.0, 1.0) * settings.samples_per_pixel as f64).round() as usize;
+    let mut last: Option<(GBuffer, Accumulation)> = None;
+
+    for frame in 0..turntable.frames {
+        pb.set_message(&format!("frame {}/{}", frame + 1, turntable.frames));
+
+        let look_from = turntable.look_from(frame);
+        let camera_settings = CameraSettings {
+            look_from: StartEndPair::new(look_from, look_from),
+            look_at: StartEndPair::new(turntable.target, turntable.target),
+            ..scene.camera.clone()
+        };
+        let start = Instant::now();
+        let camera = args.camera(&camera_settings);
+        let accumulation = if reused_samples > 0 {
+            let gbuffer = GBuffer::new(
+                &world,
+                &Arc::new(camera.clone()),
+                settings.image_width,
+                settings.image_height,
+            )?;
+            let previous = match &last {
+                Some((last_gbuffer, last_accumulation)) => {
+                    gbuffer.reproject(last_gbuffer, last_accumulation, reused_samples)
                 }
-            });
-            receiver
-        })
-        .collect(); // create workers list
+                None => Accumulation::default(),
+            };
+            let accumulation = render_from(
+                &world,
+                camera,
+                settings,
+                previous,
+                None,
+                &mut |_| Ok(()),
+                &pb,
+            )?;
+            last = Some((gbuffer, accumulation.clone()));
+            accumulation
+        } else {
+            render(&world, camera, settings, &pb)?
+        };
 
-    for j in (0..image_height).rev() {
-        pb.inc(1);
-        let worker = &workers[j as usize % num_cpus];
+        writer.set_metadata(args.metadata(
+            &turntable.scene_file,
+            &camera_settings,
+            accumulation.samples_per_pixel(),
+            start.elapsed(),
+        ));
+        writer.write_frame(
+            Some(&format!("{:04}", frame)),
+            &accumulation.image(),
+            settings.image_width,
+            settings.image_height,
+        )?;
 
-        for _ in 0..image_width {
-            let (r, g, b) = worker.recv()?;
-            image_data.push(r);
-            image_data.push(g);
-            image_data.push(b);
+        if interrupt::requested() {
+            warn!("interrupted, frame {} is only partly rendered", frame);
+            break;
         }
     }
+    stats.end_phase("rendering");
 
-    image::save_buffer(
-        args.output,
-        &image_data,
+    Ok(())
+}
+
+fn render_animation(args: &Args, animate: &Animate, stats: &mut Stats) -> Result<()> {
+    args.check_several_frames()?;
+    if args.write_every.is_some() {
+        return Err(eyre!("--write-every only applies to still images"));
+    }
+    interrupt::catch();
+    let settings = args.render_settings();
+    let pb = args.progress_bar(
+        settings.image_height as u64 * settings.samples_per_pixel as u64 * animate.frames,
+    );
+    let mut writer = args.frame_writer();
+    // The world of the last frame, if the next ones can reuse it.
+    let mut rigid: Option<Arc<World<Bvh<Object>>>> = None;
+
+    for (done, frame) in animate.frames().enumerate() {
+        pb.set_message(&format!(
+            "frame {} ({}/{})",
+            frame,
+            done + 1,
+            animate.frames
+        ));
+
+        let start = Instant::now();
+        let time = animate.time(frame, args.fps);
+        let options = LoadOptions {
+            time: Some(time.clone()),
+            ..args.load_options()
+        };
+        let (world, camera_settings, is_rigid) = match rigid.take() {
+            // Only the camera and the bounds of moving objects change from frame to frame.
+            Some(mut world) => {
+                let mut camera_settings = camera_settings(&animate.scene_file, &options)?;
+                camera_settings.time = time.clone();
+                let refit = Arc::get_mut(&mut world)
+                    .expect("the render is done with the world")
+                    .objects_mut()
+                    .refit(*time.start(), *time.end());
+                debug!("fitted the hierarchy to frame {}: {:?}", frame, refit);
+                (world, camera_settings, true)
+            }
+            None => {
+                let scene = load_scene(&animate.scene_file, &options)?;
+                let is_rigid = scene.is_rigid();
+                let world = Arc::new(World::new(
+                    scene.world,
+                    scene.lights,
+                    scene.background,
+                    scene.backplate,
+                    scene.fog,
+                    scene.interval,
+                ));
+                if !is_rigid {
+                    debug!("the scene has to be loaded again for every frame");
+                }
+                (world, scene.camera, is_rigid)
+            }
+        };
+        stats.end_phase("loading");
+
+        let camera = args.camera(&camera_settings);
+        let accumulation = render(&world, camera, settings, &pb)?;
+        stats.end_phase("rendering");
+        if is_rigid {
+            rigid = Some(world);
+        }
+
+        writer.set_metadata(args.metadata(
+            &animate.scene_file,
+            &camera_settings,
+            accumulation.samples_per_pixel(),
+            start.elapsed(),
+        ));
+        writer.write_frame(
+            Some(&format!("{:04}", frame)),
+            &accumulation.image(),
+            settings.image_width,
+            settings.image_height,
+        )?;
+        stats.end_phase("writing");
+
+        if interrupt::requested() {
+            warn!("interrupted, frame {} is only partly rendered", frame);
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn bake_lightmap(args: &Args, bake: &Bake, stats: &mut Stats) -> Result<()> {
+    if args.write_every.is_some() {
+        return Err(eyre!("--write-every only applies to still images"));
+    }
+
+    let scene = args.load_scene(&bake.scene_file)?;
+    let time = *scene.camera.time.start();
+    let triangles = textured_triangles(&bake.scene_file, &bake.object, time)?;
+    let world = Arc::new(World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    ));
+    stats.end_phase("loading");
+
+    let pb = args.progress_bar(0);
+    pb.set_message(&format!("baking {}", bake.object));
+    let image_data = bake.render(&world, &triangles, args.render_settings(), time, &pb)?;
+    pb.finish_and_clear();
+    stats.end_phase("rendering");
+
+    args.frame_writer()
+        .write_frame(None, &image_data, bake.size, bake.size)?;
+    stats.end_phase("writing");
+
+    Ok(())
+}
+
+fn render_matpreview(args: &Args, matpreview: &Matpreview, stats: &mut Stats) -> Result<()> {
+    let settings = RenderSettings {
+        image_width: matpreview.size,
+        image_height: matpreview.size,
+        ..args.render_settings()
+    };
+    let pb = args.progress_bar(settings.image_height as u64 * settings.samples_per_pixel as u64);
+
+    let options = LoadOptions {
+        source: Some(matpreview.scene_file()?),
+        ..args.load_options()
+    };
+    let scene = load_scene(&matpreview.material, &options)?;
+    let world = Arc::new(World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    ));
+    stats.end_phase("loading");
+
+    interrupt::catch();
+    let accumulation = render(&world, matpreview.camera(&scene.camera), settings, &pb)?;
+    stats.end_phase("rendering");
+
+    args.frame_writer().write_frame(
+        None,
+        &accumulation.image(),
+        settings.image_width,
+        settings.image_height,
+    )?;
+    stats.end_phase("writing");
+
+    Ok(())
+}
+
+fn render_watch(args: &Args, watch: &Watch) -> Result<()> {
+    args.check_several_frames()?;
+    if args.write_every.is_some() {
+        return Err(eyre!("watch already rewrites the output after every pass"));
+    }
+    interrupt::catch();
+    let settings = args.render_settings();
+    let path = &watch.scene_file;
+
+    let scene = args.load_scene(path)?;
+    let mut sources = scene.sources;
+    let mut modified = Modified::of(&sources);
+    let mut camera_settings = scene.camera;
+    let world = World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    );
+
+    let pb = args.progress_bar(settings.image_height as u64 * settings.samples_per_pixel as u64);
+    let mut renderer = ProgressiveRenderer::new(world, args.camera(&camera_settings), settings, pb);
+    if let Some(passes) = watch.passes {
+        renderer = renderer.stop_after(passes);
+    }
+    let mut writer = args.frame_writer();
+    renderer.start();
+
+    // The channel closes once the last pass is done, or on Ctrl-C.
+    while let Ok(snapshot) = renderer.snapshots().recv() {
+        let accumulation = snapshot.accumulation;
+        writer.set_metadata(args.metadata(
+            path,
+            &camera_settings,
+            accumulation.samples_per_pixel(),
+            snapshot.elapsed,
+        ));
+        writer.write_frame(
+            None,
+            &accumulation.image(),
+            settings.image_width,
+            settings.image_height,
+        )?;
+        debug!(
+            "wrote pass {} since the scene changed, in {:.2?}",
+            snapshot.passes, snapshot.elapsed
+        );
+
+        if interrupt::requested() {
+            warn!(
+                "interrupted, wrote {} samples per pixel",
+                accumulation.samples_per_pixel()
+            );
+            break;
+        }
+
+        let now = Modified::of(&sources);
+        let change = modified.change(&now);
+        modified = now;
+        if change == Change::None {
+            continue;
+        }
+
+        // A failed render stops here, rather than passing for a failed reload.
+        renderer.pause()?;
+        let reload = renderer.update_scene(|world, camera| {
+            let reload = match change {
+                Change::SceneFile => reload_materials(
+                    path,
+                    &args.load_options(),
+                    world.objects_mut(),
+                    &camera_settings,
+                )?,
+                _ => None,
+            };
+
+            let in_place = match reload {
+                Some(reload) => {
+                    world.set_lights(reload.lights);
+                    world.set_background(reload.background);
+                    world.set_backplate(reload.backplate);
+                    world.set_fog(reload.fog);
+                    world.set_interval(reload.interval);
+                    camera_settings = reload.camera;
+                    Some(reload.moved)
+                }
+                None => {
+                    let scene = args.load_scene(path)?;
+                    sources = scene.sources;
+                    modified = Modified::of(&sources);
+                    camera_settings = scene.camera;
+                    *world = World::new(
+                        scene.world,
+                        scene.lights,
+                        scene.background,
+                        scene.backplate,
+                        scene.fog,
+                        scene.interval,
+                    );
+                    None
+                }
+            };
+            *camera = args.camera(&camera_settings);
+
+            Ok(in_place)
+        });
+
+        match reload {
+            Ok(Some(None)) => info!("{} changed, reloaded its materials", path),
+            Ok(Some(Some((moved, refit)))) => info!(
+                "{} changed, moved {} transform groups and {} the hierarchy",
+                path,
+                moved,
+                match refit {
+                    Refit::Refitted => "refitted",
+                    Refit::Rebuilt => "rebuilt",
+                }
+            ),
+            Ok(None) => info!("{} changed, reloaded the whole scene", path),
+            Err(e) => warn!("couldn't reload {}: {}", path, e),
+        }
+        renderer.start();
+    }
+
+    renderer.pause()
+}
+
+fn render_still(args: &Args, stats: &mut Stats) -> Result<()> {
+    let scene_file = &match &args.command {
+        Some(Command::Demo(demo)) => demo.scene.to_string(),
+        _ => args
+            .scene_file
+            .clone()
+            .ok_or_else(|| eyre!("no scene file given, pass one with `-f`"))?,
+    };
+
+    // Image
+
+    let settings = args.render_settings();
+    let RenderSettings {
         image_width,
         image_height,
-        ColorType::Rgb8,
+        ..
+    } = settings;
+
+    // World
+
+    let eyes = if args.stereo.is_some() { 2 } else { 1 };
+    let pb = args.progress_bar(image_height as u64 * settings.samples_per_pixel as u64 * eyes);
+
+    let scene = args.load_scene(scene_file)?;
+    let world = Arc::new(World::new(
+        scene.world,
+        scene.lights,
+        scene.background,
+        scene.backplate,
+        scene.fog,
+        scene.interval,
+    ));
+    let mut writer = args.frame_writer();
+    stats.end_phase("loading");
+
+    // Camera
+
+    let camera = args.camera(&scene.camera);
+
+    // Render
+
+    if args.write_every.is_some() {
+        args.check_several_frames()?;
+    }
+    if args.exposure && !matches!(args.output, Output::File(_)) {
+        return Err(eyre!(
+            "--exposure writes next to the output, which must be a file"
+        ));
+    }
+
+    interrupt::catch();
+    let start = Instant::now();
+    let stereo = match args.stereo {
+        Some(stereo) => stereo,
+        None => {
+            let checkpoint = checkpoint_path(scene_file);
+            let render = {
+                let camera = camera.clone();
+                let sources = &scene.sources;
+                move || {
+                    RenderIdentity::new(
+                        image_width,
+                        image_height,
+                        settings.samples_per_pixel,
+                        &camera,
+                        sources,
+                    )
+                }
+            };
+            let previous = if args.resume {
+                read_checkpoint(&checkpoint, &render()?)?
+            } else {
+                Accumulation::default()
+            };
+            let gbuffer = args.draft_gbuffer(&world, &camera)?;
+            let camera_settings = &scene.camera;
+            let mut write = |accumulation: &Accumulation| {
+                writer.set_metadata(args.metadata(
+                    scene_file,
+                    camera_settings,
+                    accumulation.samples_per_pixel(),
+                    start.elapsed(),
+                ));
+                writer.write_frame(
+                    None,
+                    &image_data(accumulation, gbuffer.as_ref()),
+                    image_width,
+                    image_height,
+                )?;
+                debug!(
+                    "wrote the image so far, with at least {} samples per pixel",
+                    accumulation.samples_per_pixel()
+                );
+
+                Ok(())
+            };
+            let accumulation = render_from(
+                &world,
+                camera,
+                settings,
+                previous,
+                args.write_every,
+                &mut write,
+                &pb,
+            )?;
+            stats.end_phase("rendering");
+
+            writer.set_metadata(args.metadata(
+                scene_file,
+                &scene.camera,
+                accumulation.samples_per_pixel(),
+                start.elapsed(),
+            ));
+            writer.write_frame(
+                None,
+                &image_data(&accumulation, gbuffer.as_ref()),
+                image_width,
+                image_height,
+            )?;
+            if args.exposure {
+                write_exposure(&mut writer, &accumulation, image_width, image_height)?;
+            }
+            if interrupt::requested() && scene_file != "-" {
+                write_checkpoint(&checkpoint, render()?, &accumulation)?;
+                warn!(
+                    "interrupted with at least {} samples per pixel, saved them to {} for --resume",
+                    accumulation.samples_per_pixel(),
+                    checkpoint
+                );
+            } else if args.resume {
+                std::fs::remove_file(&checkpoint)
+                    .wrap_err_with(|| format!("removing {}", checkpoint))?;
+            }
+            stats.end_phase("writing");
+
+            return Ok(());
+        }
+    };
+
+    if args.resume {
+        return Err(eyre!("stereo renders can't be resumed"));
+    }
+    if args.write_every.is_some() {
+        return Err(eyre!("--write-every doesn't apply to stereo renders"));
+    }
+    if args.exposure {
+        return Err(eyre!("--exposure doesn't apply to stereo renders"));
+    }
+    if stereo == StereoMode::Separate {
+        args.check_several_frames()?;
+    }
+
+    let convergence = args.convergence.unwrap_or(args.focus_distance);
+    let half_interaxial = args.interaxial / 2.0;
+    let (left_eye, right_eye) = (
+        camera.eye(-half_interaxial, convergence),
+        camera.eye(half_interaxial, convergence),
+    );
+    let gbuffers = (
+        args.draft_gbuffer(&world, &left_eye)?,
+        args.draft_gbuffer(&world, &right_eye)?,
+    );
+    let left = render(&world, left_eye, settings, &pb)?;
+    let right = render(&world, right_eye, settings, &pb)?;
+    stats.end_phase("rendering");
+
+    let mut metadata = args.metadata(
+        scene_file,
+        &scene.camera,
+        left.samples_per_pixel().min(right.samples_per_pixel()),
+        start.elapsed(),
+    );
+    metadata.add(
+        "Stereo",
+        format!(
+            "interaxial {}, convergence {}",
+            args.interaxial, convergence
+        ),
+    );
+    writer.set_metadata(metadata);
+    let (left, right) = (
+        image_data(&left, gbuffers.0.as_ref()),
+        image_data(&right, gbuffers.1.as_ref()),
+    );
+
+    match stereo {
+        StereoMode::SideBySide => {
+            writer.write_frame(
+                None,
+                &side_by_side(&left, &right, image_width, image_height),
+                image_width * 2,
+                image_height,
+            )?;
+        }
+        StereoMode::Separate => {
+            for (image_data, suffix) in [(&left, "left"), (&right, "right")].iter() {
+                writer.write_frame(Some(suffix), image_data, image_width, image_height)?;
+            }
+        }
+    }
+    stats.end_phase("writing");
+
+    if interrupt::requested() {
+        warn!("interrupted, the images are only partly rendered");
+    }
+
+    Ok(())
+}
+
+/// Returns the RGB8 data of `accumulation`, smoothed along the surfaces that `gbuffer` saw if
+/// there is one.
+fn image_data(accumulation: &Accumulation, gbuffer: Option<&GBuffer>) -> Vec<u8> {
+    let gbuffer = match gbuffer {
+        Some(gbuffer) => gbuffer,
+        None => return accumulation.image(),
+    };
+
+    quality::smooth(&accumulation.colors(), gbuffer)
+        .iter()
+        .flat_map(|color| {
+            let (r, g, b) = clamp_color(color, 1);
+            [r, g, b]
+        })
+        .collect()
+}
+
+/// Writes the histogram and false-color map of `accumulation` next to the image, and says how
+/// much of it is clipped or crushed.
+fn write_exposure(
+    writer: &mut FrameWriter,
+    accumulation: &Accumulation,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let colors = accumulation.colors();
+
+    let (histogram, histogram_width, histogram_height) = exposure::histogram(&colors);
+    writer.write_frame(
+        Some("histogram"),
+        &histogram,
+        histogram_width,
+        histogram_height,
     )?;
+    writer.write_frame(
+        Some("exposure"),
+        &exposure::false_color(&colors),
+        width,
+        height,
+    )?;
+
+    let (clipped, crushed) = exposure::clipped_and_crushed(&colors);
+    info!(
+        "{:.2}% of the pixels are clipped and {:.2}% crushed",
+        clipped * 100.0,
+        crushed * 100.0
+    );
 
     Ok(())
 }
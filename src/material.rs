@@ -1,126 +1,1327 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::attributes::Attribute;
 use crate::hittable::HitRecord;
+use crate::memory_budget::MemoryBudget;
 use crate::ray::Ray;
 use crate::rtweekend::random_double;
+use crate::scene_error::SceneError;
+use crate::scene_loader::MaterialDef;
+use crate::spectrum::{Spectrum, Wavelengths};
+use crate::texture::Scalar;
 use crate::vec3::{
     near_zero, random_in_unit_sphere, random_unit_vector, reflect, refract, unit_vector, Color,
+    Point3, Vec3,
 };
 
 pub(crate) type Scatter = Option<(Ray, Color)>;
 
-pub(crate) trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord<Self>, scattered: &Ray) -> Scatter
-    where
-        Self: std::marker::Sized;
+/// Every kind of surface, so that objects made of different materials can share a scene and hit
+/// records don't need to know which one they carry.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "MaterialDef", into = "MaterialDef")]
+pub(crate) enum Material {
+    Lambertian(Lambertian),
+    Metal(Metal),
+    Sheen(Sheen),
+    Dielectric(Dielectric),
+    Water(Water),
+    DiffuseLight(DiffuseLight),
+    Preview(Preview),
+    ShadowCatcher(ShadowCatcher),
+    Mix(Box<Mix>),
+}
+
+impl Material {
+    /// Returns the material that is not a [`Mix`] found at `rec`, picking one of the materials of
+    /// mixes at random in proportion to how much of each there is.
+    pub(crate) fn resolve(&self, rec: &HitRecord) -> &Self {
+        match self {
+            Self::Mix(mix) => {
+                let [first, second] = &mix.materials;
+                if random_double() < mix.weight(rec) {
+                    second.resolve(rec)
+                } else {
+                    first.resolve(rec)
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// Returns whether the material mixes by attributes that meshes measure when they load.
+    pub(crate) fn needs_vertex_attributes(&self) -> bool {
+        match self {
+            Self::Mix(mix) => {
+                mix.by.is_precomputed() || mix.materials.iter().any(Self::needs_vertex_attributes)
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        match self {
+            Self::Lambertian(material) => material.scatter(r_in, rec),
+            Self::Metal(material) => material.scatter(r_in, rec),
+            Self::Sheen(material) => material.scatter(r_in, rec),
+            Self::Dielectric(material) => material.scatter(r_in, rec),
+            Self::Water(water) => water.scatter(r_in, rec),
+            Self::DiffuseLight(_) => None,
+            Self::Preview(preview) => preview.scatter(r_in, rec),
+            // Rays go through catchers, see `render::shadow_catcher`.
+            Self::ShadowCatcher(_) => None,
+            Self::Mix(_) => self.resolve(rec).scatter(r_in, rec),
+        }
+    }
+
+    /// Returns whether the surface scatters light like a [`Lambertian`], whatever its color.
+    pub(crate) fn is_diffuse(&self) -> bool {
+        matches!(self, Self::Lambertian(_) | Self::Preview(_))
+    }
+
+    /// Like [`Material::scatter`], but with mirrors and glass at least as rough as `roughness`,
+    /// so that bounces off diffuse surfaces stand a chance of finding the light through them.
+    pub(crate) fn scatter_regularized(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        roughness: f64,
+    ) -> Scatter {
+        match self {
+            Self::Metal(material) => {
+                material.scatter_with_fuzz(r_in, rec, material.fuzz_at(rec).max(roughness))
+            }
+            Self::Dielectric(material) => material.scatter_rough(r_in, rec, roughness),
+            _ => self.scatter(r_in, rec),
+        }
+    }
+
+    /// Returns the density over directions of [`Material::scatter_regularized`] sending light
+    /// out along `direction`, for mirrors and glass. Weighted by it, the attenuation that
+    /// scattering returns gives how much of the light from `direction` the surface reflects.
+    pub(crate) fn regularized_pdf(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        roughness: f64,
+        direction: &Vec3,
+    ) -> f64 {
+        match self {
+            Self::Metal(material) => {
+                material.pdf_with_fuzz(r_in, rec, material.fuzz_at(rec).max(roughness), direction)
+            }
+            Self::Dielectric(material) => material.rough_pdf(r_in, rec, roughness, direction),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the radiance the surface emits.
+    pub(crate) fn emitted(&self) -> Color {
+        match self {
+            Self::DiffuseLight(light) => light.emit,
+            _ => Color::zeros(),
+        }
+    }
+
+    /// Returns the radiance the surface emits at each of `wavelengths`, in spectral mode.
+    pub(crate) fn emitted_at(&self, wavelengths: &Wavelengths) -> Wavelengths {
+        match self {
+            Self::DiffuseLight(light) => light.emit_spectrum.at(&light.emit, wavelengths),
+            _ => Wavelengths::zeros(),
+        }
+    }
+
+    /// Returns the fraction of light at each of `wavelengths` that survives a scatter, in
+    /// spectral mode, given the `attenuation` the scatter returned.
+    pub(crate) fn attenuation_at(
+        &self,
+        attenuation: &Color,
+        wavelengths: &Wavelengths,
+    ) -> Wavelengths {
+        match self {
+            Self::Lambertian(material) => {
+                material.albedo_spectrum.at(&material.albedo, wavelengths)
+            }
+            Self::Metal(material) => material.albedo_spectrum.at(&material.albedo, wavelengths),
+            // Water absorbs along the way under its surface instead, see `Water::absorption`.
+            Self::Dielectric(_) | Self::Water(_) => Wavelengths::repeat(1.0),
+            Self::DiffuseLight(_) | Self::ShadowCatcher(_) => Wavelengths::zeros(),
+            // Previews change color from hit to hit, and cloth mixes its two by angle, so there
+            // is no spectrum to keep.
+            Self::Preview(_) | Self::Sheen(_) | Self::Mix(_) => {
+                Spectrum::from_rgb(attenuation).at_each(wavelengths)
+            }
+        }
+    }
+
+    /// Reads the textures of the material's parameters, with paths relative to `base_dir`, and
+    /// adds them to `sources`, downscaling them to fit the `budget` if there is one.
+    pub(crate) fn load_textures(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        budget: Option<&MemoryBudget>,
+    ) -> Result<(), SceneError> {
+        match self {
+            Self::Metal(material) => material.fuzz.load(base_dir, sources, budget),
+            Self::Sheen(material) => material.roughness.load(base_dir, sources, budget),
+            Self::Dielectric(material) => material.ir.load(base_dir, sources, budget),
+            Self::Water(water) => water.surface.ir.load(base_dir, sources, budget),
+            Self::Mix(mix) => mix
+                .materials
+                .iter_mut()
+                .try_for_each(|material| material.load_textures(base_dir, sources, budget)),
+            Self::Lambertian(_)
+            | Self::DiffuseLight(_)
+            | Self::Preview(_)
+            | Self::ShadowCatcher(_) => Ok(()),
+        }
+    }
+}
+
+impl Material {
+    /// Returns the bytes taken by the images of its textures.
+    pub(crate) fn texture_memory(&self) -> usize {
+        match self {
+            Self::Metal(material) => material.fuzz.memory(),
+            Self::Sheen(material) => material.roughness.memory(),
+            Self::Dielectric(material) => material.ir.memory(),
+            Self::Water(water) => water.surface.ir.memory(),
+            Self::Mix(mix) => mix.materials.iter().map(Material::texture_memory).sum(),
+            Self::Lambertian(_)
+            | Self::DiffuseLight(_)
+            | Self::Preview(_)
+            | Self::ShadowCatcher(_) => 0,
+        }
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color = |c: &Color| format!("({}, {}, {})", c.x, c.y, c.z);
+
+        match self {
+            Self::Lambertian(material) => write!(f, "lambertian {}", color(&material.albedo)),
+            Self::Metal(material) => {
+                write!(
+                    f,
+                    "metal {} fuzz {}",
+                    color(&material.albedo),
+                    material.fuzz
+                )
+            }
+            Self::Sheen(material) => write!(
+                f,
+                "sheen {} over {} roughness {}",
+                color(&material.sheen),
+                color(&material.albedo),
+                material.roughness
+            ),
+            Self::Dielectric(material) => write!(
+                f,
+                "dielectric ir {} fresnel {}",
+                material.ir, material.fresnel
+            ),
+            Self::Water(water) => write!(
+                f,
+                "water ir {} absorption {} waves {} every {}",
+                water.surface.ir,
+                color(&water.absorption),
+                water.wave_height,
+                water.wavelength
+            ),
+            Self::DiffuseLight(material) => write!(f, "light {}", color(&material.emit)),
+            Self::Preview(preview) => write!(f, "preview {}", preview),
+            Self::ShadowCatcher(catcher) => {
+                write!(f, "shadow catcher reflection {}", catcher.reflection)
+            }
+            Self::Mix(mix) => {
+                let [first, second] = &mix.materials;
+                write!(
+                    f,
+                    "mix by {} from {} to {} of ({}) and ({})",
+                    mix.by, mix.from, mix.to, first, second
+                )
+            }
+        }
+    }
+}
+
+/// The spectrum of a color, fitted the first time a spectral render needs it. Colors brighter
+/// than white are fitted at the brightness of their brightest channel, then scaled back up.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LazySpectrum(OnceLock<(Spectrum, f64)>);
+
+impl LazySpectrum {
+    pub(crate) fn at(&self, color: &Color, wavelengths: &Wavelengths) -> Wavelengths {
+        let (spectrum, scale) = self.0.get_or_init(|| {
+            let scale = color.max().max(1.0);
+            (Spectrum::from_rgb(&(color / scale)), scale)
+        });
+
+        spectrum.at_each(wavelengths) * *scale
+    }
+}
+
+/// The density over unit directions of `center + fuzz * random_in_unit_sphere()`, for a unit
+/// `center`: the volume of the fuzz ball that a direction's ray passes through, `∫ t² dt` over
+/// where it is inside the ball.
+fn fuzz_pdf(center: &Vec3, fuzz: f64, direction: &Vec3) -> f64 {
+    let c = direction.dot(center);
+    let discriminant = c * c - 1.0 + fuzz * fuzz;
+    if discriminant < 0.0 {
+        return 0.0;
+    }
+
+    let near = (c - discriminant.sqrt()).max(0.0);
+    let far = c + discriminant.sqrt();
+    if far <= 0.0 {
+        return 0.0;
+    }
+
+    (far.powi(3) - near.powi(3)) / 3.0 / (4.0 / 3.0 * PI * fuzz.powi(3))
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct Lambertian {
     albedo: Color,
+    albedo_spectrum: LazySpectrum,
 }
 
 impl Lambertian {
     pub(crate) fn new(albedo: Color) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            albedo_spectrum: LazySpectrum::default(),
+        }
+    }
+
+    pub(crate) fn albedo(&self) -> &Color {
+        &self.albedo
     }
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord<Self>, _scatteredd: &Ray) -> Scatter {
-        let mut scatter_direction = rec.normal() + random_unit_vector();
+impl Lambertian {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        scatter_diffuse(r_in, rec, self.albedo)
+    }
+}
 
-        // Catch degenerate scatter direction
-        if near_zero(&scatter_direction) {
-            scatter_direction = *rec.normal();
-        }
+/// Bounces off a diffuse surface of color `albedo`, with a cosine-weighted direction.
+fn scatter_diffuse(r_in: &Ray, rec: &HitRecord, albedo: Color) -> Scatter {
+    let mut scatter_direction = rec.normal() + random_unit_vector();
 
-        Some((
-            Ray::new(*rec.p(), scatter_direction, Some(r_in.time())),
-            self.albedo,
-        ))
+    // Catch degenerate scatter direction
+    if near_zero(&scatter_direction) {
+        scatter_direction = *rec.normal();
     }
+
+    Some((
+        Ray::new(*rec.p(), scatter_direction, Some(r_in.time())),
+        albedo,
+    ))
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct Metal {
     albedo: Color,
-    fuzz: f64,
+    albedo_spectrum: LazySpectrum,
+    fuzz: Scalar,
 }
 
 impl Metal {
-    pub(crate) fn new(albedo: Color, fuzz: f64) -> Self {
+    pub(crate) fn new(albedo: Color, fuzz: impl Into<Scalar>) -> Self {
         Self {
             albedo,
-            fuzz: fuzz.min(1.0),
+            albedo_spectrum: LazySpectrum::default(),
+            fuzz: fuzz.into(),
         }
     }
+
+    pub(crate) fn albedo(&self) -> &Color {
+        &self.albedo
+    }
+
+    pub(crate) fn fuzz(&self) -> &Scalar {
+        &self.fuzz
+    }
+
+    fn fuzz_at(&self, rec: &HitRecord) -> f64 {
+        self.fuzz.at(rec).min(1.0)
+    }
 }
 
-impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord<Self>, _scatter: &Ray) -> Scatter {
+impl Metal {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        self.scatter_with_fuzz(r_in, rec, self.fuzz_at(rec))
+    }
+
+    fn scatter_with_fuzz(&self, r_in: &Ray, rec: &HitRecord, fuzz: f64) -> Scatter {
         let reflected = reflect(&unit_vector(r_in.direction()), rec.normal());
+        let scattered = Ray::new(
+            *rec.p(),
+            reflected + fuzz * random_in_unit_sphere(),
+            Some(r_in.time()),
+        );
 
-        // The book has this surrounded by something like:
-        //
-        //     if scatter.direction().dot(rec.normal()) > 0.0 {
-        //
-        // but for some reason, that makes my metal spheres appear black.
-        //
-        // Everything *seems* to work fine without it.
-
-        return Some((
-            Ray::new(
-                *rec.p(),
-                reflected + self.fuzz * random_in_unit_sphere(),
-                Some(r_in.time()),
-            ),
-            self.albedo,
-        ));
+        // Fuzz can push the reflection below the surface, where the metal absorbs it.
+        if scattered.direction().dot(rec.normal()) > 0.0 {
+            Some((scattered, self.albedo))
+        } else {
+            None
+        }
+    }
+
+    fn pdf_with_fuzz(&self, r_in: &Ray, rec: &HitRecord, fuzz: f64, direction: &Vec3) -> f64 {
+        if direction.dot(rec.normal()) <= 0.0 {
+            return 0.0;
+        }
+
+        let reflected = reflect(&unit_vector(r_in.direction()), rec.normal());
+        fuzz_pdf(&reflected, fuzz, direction)
+    }
+}
+
+/// How a [`Dielectric`] works out the fraction of light it reflects rather than refracts.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Fresnel {
+    /// Schlick's approximation, which is cheap and close enough for most glass.
+    #[default]
+    Schlick,
+    /// The Fresnel equations for unpolarized light, which get reflections right at all angles
+    /// and for high indices of refraction, where Schlick's approximation drifts.
+    Exact,
+}
+
+impl Fresnel {
+    /// Returns the reflectance of an interface for light arriving at `cos_theta` to its normal,
+    /// going from a medium with an index of refraction `refraction_ratio` times that of the
+    /// other side.
+    pub(crate) fn reflectance(&self, cos_theta: f64, refraction_ratio: f64) -> f64 {
+        match self {
+            Self::Schlick => {
+                let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
+
+                r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
+            }
+            Self::Exact => {
+                let sin_t_squared = refraction_ratio.powi(2) * (1.0 - cos_theta.powi(2));
+                if sin_t_squared >= 1.0 {
+                    return 1.0;
+                }
+
+                let cos_t = (1.0 - sin_t_squared).sqrt();
+                let r_s =
+                    (refraction_ratio * cos_theta - cos_t) / (refraction_ratio * cos_theta + cos_t);
+                let r_p =
+                    (cos_theta - refraction_ratio * cos_t) / (cos_theta + refraction_ratio * cos_t);
+
+                (r_s * r_s + r_p * r_p) / 2.0
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fresnel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Schlick => write!(f, "schlick"),
+            Self::Exact => write!(f, "exact"),
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct Dielectric {
-    ir: f64, // Index of refraction
+    ir: Scalar, // Index of refraction
+    fresnel: Fresnel,
 }
 
 impl Dielectric {
-    pub(crate) fn new(ir: f64) -> Self {
-        Self { ir }
+    pub(crate) fn new(ir: impl Into<Scalar>, fresnel: Fresnel) -> Self {
+        Self {
+            ir: ir.into(),
+            fresnel,
+        }
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Use Schlick's approximation for reflectance.
-        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
+    pub(crate) fn ir(&self) -> &Scalar {
+        &self.ir
+    }
 
-        r0 + (1.0 + r0) * (1.0 - cosine).powi(5)
+    pub(crate) fn fresnel(&self) -> Fresnel {
+        self.fresnel
     }
 }
 
-impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord<Self>, _scattered: &Ray) -> Scatter {
+impl Dielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        self.scatter_rough(r_in, rec, 0.0)
+    }
+
+    /// Scatters like a smooth dielectric, then fuzzes the direction like [`Metal`] does.
+    fn scatter_rough(&self, r_in: &Ray, rec: &HitRecord, roughness: f64) -> Scatter {
         let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if *rec.front_face() {
-            1.0 / self.ir
-        } else {
-            self.ir
+        let (reflected, refracted, reflectance) = self.interface(r_in, rec);
+        let mut direction = match refracted {
+            Some(refracted) if reflectance <= random_double() => refracted,
+            _ => reflected,
         };
 
+        if roughness > 0.0 {
+            let fuzzed = direction + roughness * random_in_unit_sphere();
+
+            // Like fuzz on metal, roughness can push the ray through to the wrong side, where
+            // it is absorbed.
+            if fuzzed.dot(rec.normal()) * direction.dot(rec.normal()) <= 0.0 {
+                return None;
+            }
+            direction = fuzzed;
+        }
+
+        Some((
+            Ray::new(*rec.p(), direction, Some(r_in.time())),
+            attenuation,
+        ))
+    }
+
+    fn rough_pdf(&self, r_in: &Ray, rec: &HitRecord, roughness: f64, direction: &Vec3) -> f64 {
+        let (reflected, refracted, reflectance) = self.interface(r_in, rec);
+
+        if direction.dot(rec.normal()) > 0.0 {
+            reflectance * fuzz_pdf(&reflected, roughness, direction)
+        } else {
+            match refracted {
+                Some(refracted) => (1.0 - reflectance) * fuzz_pdf(&refracted, roughness, direction),
+                None => 0.0,
+            }
+        }
+    }
+
+    /// Returns the directions in which the surface reflects and refracts light arriving along
+    /// `r_in`, and the fraction of it that is reflected, which is all of it when it can't be
+    /// refracted.
+    fn interface(&self, r_in: &Ray, rec: &HitRecord) -> (Vec3, Option<Vec3>, f64) {
+        let ir = self.ir.at(rec);
+        let refraction_ratio = if *rec.front_face() { 1.0 / ir } else { ir };
+
         let unit_direction = unit_vector(r_in.direction());
         let cos_theta = (-unit_direction).dot(rec.normal()).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction;
+        let reflected = reflect(&unit_direction, rec.normal());
+        if refraction_ratio * sin_theta > 1.0 {
+            return (reflected, None, 1.0);
+        }
+
+        (
+            reflected,
+            Some(refract(&unit_direction, rec.normal(), refraction_ratio)),
+            self.fresnel
+                .reflectance(cos_theta, refraction_ratio)
+                .min(1.0),
+        )
+    }
+}
+
+/// Directions, in the horizontal plane, relative lengths and phases of the swells that make up
+/// the waves of [`Water`]. Shorter swells are proportionally lower.
+const SWELLS: [(f64, f64, f64, f64); 4] = [
+    (1.0, 0.0, 1.0, 0.0),
+    (0.6, 0.8, 0.61, 1.3),
+    (-0.7, 0.71, 0.37, 2.9),
+    (0.2, -0.98, 0.23, 4.4),
+];
+
+/// A water surface: a [`Dielectric`] whose normals follow procedural waves, and which absorbs
+/// light along the way of the paths under it, so that deeper water looks darker and bluer.
+#[derive(Clone, Debug)]
+pub(crate) struct Water {
+    surface: Dielectric,
+    /// Fraction of each channel absorbed per meter travelled under the surface.
+    absorption: Color,
+    /// Height of the longest waves, from trough to crest, and the distance between their crests.
+    wave_height: f64,
+    wavelength: f64,
+}
 
-        if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > random_double() {
-            direction = reflect(&unit_direction, rec.normal());
+impl Water {
+    pub(crate) fn new(
+        ir: impl Into<Scalar>,
+        absorption: Color,
+        wave_height: f64,
+        wavelength: f64,
+    ) -> Self {
+        Self {
+            surface: Dielectric::new(ir, Fresnel::Schlick),
+            absorption,
+            wave_height,
+            wavelength,
+        }
+    }
+
+    pub(crate) fn ir(&self) -> &Scalar {
+        &self.surface.ir
+    }
+
+    pub(crate) fn absorption(&self) -> &Color {
+        &self.absorption
+    }
+
+    pub(crate) fn wave_height(&self) -> f64 {
+        self.wave_height
+    }
+
+    pub(crate) fn wavelength(&self) -> f64 {
+        self.wavelength
+    }
+
+    /// Returns the slope of the waves at `p`, along X and Z.
+    fn slope(&self, p: &Point3) -> (f64, f64) {
+        if self.wave_height == 0.0 || self.wavelength <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        SWELLS
+            .iter()
+            .fold((0.0, 0.0), |(dx, dz), &(x, z, length, phase)| {
+                let k = 2.0 * PI / (self.wavelength * length);
+                let amplitude = self.wave_height * length / 2.0;
+                let slope = amplitude * k * (k * (x * p.x + z * p.z) + phase).cos();
+                (dx + slope * x, dz + slope * z)
+            })
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        let (dx, dz) = self.slope(rec.p());
+        let outward = if *rec.front_face() {
+            *rec.normal()
         } else {
-            direction = refract(&unit_direction, rec.normal(), refraction_ratio);
+            -rec.normal()
+        };
+
+        // Tilt the normal against the slope, within the plane of the surface.
+        let slope = Vec3::new(dx, 0.0, dz);
+        let tilt = slope - slope.dot(&outward) * outward;
+        let waved = unit_vector(&(outward - tilt));
+        let mut rec = rec.clone();
+        rec.set_normal(if *rec.front_face() { waved } else { -waved });
+
+        self.surface.scatter(r_in, &rec)
+    }
+}
+
+/// Points of the table of how much a [`Sheen`] reflects, from grazing to head-on, and
+/// roughnesses it has rows for when the roughness varies over the surface.
+const SHEEN_TABLE: usize = 32;
+const SHEEN_ROUGHNESSES: usize = 10;
+const SHEEN_MIN_ROUGHNESS: f64 = 0.1;
+
+/// Cloth and other fabrics: a diffuse base under a sheen, the light that fibres standing up from
+/// the surface send back at grazing angles. The sheen is the "Charlie" sheen of glTF's
+/// `KHR_materials_sheen`, from Estevez and Kulla's _Production Friendly Microfacet Sheen BRDF_,
+/// with Ashikhmin's visibility term.
+///
+/// The base only reflects what the sheen leaves, so that the two together reflect no more than
+/// the brighter of their colors.
+#[derive(Clone, Debug)]
+pub(crate) struct Sheen {
+    albedo: Color,
+    sheen: Color,
+    /// From 0 for velvet, which only shines at grazing angles, to 1 for a broad, dusty sheen.
+    /// Sharper sheens than 0.1 only show on a sliver of the outline, so they are kept at that.
+    roughness: Scalar,
+    /// How much of the light arriving from each angle a white sheen reflects, by the cosine of
+    /// the angle, for each roughness the sheen may have, measured the first time it is needed.
+    reflectance: OnceLock<Vec<(f64, Vec<f64>)>>,
+}
+
+impl Sheen {
+    pub(crate) fn new(albedo: Color, sheen: Color, roughness: impl Into<Scalar>) -> Self {
+        Self {
+            albedo,
+            sheen,
+            roughness: roughness.into(),
+            reflectance: OnceLock::new(),
         }
+    }
 
-        Some((
-            Ray::new(*rec.p(), direction, Some(r_in.time())),
-            attenuation,
-        ))
+    pub(crate) fn albedo(&self) -> &Color {
+        &self.albedo
+    }
+
+    pub(crate) fn sheen(&self) -> &Color {
+        &self.sheen
+    }
+
+    pub(crate) fn roughness(&self) -> &Scalar {
+        &self.roughness
+    }
+
+    /// Returns the density of fibres facing along a half-vector at `cos_h` from the normal.
+    fn distribution(roughness: f64, cos_h: f64) -> f64 {
+        let alpha = roughness * roughness;
+        let sin_squared = (1.0 - cos_h * cos_h).max(0.0);
+
+        (2.0 + 1.0 / alpha) * sin_squared.powf(0.5 / alpha) / (2.0 * PI)
+    }
+
+    /// Returns the BRDF of a white sheen, for view and light directions at `cos_v` and `cos_l`
+    /// from the normal and their half-vector at `cos_h`.
+    fn lobe(roughness: f64, cos_v: f64, cos_l: f64, cos_h: f64) -> f64 {
+        Self::distribution(roughness, cos_h) / (4.0 * (cos_l + cos_v - cos_l * cos_v))
+    }
+
+    /// Returns the fraction of the light arriving at `cosine` from the normal that a white sheen
+    /// of `roughness` reflects.
+    fn directional_reflectance(&self, roughness: f64, cosine: f64) -> f64 {
+        let rows = self.reflectance.get_or_init(|| {
+            let roughnesses = match self.roughness.constant() {
+                Some(roughness) => vec![roughness.clamp(SHEEN_MIN_ROUGHNESS, 1.0)],
+                None => (0..SHEEN_ROUGHNESSES)
+                    .map(|i| {
+                        let t = i as f64 / (SHEEN_ROUGHNESSES - 1) as f64;
+                        SHEEN_MIN_ROUGHNESS + (1.0 - SHEEN_MIN_ROUGHNESS) * t
+                    })
+                    .collect(),
+            };
+
+            roughnesses
+                .into_iter()
+                .map(|roughness| {
+                    let row = (0..=SHEEN_TABLE)
+                        .map(|i| {
+                            let cosine = (i as f64 / SHEEN_TABLE as f64).max(1e-3);
+                            Self::integrate(roughness, cosine)
+                        })
+                        .collect();
+                    (roughness, row)
+                })
+                .collect()
+        });
+
+        let x = cosine.clamp(0.0, 1.0) * SHEEN_TABLE as f64;
+        let i = (x as usize).min(SHEEN_TABLE - 1);
+        let t = x - i as f64;
+        let at = |row: &[f64]| row[i] * (1.0 - t) + row[i + 1] * t;
+
+        // Between the two rows around `roughness`, or the only one.
+        let above = rows
+            .iter()
+            .position(|(r, _)| *r >= roughness)
+            .unwrap_or(rows.len() - 1);
+        let below = above.saturating_sub(1);
+        let ((r0, row0), (r1, row1)) = (&rows[below], &rows[above]);
+        let s = if r1 > r0 {
+            ((roughness - r0) / (r1 - r0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        at(row0) * (1.0 - s) + at(row1) * s
+    }
+
+    /// Integrates the lobe times the cosine over the hemisphere, seen from `cos_v`, on a grid
+    /// uniform in solid angle.
+    fn integrate(roughness: f64, cos_v: f64) -> f64 {
+        const STEPS: usize = 64;
+
+        let v = Vec3::new((1.0 - cos_v * cos_v).sqrt(), 0.0, cos_v);
+        let mut total = 0.0;
+        for i in 0..STEPS {
+            let cos_l = (i as f64 + 0.5) / STEPS as f64;
+            let sin_l = (1.0 - cos_l * cos_l).sqrt();
+            for j in 0..STEPS {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / STEPS as f64;
+                let l = Vec3::new(sin_l * phi.cos(), sin_l * phi.sin(), cos_l);
+                let cos_h = unit_vector(&(v + l)).z;
+                total += Self::lobe(roughness, cos_v, cos_l, cos_h) * cos_l;
+            }
+        }
+
+        total * 2.0 * PI / (STEPS * STEPS) as f64
+    }
+
+    /// Returns the BRDF of the cloth, seen from `view` and lit from `direction`, both pointing
+    /// away from the surface at `rec`.
+    pub(crate) fn brdf(&self, rec: &HitRecord, view: &Vec3, direction: &Vec3) -> Color {
+        let normal = rec.normal();
+        let (cos_v, cos_l) = (view.dot(normal).max(1e-4), direction.dot(normal));
+        if cos_l <= 0.0 {
+            return Color::zeros();
+        }
+
+        let roughness = self.roughness.at(rec).clamp(SHEEN_MIN_ROUGHNESS, 1.0);
+        let cos_h = unit_vector(&(view + direction)).dot(normal);
+        let strongest = self.sheen.max();
+        let base = (1.0 - strongest * self.directional_reflectance(roughness, cos_v))
+            .min(1.0 - strongest * self.directional_reflectance(roughness, cos_l))
+            .max(0.0);
+
+        self.albedo * (base / PI) + self.sheen * Self::lobe(roughness, cos_v, cos_l, cos_h)
+    }
+
+    /// Bounces with a cosine-weighted direction, like a [`Lambertian`].
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        let (scattered, _) = scatter_diffuse(r_in, rec, self.albedo)?;
+        let view = -unit_vector(r_in.direction());
+        let direction = unit_vector(scattered.direction());
+
+        // The BRDF times the cosine, over the density of cosine / π.
+        Some((scattered, self.brdf(rec, &view, &direction) * PI))
+    }
+}
+
+/// A surface that emits light evenly in every direction and reflects none.
+#[derive(Clone, Debug)]
+pub(crate) struct DiffuseLight {
+    emit: Color,
+    emit_spectrum: LazySpectrum,
+}
+
+impl DiffuseLight {
+    pub(crate) fn new(emit: Color) -> Self {
+        Self {
+            emit,
+            emit_spectrum: LazySpectrum::default(),
+        }
+    }
+
+    pub(crate) fn emit(&self) -> &Color {
+        &self.emit
+    }
+}
+
+/// A stand-in for the ground of a backplate, to render objects over a photograph with the
+/// shadows they cast on it. Camera rays see what is behind the catcher, darkened by how much of
+/// the light it would receive the objects block, and `reflection` of the objects it mirrors;
+/// every other ray goes through it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) struct ShadowCatcher {
+    #[serde(default)]
+    pub(crate) reflection: f64,
+}
+
+/// Two materials mixed by an [`Attribute`] of the geometry, such as dirt gathering in crevices
+/// or moss growing on top: all of the first where the attribute is `from`, all of the second
+/// where it is `to`, and blended linearly in between.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Mix {
+    by: Attribute,
+    from: f64,
+    to: f64,
+    materials: [Material; 2],
+}
+
+impl Mix {
+    /// Returns how much of the second material there is at `rec`.
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        let value = self.by.at(rec);
+        let span = self.to - self.from;
+        if span == 0.0 {
+            return if value < self.to { 0.0 } else { 1.0 };
+        }
+
+        ((value - self.from) / span).clamp(0.0, 1.0)
+    }
+}
+
+/// Diffuse surfaces for telling geometry from shading, colored by the normal they are hit at or
+/// by where they lie on a checkerboard of unit cells. Objects have no texture coordinates, so the
+/// checkerboard is laid out in world space.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Preview {
+    Normals,
+    Checker,
+}
+
+impl Preview {
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        match self {
+            Self::Normals => (rec.normal() + Vec3::new(1.0, 1.0, 1.0)) / 2.0,
+            Self::Checker => {
+                // Nudged along the normal so that faces lying on cell boundaries don't flicker.
+                let p = rec.p() - rec.normal() * 1e-4;
+                let parity = (p.x.floor() + p.y.floor() + p.z.floor()) as i64 & 1;
+
+                if parity == 0 {
+                    Color::new(0.8, 0.8, 0.8)
+                } else {
+                    Color::new(0.2, 0.2, 0.2)
+                }
+            }
+        }
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Scatter {
+        scatter_diffuse(r_in, rec, self.albedo(rec))
+    }
+}
+
+impl fmt::Display for Preview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normals => write!(f, "normals"),
+            Self::Checker => write!(f, "checker"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Point3, Vec3};
+
+    /// Bins of `cos θ` and `φ` around the normal. Both are uniform in solid angle, so every
+    /// bin covers the same `4π / (COS_BINS * PHI_BINS)` steradians.
+    const COS_BINS: usize = 32;
+    const PHI_BINS: usize = 64;
+    /// Points per side of the grid the PDF is integrated over in each bin.
+    const SUBDIVISIONS: usize = 16;
+    const SAMPLES: usize = 200_000;
+    /// Bins expected to get fewer samples than this are pooled, as the test needs.
+    const MIN_EXPECTED: f64 = 5.0;
+
+    fn normal() -> Vec3 {
+        Vec3::new(0.0, 0.0, 1.0)
+    }
+
+    fn incoming() -> Ray {
+        Ray::new(
+            Point3::new(-1.0, 0.0, 1.0),
+            unit_vector(&Vec3::new(1.0, 0.0, -1.0)),
+            Some(0.0),
+        )
+    }
+
+    fn direction(cos_theta: f64, phi: f64) -> Vec3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    fn bin(direction: &Vec3) -> usize {
+        let d = unit_vector(direction);
+        let cos_bin = (((d.z + 1.0) / 2.0 * COS_BINS as f64) as usize).min(COS_BINS - 1);
+        let phi = d.y.atan2(d.x).rem_euclid(2.0 * PI);
+        let phi_bin = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+
+        cos_bin * PHI_BINS + phi_bin
+    }
+
+    /// Integrates `pdf`, a density over solid angle, over every bin.
+    fn integrate(pdf: &dyn Fn(&Vec3) -> f64) -> Vec<f64> {
+        let bin_area = 4.0 * PI / (COS_BINS * PHI_BINS) as f64;
+        let cell_area = bin_area / (SUBDIVISIONS * SUBDIVISIONS) as f64;
+        let mut integrals = vec![0.0; COS_BINS * PHI_BINS];
+
+        for cos_bin in 0..COS_BINS {
+            for phi_bin in 0..PHI_BINS {
+                let mut sum = 0.0;
+                for i in 0..SUBDIVISIONS {
+                    for j in 0..SUBDIVISIONS {
+                        let u = (cos_bin as f64 + (i as f64 + 0.5) / SUBDIVISIONS as f64)
+                            / COS_BINS as f64;
+                        let v = (phi_bin as f64 + (j as f64 + 0.5) / SUBDIVISIONS as f64)
+                            / PHI_BINS as f64;
+                        sum += pdf(&direction(2.0 * u - 1.0, 2.0 * PI * v));
+                    }
+                }
+                integrals[cos_bin * PHI_BINS + phi_bin] = sum * cell_area;
+            }
+        }
+
+        integrals
+    }
+
+    fn hit(material: &Material) -> HitRecord<'_> {
+        let mut rec = HitRecord::new(Point3::zeros(), normal(), material, 1.0);
+        rec.set_face_normal(&incoming(), &normal());
+        rec
+    }
+
+    /// Checks with Pearson's chi-squared test that the directions `material` scatters
+    /// `incoming()` into follow `pdf`, and that it absorbs the ray with probability `absorbed`,
+    /// which is tested as one more bin.
+    fn assert_follows_pdf(material: &Material, pdf: &dyn Fn(&Vec3) -> f64, absorbed: f64) {
+        assert_scatter_follows_pdf(
+            material,
+            &|rec| material.scatter(&incoming(), rec),
+            pdf,
+            absorbed,
+        );
+    }
+
+    /// Like [`assert_follows_pdf`], for the directions `scatter` samples.
+    fn assert_scatter_follows_pdf(
+        material: &Material,
+        scatter: &dyn Fn(&HitRecord) -> Scatter,
+        pdf: &dyn Fn(&Vec3) -> f64,
+        absorbed: f64,
+    ) {
+        let rec = hit(material);
+
+        let mut observed = vec![0.0; COS_BINS * PHI_BINS + 1];
+        for _ in 0..SAMPLES {
+            match scatter(&rec) {
+                Some((scattered, _)) => observed[bin(scattered.direction())] += 1.0,
+                None => observed[COS_BINS * PHI_BINS] += 1.0,
+            }
+        }
+
+        let mut expected: Vec<f64> = integrate(pdf)
+            .into_iter()
+            .map(|p| p * SAMPLES as f64)
+            .collect();
+        expected.push(absorbed * SAMPLES as f64);
+
+        let (mut statistic, mut bins) = (0.0, 0);
+        let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+        for (observed, expected) in observed.iter().zip(&expected) {
+            if *expected < MIN_EXPECTED {
+                pooled_observed += observed;
+                pooled_expected += expected;
+            } else {
+                statistic += (observed - expected).powi(2) / expected;
+                bins += 1;
+            }
+        }
+        if pooled_expected >= MIN_EXPECTED {
+            statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            bins += 1;
+        } else {
+            assert!(
+                pooled_observed < 5.0 * MIN_EXPECTED,
+                "{} samples landed where the PDF is {} samples",
+                pooled_observed,
+                pooled_expected
+            );
+        }
+
+        // Wilson–Hilferty: the cube root of chi-squared over its degrees of freedom is close
+        // to normal. 3.7 standard deviations is a significance level of about 1e-4.
+        let dof = (bins - 1) as f64;
+        let z = ((statistic / dof).cbrt() - (1.0 - 2.0 / (9.0 * dof))) / (2.0 / (9.0 * dof)).sqrt();
+        assert!(
+            z < 3.7,
+            "chi-squared {:.1} over {} degrees of freedom (z = {:.2}) for {:?}",
+            statistic,
+            dof,
+            z,
+            material
+        );
+    }
+
+    #[test]
+    fn lambertian_is_cosine_weighted() {
+        let material = Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+
+        assert_follows_pdf(&material, &|d| (d.dot(&normal()) / PI).max(0.0), 0.0);
+    }
+
+    /// The density of `reflected + fuzz * random_in_unit_sphere()` over directions, absorbed
+    /// below the surface: the volume of the fuzz ball a direction passes through is
+    /// `∫ t² dt` over where its ray is inside the ball.
+    fn fuzzy_reflection_pdf(fuzz: f64) -> impl Fn(&Vec3) -> f64 {
+        let reflected = reflect(incoming().direction(), &normal());
+
+        move |d: &Vec3| {
+            if d.dot(&normal()) <= 0.0 {
+                return 0.0;
+            }
+
+            let c = d.dot(&reflected);
+            let discriminant = c * c - 1.0 + fuzz * fuzz;
+            if discriminant < 0.0 {
+                return 0.0;
+            }
+
+            let near = (c - discriminant.sqrt()).max(0.0);
+            let far = c + discriminant.sqrt();
+            if far <= 0.0 {
+                return 0.0;
+            }
+
+            let ball = 4.0 / 3.0 * PI * fuzz.powi(3);
+            (far.powi(3) - near.powi(3)) / 3.0 / ball
+        }
+    }
+
+    /// The chance of the fuzz ball pushing the reflection below the surface: the part of the
+    /// ball on the other side of the surface's plane.
+    fn fuzzy_reflection_absorption(fuzz: f64) -> f64 {
+        let height = reflect(incoming().direction(), &normal()).dot(&normal());
+        let cap = (fuzz - height).max(0.0);
+
+        PI * cap * cap * (3.0 * fuzz - cap) / 3.0 / (4.0 / 3.0 * PI * fuzz.powi(3))
+    }
+
+    #[test]
+    fn fuzzy_metal_samples_the_fuzz_ball() {
+        for &fuzz in &[0.3, 0.8, 1.0] {
+            let material = Material::Metal(Metal::new(Color::new(0.5, 0.5, 0.5), fuzz));
+
+            assert_follows_pdf(
+                &material,
+                &fuzzy_reflection_pdf(fuzz),
+                fuzzy_reflection_absorption(fuzz),
+            );
+        }
+    }
+
+    fn cosine_pdf(d: &Vec3) -> f64 {
+        (d.dot(&normal()) / PI).max(0.0)
+    }
+
+    #[test]
+    fn previews_are_cosine_weighted() {
+        for preview in [Preview::Normals, Preview::Checker] {
+            let material = Material::Preview(preview);
+            let (_, albedo) = material.scatter(&incoming(), &hit(&material)).unwrap();
+
+            assert_follows_pdf(&material, &cosine_pdf, 0.0);
+            assert!(albedo.max() <= 1.0, "{} reflects {:?}", preview, albedo);
+        }
+    }
+
+    #[test]
+    fn rough_glass_samples_its_regularized_pdf() {
+        // Rough enough to spread over many bins, but not so rough that the refraction, 28°
+        // from straight down, wraps around the pole, where the bins are too thin to integrate.
+        const ROUGHNESS: f64 = 0.3;
+
+        for fresnel in [Fresnel::Schlick, Fresnel::Exact] {
+            let material = Material::Dielectric(Dielectric::new(1.5, fresnel));
+            let rec = hit(&material);
+            let pdf = |d: &Vec3| material.regularized_pdf(&incoming(), &rec, ROUGHNESS, d);
+
+            // Both the reflection and the refraction are further from the surface than the
+            // fuzz reaches, so none is pushed through to the wrong side and absorbed.
+            assert_scatter_follows_pdf(
+                &material,
+                &|rec| material.scatter_regularized(&incoming(), rec, ROUGHNESS),
+                &pdf,
+                0.0,
+            );
+        }
+    }
+
+    /// Checks that smooth glass and calm water reflect `incoming()` as often as the Fresnel
+    /// equations say, refract it otherwise, and keep all of its energy either way.
+    #[test]
+    fn smooth_glass_splits_light_by_its_reflectance() {
+        let materials = [
+            Material::Dielectric(Dielectric::new(1.5, Fresnel::Schlick)),
+            Material::Dielectric(Dielectric::new(1.5, Fresnel::Exact)),
+            Material::Water(Water::new(1.5, Color::zeros(), 0.0, 1.0)),
+        ];
+        for material in &materials {
+            let rec = hit(material);
+            let fresnel = match material {
+                Material::Dielectric(dielectric) => dielectric.fresnel(),
+                _ => Fresnel::Schlick,
+            };
+            let cos_theta = -incoming().direction().dot(&normal());
+            let reflectance = fresnel.reflectance(cos_theta, GLASS);
+            let reflected = reflect(incoming().direction(), &normal());
+
+            let mut reflections = 0;
+            for _ in 0..SAMPLES {
+                let (scattered, attenuation) = material.scatter(&incoming(), &rec).unwrap();
+                assert_eq!(attenuation, Color::new(1.0, 1.0, 1.0));
+                if scattered.direction().dot(&normal()) > 0.0 {
+                    assert!((scattered.direction() - reflected).norm() < 1e-12);
+                    reflections += 1;
+                }
+            }
+
+            // Within 4 standard deviations of the binomial distribution.
+            let expected = reflectance * SAMPLES as f64;
+            let deviation = (expected * (1.0 - reflectance)).sqrt();
+            assert!(
+                (reflections as f64 - expected).abs() < 4.0 * deviation,
+                "{} reflected {} times, expected {:.0}",
+                material,
+                reflections,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn sheen_is_cosine_weighted_and_conserves_energy() {
+        let material = Material::Sheen(Sheen::new(
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(1.0, 1.0, 1.0),
+            0.3,
+        ));
+        assert_follows_pdf(&material, &cosine_pdf, 0.0);
+
+        // White cloth reflects at most all the light, from any angle, and the same both ways.
+        let sheen = match &material {
+            Material::Sheen(sheen) => sheen,
+            _ => unreachable!(),
+        };
+        for i in 1..=10 {
+            let view = direction(i as f64 / 10.0, 0.0);
+            let rec = hit(&material);
+            let reflected: f64 = integrate(&|l| sheen.brdf(&rec, &view, l).x * l.z.max(0.0))
+                .iter()
+                .sum();
+            assert!(reflected <= 1.01, "reflects {} from {:?}", reflected, view);
+
+            let light = direction(0.35, 2.0);
+            let there = sheen.brdf(&rec, &view, &light);
+            let back = sheen.brdf(&rec, &light, &view);
+            assert!((there - back).norm() < 1e-9, "{:?} and {:?}", there, back);
+        }
+    }
+
+    #[test]
+    fn mix_samples_both_materials_by_weight() {
+        // Half and half at the height of the hit.
+        let fuzz = 0.5;
+        let material = Material::Mix(Box::new(Mix {
+            by: Attribute::Height,
+            from: -1.0,
+            to: 1.0,
+            materials: [
+                Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+                Material::Metal(Metal::new(Color::new(0.5, 0.5, 0.5), fuzz)),
+            ],
+        }));
+        let metal = fuzzy_reflection_pdf(fuzz);
+
+        assert_follows_pdf(
+            &material,
+            &|d| (cosine_pdf(d) + metal(d)) / 2.0,
+            fuzzy_reflection_absorption(fuzz) / 2.0,
+        );
+    }
+
+    #[test]
+    fn lights_and_shadow_catchers_scatter_nothing() {
+        let light = Material::DiffuseLight(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let catcher = Material::ShadowCatcher(ShadowCatcher { reflection: 0.5 });
+
+        for material in [&light, &catcher] {
+            assert!(material.scatter(&incoming(), &hit(material)).is_none());
+        }
+        // Lights give out what they emit and nothing else.
+        assert_eq!(light.emitted(), Color::new(4.0, 4.0, 4.0));
+        assert_eq!(catcher.emitted(), Color::zeros());
+    }
+
+    /// The refraction ratio of light going from the air into glass.
+    const GLASS: f64 = 1.0 / 1.5;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "reflectance is {}, expected {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn schlick_matches_its_known_values() {
+        let schlick = Fresnel::Schlick;
+
+        assert_close(schlick.reflectance(1.0, GLASS), 0.04, 1e-12);
+        assert_close(schlick.reflectance(0.5, GLASS), 0.04 + 0.96 / 32.0, 1e-12);
+        assert_close(schlick.reflectance(0.0, GLASS), 1.0, 1e-12);
+    }
+
+    #[test]
+    fn reflectance_stays_between_none_and_all() {
+        for fresnel in [Fresnel::Schlick, Fresnel::Exact] {
+            for refraction_ratio in [GLASS, 1.5, 1.0 / 2.42, 2.42] {
+                for i in 0..=100 {
+                    let reflectance = fresnel.reflectance(i as f64 / 100.0, refraction_ratio);
+                    assert!(
+                        (0.0..=1.0).contains(&reflectance),
+                        "{} reflectance {} at cos {} and ratio {}",
+                        fresnel,
+                        reflectance,
+                        i as f64 / 100.0,
+                        refraction_ratio
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_fresnel_matches_its_known_values() {
+        let exact = Fresnel::Exact;
+
+        // At normal incidence both are `((n - 1) / (n + 1))²`, 4% for glass and 17% for diamond.
+        assert_close(exact.reflectance(1.0, GLASS), 0.04, 1e-12);
+        assert_close(
+            exact.reflectance(1.0, 1.0 / 2.42),
+            (1.42 / 3.42_f64).powi(2),
+            1e-12,
+        );
+
+        // Glass reflects 9.2% of s-polarized light at 45° and 0.85% of p-polarized light.
+        assert_close(exact.reflectance(0.5_f64.sqrt(), GLASS), 0.0502, 1e-4);
+
+        // No p-polarized light is reflected at Brewster's angle, `atan(n)`, which leaves half
+        // of the s-polarized `((n² - 1) / (n² + 1))²`.
+        let brewster = 1.5_f64.atan().cos();
+        assert_close(
+            exact.reflectance(brewster, GLASS),
+            (1.25 / 3.25_f64).powi(2) / 2.0,
+            1e-12,
+        );
+
+        assert_close(exact.reflectance(0.0, GLASS), 1.0, 1e-12);
+    }
+
+    #[test]
+    fn exact_fresnel_reflects_everything_past_the_critical_angle() {
+        let critical = (1.0 / 1.5_f64).asin().cos();
+
+        assert_close(Fresnel::Exact.reflectance(critical - 1e-6, 1.5), 1.0, 1e-12);
+        assert_close(Fresnel::Exact.reflectance(0.5_f64.sqrt(), 1.5), 1.0, 1e-12);
+        assert!(Fresnel::Exact.reflectance(critical + 1e-3, 1.5) < 1.0);
+    }
+
+    #[test]
+    fn exact_fresnel_is_the_same_both_ways() {
+        // Light refracted from the air into glass comes back out along the same path, and the
+        // interface reflects as much of it either way.
+        for i in 0..=20 {
+            let cos_i = i as f64 / 20.0;
+            let sin_t = GLASS * (1.0 - cos_i * cos_i).sqrt();
+            let cos_t = (1.0 - sin_t * sin_t).sqrt();
+
+            assert_close(
+                Fresnel::Exact.reflectance(cos_t, 1.0 / GLASS),
+                Fresnel::Exact.reflectance(cos_i, GLASS),
+                1e-9,
+            );
+        }
+    }
+
+    #[test]
+    fn schlick_stays_close_to_exact_fresnel_for_glass() {
+        for i in 0..=100 {
+            let cos_theta = i as f64 / 100.0;
+
+            assert_close(
+                Fresnel::Schlick.reflectance(cos_theta, GLASS),
+                Fresnel::Exact.reflectance(cos_theta, GLASS),
+                0.05,
+            );
+        }
     }
 }
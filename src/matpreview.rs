@@ -0,0 +1,95 @@
+use argh::FromArgs;
+use color_eyre::eyre::{Result, WrapErr};
+
+use crate::camera::Camera;
+use crate::demo::{camera, light, quad, sphere, Document};
+use crate::material::{Material, Preview};
+use crate::scene_loader::CameraSettings;
+use crate::vec3::{Point3, Vec3};
+
+/// Vertical field of view of the preview, in degrees.
+const VFOV: f64 = 24.0;
+
+/// Render a material on a preview sphere in a fixed studio, to look at it without writing a
+/// scene around it.
+///
+/// The studio is lit by a large key light on the left, a dimmer fill light on the right and a
+/// rim light behind, in a grey surround, over a checkerboard floor that shows off reflections
+/// and refractions. It stays the same from one version to the next so that previews compare.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "matpreview")]
+pub(crate) struct Matpreview {
+    /// YAML file of the material, written as in scene files
+    #[argh(positional)]
+    pub(crate) material: String,
+
+    /// width and height of the image, in place of `-w` and `-a`
+    #[argh(option, default = "256")]
+    pub(crate) size: u32,
+}
+
+impl Matpreview {
+    /// Returns the scene file of the studio with the preview sphere made of the material.
+    pub(crate) fn scene_file(&self) -> Result<String> {
+        let source = std::fs::read_to_string(&self.material)
+            .wrap_err_with(|| format!("reading {}", self.material))?;
+        let material: Material =
+            serde_yaml::from_str(&source).wrap_err_with(|| format!("parsing {}", self.material))?;
+
+        Ok(serde_yaml::to_string(&studio(material))?)
+    }
+
+    /// Returns the camera of the preview, which ignores the camera options of the command line.
+    pub(crate) fn camera(&self, settings: &CameraSettings) -> Camera {
+        Camera::new(
+            *settings.look_from.start(),
+            *settings.look_at.start(),
+            settings.vup,
+            VFOV,
+            1.0,
+            0.0,
+            1.0,
+            settings.time.clone(),
+        )
+    }
+}
+
+fn studio(material: Material) -> Document {
+    let floor = Material::Preview(Preview::Checker);
+
+    Document {
+        camera: camera((0.0, 2.4, 8.0), (0.0, 0.9, 0.0)),
+        background: Some((&crate::Color::new(0.06, 0.06, 0.06)).into()),
+        objects: vec![
+            sphere((0.0, 1.0, 0.0), 1.0, material),
+            quad(
+                Point3::new(-20.0, 0.0, -20.0),
+                Vec3::new(0.0, 0.0, 40.0),
+                Vec3::new(40.0, 0.0, 0.0),
+                floor,
+            ),
+            // Key, fill and rim lights, all out of frame.
+            sphere((-6.0, 7.0, 5.0), 2.0, light(12.0)),
+            sphere((7.0, 3.0, 5.0), 1.5, light(2.5)),
+            sphere((3.0, 6.0, -8.0), 1.5, light(8.0)),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_loader::{load_scene, LoadOptions};
+
+    #[test]
+    fn studio_loads_with_the_material() {
+        let document = studio(Material::Preview(Preview::Normals));
+        let options = LoadOptions {
+            source: Some(serde_yaml::to_string(&document).unwrap()),
+            ..LoadOptions::default()
+        };
+        let scene = load_scene("material.yml", &options).unwrap();
+
+        assert_eq!(scene.lights.len(), 3);
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use tracing::warn;
+
+use crate::info::format_bytes;
+use crate::streamed_mesh::GeometryCache;
+
+/// How much of the budget meshes streamed from disk because they didn't fit keep in memory.
+const STREAMING_SHARE: usize = 8;
+
+/// How much memory a scene may take once loaded, for render nodes that kill what goes over.
+///
+/// Loading counts what each texture, mesh and point cloud takes against the budget. Those that
+/// would go over it are made smaller instead, with a warning: textures are downscaled, large
+/// meshes are streamed from disk, and point clouds are thinned out. What still goes over it is
+/// loaded anyway, with a warning, since nothing else can give way.
+pub(crate) struct MemoryBudget {
+    limit: usize,
+    used: AtomicUsize,
+    /// Shared by the meshes streamed to fit, created with the first of them.
+    streaming: OnceLock<Arc<GeometryCache>>,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+            streaming: OnceLock::new(),
+        }
+    }
+
+    /// Returns whether `bytes` more still fit within the budget.
+    pub(crate) fn fits(&self, bytes: usize) -> bool {
+        bytes <= self.remaining()
+    }
+
+    /// Returns how many bytes are left.
+    pub(crate) fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.used.load(Ordering::Relaxed))
+    }
+
+    /// Counts `bytes` taken by `what` against the budget, warning if they go over it.
+    pub(crate) fn take(&self, bytes: usize, what: &str) {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if used > self.limit && used - bytes <= self.limit {
+            warn!(
+                "{} goes over the memory budget of {}, with {} loaded so far",
+                what,
+                format_bytes(self.limit),
+                format_bytes(used)
+            );
+        }
+    }
+
+    /// Returns the cache that meshes too large for the budget are streamed through, taking its
+    /// share of the budget the first time.
+    pub(crate) fn streaming(&self) -> Arc<GeometryCache> {
+        self.streaming
+            .get_or_init(|| {
+                let capacity = self.limit / STREAMING_SHARE;
+                self.take(capacity, "streaming meshes from disk");
+                Arc::new(GeometryCache::new(capacity))
+            })
+            .clone()
+    }
+
+    /// Returns how many bytes were taken.
+    pub(crate) fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_what_loads_against_the_limit() {
+        let budget = MemoryBudget::new(1000);
+        assert!(budget.fits(1000));
+
+        budget.take(600, "a texture");
+        assert_eq!(budget.remaining(), 400);
+        assert!(!budget.fits(500));
+
+        // Going over leaves nothing, rather than wrapping around.
+        budget.take(600, "a mesh");
+        assert_eq!(budget.remaining(), 0);
+        assert_eq!(budget.used(), 1200);
+    }
+}
@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::attributes::VertexAttributes;
+use crate::bvh::Bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::INFINITY;
+use crate::scene_loader::StartEndPair;
+use crate::vec3::{is_finite, unit_vector, Point3, Vec3};
+
+/// Determinants smaller than this mean the ray runs parallel to the triangle.
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// What rays see when they hit the back of a face, the side from which its vertices go around
+/// clockwise.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Backfaces {
+    /// The face, shaded with its normal flipped toward the ray, as if it had two fronts.
+    #[default]
+    Flip,
+    /// The face, shaded with the normal of its front, so that it shows the light falling on
+    /// the front as thin leaves do.
+    Keep,
+    /// Nothing: rays go through, which single-sided meshes like open boxes need to be seen
+    /// into, and which skips work on closed meshes.
+    Cull,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct MeshData {
+    /// One set of vertex positions per frame, all with the same topology.
+    frames: Vec<Vec<Point3>>,
+    /// The shading normal of every vertex in each frame, or nothing for a faceted mesh shaded
+    /// with the normals of its faces.
+    #[serde(default)]
+    normals: Vec<Vec<Vec3>>,
+    time: StartEndPair<f64>,
+    material: Material,
+    #[serde(default)]
+    backfaces: Backfaces,
+    /// Measured on the first frame once the mesh is built, if the material needs them.
+    #[serde(skip)]
+    attributes: OnceLock<VertexAttributes>,
+}
+
+impl MeshData {
+    /// Returns the position of vertex `index` at `time`, blending the two nearest frames.
+    fn vertex(&self, index: usize, time: f64) -> Point3 {
+        if self.frames.len() == 1 {
+            return self.frames[0][index];
+        }
+
+        let duration = self.time.end() - self.time.start();
+        let f = if duration > 0.0 {
+            ((time - self.time.start()) / duration).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let position = f * (self.frames.len() - 1) as f64;
+        let frame = (position.floor() as usize).min(self.frames.len() - 2);
+
+        self.frames[frame][index].lerp(&self.frames[frame + 1][index], position - frame as f64)
+    }
+
+    /// Returns the shading normal at `u` and `v` across the face with vertices `indices` at
+    /// `time`, blending the two nearest frames as [`MeshData::vertex`] does, if the mesh has
+    /// them.
+    fn normal(&self, indices: [usize; 3], u: f64, v: f64, time: f64) -> Option<Vec3> {
+        let at = |frame: &[Vec3]| {
+            let [a, b, c] = indices;
+            frame[a] * (1.0 - u - v) + frame[b] * u + frame[c] * v
+        };
+        let normal = match self.normals.len() {
+            0 => return None,
+            1 => at(&self.normals[0]),
+            count => {
+                let duration = self.time.end() - self.time.start();
+                let f = if duration > 0.0 {
+                    ((time - self.time.start()) / duration).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let position = f * (count - 1) as f64;
+                let frame = (position.floor() as usize).min(count - 2);
+                at(&self.normals[frame])
+                    .lerp(&at(&self.normals[frame + 1]), position - frame as f64)
+            }
+        };
+
+        // Opposite normals on either side of a vertex cancel out, leaving the face's own.
+        (normal.norm_squared() > 0.0).then(|| unit_vector(&normal))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Triangle {
+    mesh: Arc<MeshData>,
+    indices: [usize; 3],
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let [a, b, c] = self.indices;
+        let v0 = self.mesh.vertex(a, r.time());
+        let v1 = self.mesh.vertex(b, r.time());
+        let v2 = self.mesh.vertex(c, r.time());
+
+        // Möller–Trumbore
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+        let p_vec = r.direction().cross(&edge2);
+        let determinant = edge1.dot(&p_vec);
+
+        // The determinant is positive when the ray comes at the front of the face.
+        let culled = self.mesh.backfaces == Backfaces::Cull && determinant < 0.0;
+        if culled || determinant.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let inv_determinant = 1.0 / determinant;
+        let t_vec = r.origin() - v0;
+        let u = t_vec.dot(&p_vec) * inv_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q_vec = t_vec.cross(&edge1);
+        let v = r.direction().dot(&q_vec) * inv_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q_vec) * inv_determinant;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let outward_normal = unit_vector(&edge1.cross(&edge2));
+        let mut rec = HitRecord::new(r.at(t), outward_normal, &self.mesh.material, t);
+        // Which side the ray is on is up to the face itself, even where it is shaded smooth.
+        rec.set_face_normal(r, &outward_normal);
+        let shading_normal = self
+            .mesh
+            .normal(self.indices, u, v, r.time())
+            .unwrap_or(outward_normal);
+        if self.mesh.backfaces == Backfaces::Keep || *rec.front_face() {
+            rec.set_normal(shading_normal);
+        } else {
+            rec.set_normal(-shading_normal);
+        }
+        if let Some(attributes) = self.mesh.attributes.get() {
+            let (occlusion, curvature) = attributes.at(self.indices, u, v);
+            rec.set_attributes(occlusion, curvature);
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // Vertices move linearly between frames, so the frames themselves bound the motion.
+        Aabb::from_points(
+            self.mesh
+                .frames
+                .iter()
+                .flat_map(|frame| self.indices.iter().map(move |&i| frame[i])),
+        )
+    }
+}
+
+/// A triangle mesh, optionally deforming over time through several sets of vertex positions.
+///
+/// Frames are spread evenly over `time`, and vertices are interpolated linearly between
+/// consecutive frames, so fast-moving parts of an animated mesh blur along their own path.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "MeshRepr", into = "MeshRepr")]
+pub(crate) struct Mesh {
+    data: Arc<MeshData>,
+    triangles: Bvh<Triangle>,
+}
+
+/// How a [`Mesh`] is serialized: the vertices once, and faces in the order the tree holds them.
+#[derive(Deserialize, Serialize)]
+struct MeshRepr {
+    data: MeshData,
+    faces: Bvh<[usize; 3]>,
+}
+
+impl From<Mesh> for MeshRepr {
+    fn from(mesh: Mesh) -> Self {
+        Self {
+            faces: mesh.triangles.map(|triangle| triangle.indices),
+            data: (*mesh.data).clone(),
+        }
+    }
+}
+
+impl From<MeshRepr> for Mesh {
+    fn from(repr: MeshRepr) -> Self {
+        let data = Arc::new(repr.data);
+        let triangles = repr.faces.map(|&indices| Triangle {
+            mesh: data.clone(),
+            indices,
+        });
+
+        Self { data, triangles }.measured()
+    }
+}
+
+impl Mesh {
+    /// Builds a mesh from `frames` of vertex positions, which must pass [`Mesh::validate`], and
+    /// the `normals` of their vertices for smooth shading, as [`crease_normals`] makes them, or
+    /// none for a faceted mesh.
+    pub(crate) fn new(
+        frames: Vec<Vec<Point3>>,
+        normals: Vec<Vec<Vec3>>,
+        faces: &[[usize; 3]],
+        time: StartEndPair<f64>,
+        material: Material,
+        backfaces: Backfaces,
+    ) -> Result<Self, String> {
+        Self::validate(&frames, faces)?;
+        if !normals.is_empty()
+            && (normals.len() != frames.len()
+                || normals.iter().any(|normal| normal.len() != frames[0].len()))
+        {
+            return Err("a smooth mesh needs a normal for every vertex of every frame".to_string());
+        }
+
+        let data = Arc::new(MeshData {
+            frames,
+            normals,
+            time,
+            material,
+            backfaces,
+            attributes: OnceLock::new(),
+        });
+        let triangles = faces
+            .iter()
+            .map(|&indices| Triangle {
+                mesh: data.clone(),
+                indices,
+            })
+            .collect();
+
+        Ok(Self {
+            data,
+            triangles: Bvh::new(triangles, 0.0, 1.0),
+        }
+        .measured())
+    }
+
+    /// Returns the same mesh made of `material`, with its hierarchy as it is.
+    pub(crate) fn with_material(&self, material: Material) -> Self {
+        let data = Arc::new(MeshData {
+            material,
+            attributes: OnceLock::new(),
+            ..(*self.data).clone()
+        });
+        let triangles = self.triangles.map(|triangle| Triangle {
+            mesh: data.clone(),
+            indices: triangle.indices,
+        });
+
+        Self { data, triangles }.measured()
+    }
+
+    /// Measures the vertex attributes of the mesh if its material mixes by them.
+    fn measured(self) -> Self {
+        if !self.data.material.needs_vertex_attributes() {
+            return self;
+        }
+
+        let t_min = self
+            .bounding_box(0.0, 1.0)
+            .map_or(0.0, |bbox| (bbox.max() - bbox.min()).norm() * 1e-6);
+        let faces: Vec<_> = self
+            .triangles
+            .iter()
+            .map(|triangle| triangle.indices)
+            .collect();
+        let attributes = VertexAttributes::new(&self.data.frames[0], &faces, |ray| {
+            self.triangles
+                .occluder(ray, t_min, INFINITY, None)
+                .is_some()
+        });
+        let _ = self.data.attributes.set(attributes);
+
+        self
+    }
+
+    /// Checks that there is at least one frame, that every frame has as many vertices as the
+    /// first one, and that every face index is in range.
+    pub(crate) fn validate(frames: &[Vec<Point3>], faces: &[[usize; 3]]) -> Result<(), String> {
+        let vertex_count = match frames.first() {
+            Some(frame) => frame.len(),
+            None => return Err("a mesh needs at least one set of vertices".to_string()),
+        };
+
+        if let Some(frame) = frames.iter().position(|frame| frame.len() != vertex_count) {
+            return Err(format!(
+                "frame {} has {} vertices, but frame 0 has {}",
+                frame,
+                frames[frame].len(),
+                vertex_count
+            ));
+        }
+
+        if let Some(face) = faces.iter().flatten().find(|&&i| i >= vertex_count) {
+            return Err(format!(
+                "face index {} is out of range for {} vertices",
+                face, vertex_count
+            ));
+        }
+
+        for (frame, vertices) in frames.iter().enumerate() {
+            if let Some(vertex) = vertices.iter().position(|p| !is_finite(p)) {
+                return Err(format!(
+                    "vertex {} of frame {} has an infinite or NaN coordinate",
+                    vertex, frame
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the face with vertices `indices` has no area in any of the `frames`,
+    /// such as faces that repeat a vertex, so that no ray can ever hit it.
+    pub(crate) fn is_degenerate(frames: &[Vec<Point3>], indices: [usize; 3]) -> bool {
+        let [a, b, c] = indices;
+
+        frames.iter().all(|vertices| {
+            let normal = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+            normal.norm_squared() == 0.0
+        })
+    }
+}
+
+impl Mesh {
+    pub(crate) fn triangle_count(&self) -> usize {
+        self.triangles.iter().count()
+    }
+
+    /// Returns the bytes taken by the vertices, their normals and the hierarchy of triangles.
+    pub(crate) fn memory(&self) -> usize {
+        let vertices: usize = self.data.frames.iter().map(Vec::capacity).sum();
+        let normals: usize = self.data.normals.iter().map(Vec::capacity).sum();
+
+        (vertices + normals) * std::mem::size_of::<Point3>() + self.triangles.memory()
+    }
+
+    /// Returns about how many bytes [`Mesh::memory`] comes to for a mesh of `vertices`, counting
+    /// those of every frame and their normals, and `faces`, to tell before building it.
+    pub(crate) fn memory_for(vertices: usize, faces: usize) -> usize {
+        vertices * std::mem::size_of::<Point3>() + Bvh::<Triangle>::memory_for(faces)
+    }
+
+    /// Returns the number of nodes in the hierarchy of triangles.
+    pub(crate) fn node_count(&self) -> usize {
+        self.triangles.node_count()
+    }
+}
+
+/// The vertex positions of every frame of a mesh, their normals, and its faces.
+pub(crate) type SmoothGeometry = (Vec<Vec<Point3>>, Vec<Vec<Vec3>>, Vec<[usize; 3]>);
+
+/// Gives the mesh with `frames` and `faces` normals for smooth shading, smoothed across edges
+/// where faces meet at less than `crease_angle` degrees and faceted along sharper ones. Where
+/// vertices are on creases, the faces on either side need normals of their own, so those
+/// vertices are split in as many as there are sides. Returns the new frames, their normals, and
+/// the faces with the split vertices.
+///
+/// Which faces a corner is smoothed with is decided on the first frame, so that every frame
+/// keeps the same topology.
+pub(crate) fn crease_normals(
+    frames: Vec<Vec<Point3>>,
+    faces: &[[usize; 3]],
+    crease_angle: f64,
+) -> SmoothGeometry {
+    // Weighted by area, so that thin slivers along the edges of a face barely bend its normal.
+    let face_normal = |vertices: &[Point3], [a, b, c]: [usize; 3]| {
+        (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]))
+    };
+    let first: Vec<_> = faces
+        .iter()
+        .map(|&face| unit_vector(&face_normal(&frames[0], face)))
+        .collect();
+
+    let mut incident = vec![vec![]; frames[0].len()];
+    for (f, face) in faces.iter().enumerate() {
+        for &vertex in face {
+            incident[vertex].push(f);
+        }
+    }
+
+    // Each corner is smoothed with the faces around its vertex close enough to its own, and
+    // corners smoothed with the same faces share a vertex.
+    let min_cos = crease_angle.to_radians().cos();
+    let mut split = HashMap::new();
+    let mut groups: Vec<(usize, Vec<usize>)> = vec![];
+    let split_faces = faces
+        .iter()
+        .enumerate()
+        .map(|(f, face)| {
+            face.map(|vertex| {
+                let group: Vec<_> = incident[vertex]
+                    .iter()
+                    .copied()
+                    .filter(|&g| first[g].dot(&first[f]) >= min_cos)
+                    .collect();
+                *split.entry((vertex, group.clone())).or_insert_with(|| {
+                    groups.push((vertex, group));
+                    groups.len() - 1
+                })
+            })
+        })
+        .collect();
+
+    let normals = frames
+        .iter()
+        .map(|vertices| {
+            groups
+                .iter()
+                .map(|(_, group)| {
+                    let sum: Vec3 = group.iter().map(|&g| face_normal(vertices, faces[g])).sum();
+                    // Left at zero if the faces cancel out, for the face's own normal to show.
+                    if sum.norm_squared() > 0.0 {
+                        unit_vector(&sum)
+                    } else {
+                        sum
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let frames = frames
+        .iter()
+        .map(|vertices| groups.iter().map(|&(vertex, _)| vertices[vertex]).collect())
+        .collect();
+
+    (frames, normals, split_faces)
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.triangles.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.triangles.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::vec3::Vec3;
+
+    /// A triangle in the plane `z = -2`, going around counterclockwise seen from +Z so that
+    /// its front faces the origin.
+    fn triangle(backfaces: Backfaces) -> Mesh {
+        let vertices = vec![
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+        ];
+
+        Mesh::new(
+            vec![vertices],
+            vec![],
+            &[[0, 1, 2]],
+            StartEndPair::new(0.0, 1.0),
+            material(),
+            backfaces,
+        )
+        .unwrap()
+    }
+
+    fn front_ray() -> Ray {
+        Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0))
+    }
+
+    fn back_ray() -> Ray {
+        Ray::new(
+            Point3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Some(0.0),
+        )
+    }
+
+    #[test]
+    fn hits_the_front() {
+        let r = front_ray();
+
+        for backfaces in [Backfaces::Flip, Backfaces::Keep, Backfaces::Cull] {
+            let mesh = triangle(backfaces);
+            assert_hit(
+                mesh.hit(&r, 0.001, INFINITY),
+                &r,
+                2.0,
+                Vec3::new(0.0, 0.0, 1.0),
+                true,
+            );
+        }
+    }
+
+    #[test]
+    fn hits_the_back_as_its_backfaces_say() {
+        let r = back_ray();
+
+        let flipped = triangle(Backfaces::Flip);
+        assert_hit(
+            flipped.hit(&r, 0.001, INFINITY),
+            &r,
+            2.0,
+            Vec3::new(0.0, 0.0, -1.0),
+            false,
+        );
+
+        let kept = triangle(Backfaces::Keep);
+        assert_hit(
+            kept.hit(&r, 0.001, INFINITY),
+            &r,
+            2.0,
+            Vec3::new(0.0, 0.0, 1.0),
+            false,
+        );
+
+        assert!(triangle(Backfaces::Cull).hit(&r, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn hits_inside_the_edges_only() {
+        let mesh = triangle(Backfaces::Flip);
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let at = |x, y| Ray::new(Point3::new(x, y, 0.0), direction, Some(0.0));
+
+        let corner = at(-0.999, -0.999);
+        assert_hit(
+            mesh.hit(&corner, 0.001, INFINITY),
+            &corner,
+            2.0,
+            -direction,
+            true,
+        );
+
+        assert!(mesh.hit(&at(-1.001, -1.0), 0.001, INFINITY).is_none());
+        assert!(mesh.hit(&at(0.0, -1.001), 0.001, INFINITY).is_none());
+        assert!(mesh.hit(&at(0.6, 0.0), 0.001, INFINITY).is_none());
+        assert!(mesh.hit(&at(0.0, 1.001), 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn misses_parallel_rays_and_outside_the_interval() {
+        let mesh = triangle(Backfaces::Flip);
+        let parallel = Ray::new(Point3::new(0.0, -3.0, -2.0), Vec3::new(0.0, 1.0, 0.0), None);
+
+        assert!(mesh.hit(&parallel, 0.001, INFINITY).is_none());
+        assert!(mesh.hit(&front_ray(), 0.001, 1.5).is_none());
+        assert!(mesh.hit(&front_ray(), 2.5, INFINITY).is_none());
+    }
+
+    /// Two faces meeting at a right angle along the X axis, like the ridge of a roof, with
+    /// their fronts up and out.
+    fn roof() -> (Vec<Vec<Point3>>, Vec<[usize; 3]>) {
+        let vertices = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, -1.0, 1.0),
+            Point3::new(0.0, -1.0, -1.0),
+        ];
+
+        (vec![vertices], vec![[0, 2, 1], [0, 1, 3]])
+    }
+
+    #[test]
+    fn crease_normals_split_vertices_along_sharp_edges() {
+        let (frames, faces) = roof();
+
+        let (sharp, normals, split) = crease_normals(frames.clone(), &faces, 30.0);
+        assert_eq!(sharp[0].len(), 6);
+        let sides = [Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, 1.0, -1.0)];
+        for (face, side) in split.iter().zip(sides) {
+            for &corner in face {
+                assert!((normals[0][corner] - unit_vector(&side)).norm() < 1e-9);
+            }
+        }
+
+        let (smooth, normals, split) = crease_normals(frames.clone(), &faces, 100.0);
+        assert_eq!(smooth[0].len(), 4);
+        for (face, original) in split.iter().zip(&faces) {
+            assert_eq!(face.map(|i| smooth[0][i]), original.map(|i| frames[0][i]));
+        }
+        // The ridge leans both ways.
+        let ridge = split[0][0];
+        assert!((normals[0][ridge] - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn smooth_meshes_shade_with_blended_normals() {
+        let (frames, faces) = roof();
+        let (frames, normals, faces) = crease_normals(frames, &faces, 100.0);
+        let mesh = Mesh::new(
+            frames,
+            normals,
+            &faces,
+            StartEndPair::new(0.0, 1.0),
+            material(),
+            Backfaces::Flip,
+        )
+        .unwrap();
+
+        // Straight down onto the ridge, where both faces lean equally.
+        let r = Ray::new(
+            Point3::new(0.5, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Some(0.0),
+        );
+        assert_hit(
+            mesh.hit(&r, 0.001, INFINITY),
+            &r,
+            1.0,
+            Vec3::new(0.0, 1.0, 0.0),
+            true,
+        );
+    }
+
+    #[test]
+    fn faces_without_area_are_degenerate() {
+        let flat = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Point3::new(2.0, 2.0, 2.0),
+        ];
+        let opened = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 2.0, 2.0),
+        ];
+
+        let frames = vec![flat, opened];
+        assert!(Mesh::is_degenerate(&frames[..1], [0, 1, 2]));
+        assert!(Mesh::is_degenerate(&frames[1..], [0, 1, 1]));
+        // Faces that open up during the motion can still be hit.
+        assert!(!Mesh::is_degenerate(&frames, [0, 1, 2]));
+
+        let mut broken = frames[1].clone();
+        broken[2].y = f64::NAN;
+        assert!(Mesh::validate(&[broken], &[[0, 1, 2]]).is_err());
+    }
+}
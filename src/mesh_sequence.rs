@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::ray::Ray;
+
+/// A mesh animated by a file per frame, as simulations write them. Each frame shows from its
+/// own time until the next one starts, as a mesh of its own so that frames may have different
+/// faces. Frames with the same faces as the next may deform into it instead, so that they blur
+/// along their motion.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct MeshSequence {
+    /// The time at which each frame starts showing, in order.
+    starts: Vec<f64>,
+    frames: Vec<Mesh>,
+}
+
+impl MeshSequence {
+    /// Makes a sequence of `frames`, each with the time at which it starts showing, in order.
+    /// The first also shows before it starts, and the last until the end.
+    pub(crate) fn new(frames: Vec<(f64, Mesh)>) -> Self {
+        let (starts, frames) = frames.into_iter().unzip();
+
+        Self { starts, frames }
+    }
+
+    /// Returns the same sequence made of `material`.
+    pub(crate) fn with_material(&self, material: Material) -> Self {
+        Self {
+            starts: self.starts.clone(),
+            frames: self
+                .frames
+                .iter()
+                .map(|frame| frame.with_material(material.clone()))
+                .collect(),
+        }
+    }
+
+    /// Returns the index of the frame showing at `time`.
+    fn frame(&self, time: f64) -> usize {
+        self.starts
+            .partition_point(|&start| start <= time)
+            .saturating_sub(1)
+    }
+
+    pub(crate) fn triangle_count(&self) -> usize {
+        self.frames.iter().map(Mesh::triangle_count).sum()
+    }
+
+    /// Returns the bytes taken by the vertices and the hierarchies of triangles of every frame.
+    pub(crate) fn memory(&self) -> usize {
+        self.frames.iter().map(Mesh::memory).sum()
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.frames.iter().map(Mesh::node_count).sum()
+    }
+}
+
+impl Hittable for MeshSequence {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.frames.get(self.frame(r.time()))?.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let (first, last) = (self.frame(time0), self.frame(time1));
+
+        self.frames
+            .get(first..=last)?
+            .iter()
+            .filter_map(|frame| frame.bounding_box(time0, time1))
+            .reduce(|a, b| surrounding_box(&a, &b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::material;
+    use crate::mesh::Backfaces;
+    use crate::scene_loader::StartEndPair;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn frames_show_from_their_start_until_the_next() {
+        // A triangle facing the origin from `z = -2` that moves by one along X at each frame,
+        // the second frame sliding into the third.
+        let triangle = |x: f64| {
+            vec![
+                Point3::new(x - 1.0, -1.0, -2.0),
+                Point3::new(x + 1.0, -1.0, -2.0),
+                Point3::new(x, 1.0, -2.0),
+            ]
+        };
+        let mesh = |frames: Vec<Vec<Point3>>, start: f64, end: f64| {
+            let time = StartEndPair::new(start, end);
+            Mesh::new(
+                frames,
+                vec![],
+                &[[0, 1, 2]],
+                time,
+                material(),
+                Backfaces::Keep,
+            )
+            .unwrap()
+        };
+        let sequence = MeshSequence::new(vec![
+            (0.0, mesh(vec![triangle(0.0)], 0.0, 1.0)),
+            (1.0, mesh(vec![triangle(1.0), triangle(2.0)], 1.0, 2.0)),
+            (2.0, mesh(vec![triangle(2.0)], 2.0, 3.0)),
+        ]);
+
+        let hits_at = |x: f64, time: f64| {
+            let ray = Ray::new(
+                Point3::new(x, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Some(time),
+            );
+            sequence.hit(&ray, 0.001, f64::INFINITY).is_some()
+        };
+        assert!(hits_at(0.0, -1.0));
+        assert!(hits_at(0.0, 0.5));
+        assert!(!hits_at(0.0, 1.0));
+        assert!(hits_at(1.0, 1.0));
+        assert!(hits_at(1.5, 1.5));
+        assert!(!hits_at(0.5, 1.5));
+        assert!(hits_at(2.0, 4.0));
+
+        let bbox = sequence.bounding_box(0.5, 1.5).unwrap();
+        assert_eq!(bbox.min().x, -1.0);
+        assert_eq!(bbox.max().x, 3.0);
+    }
+}
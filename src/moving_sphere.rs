@@ -1,25 +1,27 @@
-use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
 
+use crate::aabb::{surrounding_box, Aabb};
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::scene_loader::StartEndPair;
-use crate::vec3::{length_squared, Point3};
+use crate::scene_loader::{MovingSphereDef, StartEndPair};
+use crate::vec3::{length_squared, Point3, Vec3};
 
-#[derive(Debug)]
-pub(crate) struct MovingSphere<M: Material + Debug> {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "MovingSphereDef", into = "MovingSphereDef")]
+pub(crate) struct MovingSphere {
     center: StartEndPair<Point3>,
     time: StartEndPair<f64>,
     radius: f64,
-    material: M,
+    material: Material,
 }
 
-impl<M: Material + Debug> MovingSphere<M> {
+impl MovingSphere {
     pub(crate) fn new(
         center: StartEndPair<Point3>,
         time: StartEndPair<f64>,
         radius: f64,
-        material: M,
+        material: Material,
     ) -> Self {
         Self {
             center,
@@ -29,6 +31,26 @@ impl<M: Material + Debug> MovingSphere<M> {
         }
     }
 
+    pub(crate) fn centers(&self) -> &StartEndPair<Point3> {
+        &self.center
+    }
+
+    pub(crate) fn time(&self) -> &StartEndPair<f64> {
+        &self.time
+    }
+
+    pub(crate) fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub(crate) fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub(crate) fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
     fn center(&self, time: f64) -> Point3 {
         self.center.start()
             + ((time - self.time.start()) / (self.time.end() - self.time.start()))
@@ -36,10 +58,8 @@ impl<M: Material + Debug> MovingSphere<M> {
     }
 }
 
-impl<M: Material + Clone + Debug> Hittable for MovingSphere<M> {
-    type Material = M;
-
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<Self::Material>> {
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = r.origin() - self.center(r.time());
         let a = length_squared(r.direction());
         let half_b = oc.dot(r.direction());
@@ -69,12 +89,94 @@ impl<M: Material + Clone + Debug> Hittable for MovingSphere<M> {
         let mut rec = HitRecord::new(
             p,
             (p - self.center(r.time())) / self.radius,
-            self.material.clone(),
+            &self.material,
             t,
         );
         let outward_normal = (rec.p() - self.center(r.time())) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        rec.set_attributes(1.0, 1.0 / self.radius);
 
         Some(rec)
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        // Hollow spheres have a negative radius, but take the same space.
+        let radius = self.radius.abs();
+        let radius = Vec3::new(radius, radius, radius);
+        let box0 = Aabb::new(self.center(time0) - radius, self.center(time0) + radius);
+        let box1 = Aabb::new(self.center(time1) - radius, self.center(time1) + radius);
+
+        Some(surrounding_box(&box0, &box1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::rtweekend::INFINITY;
+
+    /// A sphere moving one unit along X over the shutter interval.
+    fn sphere() -> MovingSphere {
+        MovingSphere::new(
+            StartEndPair::new(Point3::new(0.0, 0.0, -2.0), Point3::new(1.0, 0.0, -2.0)),
+            StartEndPair::new(0.0, 1.0),
+            0.5,
+            material(),
+        )
+    }
+
+    #[test]
+    fn hits_the_sphere_where_it_is_at_the_ray_time() {
+        let sphere = sphere();
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+
+        let start = Ray::new(Point3::zeros(), direction, Some(0.0));
+        assert_hit(
+            sphere.hit(&start, 0.001, INFINITY),
+            &start,
+            1.5,
+            normal,
+            true,
+        );
+
+        let middle = Ray::new(Point3::new(0.5, 0.0, 0.0), direction, Some(0.5));
+        assert_hit(
+            sphere.hit(&middle, 0.001, INFINITY),
+            &middle,
+            1.5,
+            normal,
+            true,
+        );
+
+        let end = Ray::new(Point3::new(1.0, 0.0, 0.0), direction, Some(1.0));
+        assert_hit(sphere.hit(&end, 0.001, INFINITY), &end, 1.5, normal, true);
+    }
+
+    #[test]
+    fn misses_where_the_sphere_has_left() {
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(1.0));
+
+        assert!(sphere().hit(&r, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn normal_follows_the_moving_center() {
+        // At time 0.5 the center is at (0.5, 0, -2), so a ray along X from the middle of its
+        // path leaves it through the side facing +X.
+        let r = Ray::new(
+            Point3::new(0.5, 0.0, -2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Some(0.5),
+        );
+
+        assert_hit(
+            sphere().hit(&r, 0.001, INFINITY),
+            &r,
+            0.5,
+            Vec3::new(-1.0, 0.0, 0.0),
+            false,
+        );
+    }
 }
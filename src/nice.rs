@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::interrupt;
+
+/// How often the load of the rest of the system is measured.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// How many render threads wait for the rest of the system to have cores to spare.
+static PAUSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Renders in the background of a machine someone works on: render threads run at the lowest
+/// priority, one core is left free, and render threads pause for as many cores as the rest of
+/// the system keeps busy.
+///
+/// Render threads run niced, so that the time the rest of the system spends is what `/proc/stat`
+/// counts as user and system time. Systems without it still get the free core, and the priority
+/// on Unix.
+pub(crate) fn enable() {
+    lower_priority();
+    ENABLED.store(true, Ordering::Relaxed);
+
+    spawn(|| {
+        let mut last = match read_busy_time() {
+            Some(busy) => busy,
+            None => return,
+        };
+        loop {
+            sleep(MONITOR_INTERVAL);
+            let busy = match read_busy_time() {
+                Some(busy) => busy,
+                None => return,
+            };
+            let paused = busy_cores(last, busy, num_cpus::get());
+            if PAUSED.swap(paused, Ordering::Relaxed) != paused {
+                debug!(
+                    "pausing {} render threads for the rest of the system",
+                    paused
+                );
+            }
+            last = busy;
+        }
+    });
+}
+
+#[cfg(unix)]
+fn lower_priority() {
+    // Threads spawned from now on inherit the priority of this one.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } != 0 {
+        warn!(
+            "could not lower the priority of the render: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {
+    warn!("render threads keep their priority on this system, only a core is left free");
+}
+
+/// Returns how many threads renders run on: one per core, less one left free with `--nice`.
+pub(crate) fn threads() -> usize {
+    let cores = num_cpus::get();
+    if ENABLED.load(Ordering::Relaxed) {
+        (cores - 1).max(1)
+    } else {
+        cores
+    }
+}
+
+/// Waits while render thread `n` is paused for the rest of the system. The first thread is
+/// never paused, so that renders keep going however busy the system is.
+pub(crate) fn wait_turn(n: usize) {
+    while n > 0 && !interrupt::requested() {
+        let running = threads().saturating_sub(PAUSED.load(Ordering::Relaxed));
+        if n < running {
+            return;
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+/// Returns the time all cores spent running processes that aren't niced, and the time they
+/// spent in total, in clock ticks since boot.
+fn read_busy_time() -> Option<(u64, u64)> {
+    busy_time(&std::fs::read_to_string("/proc/stat").ok()?)
+}
+
+fn busy_time(stat: &str) -> Option<(u64, u64)> {
+    let ticks = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<u64>, _>>()
+        .ok()?;
+    // user, nice, system, idle, iowait, irq, softirq, then steal and guests if any.
+    let busy = ticks.first()? + ticks.get(2)? + ticks.get(5)? + ticks.get(6)?;
+    let total = ticks.iter().take(8).sum();
+
+    Some((busy, total))
+}
+
+/// Returns how many of `cores` were kept busy between two readings of [`busy_time`].
+fn busy_cores(
+    (busy_then, total_then): (u64, u64),
+    (busy, total): (u64, u64),
+    cores: usize,
+) -> usize {
+    let elapsed = total.saturating_sub(total_then);
+    if elapsed == 0 {
+        return 0;
+    }
+    let share = busy.saturating_sub(busy_then) as f64 / elapsed as f64;
+
+    (share * cores as f64).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_cores_busy_outside_of_the_render() {
+        let then = busy_time("cpu  100 5000 50 850 0 0 0 0 0 0\ncpu0 1 2 3 4\n").unwrap();
+        assert_eq!(then, (150, 6000));
+
+        // Over 4 cores and 400 ticks, 100 went to the rest of the system, 300 to the render.
+        let now = busy_time("cpu  180 5300 70 850 0 0 0 0 0 0\n").unwrap();
+        assert_eq!(busy_cores(then, now, 4), 1);
+        assert_eq!(busy_cores(now, now, 4), 0);
+
+        assert!(busy_time("intr 1 2 3\n").is_none());
+    }
+}
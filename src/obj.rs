@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+
+use crate::vec3::Point3;
+
+/// The geometry of a Wavefront OBJ file: vertex positions and triangles indexing into them.
+///
+/// Only `v`, `vt` and `f` statements are read. Polygons are split into triangle fans, and
+/// normals, groups and materials are ignored.
+#[derive(Debug)]
+pub(crate) struct ObjGeometry {
+    pub(crate) vertices: Vec<Point3>,
+    pub(crate) faces: Vec<[usize; 3]>,
+    pub(crate) texture_coordinates: Vec<(f64, f64)>,
+    /// The texture coordinates of each corner of `faces`, if every face has them.
+    pub(crate) texture_faces: Option<Vec<[usize; 3]>>,
+}
+
+pub(crate) fn load_obj(path: &str) -> Result<ObjGeometry> {
+    parse_obj(statements(path)?).wrap_err_with(|| format!("parsing {}", path))
+}
+
+/// Reads the statements of the OBJ file at `path` one line at a time, so that files too large
+/// to hold needn't be read whole.
+fn statements(path: &str) -> Result<Statements<BufReader<File>>> {
+    let file = File::open(path).wrap_err_with(|| format!("reading {}", path))?;
+
+    Ok(Statements::new(BufReader::new(file)))
+}
+
+/// Reads the vertex positions of the OBJ file at `path`, and nothing else.
+pub(crate) fn load_vertices(path: &str) -> Result<Vec<Point3>> {
+    statements(path)?
+        .filter_map(|statement| match statement {
+            Ok(Statement::Vertex(p)) => Some(Ok(p)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<Result<_>>()
+        .wrap_err_with(|| format!("parsing {}", path))
+}
+
+/// Reads the triangles of the OBJ file at `path` one at a time, with polygons split into fans.
+pub(crate) fn triangles(path: &str) -> Result<impl Iterator<Item = Result<[usize; 3]>>> {
+    let path = path.to_string();
+
+    Ok(statements(&path)?.flat_map(move |statement| {
+        let triangles: Vec<_> = match statement {
+            Ok(statement) => statement.triangles().map(Ok).collect(),
+            Err(e) => vec![Err(e.wrap_err(format!("parsing {}", path)))],
+        };
+        triangles
+    }))
+}
+
+/// Counts what an OBJ file holds without parsing its numbers, to tell what reading it would
+/// take: its vertices, and the triangles its faces split into.
+pub(crate) fn count_obj(path: &str) -> Result<(usize, usize)> {
+    let mut reader =
+        BufReader::new(File::open(path).wrap_err_with(|| format!("reading {}", path))?);
+    let (mut vertices, mut triangles) = (0, 0);
+    let mut line = String::new();
+
+    while reader
+        .read_line(&mut line)
+        .wrap_err_with(|| format!("reading {}", path))?
+        > 0
+    {
+        let mut words = line
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        match words.next() {
+            Some("v") => vertices += 1,
+            Some("f") => triangles += words.count().saturating_sub(2),
+            _ => {}
+        }
+        line.clear();
+    }
+
+    Ok((vertices, triangles))
+}
+
+fn parse_obj(statements: impl Iterator<Item = Result<Statement>>) -> Result<ObjGeometry> {
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    let mut texture_coordinates = vec![];
+    let mut texture_faces = vec![];
+
+    for statement in statements {
+        match statement? {
+            Statement::Vertex(p) => vertices.push(p),
+            Statement::TextureCoordinates(u, v) => texture_coordinates.push((u, v)),
+            Statement::Face(polygon, texture_polygon) => {
+                for i in 1..polygon.len() - 1 {
+                    faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                    texture_faces.push(
+                        texture_polygon
+                            .as_ref()
+                            .map(|polygon| [polygon[0], polygon[i], polygon[i + 1]]),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(ObjGeometry {
+        vertices,
+        faces,
+        texture_coordinates,
+        texture_faces: texture_faces.into_iter().collect(),
+    })
+}
+
+/// A statement of an OBJ file that the raytracer reads.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Statement {
+    Vertex(Point3),
+    TextureCoordinates(f64, f64),
+    /// A polygon, by the zero-based indices of its vertices and of their texture coordinates,
+    /// if they all have some.
+    Face(Vec<usize>, Option<Vec<usize>>),
+}
+
+impl Statement {
+    /// Returns the triangles of a face, split into a fan.
+    pub(crate) fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        let polygon = match self {
+            Self::Face(polygon, _) => &polygon[..],
+            _ => &[],
+        };
+
+        (1..polygon.len().saturating_sub(1)).map(move |i| [polygon[0], polygon[i], polygon[i + 1]])
+    }
+}
+
+/// The statements of an OBJ file, read a line at a time, with the indices of faces resolved
+/// against the vertices and texture coordinates before them.
+pub(crate) struct Statements<R> {
+    reader: R,
+    line: String,
+    number: usize,
+    vertices: usize,
+    texture_coordinates: usize,
+}
+
+impl<R: BufRead> Statements<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            number: 0,
+            vertices: 0,
+            texture_coordinates: 0,
+        }
+    }
+
+    /// Parses the current line, if it is a statement that the raytracer reads.
+    fn parse(&mut self) -> Result<Option<Statement>> {
+        let number = self.number;
+        let line = self.line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("v") => {
+                let coordinates = words
+                    .take(3)
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .wrap_err_with(|| format!("line {}", number))?;
+
+                match coordinates[..] {
+                    [x, y, z] => {
+                        self.vertices += 1;
+                        Ok(Some(Statement::Vertex(Point3::new(x, y, z))))
+                    }
+                    _ => bail!("line {}: expected three coordinates", number),
+                }
+            }
+            Some("vt") => {
+                let coordinates = words
+                    .take(2)
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .wrap_err_with(|| format!("line {}", number))?;
+
+                self.texture_coordinates += 1;
+                match coordinates[..] {
+                    // A missing `v` is 0.
+                    [u] => Ok(Some(Statement::TextureCoordinates(u, 0.0))),
+                    [u, v] => Ok(Some(Statement::TextureCoordinates(u, v))),
+                    _ => bail!("line {}: expected texture coordinates", number),
+                }
+            }
+            Some("f") => {
+                let words: Vec<_> = words.collect();
+                let polygon = words
+                    .iter()
+                    .map(|word| vertex_index(word, self.vertices))
+                    .collect::<Result<Vec<_>>>()
+                    .wrap_err_with(|| format!("line {}", number))?;
+                let texture_polygon = words
+                    .iter()
+                    .map(|word| texture_index(word, self.texture_coordinates))
+                    .collect::<Result<Option<Vec<_>>>>()
+                    .wrap_err_with(|| format!("line {}", number))?;
+
+                if polygon.len() < 3 {
+                    bail!("line {}: a face needs at least three vertices", number);
+                }
+
+                Ok(Some(Statement::Face(polygon, texture_polygon)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Statements<R> {
+    type Item = Result<Statement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.number += 1,
+                Err(e) => return Some(Err(e).wrap_err(format!("line {}", self.number + 1))),
+            }
+
+            match self.parse() {
+                Ok(None) => continue,
+                Ok(Some(statement)) => return Some(Ok(statement)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Resolves a face vertex like `3`, `3/1/2` or `-1` to a zero-based vertex index.
+fn vertex_index(word: &str, vertex_count: usize) -> Result<usize> {
+    let position = word.split('/').next().unwrap_or_default();
+
+    resolve_index(position, word, vertex_count)
+}
+
+/// Resolves the texture coordinates of a face vertex like `3/1/2` to a zero-based index, or
+/// nothing for a vertex like `3` or `3//2` that has none. Some exporters refer to texture
+/// coordinates in files where they never wrote any, which only matters when they are needed, so
+/// those have none either.
+fn texture_index(word: &str, texture_coordinate_count: usize) -> Result<Option<usize>> {
+    match word.split('/').nth(1) {
+        Some(index) if !index.is_empty() && texture_coordinate_count > 0 => {
+            Ok(Some(resolve_index(index, word, texture_coordinate_count)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Resolves `index`, one-based or negative to count from the end, out of `word`.
+fn resolve_index(index: &str, word: &str, count: usize) -> Result<usize> {
+    let index: i64 = index
+        .parse()
+        .map_err(|_| eyre!("invalid vertex index `{}`", word))?;
+
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        bail!("vertex index `{}` is out of range", word);
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<ObjGeometry> {
+        parse_obj(Statements::new(source.as_bytes()))
+    }
+
+    #[test]
+    fn texture_indices_fail_like_vertex_indices() {
+        let square = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\n";
+
+        let obj = parse(&format!("{}f 1/1 2/2 3/3\n", square)).unwrap();
+        assert_eq!(obj.texture_faces, Some(vec![[0, 1, 2]]));
+        assert!(parse(&format!("{}f 1/x/1 2/2 3/3\n", square)).is_err());
+        assert!(parse(&format!("{}f 1/4 2/2 3/3\n", square)).is_err());
+
+        // Faces without texture coordinates, or in files that have none, go without.
+        let obj = parse(&format!("{}f 1//1 2 3/3\n", square)).unwrap();
+        assert_eq!(obj.texture_faces, None);
+        let obj = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1 2/2 3/3\n").unwrap();
+        assert_eq!(obj.texture_faces, None);
+    }
+}
@@ -0,0 +1,384 @@
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use color_eyre::eyre::{bail, Result};
+use image::png::PngEncoder;
+use image::{ColorType, ImageFormat};
+
+/// Where rendered frames go.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Output {
+    /// An image file; the format is picked from the extension.
+    File(String),
+    /// A single PNG image on stdout, written as `-`.
+    Stdout,
+    /// A stream of frames on stdout, for piping into `ffmpeg` or `mpv`.
+    Pipe(PipeFormat),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PipeFormat {
+    /// YUV4MPEG2 with 4:4:4 chroma, as understood by `ffmpeg -f yuv4mpegpipe`.
+    Y4m,
+    /// Binary PPM images back to back, as understood by `ffmpeg -f image2pipe`.
+    Ppm,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Self::Stdout);
+        }
+
+        match s.strip_prefix("pipe:") {
+            Some("y4m") => Ok(Self::Pipe(PipeFormat::Y4m)),
+            Some("ppm") => Ok(Self::Pipe(PipeFormat::Ppm)),
+            Some(format) => Err(format!(
+                "unknown pipe format `{}` (expected `pipe:y4m` or `pipe:ppm`)",
+                format
+            )),
+            None => Ok(Self::File(s.to_string())),
+        }
+    }
+}
+
+/// The width of pixels over their height, a positive number like `2` for anamorphic formats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PixelAspect(pub(crate) f64);
+
+impl PixelAspect {
+    /// Returns the ratio as a fraction, to the nearest thousandth.
+    fn fraction(&self) -> (u32, u32) {
+        let (mut a, mut b) = ((self.0 * 1000.0).round() as u32, 1000);
+        let (numerator, denominator) = (a, b);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+
+        (numerator / a, denominator / a)
+    }
+}
+
+impl FromStr for PixelAspect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .ok()
+            .filter(|aspect: &f64| *aspect >= 0.001 && aspect.is_finite())
+            .map(Self)
+            .ok_or_else(|| {
+                format!(
+                    "unknown pixel aspect ratio `{}` (expected a positive number like `2` or `1.33`)",
+                    s
+                )
+            })
+    }
+}
+
+/// Text written into PNG outputs, so that images still say how they were made long after.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Metadata(Vec<(String, String)>);
+
+impl Metadata {
+    /// Adds an entry. PNG keywords are 1 to 79 Latin-1 characters.
+    pub(crate) fn add(&mut self, keyword: &str, text: impl ToString) {
+        self.0.push((keyword.to_string(), text.to_string()));
+    }
+
+    /// Returns `png`, a whole PNG file, with a text chunk for each entry right after its header,
+    /// and a pHYs chunk giving the shape of its pixels unless they are square.
+    fn embed(&self, png: &[u8], pixel_aspect: PixelAspect) -> Vec<u8> {
+        // The signature, then the IHDR chunk: length, type, 13 bytes of data and CRC.
+        let header_end = 8 + 4 + 4 + 13 + 4;
+        let mut embedded = png[..header_end].to_vec();
+
+        if pixel_aspect != PixelAspect(1.0) {
+            // Pixels per unit across and down, with the unit left unknown: fewer of wide pixels
+            // fit across.
+            let (width, height) = pixel_aspect.fraction();
+            let data = [&height.to_be_bytes()[..], &width.to_be_bytes(), &[0]].concat();
+            chunk(&mut embedded, b"pHYs", &data);
+        }
+
+        for (keyword, text) in &self.0 {
+            // tEXt is Latin-1, so anything else goes in an international, UTF-8 iTXt chunk.
+            let (chunk_type, data) = if text.is_ascii() {
+                (
+                    b"tEXt",
+                    [keyword.as_bytes(), b"\0", text.as_bytes()].concat(),
+                )
+            } else {
+                // No compression, and no language or translated keyword.
+                let prefix = [0, 0, 0, 0];
+                (
+                    b"iTXt",
+                    [keyword.as_bytes(), b"\0", &prefix, text.as_bytes()].concat(),
+                )
+            };
+            chunk(&mut embedded, chunk_type, &data);
+        }
+
+        embedded.extend_from_slice(&png[header_end..]);
+        embedded
+    }
+}
+
+/// Appends a PNG chunk of `chunk_type` holding `data` to `png`.
+fn chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+/// Writes RGB8 frames to an [`Output`].
+pub(crate) struct FrameWriter {
+    output: Output,
+    fps: u32,
+    frame_size: Option<(u32, u32)>,
+    metadata: Metadata,
+    pixel_aspect: PixelAspect,
+}
+
+impl FrameWriter {
+    pub(crate) fn new(output: Output, fps: u32) -> Self {
+        Self {
+            output,
+            fps,
+            frame_size: None,
+            metadata: Metadata::default(),
+            pixel_aspect: PixelAspect(1.0),
+        }
+    }
+
+    /// Makes PNG images and `pipe:y4m` streams say that their pixels are `aspect` times as wide
+    /// as they are tall. Other formats have nowhere to say it.
+    pub(crate) fn with_pixel_aspect(self, aspect: f64) -> Self {
+        Self {
+            pixel_aspect: PixelAspect(aspect),
+            ..self
+        }
+    }
+
+    /// Sets what the next PNG images written say about themselves.
+    pub(crate) fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+
+    fn png(&self, image_data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+        let mut png = vec![];
+        PngEncoder::new(&mut png).encode(image_data, width, height, ColorType::Rgb8)?;
+
+        Ok(self.metadata.embed(&png, self.pixel_aspect))
+    }
+
+    /// Writes one frame. For file outputs `suffix`, if any, is appended to the file name so that
+    /// several frames of the same run don't overwrite each other; pipes ignore it.
+    pub(crate) fn write_frame(
+        &mut self,
+        suffix: Option<&str>,
+        image_data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let format = match &self.output {
+            Output::File(path) => {
+                let path = match suffix {
+                    Some(suffix) => suffixed_path(path, suffix),
+                    None => path.clone(),
+                };
+                let is_png = Path::new(&path)
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+
+                if is_png {
+                    let png = self.png(image_data, width, height)?;
+                    write_atomically(Path::new(&path), |partial| {
+                        Ok(std::fs::write(partial, &png)?)
+                    })?;
+                } else {
+                    let format = ImageFormat::from_path(&path)?;
+                    write_atomically(Path::new(&path), |partial| {
+                        Ok(image::save_buffer_with_format(
+                            partial,
+                            image_data,
+                            width,
+                            height,
+                            ColorType::Rgb8,
+                            format,
+                        )?)
+                    })?;
+                }
+
+                return Ok(());
+            }
+            Output::Stdout if self.frame_size.is_some() => {
+                bail!("`-o -` takes a single image, pipe several with `pipe:y4m` or `pipe:ppm`")
+            }
+            Output::Stdout => {
+                self.frame_size = Some((width, height));
+
+                let png = self.png(image_data, width, height)?;
+
+                // Nothing else writes to stdout, so the image comes out whole.
+                let stdout = stdout();
+                let mut out = stdout.lock();
+                out.write_all(&png)?;
+                out.flush()?;
+
+                return Ok(());
+            }
+            Output::Pipe(format) => *format,
+        };
+
+        let first_frame = match self.frame_size {
+            Some(size) if size != (width, height) => bail!(
+                "cannot pipe a {}x{} frame into a {}x{} stream",
+                width,
+                height,
+                size.0,
+                size.1
+            ),
+            Some(_) => false,
+            None => {
+                self.frame_size = Some((width, height));
+                true
+            }
+        };
+
+        let stdout = stdout();
+        let mut out = BufWriter::new(stdout.lock());
+
+        match format {
+            PipeFormat::Y4m => {
+                if first_frame {
+                    let (across, down) = self.pixel_aspect.fraction();
+                    writeln!(
+                        out,
+                        "YUV4MPEG2 W{} H{} F{}:1 Ip A{}:{} C444",
+                        width, height, self.fps, across, down
+                    )?;
+                }
+                writeln!(out, "FRAME")?;
+                out.write_all(&rgb_to_yuv444(image_data))?;
+            }
+            PipeFormat::Ppm => {
+                write!(out, "P6\n{} {}\n255\n", width, height)?;
+                out.write_all(image_data)?;
+            }
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Converts interleaved RGB8 to planar Y'CbCr 4:4:4 (BT.601, limited range).
+fn rgb_to_yuv444(image_data: &[u8]) -> Vec<u8> {
+    let pixels = image_data.len() / 3;
+    let mut planes = vec![0; pixels * 3];
+    let (y_plane, chroma) = planes.split_at_mut(pixels);
+    let (u_plane, v_plane) = chroma.split_at_mut(pixels);
+
+    for (i, rgb) in image_data.chunks(3).enumerate() {
+        let r = rgb[0] as f64 / 255.0;
+        let g = rgb[1] as f64 / 255.0;
+        let b = rgb[2] as f64 / 255.0;
+
+        y_plane[i] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        u_plane[i] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        v_plane[i] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+
+    planes
+}
+
+/// Writes the file at `path` through `write`, which writes it whole to the path it is given,
+/// next to `path`, that then replaces `path` at once. A render interrupted or out of disk space
+/// midway leaves the file as it was rather than cut short, for whatever reads it to mistake for
+/// a finished one.
+pub(crate) fn write_atomically(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let partial = partial_path(path);
+    let written = write(&partial).and_then(|()| {
+        // Make sure it is all on disk before it takes the place of the old file.
+        File::open(&partial)?.sync_all()?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    });
+    if written.is_err() {
+        std::fs::remove_file(&partial).ok();
+    }
+
+    written
+}
+
+/// Returns where [`write_atomically`] writes `path` before it is done: a hidden file next to
+/// it, so that renaming it doesn't move it across file systems, that keeps its extension.
+fn partial_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    path.with_file_name(format!(
+        ".{}.{}.partial{}",
+        file_name,
+        std::process::id(),
+        extension
+    ))
+}
+
+/// Inserts `-{suffix}` between the file stem and the extension of `output`.
+pub(crate) fn suffixed_path(output: &str, suffix: &str) -> String {
+    let path = Path::new(output);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::eyre::eyre;
+
+    #[test]
+    fn failed_writes_leave_the_old_file() {
+        let path = std::env::temp_dir().join(format!("atomic-{}.png", std::process::id()));
+        std::fs::write(&path, b"finished").unwrap();
+
+        let failed = write_atomically(&path, |partial| {
+            std::fs::write(partial, b"half")?;
+            Err(eyre!("out of disk space"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"finished");
+        assert!(!partial_path(&path).exists());
+
+        write_atomically(&path, |partial| Ok(std::fs::write(partial, b"new")?)).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind};
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+
+use crate::vec3::Point3;
+
+/// The geometry of a PLY file: vertex positions and triangles indexing into them.
+///
+/// ASCII and binary files are read, but only the vertices and the `vertex_indices` (or
+/// `vertex_index`) of faces. Polygons are split into triangle fans, and other elements and
+/// list properties are skipped.
+#[derive(Debug)]
+pub(crate) struct PlyGeometry {
+    pub(crate) vertices: Vec<Point3>,
+    pub(crate) faces: Vec<[usize; 3]>,
+    /// The values of the other scalar properties of the vertices, such as their normals or
+    /// colors, by name. Colors (`red`, `green`, `blue` and `alpha`) stored as unsigned integers
+    /// are divided by the largest one, so that they go from 0 to 1 like those stored as floats.
+    pub(crate) properties: BTreeMap<String, Vec<f64>>,
+}
+
+impl PlyGeometry {
+    /// Returns the values of properties `names` of every vertex, if the vertices have all of
+    /// them.
+    pub(crate) fn vectors<const N: usize>(&self, names: [&str; N]) -> Option<Vec<[f64; N]>> {
+        let columns = names
+            .iter()
+            .map(|name| self.properties.get(*name))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(
+            (0..self.vertices.len())
+                .map(|i| std::array::from_fn(|axis| columns[axis][i]))
+                .collect(),
+        )
+    }
+}
+
+/// What the header of a PLY file says of its vertices.
+pub(crate) struct VertexHeader {
+    pub(crate) count: usize,
+    /// The names of their scalar properties, coordinates included.
+    pub(crate) properties: Vec<String>,
+}
+
+impl VertexHeader {
+    /// Returns whether the vertices have all of properties `names`.
+    pub(crate) fn has(&self, names: &[&str]) -> bool {
+        names
+            .iter()
+            .all(|name| self.properties.iter().any(|p| p == name))
+    }
+}
+
+pub(crate) fn load_ply(path: &str) -> Result<PlyGeometry> {
+    load_ply_thinned(path, |_| 1)
+}
+
+/// Reads the PLY file at `path` as [`load_ply`] does, but keeps only one vertex in the number
+/// `stride` returns once given what the header says of them, so that the others are never
+/// held. Faces index into all the vertices, so they are left out of thinned files.
+pub(crate) fn load_ply_thinned(
+    path: &str,
+    stride: impl FnOnce(&VertexHeader) -> usize,
+) -> Result<PlyGeometry> {
+    let file = File::open(path).wrap_err_with(|| format!("reading {}", path))?;
+
+    parse_ply(BufReader::new(file), stride).wrap_err_with(|| format!("parsing {}", path))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => bail!("unknown property type `{}`", name),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Returns the largest value of unsigned integers, that colors stored as them are divided
+    /// by, or 1 for other types.
+    fn color_scale(self) -> f64 {
+        match self {
+            Self::U8 => u8::MAX as f64,
+            Self::U16 => u16::MAX as f64,
+            Self::U32 => u32::MAX as f64,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    /// A list of `1` values, preceded by their count as a `0`.
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Self::Scalar(name, _) | Self::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The values after the header, read in turn.
+struct Body<R> {
+    format: Format,
+    reader: R,
+    /// The line of an ASCII file being read, and how far.
+    line: String,
+    cursor: usize,
+}
+
+impl<R: BufRead> Body<R> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64> {
+        if self.format == Format::Ascii {
+            let word = self.word()?;
+            return word.parse().map_err(|_| eyre!("invalid number `{}`", word));
+        }
+
+        let size = scalar.size();
+        let mut buffer = [0; 8];
+        self.reader
+            .read_exact(&mut buffer[..size])
+            .map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => eyre!("the file ends before its last element"),
+                _ => eyre!(e),
+            })?;
+        if self.format == Format::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        let [a, b, c, d, ..] = buffer;
+
+        Ok(match scalar {
+            Scalar::I8 => a as i8 as f64,
+            Scalar::U8 => a as f64,
+            Scalar::I16 => i16::from_le_bytes([a, b]) as f64,
+            Scalar::U16 => u16::from_le_bytes([a, b]) as f64,
+            Scalar::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    /// Returns the next word of an ASCII file, reading lines as they are needed.
+    fn word(&mut self) -> Result<&str> {
+        loop {
+            match self.line[self.cursor..].find(|c: char| !c.is_ascii_whitespace()) {
+                Some(start) => {
+                    self.cursor += start;
+                    break;
+                }
+                None => {
+                    self.line.clear();
+                    self.cursor = 0;
+                    let read =
+                        self.reader
+                            .read_line(&mut self.line)
+                            .map_err(|e| match e.kind() {
+                                ErrorKind::InvalidData => {
+                                    eyre!("the body of an ASCII file isn't text")
+                                }
+                                _ => eyre!(e),
+                            })?;
+                    if read == 0 {
+                        bail!("the file ends before its last element");
+                    }
+                }
+            }
+        }
+
+        let rest = &self.line[self.cursor..];
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let start = self.cursor;
+        self.cursor += end;
+
+        Ok(&self.line[start..start + end])
+    }
+
+    fn read_index(&mut self, scalar: Scalar) -> Result<usize> {
+        let value = self.read(scalar)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            bail!("invalid index `{}`", value);
+        }
+
+        Ok(value as usize)
+    }
+}
+
+fn parse_ply(
+    mut reader: impl BufRead,
+    stride: impl FnOnce(&VertexHeader) -> usize,
+) -> Result<PlyGeometry> {
+    // The body starts on the line after `end_header`.
+    let mut header = vec![];
+    loop {
+        let mut line = vec![];
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            bail!("the header has no `end_header`");
+        }
+        let line = String::from_utf8(line).map_err(|_| eyre!("the header isn't text"))?;
+        if line.trim() == "end_header" {
+            break;
+        }
+        header.push(line);
+    }
+
+    let mut lines = header.iter().map(|line| line.trim());
+    if lines.next() != Some("ply") {
+        bail!("not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for (number, line) in lines.enumerate() {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let error = || eyre!("header line {}: invalid `{}`", number + 2, line);
+
+        match words[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => bail!("unknown format `{}`", name),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error())?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or_else(error)?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or_else(error)?
+                .properties
+                .push(Property::Scalar(name.to_string(), Scalar::parse(scalar)?)),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error()),
+        }
+    }
+
+    let format = format.ok_or_else(|| eyre!("the header has no format"))?;
+    let mut body = Body {
+        format,
+        reader,
+        line: String::new(),
+        cursor: 0,
+    };
+    let stride = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map(|element| {
+            stride(&VertexHeader {
+                count: element.count,
+                properties: element
+                    .properties
+                    .iter()
+                    .filter(|property| matches!(property, Property::Scalar(..)))
+                    .map(|property| property.name().to_string())
+                    .collect(),
+            })
+        })
+        .unwrap_or(1)
+        .max(1);
+
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    let mut properties: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for element in &elements {
+        let position = |name: &str| {
+            element
+                .properties
+                .iter()
+                .position(|property| property.name() == name)
+        };
+        let coordinates = [position("x"), position("y"), position("z")];
+        let indices = position("vertex_indices").or_else(|| position("vertex_index"));
+        if element.name == "vertex" && coordinates.iter().any(Option::is_none) {
+            bail!("vertices need `x`, `y` and `z`");
+        }
+        if element.name == "face" && indices.is_none() {
+            bail!("faces need `vertex_indices`");
+        }
+
+        for number in 0..element.count {
+            let mut point = [0.0; 3];
+            let mut polygon = vec![];
+            // Values of the vertices left out are read all the same, to get past them.
+            let kept = element.name != "vertex" || number % stride == 0;
+
+            for (index, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(_, scalar) => {
+                        let value = body.read(*scalar)?;
+                        if element.name == "vertex" && kept {
+                            match coordinates.iter().position(|&c| c == Some(index)) {
+                                Some(axis) => point[axis] = value,
+                                None => {
+                                    let name = property.name();
+                                    let scale = match name {
+                                        "red" | "green" | "blue" | "alpha" => scalar.color_scale(),
+                                        _ => 1.0,
+                                    };
+                                    properties
+                                        .entry(name.to_string())
+                                        .or_insert_with(|| {
+                                            Vec::with_capacity(element.count.div_ceil(stride))
+                                        })
+                                        .push(value / scale);
+                                }
+                            }
+                        }
+                    }
+                    Property::List(_, count, item) => {
+                        let count = body.read_index(*count)?;
+                        for _ in 0..count {
+                            let value = body.read_index(*item)?;
+                            if element.name == "face" && indices == Some(index) {
+                                polygon.push(value);
+                            }
+                        }
+                    }
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" if kept => vertices.push(Point3::new(point[0], point[1], point[2])),
+                "face" if stride == 1 => {
+                    if polygon.len() < 3 {
+                        bail!("face {} has fewer than three vertices", faces.len());
+                    }
+                    for i in 1..polygon.len() - 1 {
+                        faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(index) = faces
+        .iter()
+        .flatten()
+        .find(|&&index| index >= vertices.len())
+    {
+        bail!("a face refers to vertex {} of {}", index, vertices.len());
+    }
+
+    Ok(PlyGeometry {
+        vertices,
+        faces,
+        properties,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_ascii_and_binary_files_alike() {
+        let header = |format: &str| {
+            format!(
+                "ply\nformat {} 1.0\ncomment a unit square\nelement vertex 4\n\
+                 property float x\nproperty float y\nproperty float z\nproperty uchar red\n\
+                 element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+                format
+            )
+        };
+        let ascii = header("ascii") + "0 0 0 255\n1 0 0 255\n1 1 0 255\n0 1 0 255\n4 0 1 2 3\n";
+
+        let mut binary = header("binary_big_endian").into_bytes();
+        for [x, y] in [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+            for coordinate in [x, y, 0.0] {
+                binary.extend(coordinate.to_be_bytes());
+            }
+            binary.push(255);
+        }
+        binary.push(4);
+        for index in 0..4i32 {
+            binary.extend(index.to_be_bytes());
+        }
+
+        for bytes in [ascii.as_bytes(), &binary[..]] {
+            let geometry = parse_ply(bytes, |_| 1).unwrap();
+            assert_eq!(geometry.vertices.len(), 4);
+            assert_eq!(geometry.vertices[2], Point3::new(1.0, 1.0, 0.0));
+            assert_eq!(geometry.faces, vec![[0, 1, 2], [0, 2, 3]]);
+            assert_eq!(geometry.properties["red"], vec![1.0; 4]);
+        }
+
+        let truncated = &binary[..binary.len() - 2];
+        assert!(parse_ply(truncated, |_| 1).is_err());
+    }
+
+    #[test]
+    fn thinned_files_keep_one_vertex_in_the_stride() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\nproperty float y\n\
+                   property float z\nproperty float radius\nelement face 1\n\
+                   property list uchar int vertex_indices\nend_header\n\
+                   0 0 0 1\n1 0 0 2\n2 0 0 3\n3 0 0 4\n4 0 0 5\n3 0 1 2\n";
+
+        let geometry = parse_ply(ply.as_bytes(), |header| {
+            assert_eq!(header.count, 5);
+            assert!(header.has(&["x", "y", "z", "radius"]));
+            assert!(!header.has(&["nx"]));
+            2
+        })
+        .unwrap();
+        assert_eq!(
+            geometry.vertices,
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(4.0, 0.0, 0.0)
+            ]
+        );
+        assert_eq!(geometry.properties["radius"], vec![1.0, 3.0, 5.0]);
+        assert!(geometry.faces.is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{is_finite, length_squared, unit_vector, Point3, Vec3};
+
+/// Cosines smaller than this between a ray and a disk mean the ray runs along the disk.
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// What each point of a [`PointCloud`] is drawn as.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Splat {
+    /// A sphere, which looks the same from every side.
+    #[default]
+    Sphere,
+    /// A flat disk facing along the normal of the point, as scanners and surface
+    /// reconstructions give them, which closes up into a surface where points are dense.
+    Disk,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CloudData {
+    centers: Vec<Point3>,
+    radii: Vec<f64>,
+    /// The normal of each point, for disks. Empty for spheres.
+    normals: Vec<Vec3>,
+    /// The material of each point, as an index into `materials`, or empty when every point is
+    /// made of the first one.
+    palette: Vec<u32>,
+    materials: Vec<Material>,
+    splat: Splat,
+}
+
+impl CloudData {
+    fn material(&self, index: usize) -> &Material {
+        match self.palette.get(index) {
+            Some(&entry) => &self.materials[entry as usize],
+            None => &self.materials[0],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CloudPoint {
+    cloud: Arc<CloudData>,
+    index: usize,
+}
+
+impl CloudPoint {
+    fn hit_sphere(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Vec3)> {
+        let center = self.cloud.centers[self.index];
+        let radius = self.cloud.radii[self.index];
+        let oc = r.origin() - center;
+        let a = length_squared(r.direction());
+        let half_b = oc.dot(r.direction());
+        let c = length_squared(&oc) - radius * radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        let mut t = (-half_b - sqrtd) / a;
+        if t < t_min || t_max < t {
+            t = (-half_b + sqrtd) / a;
+            if t < t_min || t_max < t {
+                return None;
+            }
+        }
+
+        Some((t, (r.at(t) - center) / radius))
+    }
+
+    fn hit_disk(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Vec3)> {
+        let center = self.cloud.centers[self.index];
+        let radius = self.cloud.radii[self.index];
+        let normal = self.cloud.normals[self.index];
+
+        let denominator = r.direction().dot(&normal);
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let t = (center - r.origin()).dot(&normal) / denominator;
+        if t < t_min || t_max < t || length_squared(&(r.at(t) - center)) > radius * radius {
+            return None;
+        }
+
+        Some((t, normal))
+    }
+}
+
+impl Hittable for CloudPoint {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, outward_normal) = match self.cloud.splat {
+            Splat::Sphere => self.hit_sphere(r, t_min, t_max)?,
+            Splat::Disk => self.hit_disk(r, t_min, t_max)?,
+        };
+
+        let material = self.cloud.material(self.index);
+        let mut rec = HitRecord::new(r.at(t), outward_normal, material, t);
+        rec.set_face_normal(r, &outward_normal);
+        rec.set_attributes(1.0, 1.0 / self.cloud.radii[self.index]);
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let center = self.cloud.centers[self.index];
+        let radius = self.cloud.radii[self.index];
+        // A disk reaches less far along the axes it leans toward.
+        let extent = match self.cloud.splat {
+            Splat::Sphere => Vec3::new(radius, radius, radius),
+            Splat::Disk => {
+                self.cloud.normals[self.index].map(|n| radius * (1.0 - n * n).max(0.0).sqrt())
+            }
+        };
+
+        Some(Aabb::new(center - extent, center + extent))
+    }
+}
+
+/// Points drawn as tiny spheres or disks, each with a radius and a material of its own, such
+/// as LiDAR scans or the particles of a simulation. The points have a hierarchy of their own,
+/// so that a cloud is a single object of the scene however many points it has.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "PointCloudRepr", into = "PointCloudRepr")]
+pub(crate) struct PointCloud {
+    data: Arc<CloudData>,
+    points: Bvh<CloudPoint>,
+}
+
+/// How a [`PointCloud`] is serialized: the points once, and their indices in the order the tree
+/// holds them.
+#[derive(Deserialize, Serialize)]
+struct PointCloudRepr {
+    data: CloudData,
+    points: Bvh<usize>,
+}
+
+impl From<PointCloud> for PointCloudRepr {
+    fn from(cloud: PointCloud) -> Self {
+        Self {
+            points: cloud.points.map(|point| point.index),
+            data: (*cloud.data).clone(),
+        }
+    }
+}
+
+impl From<PointCloudRepr> for PointCloud {
+    fn from(repr: PointCloudRepr) -> Self {
+        let data = Arc::new(repr.data);
+        let points = repr.points.map(|&index| CloudPoint {
+            cloud: data.clone(),
+            index,
+        });
+
+        Self { data, points }
+    }
+}
+
+impl PointCloud {
+    /// Builds a cloud of points at `centers` with their `radii`, drawn as `splat`. Disks need
+    /// the `normals` of the points. Each point is made of the material at its index in
+    /// `palette` among `materials`, or of the first material if `palette` is empty.
+    pub(crate) fn new(
+        centers: Vec<Point3>,
+        radii: Vec<f64>,
+        normals: Option<Vec<Vec3>>,
+        palette: Vec<u32>,
+        materials: Vec<Material>,
+        splat: Splat,
+    ) -> Result<Self, String> {
+        if radii.len() != centers.len() {
+            return Err(format!(
+                "{} points have {} radii",
+                centers.len(),
+                radii.len()
+            ));
+        }
+        if let Some(point) = centers.iter().position(|p| !is_finite(p)) {
+            return Err(format!("point {} has an infinite or NaN coordinate", point));
+        }
+        if let Some(point) = radii.iter().position(|&r| !(r > 0.0 && r.is_finite())) {
+            return Err(format!("point {} has a radius of {}", point, radii[point]));
+        }
+        if materials.is_empty() || !(palette.is_empty() || palette.len() == centers.len()) {
+            return Err("every point needs a material".to_string());
+        }
+        if palette
+            .iter()
+            .any(|&entry| entry as usize >= materials.len())
+        {
+            return Err("a point refers to a material out of range".to_string());
+        }
+
+        let normals = match (splat, normals) {
+            (Splat::Sphere, _) => vec![],
+            (Splat::Disk, None) => {
+                return Err("disks need the normals `nx`, `ny` and `nz` of the points".to_string())
+            }
+            (Splat::Disk, Some(normals)) => {
+                if let Some(point) = normals
+                    .iter()
+                    .position(|n| !(is_finite(n) && n.norm() > 0.0))
+                {
+                    return Err(format!("point {} has no normal", point));
+                }
+                normals.iter().map(unit_vector).collect()
+            }
+        };
+
+        let data = Arc::new(CloudData {
+            centers,
+            radii,
+            normals,
+            palette,
+            materials,
+            splat,
+        });
+        let points = (0..data.centers.len())
+            .map(|index| CloudPoint {
+                cloud: data.clone(),
+                index,
+            })
+            .collect();
+
+        Ok(Self {
+            data,
+            points: Bvh::new(points, 0.0, 1.0),
+        })
+    }
+
+    /// Returns the same cloud with every point made of `material`, with its hierarchy as it is.
+    pub(crate) fn with_material(&self, material: Material) -> Self {
+        let data = Arc::new(CloudData {
+            palette: vec![],
+            materials: vec![material],
+            ..(*self.data).clone()
+        });
+        let points = self.points.map(|point| CloudPoint {
+            cloud: data.clone(),
+            index: point.index,
+        });
+
+        Self { data, points }
+    }
+
+    pub(crate) fn point_count(&self) -> usize {
+        self.data.centers.len()
+    }
+
+    /// Returns the bytes taken by the points and their hierarchy.
+    pub(crate) fn memory(&self) -> usize {
+        let data = &self.data;
+
+        data.centers.capacity() * std::mem::size_of::<Point3>()
+            + data.radii.capacity() * std::mem::size_of::<f64>()
+            + data.normals.capacity() * std::mem::size_of::<Vec3>()
+            + data.palette.capacity() * std::mem::size_of::<u32>()
+            + data.materials.capacity() * std::mem::size_of::<Material>()
+            + self.points.memory()
+    }
+
+    /// Returns about how many bytes [`PointCloud::memory`] comes to for `points`, with or
+    /// without `normals` and a `palette`, to tell before building the cloud.
+    pub(crate) fn memory_for(points: usize, normals: bool, palette: bool) -> usize {
+        let point = std::mem::size_of::<Point3>()
+            + std::mem::size_of::<f64>()
+            + normals as usize * std::mem::size_of::<Vec3>()
+            + palette as usize * std::mem::size_of::<u32>();
+
+        points * point + Bvh::<CloudPoint>::memory_for(points)
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.points.node_count()
+    }
+}
+
+impl Hittable for PointCloud {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.points.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.points.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::material::Lambertian;
+    use crate::rtweekend::INFINITY;
+    use crate::vec3::Color;
+
+    fn ray() -> Ray {
+        Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0))
+    }
+
+    #[test]
+    fn hits_the_nearest_point() {
+        let centers = vec![Point3::new(0.0, 0.0, -4.0), Point3::new(0.0, 0.0, -2.0)];
+        let materials = vec![
+            material(),
+            Material::Lambertian(Lambertian::new(Color::new(1.0, 0.0, 0.0))),
+        ];
+        let cloud = PointCloud::new(
+            centers,
+            vec![0.5, 0.25],
+            None,
+            vec![0, 1],
+            materials,
+            Splat::Sphere,
+        )
+        .unwrap();
+
+        let rec = cloud.hit(&ray(), 0.001, INFINITY);
+        match rec.as_ref().map(|rec| rec.material()) {
+            Some(Material::Lambertian(lambertian)) => {
+                assert_eq!(*lambertian.albedo(), Color::new(1.0, 0.0, 0.0))
+            }
+            _ => panic!("the red point should be hit"),
+        }
+        assert_hit(rec, &ray(), 1.75, Vec3::new(0.0, 0.0, 1.0), true);
+    }
+
+    #[test]
+    fn disks_face_along_their_normals() {
+        let cloud = |normal: Vec3| {
+            PointCloud::new(
+                vec![Point3::new(0.0, 0.0, -2.0)],
+                vec![0.5],
+                Some(vec![normal]),
+                vec![],
+                vec![material()],
+                Splat::Disk,
+            )
+            .unwrap()
+        };
+
+        let facing = cloud(Vec3::new(0.0, 0.0, 2.0));
+        assert_hit(
+            facing.hit(&ray(), 0.001, INFINITY),
+            &ray(),
+            2.0,
+            Vec3::new(0.0, 0.0, 1.0),
+            true,
+        );
+        let bbox = facing.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bbox.min(), &Point3::new(-0.5, -0.5, -2.0));
+
+        // Seen edge on, a disk has nothing to hit.
+        let edge_on = cloud(Vec3::new(1.0, 0.0, 0.0));
+        assert!(edge_on.hit(&ray(), 0.001, INFINITY).is_none());
+        let beside = Ray::new(Point3::new(0.6, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), None);
+        assert!(facing.hit(&beside, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn rejects_points_it_cannot_draw() {
+        let new = |radius: f64, normals: Option<Vec<Vec3>>, splat| {
+            PointCloud::new(
+                vec![Point3::zeros()],
+                vec![radius],
+                normals,
+                vec![],
+                vec![material()],
+                splat,
+            )
+        };
+
+        assert!(new(0.0, None, Splat::Sphere).is_err());
+        assert!(new(f64::NAN, None, Splat::Sphere).is_err());
+        assert!(new(1.0, None, Splat::Disk).is_err());
+        assert!(new(1.0, Some(vec![Vec3::zeros()]), Splat::Disk).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use image::codecs::hdr::HdrDecoder;
+
+use crate::color::clamp_color;
+use crate::exposure::Zone;
+use crate::light::luminance;
+use crate::vec3::Color;
+
+/// Print what pixels of a rendered image hold: the color written, the linear radiance it stands
+/// for, its alpha and its exposure zone, to tell why a pixel looks as it does.
+///
+/// Images are read as any format `image` reads, such as PNG. Renders are written with a gamma
+/// of 2, so the radiance of their pixels is their value squared, up to white. Radiance `.hdr`
+/// images hold linear radiance as it is, and are shown as the raytracer would write them.
+///
+/// Renders hold only colors, so there are no passes such as depth or normals to print, and
+/// there is no preview window to pick pixels in.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "probe")]
+pub(crate) struct Probe {
+    /// image to read
+    #[argh(positional)]
+    image: String,
+
+    /// pixel to print, as `x,y` counted from the top left corner; can be repeated
+    #[argh(option)]
+    pixel: Vec<PixelPosition>,
+}
+
+/// Where a pixel is, counted from the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PixelPosition {
+    x: u32,
+    y: u32,
+}
+
+impl FromStr for PixelPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid pixel `{}`: {}", s, e))?;
+
+        match coordinates[..] {
+            [x, y] => Ok(Self { x, y }),
+            _ => Err(format!("invalid pixel `{}`: expected `x,y`", s)),
+        }
+    }
+}
+
+/// The pixels of an image, from the top row down.
+enum Pixels {
+    /// Linear radiance, as high dynamic range formats hold it.
+    Linear(Vec<Color>),
+    /// Channels as written, from 0 to 1, with the alpha of images that have one.
+    Written(Vec<[f64; 3]>, Option<Vec<f64>>),
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+/// What a pixel holds.
+#[derive(Debug, PartialEq)]
+struct Sample {
+    /// The 8-bit color as it shows.
+    written: (u8, u8, u8),
+    linear: Color,
+    /// Whether a channel is written as white in an image that only holds what is written, so
+    /// that the radiance behind it may be higher.
+    clipped: bool,
+    alpha: Option<f64>,
+}
+
+impl Image {
+    fn load(path: &str) -> Result<Self> {
+        let is_hdr = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            let file = File::open(path).wrap_err_with(|| format!("reading {}", path))?;
+            let decoder = HdrDecoder::new(BufReader::new(file))
+                .wrap_err_with(|| format!("reading {}", path))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()
+                .wrap_err_with(|| format!("reading {}", path))?
+                .iter()
+                .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect();
+
+            return Ok(Self {
+                width: metadata.width,
+                height: metadata.height,
+                pixels: Pixels::Linear(pixels),
+            });
+        }
+
+        let image = image::open(path).wrap_err_with(|| format!("reading {}", path))?;
+        let has_alpha = image.color().has_alpha();
+        // Reading 16 bits keeps the precision of images that have it.
+        let image = image.to_rgba16();
+        let channel = |c: u16| c as f64 / u16::MAX as f64;
+
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: Pixels::Written(
+                image
+                    .pixels()
+                    .map(|p| [channel(p[0]), channel(p[1]), channel(p[2])])
+                    .collect(),
+                has_alpha.then(|| image.pixels().map(|p| channel(p[3])).collect()),
+            ),
+        })
+    }
+
+    fn sample(&self, position: PixelPosition) -> Result<Sample> {
+        if position.x >= self.width || position.y >= self.height {
+            bail!(
+                "pixel {},{} is outside of the {}x{} image",
+                position.x,
+                position.y,
+                self.width,
+                self.height
+            );
+        }
+        let index = (position.y * self.width + position.x) as usize;
+
+        Ok(match &self.pixels {
+            Pixels::Linear(pixels) => {
+                let linear = pixels[index];
+
+                Sample {
+                    written: clamp_color(&linear, 1),
+                    linear,
+                    clipped: false,
+                    alpha: None,
+                }
+            }
+            Pixels::Written(pixels, alpha) => {
+                let [r, g, b] = pixels[index];
+                let byte = |c: f64| (c * 255.0).round() as u8;
+
+                Sample {
+                    written: (byte(r), byte(g), byte(b)),
+                    // Undo the gamma of 2 that images are written with.
+                    linear: Color::new(r * r, g * g, b * b),
+                    clipped: [r, g, b].contains(&1.0),
+                    alpha: alpha.as_ref().map(|alpha| alpha[index]),
+                }
+            }
+        })
+    }
+}
+
+impl Probe {
+    pub(crate) fn run(&self) -> Result<()> {
+        if self.pixel.is_empty() {
+            bail!("`probe` needs at least one `--pixel x,y`");
+        }
+        let image = Image::load(&self.image)?;
+
+        for (i, &position) in self.pixel.iter().enumerate() {
+            let sample = image.sample(position)?;
+            let (r, g, b) = sample.written;
+            let linear = sample.linear;
+
+            if i > 0 {
+                println!();
+            }
+            println!(
+                "pixel       {},{} of {}x{}",
+                position.x, position.y, image.width, image.height
+            );
+            println!(
+                "written     {} {} {} (#{:02x}{:02x}{:02x})",
+                r, g, b, r, g, b
+            );
+            println!(
+                "linear      {:.4} {:.4} {:.4}{}",
+                linear.x,
+                linear.y,
+                linear.z,
+                if sample.clipped {
+                    ", or more where written as white"
+                } else {
+                    ""
+                }
+            );
+            println!("luminance   {:.4}", luminance(&linear));
+            match sample.alpha {
+                Some(alpha) => println!("alpha       {:.4}", alpha),
+                None => println!("alpha       1 (the image has no alpha)"),
+            }
+            println!("zone        {}", Zone::of(&linear));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_radiance_behind_written_pixels() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: Pixels::Written(vec![[0.5, 0.0, 1.0], [0.0; 3]], Some(vec![1.0, 0.25])),
+        };
+
+        let sample = image.sample("0,0".parse().unwrap()).unwrap();
+        assert_eq!(sample.written, (128, 0, 255));
+        assert_eq!(sample.linear, Color::new(0.25, 0.0, 1.0));
+        assert!(sample.clipped);
+        assert_eq!(
+            image.sample(PixelPosition { x: 1, y: 0 }).unwrap().alpha,
+            Some(0.25)
+        );
+        assert!(image.sample(PixelPosition { x: 0, y: 1 }).is_err());
+
+        // Linear images are shown as they would be written.
+        let hdr = Image {
+            width: 1,
+            height: 1,
+            pixels: Pixels::Linear(vec![Color::new(0.25, 4.0, 0.0)]),
+        };
+        let sample = hdr.sample(PixelPosition { x: 0, y: 0 }).unwrap();
+        assert_eq!(sample.written, (128, 255, 0));
+        // Their radiance is known even where it is written as white.
+        assert!(!sample.clipped);
+
+        assert!("12".parse::<PixelPosition>().is_err());
+        assert!("-1,3".parse::<PixelPosition>().is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt::Write;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread::spawn;
+
+use argh::FromArgs;
+use color_eyre::eyre::{bail, eyre, Result};
+use indicatif::ProgressBar;
+
+use crate::hittable::Hittable;
+use crate::nice;
+use crate::ray::Ray;
+use crate::render::{ray_color, PathSampling, RenderSettings, World};
+use crate::turntable::parse_point;
+use crate::vec3::{random_unit_vector, Color, Point3, Vec3};
+
+/// Number of real spherical harmonics up to band 2.
+const COEFFICIENTS: usize = 9;
+
+/// Sample the light arriving at points of a scene and print it as JSON, for game engines to
+/// light what moves through the scene with.
+///
+/// The radiance arriving from every direction at each probe is projected on the real spherical
+/// harmonics up to band 2, in the axes of the scene (`y` up), as `[r, g, b]` for each of the
+/// 9 coefficients in the usual order (`l = 0`, then `m = -1, 0, 1` for `l = 1`, and
+/// `m = -2..2` for `l = 2`). Scale the bands by pi, 2 pi / 3 and pi / 4 for irradiance.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "probes")]
+pub(crate) struct Probes {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// position of a probe, as `x,y,z` (can be repeated)
+    #[argh(option, from_str_fn(parse_point))]
+    pub(crate) at: Vec<Point3>,
+
+    /// number of rays traced from each probe
+    #[argh(option, default = "10_000")]
+    pub(crate) samples: usize,
+}
+
+impl Probes {
+    /// Samples each probe in `world` at `time`, and returns their coefficients in the order
+    /// given.
+    pub(crate) fn sample<H>(
+        &self,
+        world: &Arc<World<H>>,
+        settings: RenderSettings,
+        time: f64,
+        pb: &ProgressBar,
+    ) -> Result<Vec<[Color; COEFFICIENTS]>>
+    where
+        H: Hittable + Send + Sync + 'static,
+    {
+        if self.at.is_empty() {
+            bail!("no probes given, place them with `--at x,y,z`");
+        }
+
+        let positions = Arc::new(self.at.clone());
+        let num_cpus = nice::threads();
+        let samples = self.samples;
+        let (sender, receiver) = channel();
+        let workers: Vec<_> = (0..num_cpus)
+            .map(|n| {
+                let world = world.clone();
+                let positions = positions.clone();
+                let sender = sender.clone();
+                spawn(move || {
+                    let occlusion = world.occlusion_cache(&settings);
+                    let sampling = PathSampling {
+                        regularization: settings.regularization,
+                        lights: settings.light_sampling,
+                        guide: None,
+                        training: false,
+                        occlusion: occlusion.as_ref(),
+                    };
+
+                    // Workers share the samples of every probe.
+                    let share = samples / num_cpus + usize::from(n < samples % num_cpus);
+                    for (index, p) in positions.iter().enumerate() {
+                        nice::wait_turn(n);
+                        let mut sums = [Color::zeros(); COEFFICIENTS];
+                        for _ in 0..share {
+                            let direction = random_unit_vector();
+                            let r = Ray::new(*p, direction, Some(time));
+                            let radiance = ray_color(&r, &world, settings.max_depth, &sampling);
+
+                            for (sum, y) in sums.iter_mut().zip(&basis(&direction)) {
+                                *sum += radiance * *y;
+                            }
+                        }
+
+                        sender.send((index, sums, share)).ok();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut coefficients = vec![[Color::zeros(); COEFFICIENTS]; positions.len()];
+        for _ in 0..positions.len() * num_cpus {
+            let (index, sums, share) = receiver.recv()?;
+            for (total, sum) in coefficients[index].iter_mut().zip(&sums) {
+                *total += sum;
+            }
+            pb.inc(share as u64);
+        }
+
+        for handle in workers {
+            handle
+                .join()
+                .map_err(|_| eyre!("a probe thread panicked"))?;
+        }
+
+        // Directions are uniform over the sphere, of density 1 / 4 pi.
+        let weight = 4.0 * std::f64::consts::PI / samples.max(1) as f64;
+        for probe in &mut coefficients {
+            for coefficient in probe.iter_mut() {
+                *coefficient *= weight;
+            }
+        }
+
+        Ok(coefficients)
+    }
+
+    /// Returns the probes and their `coefficients` as a JSON document.
+    pub(crate) fn to_json(&self, coefficients: &[[Color; COEFFICIENTS]]) -> String {
+        let mut json = String::from("{\n  \"probes\": [\n");
+
+        for (i, (p, coefficients)) in self.at.iter().zip(coefficients).enumerate() {
+            let coefficients: Vec<_> = coefficients
+                .iter()
+                .map(|c| format!("[{}, {}, {}]", c.x, c.y, c.z))
+                .collect();
+            let separator = if i + 1 < self.at.len() { "," } else { "" };
+
+            writeln!(
+                json,
+                "    {{\"position\": [{}, {}, {}], \"coefficients\": [{}]}}{}",
+                p.x,
+                p.y,
+                p.z,
+                coefficients.join(", "),
+                separator
+            )
+            .ok();
+        }
+
+        json.push_str("  ]\n}");
+        json
+    }
+}
+
+/// Returns the real spherical harmonics up to band 2 in the direction `d`, a unit vector.
+fn basis(d: &Vec3) -> [f64; COEFFICIENTS] {
+    let (x, y, z) = (d.x, d.y, d.z);
+
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
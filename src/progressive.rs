@@ -0,0 +1,312 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Result};
+use indicatif::ProgressBar;
+
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::interrupt;
+use crate::render::{accumulate, Accumulation, RenderSettings, World};
+
+/// The image after a pass of a [`ProgressiveRenderer`].
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) accumulation: Accumulation,
+    /// Passes in the image, since the scene last changed.
+    pub(crate) passes: usize,
+    /// Time spent rendering the image, pauses left out.
+    pub(crate) elapsed: Duration,
+}
+
+/// Renders a scene pass after pass of `settings.samples_per_pixel` on a thread of its own,
+/// sending the image after each pass to [`ProgressiveRenderer::snapshots`], for windows that
+/// show a render as it improves and let it be paused or the scene edited.
+///
+/// Passes run to the end once started, so pausing and updating the scene wait for the one
+/// under way: a few samples per pixel keep them quick. Ctrl-C stops the render after the pass
+/// it interrupts.
+pub(crate) struct ProgressiveRenderer<H> {
+    settings: RenderSettings,
+    /// Stop once this many passes are done in all, across scene updates.
+    limit: Option<usize>,
+    pb: ProgressBar,
+    /// The scene and the image so far, while paused.
+    state: Option<State<H>>,
+    running: Option<Running<H>>,
+    snapshots: Receiver<Snapshot>,
+}
+
+struct State<H> {
+    world: Arc<World<H>>,
+    camera: Arc<Camera>,
+    accumulation: Accumulation,
+    passes: usize,
+    /// Passes since the renderer was made, for `limit`.
+    total: usize,
+    elapsed: Duration,
+}
+
+struct Running<H> {
+    pause: Arc<AtomicBool>,
+    thread: JoinHandle<(State<H>, Result<()>)>,
+}
+
+impl<H> ProgressiveRenderer<H>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    /// Makes a paused renderer of `world` as seen from `camera`, showing each pass on `pb`.
+    pub(crate) fn new(
+        world: World<H>,
+        camera: Camera,
+        settings: RenderSettings,
+        pb: ProgressBar,
+    ) -> Self {
+        Self {
+            settings,
+            limit: None,
+            pb,
+            state: Some(State {
+                world: Arc::new(world),
+                camera: Arc::new(camera),
+                accumulation: Accumulation::default(),
+                passes: 0,
+                total: 0,
+                elapsed: Duration::ZERO,
+            }),
+            running: None,
+            snapshots: channel().1,
+        }
+    }
+
+    /// Stops the render after `passes` in all, counting those before scene updates.
+    pub(crate) fn stop_after(mut self, passes: usize) -> Self {
+        self.limit = Some(passes);
+        self
+    }
+
+    /// Where the image arrives after each pass. The channel closes when the render stops by
+    /// itself, after its last pass, after Ctrl-C or on an error, which [`Self::pause`] returns.
+    ///
+    /// Starting again opens a new channel, so that no image from before a pause or an update
+    /// arrives after it.
+    pub(crate) fn snapshots(&self) -> &Receiver<Snapshot> {
+        &self.snapshots
+    }
+
+    /// Starts rendering, or goes on from where it was paused. Does nothing if it is rendering.
+    pub(crate) fn start(&mut self) {
+        let mut state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+        let (sender, receiver) = channel();
+        self.snapshots = receiver;
+
+        let pause = Arc::new(AtomicBool::new(false));
+        let settings = self.settings;
+        let limit = self.limit;
+        let pb = self.pb.clone();
+        let paused = pause.clone();
+        let thread = spawn(move || {
+            let result = render_passes(&mut state, settings, limit, &paused, &sender, &pb);
+            pb.finish_and_clear();
+            (state, result)
+        });
+
+        self.running = Some(Running { pause, thread });
+    }
+
+    /// Stops rendering after the pass under way, and waits for it. Fails if the render failed.
+    pub(crate) fn pause(&mut self) -> Result<()> {
+        let running = match self.running.take() {
+            Some(running) => running,
+            None => return Ok(()),
+        };
+        running.pause.store(true, Ordering::Relaxed);
+        let (state, result) = running
+            .thread
+            .join()
+            .map_err(|_| eyre!("the render thread panicked"))?;
+        self.state = Some(state);
+
+        result
+    }
+
+    /// Lets `update` change the scene and the camera, then starts the image over from
+    /// nothing, rendering again if it was. Meant for edits like those of a material or of the
+    /// camera, in place: the world can also be replaced whole.
+    ///
+    /// When `update` fails, the scene is left as `update` left it and the image goes on.
+    pub(crate) fn update_scene<T>(
+        &mut self,
+        update: impl FnOnce(&mut World<H>, &mut Camera) -> Result<T>,
+    ) -> Result<T> {
+        let was_running = self.running.is_some();
+        self.pause()?;
+
+        let result = self.edit(update);
+        if was_running {
+            self.start();
+        }
+
+        result
+    }
+
+    fn edit<T>(
+        &mut self,
+        update: impl FnOnce(&mut World<H>, &mut Camera) -> Result<T>,
+    ) -> Result<T> {
+        let state = self
+            .state
+            .as_mut()
+            .ok_or_else(|| eyre!("the render is under way"))?;
+        // The render threads are done with the world, so it can be changed in place.
+        let world = Arc::get_mut(&mut state.world)
+            .ok_or_else(|| eyre!("the world is still being rendered"))?;
+        let camera = Arc::make_mut(&mut state.camera);
+
+        let value = update(world, camera)?;
+        state.accumulation.clear();
+        state.passes = 0;
+        state.elapsed = Duration::ZERO;
+
+        Ok(value)
+    }
+}
+
+impl<H> Drop for ProgressiveRenderer<H> {
+    fn drop(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.pause.store(true, Ordering::Relaxed);
+            running.thread.join().ok();
+        }
+    }
+}
+
+fn render_passes<H>(
+    state: &mut State<H>,
+    settings: RenderSettings,
+    limit: Option<usize>,
+    pause: &AtomicBool,
+    sender: &Sender<Snapshot>,
+    pb: &ProgressBar,
+) -> Result<()>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    while !pause.load(Ordering::Relaxed) && limit.is_none_or(|limit| state.total < limit) {
+        pb.reset();
+        pb.set_length(settings.image_height as u64 * settings.samples_per_pixel as u64);
+        pb.set_message(&format!(
+            "pass {}, {} samples per pixel",
+            state.total + 1,
+            state.accumulation.samples_per_pixel() + settings.samples_per_pixel
+        ));
+
+        let start = Instant::now();
+        accumulate(
+            &state.world,
+            &state.camera,
+            settings,
+            &mut state.accumulation,
+            pb,
+        )?;
+        state.elapsed += start.elapsed();
+        state.passes += 1;
+        state.total += 1;
+
+        let snapshot = Snapshot {
+            accumulation: state.accumulation.clone(),
+            passes: state.passes,
+            elapsed: state.elapsed,
+        };
+        // Nobody is looking any more.
+        if sender.send(snapshot).is_err() || interrupt::requested() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::LightSampling;
+    use crate::render::Regularization;
+    use crate::sampler::Sampler;
+    use crate::scene_loader::{load_scene, LoadOptions};
+
+    #[test]
+    fn updates_start_the_image_over() {
+        let source = "
+camera:
+  look_from: { x: 0, y: 0, z: 3 }
+  look_at: { x: 0, y: 0, z: 0 }
+objects:
+  - center: { x: 0, y: 0, z: 0 }
+    radius: 1
+    material: { albedo: { r: 0.5, g: 0.5, b: 0.5 } }
+";
+        let options = LoadOptions {
+            source: Some(source.to_string()),
+            ..LoadOptions::default()
+        };
+        let scene = load_scene("scene.yml", &options).unwrap();
+        let world = World::new(
+            scene.world,
+            scene.lights,
+            scene.background,
+            scene.backplate,
+            scene.fog,
+            scene.interval,
+        );
+        let camera = Camera::new(
+            *scene.camera.look_from.start(),
+            *scene.camera.look_at.start(),
+            scene.camera.vup,
+            40.0,
+            1.0,
+            0.0,
+            1.0,
+            scene.camera.time.clone(),
+        );
+        let settings = RenderSettings {
+            image_width: 4,
+            image_height: 4,
+            samples_per_pixel: 2,
+            max_depth: 4,
+            spectral: false,
+            light_sampling: LightSampling::Tree,
+            guide_passes: 0,
+            regularization: Regularization::Off,
+            occlusion_cache: false,
+            sampler: Sampler::Random,
+            time_limit: None,
+        };
+
+        let mut renderer = ProgressiveRenderer::new(world, camera, settings, ProgressBar::hidden());
+        renderer.start();
+        let first = renderer.snapshots().recv().unwrap();
+        assert_eq!(first.passes, 1);
+        assert_eq!(first.accumulation.samples_per_pixel(), 2);
+
+        // Whatever passes were under way, the next image is the first of the new scene.
+        renderer.update_scene(|_, _| Ok(())).unwrap();
+        let next = renderer.snapshots().recv().unwrap();
+        assert_eq!(next.passes, 1);
+        renderer.pause().unwrap();
+
+        // Passes count across updates towards the limit.
+        let mut renderer = renderer.stop_after(next.passes + 2);
+        renderer.start();
+        let passes: Vec<_> = renderer.snapshots().iter().map(|s| s.passes).collect();
+        renderer.pause().unwrap();
+        assert!(passes.len() <= 2);
+    }
+}
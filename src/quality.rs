@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use crate::render::RenderSettings;
+use crate::sampler::Sampler;
+use crate::temporal::GBuffer;
+use crate::vec3::Color;
+
+/// Pixels on each side of a pixel that the draft filter averages it with.
+const FILTER_RADIUS: i64 = 3;
+
+/// How sharply the draft filter tells apart surfaces facing different ways: the cosine between
+/// their normals is raised to this power.
+const NORMAL_SHARPNESS: i32 = 32;
+
+/// How far out of the surface of a pixel, for each unit along it, a neighbour may see before
+/// the draft filter stops taking it for the same surface.
+const PLANE_TOLERANCE: f64 = 0.1;
+
+/// Settings picked together for how long a render may take.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Quality {
+    /// A sample per pixel, dithered, with paths ending after one bounce off what the camera
+    /// sees, then smoothed along surfaces: a rough look at the scene in under a second.
+    Draft,
+    /// 16 samples per pixel, dithered, with paths of up to 8 bounces.
+    Medium,
+    /// The samples per pixel, depth and sampler as given.
+    Final,
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(Self::Draft),
+            "medium" => Ok(Self::Medium),
+            "final" => Ok(Self::Final),
+            _ => Err(format!(
+                "unknown quality `{}`, expected `draft`, `medium` or `final`",
+                s
+            )),
+        }
+    }
+}
+
+impl Quality {
+    /// Returns `settings` with the sampler and the samples and depth of paths of the preset.
+    pub(crate) fn apply(self, settings: RenderSettings) -> RenderSettings {
+        match self {
+            Self::Draft => RenderSettings {
+                samples_per_pixel: 1,
+                max_depth: 2,
+                sampler: Sampler::BlueNoise,
+                guide_passes: 0,
+                ..settings
+            },
+            Self::Medium => RenderSettings {
+                samples_per_pixel: 16,
+                max_depth: 8,
+                sampler: Sampler::BlueNoise,
+                ..settings
+            },
+            Self::Final => settings,
+        }
+    }
+
+    /// Whether the image is smoothed with [`smooth`] before it is written.
+    pub(crate) fn smooths(self) -> bool {
+        self == Self::Draft
+    }
+}
+
+/// Smooths `colors`, an image with few samples per pixel, by averaging each pixel with those
+/// around it that see the same surface as it in `gbuffer`, of the same frame: facing the
+/// same way, and not in front of it or behind it. Edges between objects and creases stay
+/// sharp, while texture and shadows on a surface blur.
+pub(crate) fn smooth(colors: &[Color], gbuffer: &GBuffer) -> Vec<Color> {
+    let (width, height) = gbuffer.size();
+    let (width, height) = (width as i64, height as i64);
+    let hits = gbuffer.hits();
+    let sigma = FILTER_RADIUS as f64 / 2.0;
+
+    (0..height * width)
+        .map(|index| {
+            let (p, n) = match &hits[index as usize] {
+                Some(hit) => hit,
+                // The background is smooth already.
+                None => return colors[index as usize],
+            };
+            let (i, j) = (index % width, index / width);
+
+            let mut sum = Color::zeros();
+            let mut total = 0.0;
+            for y in (j - FILTER_RADIUS).max(0)..=(j + FILTER_RADIUS).min(height - 1) {
+                for x in (i - FILTER_RADIUS).max(0)..=(i + FILTER_RADIUS).min(width - 1) {
+                    let neighbour = (y * width + x) as usize;
+                    let (q, m) = match &hits[neighbour] {
+                        Some(hit) => hit,
+                        None => continue,
+                    };
+
+                    let offset = q - p;
+                    let along = offset.norm();
+                    let out_of_plane = offset.dot(n).abs();
+                    if out_of_plane > PLANE_TOLERANCE * along {
+                        continue;
+                    }
+                    let facing = n.dot(m).max(0.0).powi(NORMAL_SHARPNESS);
+                    let d2 = ((x - i).pow(2) + (y - j).pow(2)) as f64;
+                    let weight = facing * (-d2 / (2.0 * sigma * sigma)).exp();
+
+                    sum += colors[neighbour] * weight;
+                    total += weight;
+                }
+            }
+
+            if total > 0.0 {
+                sum / total
+            } else {
+                colors[index as usize]
+            }
+        })
+        .collect()
+}
@@ -1,10 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use crate::rtweekend::INFINITY;
+use crate::scene_loader::IntervalDef;
 use crate::vec3::Point3;
 
-#[derive(Default)]
+/// The part of every ray along which hits count, as distances from its origin.
+///
+/// Rays leaving a surface start `t_min` away from it so as not to hit it again through
+/// rounding, which large scenes need more of and small ones, with surfaces closer together than
+/// that, less. Nothing lies beyond `t_max`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(from = "IntervalDef", into = "IntervalDef")]
+pub(crate) struct Interval {
+    pub(crate) t_min: f64,
+    pub(crate) t_max: f64,
+}
+
+impl Interval {
+    pub(crate) fn new(t_min: f64, t_max: f64) -> Self {
+        Self { t_min, t_max }
+    }
+
+    /// Returns the interval in units of `t` along `r`, whose direction may not be a unit vector.
+    pub(crate) fn along(&self, r: &Ray) -> (f64, f64) {
+        let speed = r.direction().norm();
+
+        (self.t_min / speed, self.t_max / speed)
+    }
+
+    /// Returns the interval for a scene scaled by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Self {
+        Self::new(self.t_min * factor, self.t_max * factor)
+    }
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Self::new(0.001, INFINITY)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
 pub(crate) struct Ray {
     origin: Point3,
     direction: Point3,
     time: Option<f64>,
+    /// How far along the ray the first hit may be, when it is clipped (see [`Ray::clipped`]).
+    clip: Option<(f64, f64)>,
+    /// Where a camera ray goes through the frame, from its bottom left corner to its top right.
+    screen: Option<(f64, f64)>,
 }
 
 impl Ray {
@@ -13,9 +57,37 @@ impl Ray {
             origin,
             direction,
             time,
+            clip: None,
+            screen: None,
+        }
+    }
+
+    /// Returns the ray with hits only counting between `t_min` and `t_max`, for camera rays
+    /// clipped by near and far planes. Rays bouncing off what it hits aren't clipped.
+    pub(crate) fn clipped(self, t_min: f64, t_max: f64) -> Self {
+        Self {
+            clip: Some((t_min, t_max)),
+            ..self
         }
     }
 
+    pub(crate) fn clip(&self) -> Option<(f64, f64)> {
+        self.clip
+    }
+
+    /// Returns the ray marked as a camera ray through `(u, v)` on the frame, for what it hits
+    /// first to tell.
+    pub(crate) fn on_screen(self, u: f64, v: f64) -> Self {
+        Self {
+            screen: Some((u, v)),
+            ..self
+        }
+    }
+
+    pub(crate) fn screen(&self) -> Option<(f64, f64)> {
+        self.screen
+    }
+
     pub(crate) fn origin(&self) -> &Point3 {
         &self.origin
     }
@@ -32,3 +104,30 @@ impl Ray {
         self.origin + t * self.direction
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::vec3::tests::vector;
+
+    const TOLERANCE: f64 = 1e-9;
+
+    proptest! {
+        #[test]
+        fn at_is_linear_in_t(
+            origin in vector(),
+            direction in vector(),
+            s in -10.0..10.0_f64,
+            t in -10.0..10.0_f64,
+        ) {
+            let r = Ray::new(origin, direction, None);
+
+            prop_assert_eq!(r.at(0.0), origin);
+            prop_assert!((r.at(1.0) - (origin + direction)).norm() < TOLERANCE);
+            prop_assert!((r.at(s + t) - r.at(s) - t * direction).norm() < TOLERANCE);
+            prop_assert!((r.at(s * t) - origin - s * (r.at(t) - origin)).norm() < TOLERANCE);
+        }
+    }
+}
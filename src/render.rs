@@ -0,0 +1,1338 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Result};
+use indicatif::ProgressBar;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::backplate::Backplate;
+use crate::camera::Camera;
+use crate::color::clamp_color;
+use crate::fog::Fog;
+use crate::guide::{DirectionalTree, Guide};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interrupt;
+use crate::light::{
+    luminance, power_heuristic, LightLink, LightSample, LightSampling, LightTree, OcclusionCache,
+};
+use crate::material::{LazySpectrum, Material, ShadowCatcher};
+use crate::nice;
+use crate::ray::{Interval, Ray};
+use crate::rtweekend::{random_double, INFINITY};
+use crate::sampler::{dither, Sampler};
+use crate::spectrum::{blend_rgb, sample_wavelengths, to_pixel, Spectrum, Wavelengths};
+use crate::tiles::{self, Tile};
+use crate::vec3::{
+    length, near_zero, random_unit_vector, reflect, unit_vector, Color, Point3, Vec3,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RenderSettings {
+    pub(crate) image_width: u32,
+    pub(crate) image_height: u32,
+    pub(crate) samples_per_pixel: usize,
+    pub(crate) max_depth: usize,
+    /// Trace one wavelength per sample instead of RGB.
+    pub(crate) spectral: bool,
+    pub(crate) light_sampling: LightSampling,
+    /// Passes spent training a path guide before the render itself, if any.
+    pub(crate) guide_passes: usize,
+    pub(crate) regularization: Regularization,
+    /// Test what blocked the last shadow ray toward a light first, see [`OcclusionCache`].
+    pub(crate) occlusion_cache: bool,
+    pub(crate) sampler: Sampler,
+    /// Keep adding passes of `samples_per_pixel` until this much time has passed since the
+    /// render started, rather than stopping after the first.
+    pub(crate) time_limit: Option<Duration>,
+}
+
+/// Renders first take 1 / `PREVIEW_FRACTION` of their samples in every pixel, then the rest.
+const PREVIEW_FRACTION: usize = 8;
+
+lazy_static! {
+    static ref SKY_BLUE: Spectrum = Spectrum::from_rgb(&Color::new(0.5, 0.7, 1.0));
+}
+
+/// What rays see when they hit nothing.
+pub(crate) enum Background {
+    /// A gradient from white at the horizon to blue overhead.
+    Sky,
+    Uniform(Color, LazySpectrum),
+}
+
+/// Everything a render traces: the objects, the lights among them, and what lies beyond.
+pub(crate) struct World<H> {
+    objects: H,
+    lights: LightTree,
+    background: Background,
+    backplate: Option<Backplate>,
+    fog: Option<Fog>,
+    interval: Interval,
+}
+
+impl<H> World<H> {
+    pub(crate) fn new(
+        objects: H,
+        lights: LightTree,
+        background: Option<Color>,
+        backplate: Option<Backplate>,
+        fog: Option<Fog>,
+        interval: Interval,
+    ) -> Self {
+        let mut world = Self {
+            objects,
+            lights,
+            background: Background::Sky,
+            backplate,
+            fog,
+            interval,
+        };
+        world.set_background(background);
+
+        world
+    }
+
+    pub(crate) fn objects(&self) -> &H {
+        &self.objects
+    }
+
+    pub(crate) fn interval(&self) -> &Interval {
+        &self.interval
+    }
+
+    /// Returns the objects, to change what they are made of between renders.
+    pub(crate) fn objects_mut(&mut self) -> &mut H {
+        &mut self.objects
+    }
+
+    /// Returns an empty cache for one render thread, if `settings` ask for one.
+    pub(crate) fn occlusion_cache(&self, settings: &RenderSettings) -> Option<OcclusionCache> {
+        if settings.occlusion_cache {
+            Some(OcclusionCache::new(&self.lights))
+        } else {
+            None
+        }
+    }
+
+    /// Returns how far a shadow ray toward `sample` goes: just short of the light, so as not to
+    /// find the light itself in the way, and no further than any ray.
+    fn shadow_t_max(&self, sample: &LightSample) -> f64 {
+        (sample.distance * (1.0 - 1e-4)).min(self.interval.t_max)
+    }
+
+    pub(crate) fn set_lights(&mut self, lights: LightTree) {
+        self.lights = lights;
+    }
+
+    pub(crate) fn set_background(&mut self, background: Option<Color>) {
+        self.background = match background {
+            Some(color) => Background::Uniform(color, LazySpectrum::default()),
+            None => Background::Sky,
+        };
+    }
+
+    pub(crate) fn set_backplate(&mut self, backplate: Option<Backplate>) {
+        self.backplate = backplate;
+    }
+
+    pub(crate) fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+    }
+
+    pub(crate) fn set_interval(&mut self, interval: Interval) {
+        self.interval = interval;
+    }
+}
+
+/// What a path carries, either RGB or the radiance at a few wavelengths, so that both render
+/// modes share the same integrator.
+trait Channels {
+    type Value: Copy + Add<Output = Self::Value> + Mul<f64, Output = Self::Value>;
+
+    fn zero(&self) -> Self::Value;
+    fn product(a: &Self::Value, b: &Self::Value) -> Self::Value;
+    /// Returns a single measure of how bright `value` is.
+    fn brightness(value: &Self::Value) -> f64;
+    /// Returns the attenuation of a scatter off `material`, which returned `attenuation`.
+    fn attenuation(&self, material: &Material, attenuation: &Color) -> Self::Value;
+    fn emitted(&self, material: &Material) -> Self::Value;
+    fn background(&self, background: &Background, direction: &Vec3) -> Self::Value;
+    /// Returns light of `color`, which may change from one ray to the next.
+    fn color(&self, color: &Color) -> Self::Value;
+}
+
+struct Rgb;
+
+impl Channels for Rgb {
+    type Value = Color;
+
+    fn zero(&self) -> Color {
+        Color::zeros()
+    }
+
+    fn product(a: &Color, b: &Color) -> Color {
+        a.component_mul(b)
+    }
+
+    fn brightness(value: &Color) -> f64 {
+        luminance(value)
+    }
+
+    fn attenuation(&self, _material: &Material, attenuation: &Color) -> Color {
+        *attenuation
+    }
+
+    fn emitted(&self, material: &Material) -> Color {
+        material.emitted()
+    }
+
+    fn background(&self, background: &Background, direction: &Vec3) -> Color {
+        match background {
+            Background::Sky => {
+                let unit_direction = unit_vector(direction);
+                let t = 0.5 * (unit_direction.y + 1.0);
+
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Uniform(color, _) => *color,
+        }
+    }
+
+    fn color(&self, color: &Color) -> Color {
+        *color
+    }
+}
+
+struct Spectral<'a>(&'a Wavelengths);
+
+impl Channels for Spectral<'_> {
+    type Value = Wavelengths;
+
+    fn zero(&self) -> Wavelengths {
+        Wavelengths::zeros()
+    }
+
+    fn product(a: &Wavelengths, b: &Wavelengths) -> Wavelengths {
+        a.component_mul(b)
+    }
+
+    fn brightness(value: &Wavelengths) -> f64 {
+        value.mean()
+    }
+
+    fn attenuation(&self, material: &Material, attenuation: &Color) -> Wavelengths {
+        material.attenuation_at(attenuation, self.0)
+    }
+
+    fn emitted(&self, material: &Material) -> Wavelengths {
+        material.emitted_at(self.0)
+    }
+
+    fn background(&self, background: &Background, direction: &Vec3) -> Wavelengths {
+        match background {
+            Background::Sky => {
+                let unit_direction = unit_vector(direction);
+                let t = 0.5 * (unit_direction.y + 1.0);
+
+                // RGB is linear in the spectrum, so blending spectra blends the sky's colors.
+                Wavelengths::repeat(1.0 - t) + t * SKY_BLUE.at_each(self.0)
+            }
+            Background::Uniform(color, spectrum) => spectrum.at(color, self.0),
+        }
+    }
+
+    fn color(&self, color: &Color) -> Wavelengths {
+        blend_rgb(color, self.0)
+    }
+}
+
+/// Chance of a guided diffuse bounce following the guide rather than the material.
+const GUIDE_FRACTION: f64 = 0.5;
+
+/// How rough mirrors and glass become once paths regularize them.
+const REGULARIZED_ROUGHNESS: f64 = 0.1;
+
+/// When paths start treating mirrors and glass as slightly rough.
+///
+/// Light that reaches a diffuse surface through a mirror or through glass, from a light that is
+/// small, can only be found by a bounce that happens to go exactly the right way. Such paths are
+/// rare, and show up as fireflies that never average out. Roughening the surfaces once a path
+/// has bounced off something diffuse spreads their light out over many more paths, at the cost
+/// of slightly blurred caustics, while mirrors and glass seen from the camera stay sharp.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Regularization {
+    Off,
+    /// After this many diffuse bounces.
+    After(usize),
+}
+
+impl FromStr for Regularization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            _ => s.parse().map(Self::After).map_err(|_| {
+                format!(
+                    "unknown regularization `{}` (expected a number of diffuse bounces or `off`)",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+/// How often a render hands over the image so far, so that it can be written out while it
+/// improves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Refresh {
+    Seconds(f64),
+    /// After every this many passes of 1 / `PREVIEW_FRACTION` of the samples.
+    Passes(usize),
+}
+
+impl FromStr for Refresh {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let refresh = match s.strip_suffix('s') {
+            Some(seconds) => seconds
+                .parse()
+                .ok()
+                .filter(|seconds: &f64| *seconds > 0.0)
+                .map(Self::Seconds),
+            None => s
+                .parse()
+                .ok()
+                .filter(|passes| *passes > 0)
+                .map(Self::Passes),
+        };
+
+        refresh.ok_or_else(|| {
+            format!(
+                "unknown interval `{}` (expected seconds like `30s` or a number of passes)",
+                s
+            )
+        })
+    }
+}
+
+/// A length of time, given in seconds, minutes or hours like `90s`, `10m` or `1.5h`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TimeLimit(pub(crate) Duration);
+
+impl FromStr for TimeLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let units = [('s', 1.0), ('m', 60.0), ('h', 3600.0)];
+        units
+            .iter()
+            .find_map(|&(suffix, unit)| Some((s.strip_suffix(suffix)?.parse::<f64>().ok()?, unit)))
+            .filter(|(amount, _)| *amount > 0.0 && amount.is_finite())
+            .and_then(|(amount, unit)| Duration::try_from_secs_f64(amount * unit).ok())
+            .map(Self)
+            .ok_or_else(|| {
+                format!(
+                    "unknown time limit `{}` (expected a duration like `90s`, `10m` or `1h`)",
+                    s
+                )
+            })
+    }
+}
+
+/// Returns whether a render should stop taking samples, because Ctrl-C was pressed or it is
+/// past its `deadline`.
+fn stopped(deadline: Option<Instant>) -> bool {
+    interrupt::requested() || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// How paths pick their directions, beyond what their materials do.
+#[derive(Clone, Copy)]
+pub(crate) struct PathSampling<'a> {
+    pub(crate) regularization: Regularization,
+    pub(crate) lights: LightSampling,
+    pub(crate) guide: Option<&'a Guide>,
+    /// Record the light paths find into `guide`, to train it.
+    pub(crate) training: bool,
+    pub(crate) occlusion: Option<&'a OcclusionCache>,
+}
+
+/// A bounce that also aimed at a light, and that a path continued from.
+struct Bounce {
+    p: Point3,
+    /// Density over solid angle of the direction the path took.
+    pdf: f64,
+}
+
+/// What a path went through before its current ray.
+#[derive(Default)]
+struct History {
+    diffuse_bounces: usize,
+    /// Where the ray comes from, if that bounce also aimed at a light.
+    bounce: Option<Bounce>,
+    /// What the water the ray travels under absorbs per meter, see [`crate::material::Water`].
+    under_water: Option<Color>,
+    /// The linked lights that don't light what the ray last bounced off, one bit each as in
+    /// [`crate::light::LightLinks`], whose light it doesn't bring back.
+    unlit: u64,
+}
+
+pub(crate) fn ray_color<H: Hittable>(
+    r: &Ray,
+    world: &World<H>,
+    depth: usize,
+    sampling: &PathSampling,
+) -> Color {
+    trace(r, world, depth, &Rgb, sampling, &History::default())
+}
+
+/// Like [`ray_color`], but returns the radiance at each of `wavelengths`.
+pub(crate) fn ray_radiance<H: Hittable>(
+    r: &Ray,
+    world: &World<H>,
+    depth: usize,
+    sampling: &PathSampling,
+    wavelengths: &Wavelengths,
+) -> Wavelengths {
+    trace(
+        r,
+        world,
+        depth,
+        &Spectral(wavelengths),
+        sampling,
+        &History::default(),
+    )
+}
+
+fn trace<H: Hittable, C: Channels>(
+    r: &Ray,
+    world: &World<H>,
+    depth: usize,
+    channels: &C,
+    sampling: &PathSampling,
+    history: &History,
+) -> C::Value {
+    // If we've exceeded the ray bounce limit, no more light is gathered.
+    if depth == 0 {
+        return channels.zero();
+    }
+
+    let (t_min, t_max) = world.interval.along(r);
+    let (t_min, t_max) = r.clip().map_or((t_min, t_max), |(near, far)| {
+        (near.max(t_min), far.min(t_max))
+    });
+    let hit = world.objects.hit(r, t_min, t_max);
+    if world.fog.is_none() && history.under_water.is_none() {
+        return shade(r, hit, world, depth, channels, sampling, history);
+    }
+
+    // Distances along the ray, which may not have a unit direction.
+    let speed = length(r.direction());
+    let near = t_min * speed;
+    let far = hit.as_ref().map_or(t_max, |rec| *rec.t()) * speed;
+    let mut seen = shade(r, hit, world, depth, channels, sampling, history);
+    if let Some(absorption) = &history.under_water {
+        let transmitted = absorption.map(|a| {
+            if a > 0.0 {
+                (-a * (far - near)).exp()
+            } else {
+                1.0
+            }
+        });
+        seen = C::product(&channels.color(&transmitted), &seen);
+    }
+
+    let fog = match &world.fog {
+        Some(fog) => fog,
+        None => return seen,
+    };
+    seen = seen * fog.transmittance(r, near, far);
+    if r.screen().is_none() {
+        return seen;
+    }
+
+    seen + in_scattered(fog, r, near, far, world, channels, sampling)
+}
+
+/// Returns the light that `r` brings back from `hit`, what it hit first, not counting fog.
+fn shade<H: Hittable, C: Channels>(
+    r: &Ray,
+    hit: Option<HitRecord>,
+    world: &World<H>,
+    depth: usize,
+    channels: &C,
+    sampling: &PathSampling,
+    history: &History,
+) -> C::Value {
+    let rec = match hit {
+        Some(rec) => rec,
+        None => {
+            return match (&world.backplate, r.screen()) {
+                (Some(backplate), Some((u, v))) => channels.color(&backplate.at(u, v)),
+                _ => channels.background(&world.background, r.direction()),
+            }
+        }
+    };
+    let material = rec.material().resolve(&rec);
+
+    if let Material::DiffuseLight(_) = material {
+        // However the path got here, linked lights only light what they are linked to.
+        if let Some(link) = &rec.links().light {
+            if history.unlit & (1 << link.bit) != 0 {
+                return channels.zero();
+            }
+        }
+
+        // The last bounce may also have aimed at this light, so the two share its contribution.
+        let weight = match &history.bounce {
+            Some(from) => power_heuristic(
+                from.pdf,
+                world
+                    .lights
+                    .pdf(&from.p, rec.p(), r.time(), sampling.lights),
+            ),
+            None => 1.0,
+        };
+
+        return channels.emitted(material) * weight;
+    }
+
+    if let Material::ShadowCatcher(catcher) = material {
+        return shadow_catcher(catcher, r, &rec, world, depth, channels, sampling, history);
+    }
+
+    let regularize = match sampling.regularization {
+        Regularization::After(after) => history.diffuse_bounces >= after,
+        Regularization::Off => false,
+    };
+    let scatter = if regularize {
+        material.scatter_regularized(r, &rec, REGULARIZED_ROUGHNESS)
+    } else {
+        material.scatter(r, &rec)
+    };
+    let (scattered, attenuation) = match scatter {
+        Some(scatter) => scatter,
+        None => return channels.zero(),
+    };
+    let attenuation = channels.attenuation(material, &attenuation);
+
+    if let Material::Sheen(sheen) = material {
+        // Cloth bounces like a diffuse surface, without the guide, and aims at lights as well.
+        let view = -unit_vector(r.direction());
+        let direct = direct_light(world, &rec, r.time(), channels, sampling, |d| {
+            let cosine = d.dot(rec.normal()).max(0.0);
+            (
+                channels.color(&(sheen.brdf(&rec, &view, d) * cosine)),
+                cosine / PI,
+            )
+        });
+
+        let cosine = unit_vector(scattered.direction()).dot(rec.normal());
+        let history = History {
+            diffuse_bounces: history.diffuse_bounces + 1,
+            bounce: Some(Bounce {
+                p: *rec.p(),
+                pdf: cosine.max(0.0) / PI,
+            }),
+            under_water: history.under_water,
+            unlit: !rec.links().lit_by,
+        };
+        let indirect = trace(&scattered, world, depth - 1, channels, sampling, &history);
+
+        return direct + C::product(&attenuation, &indirect);
+    }
+
+    if material.is_diffuse() {
+        let distribution = sampling.guide.and_then(|guide| guide.distribution(rec.p()));
+        // A Lambertian surface reflects `albedo / π` of the light it receives, times the cosine.
+        let direct = direct_light(world, &rec, r.time(), channels, sampling, |d| {
+            let cosine = d.dot(rec.normal()).max(0.0);
+            (
+                attenuation * (cosine / PI),
+                diffuse_pdf(distribution, rec.normal(), d),
+            )
+        });
+
+        let scattered = match distribution {
+            Some(distribution) if random_double() < GUIDE_FRACTION => {
+                Ray::new(*rec.p(), distribution.sample().0, Some(r.time()))
+            }
+            _ => scattered,
+        };
+        let direction = unit_vector(scattered.direction());
+        let cosine = direction.dot(rec.normal());
+        if cosine <= 0.0 {
+            return direct;
+        }
+
+        let pdf = diffuse_pdf(distribution, rec.normal(), &direction);
+        let history = History {
+            diffuse_bounces: history.diffuse_bounces + 1,
+            bounce: Some(Bounce { p: *rec.p(), pdf }),
+            under_water: history.under_water,
+            unlit: !rec.links().lit_by,
+        };
+        let indirect = trace(&scattered, world, depth - 1, channels, sampling, &history);
+
+        if let (Some(guide), true) = (sampling.guide, sampling.training) {
+            guide.record(rec.p(), &direction, C::brightness(&indirect), pdf);
+        }
+
+        // The material's attenuation is for bouncing with a density of cosine / π.
+        return direct + C::product(&attenuation, &indirect) * (cosine / PI / pdf);
+    }
+
+    if regularize && matches!(material, Material::Metal(_) | Material::Dielectric(_)) {
+        // Rough enough to aim at lights too, unlike perfect mirrors and glass.
+        let lobe = |d: &Vec3| material.regularized_pdf(r, &rec, REGULARIZED_ROUGHNESS, d);
+        let direct = direct_light(world, &rec, r.time(), channels, sampling, |d| {
+            let pdf = lobe(d);
+            (attenuation * pdf, pdf)
+        });
+
+        let history = History {
+            diffuse_bounces: history.diffuse_bounces,
+            bounce: Some(Bounce {
+                p: *rec.p(),
+                pdf: lobe(&unit_vector(scattered.direction())),
+            }),
+            under_water: history.under_water,
+            unlit: !rec.links().lit_by,
+        };
+        let indirect = trace(&scattered, world, depth - 1, channels, sampling, &history);
+
+        return direct + C::product(&attenuation, &indirect);
+    }
+
+    // Rays going through a water surface go under or come out of the water.
+    let under_water = match material {
+        Material::Water(water) if scattered.direction().dot(rec.normal()) < 0.0 => {
+            rec.front_face().then(|| *water.absorption())
+        }
+        _ => history.under_water,
+    };
+    let history = History {
+        diffuse_bounces: history.diffuse_bounces,
+        bounce: None,
+        under_water,
+        unlit: !rec.links().lit_by,
+    };
+    let indirect = trace(&scattered, world, depth - 1, channels, sampling, &history);
+
+    C::product(&attenuation, &indirect)
+}
+
+/// Returns the light that the fog scatters toward the start of `r` between distances `near`
+/// and `far` along it, from the lights shining on it, gathered at points spread evenly over
+/// the parts of the ray in the fog.
+fn in_scattered<H: Hittable, C: Channels>(
+    fog: &Fog,
+    r: &Ray,
+    near: f64,
+    far: f64,
+    world: &World<H>,
+    channels: &C,
+    sampling: &PathSampling,
+) -> C::Value {
+    let mut total = channels.zero();
+    let mut in_fog = 0.0;
+    fog.for_each_span(r, near, far, |near, far| in_fog += far - near);
+    if in_fog <= 0.0 {
+        return total;
+    }
+
+    let direction = unit_vector(r.direction());
+    let step = in_fog / fog.steps() as f64;
+    let color = channels.color(fog.color());
+    // How far into the fog the next point lies, and how much fog the spans so far covered.
+    let mut i = 0;
+    let mut next = random_double() * step;
+    let mut covered = 0.0;
+    fog.for_each_span(r, near, far, |span_near, span_far| {
+        let end = covered + (span_far - span_near);
+        while i < fog.steps() && next < end {
+            let distance = span_near + (next - covered);
+            let reached = (-fog.density() * next).exp();
+            i += 1;
+            next = (i as f64 + random_double()) * step;
+
+            let p = r.origin() + direction * distance;
+            let sample = match world.lights.sample(&p, r.time(), sampling.lights) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let shadow_ray = Ray::new(p, sample.direction, Some(r.time()));
+            if occluded(world, None, &sample, &shadow_ray) {
+                continue;
+            }
+
+            // The fog scatters evenly in every direction, so a 1 / 4π of what it stops comes
+            // back.
+            let reached = reached * fog.transmittance(&shadow_ray, 0.0, sample.distance);
+            let scattered = fog.density() * step / (4.0 * PI);
+            total = total
+                + C::product(&color, &channels.emitted(sample.material))
+                    * (reached * scattered / sample.pdf);
+        }
+        covered = end;
+    });
+
+    total
+}
+
+/// Returns what `r` sees of `catcher`, which it hit at `rec`: for camera rays, what is behind
+/// it (the backplate, or the background) darkened by the shadows the catcher receives, with
+/// the objects it reflects mixed in; other rays go through.
+///
+/// The shadow is the fraction of the light a diffuse surface there would receive that objects
+/// don't block, from one direction picked by cosine and one shadow ray aimed at a light. Other
+/// catchers, standing in for the same ground, block nothing.
+#[allow(clippy::too_many_arguments)]
+fn shadow_catcher<H: Hittable, C: Channels>(
+    catcher: &ShadowCatcher,
+    r: &Ray,
+    rec: &HitRecord,
+    world: &World<H>,
+    depth: usize,
+    channels: &C,
+    sampling: &PathSampling,
+    history: &History,
+) -> C::Value {
+    let (t_min, t_max) = world.interval.along(r);
+    let far = r.clip().map_or(t_max, |(_, far)| far);
+    let behind = trace(
+        &r.clipped(rec.t() + t_min, far),
+        world,
+        depth,
+        channels,
+        sampling,
+        history,
+    );
+    if r.screen().is_none() {
+        return behind;
+    }
+
+    let time = r.time();
+    let normal = rec.normal();
+    let (mut received, mut unshadowed) = (0.0, 0.0);
+
+    let mut direction = normal + random_unit_vector();
+    if near_zero(&direction) {
+        direction = *normal;
+    }
+    let sky = C::brightness(&channels.background(&world.background, &direction));
+    unshadowed += sky;
+    if !blocked(
+        world,
+        &Ray::new(*rec.p(), direction, Some(time)),
+        INFINITY,
+        None,
+    ) {
+        received += sky;
+    }
+
+    if let Some(sample) = world.lights.sample(rec.p(), time, sampling.lights) {
+        let cosine = sample.direction.dot(normal);
+        if cosine > 0.0 && rec.links().is_lit_by(sample.link) {
+            let light =
+                C::brightness(&channels.emitted(sample.material)) * cosine / PI / sample.pdf;
+            unshadowed += light;
+            let shadow_ray = Ray::new(*rec.p(), sample.direction, Some(time));
+            if !blocked(
+                world,
+                &shadow_ray,
+                sample.distance * (1.0 - 1e-4),
+                sample.link,
+            ) {
+                received += light;
+            }
+        }
+    }
+
+    let shadowed = if unshadowed > 0.0 {
+        behind * (received / unshadowed)
+    } else {
+        behind
+    };
+
+    if catcher.reflection <= 0.0 {
+        return shadowed;
+    }
+    let mirrored = Ray::new(
+        *rec.p(),
+        reflect(&unit_vector(r.direction()), normal),
+        Some(time),
+    );
+    if !blocked(world, &mirrored, INFINITY, None) {
+        return shadowed;
+    }
+    let history = History {
+        diffuse_bounces: history.diffuse_bounces,
+        bounce: None,
+        under_water: history.under_water,
+        unlit: !rec.links().lit_by,
+    };
+    let reflected = trace(&mirrored, world, depth - 1, channels, sampling, &history);
+
+    shadowed * (1.0 - catcher.reflection) + reflected * catcher.reflection
+}
+
+/// Returns whether anything but shadow catchers lies along `r` before `t_max`, or before the
+/// end of the interval of the world if that comes first, leaving out what doesn't cast the
+/// shadows of the light of `link`.
+fn blocked<H: Hittable>(world: &World<H>, r: &Ray, t_max: f64, link: Option<&LightLink>) -> bool {
+    let (step, end) = world.interval.along(r);
+    let (mut t_min, t_max) = (step, t_max.min(end));
+    while let Some(rec) = world.objects.hit(r, t_min, t_max) {
+        if !matches!(rec.material(), Material::ShadowCatcher(_)) && rec.links().casts(link) {
+            return true;
+        }
+        t_min = rec.t() + step;
+    }
+
+    false
+}
+
+/// Returns whether something that casts the shadows of the light of `sample` lies along `r`, a
+/// shadow ray toward it, testing what `cache` remembers first if given.
+fn occluded<H: Hittable>(
+    world: &World<H>,
+    cache: Option<&OcclusionCache>,
+    sample: &LightSample,
+    r: &Ray,
+) -> bool {
+    let (t_min, t_max) = (world.interval.t_min, world.shadow_t_max(sample));
+
+    match (sample.link, cache) {
+        // Only some objects block the light, so shadow rays look past the others.
+        (Some(link), _) if link.shadows => {
+            let mut t = t_min;
+            while let Some(rec) = world.objects.hit(r, t, t_max) {
+                if rec.links().casts(Some(link)) {
+                    return true;
+                }
+                t = rec.t() + t_min;
+            }
+
+            false
+        }
+        (_, Some(cache)) => cache.occluded(&world.objects, sample.light, r, t_min, t_max),
+        (_, None) => world.objects.occluder(r, t_min, t_max, None).is_some(),
+    }
+}
+
+/// Returns the density of a diffuse bounce picking `direction`, following either the cosine
+/// or, half of the time, what the guide learned.
+fn diffuse_pdf(distribution: Option<&DirectionalTree>, normal: &Vec3, direction: &Vec3) -> f64 {
+    let cosine_pdf = direction.dot(normal).max(0.0) / PI;
+
+    match distribution {
+        Some(distribution) => {
+            GUIDE_FRACTION * distribution.pdf(direction) + (1.0 - GUIDE_FRACTION) * cosine_pdf
+        }
+        None => cosine_pdf,
+    }
+}
+
+/// Aims a shadow ray at one of the lights, and returns the light it receives that way,
+/// weighted against finding the light by bouncing.
+///
+/// `bsdf` gives, for a direction, the fraction of the light arriving from it that the surface
+/// sends back along the path, by channel, and the density of bouncing that way.
+fn direct_light<H: Hittable, C: Channels>(
+    world: &World<H>,
+    rec: &HitRecord,
+    time: f64,
+    channels: &C,
+    sampling: &PathSampling,
+    bsdf: impl Fn(&Vec3) -> (C::Value, f64),
+) -> C::Value {
+    let sample = match world.lights.sample(rec.p(), time, sampling.lights) {
+        Some(sample) if rec.links().is_lit_by(sample.link) => sample,
+        // Bouncing finds no light from lights that aren't linked, so the two still agree.
+        _ => return channels.zero(),
+    };
+
+    let (reflected, pdf) = bsdf(&sample.direction);
+    if C::brightness(&reflected) <= 0.0 {
+        return channels.zero();
+    }
+
+    let shadow_ray = Ray::new(*rec.p(), sample.direction, Some(time));
+    if occluded(world, sampling.occlusion, &sample, &shadow_ray) {
+        return channels.zero();
+    }
+
+    let weight = power_heuristic(sample.pdf, pdf);
+    let fog = world.fog.as_ref().map_or(1.0, |fog| {
+        fog.transmittance(&shadow_ray, 0.0, sample.distance)
+    });
+
+    C::product(&reflected, &channels.emitted(sample.material)) * (weight * fog / sample.pdf)
+}
+
+/// Renders `world` as seen from `camera`, with `settings.samples_per_pixel` in every pixel.
+/// When Ctrl-C is pressed, the render stops early with whatever samples it took so far.
+pub(crate) fn render<H>(
+    world: &Arc<World<H>>,
+    camera: Camera,
+    settings: RenderSettings,
+    pb: &ProgressBar,
+) -> Result<Accumulation>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    render_from(
+        world,
+        camera,
+        settings,
+        Accumulation::default(),
+        None,
+        &mut |_| Ok(()),
+        pb,
+    )
+}
+
+/// Like [`render`], but only takes the samples that `previous`, an interrupted render of the
+/// same image, is missing, and hands the image so far to `write` as often as `refresh` says
+/// until it is done.
+///
+/// With a `settings.time_limit`, passes of `settings.samples_per_pixel` follow each other
+/// until the time is up, the last one stopping midway.
+pub(crate) fn render_from<H>(
+    world: &Arc<World<H>>,
+    camera: Camera,
+    settings: RenderSettings,
+    mut previous: Accumulation,
+    refresh: Option<Refresh>,
+    write: &mut dyn FnMut(&Accumulation) -> Result<()>,
+    pb: &ProgressBar,
+) -> Result<Accumulation>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    let camera = Arc::new(camera);
+    let deadline = settings.time_limit.map(|limit| {
+        let now = Instant::now();
+        // Limits further off than the clock counts are as good as a century.
+        now.checked_add(limit)
+            .unwrap_or_else(|| now + Duration::from_secs(100 * 365 * 24 * 3600))
+    });
+
+    let mut guide = match world.objects.bounding_box(0.0, 1.0) {
+        Some(bbox) if settings.guide_passes > 0 => Some(Arc::new(Guide::new(bbox))),
+        _ => None,
+    };
+    for pass in 0..settings.guide_passes {
+        guide = match guide {
+            Some(guide) if !stopped(deadline) => {
+                // Each pass takes twice as many samples as the last, since it has more to go by.
+                let samples = 1 << pass;
+                render_pass(
+                    world,
+                    &camera,
+                    settings,
+                    samples,
+                    &Arc::new(vec![]),
+                    Arc::new(tiles::sweep(settings.image_width, settings.image_height)),
+                    Some((guide.clone(), true)),
+                    deadline,
+                    &mut |_| Ok(()),
+                    &ProgressBar::hidden(),
+                )?;
+
+                debug!("trained the path guide over {} samples per pixel", samples);
+                Some(Arc::new(guide.refined()))
+            }
+            guide => guide,
+        };
+    }
+
+    // A first round with a few samples in every pixel means an interrupted render still
+    // covers the whole image. More rounds would cost more than they give: rendering a pixel's
+    // samples in a row is faster, with the same part of the scene cached, unless the image is
+    // to be written after every few rounds.
+    let guide = guide.map(|guide| (guide, false));
+    let samples_per_pixel = settings.samples_per_pixel;
+    let preview = (samples_per_pixel / PREVIEW_FRACTION).max(1);
+    let rounds: Box<dyn Iterator<Item = usize>> = match (deadline, refresh) {
+        (Some(_), _) => Box::new((1..).map(move |round| round * samples_per_pixel)),
+        (None, Some(Refresh::Passes(_))) => Box::new(
+            (1..=samples_per_pixel.div_ceil(preview))
+                .map(move |round| (round * preview).min(samples_per_pixel)),
+        ),
+        (None, _) => Box::new(vec![preview, samples_per_pixel].into_iter()),
+    };
+    let last = if deadline.is_some() {
+        usize::MAX
+    } else {
+        samples_per_pixel
+    };
+    let pixels = (settings.image_width * settings.image_height) as usize;
+    let mut written = Instant::now();
+
+    for (round, samples) in rounds.enumerate() {
+        if stopped(deadline) && previous.len() > 0 {
+            break;
+        }
+        // Time-limited renders only know how many samples they take as they go.
+        if deadline.is_some() && round > 0 {
+            pb.inc_length(settings.image_height as u64 * samples_per_pixel as u64);
+        }
+
+        let start = Instant::now();
+        let pass = render_pass(
+            world,
+            &camera,
+            settings,
+            samples,
+            &Arc::new(previous.samples.clone()),
+            Arc::new(tiles::by_noise(
+                &previous,
+                settings.image_width,
+                settings.image_height,
+            )),
+            guide.clone(),
+            deadline,
+            &mut |pass| match refresh {
+                Some(Refresh::Seconds(seconds)) if written.elapsed().as_secs_f64() >= seconds => {
+                    write(&previous.with_pass(pass, pixels))?;
+                    written = Instant::now();
+                    Ok(())
+                }
+                _ => Ok(()),
+            },
+            pb,
+        )?;
+        previous.add(pass);
+        debug!(
+            "rendered up to {} samples per pixel in {:.2?}",
+            samples,
+            start.elapsed()
+        );
+
+        // The last round is the finished image, which the caller writes itself.
+        if let Some(Refresh::Passes(passes)) = refresh {
+            if (round + 1) % passes == 0 && samples < last && !stopped(deadline) {
+                write(&previous)?;
+            }
+        }
+    }
+
+    Ok(previous)
+}
+
+/// Pixel colors summed over every sample taken so far, for images that keep improving or
+/// that were interrupted, from the top row down.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub(crate) struct Accumulation {
+    sums: Vec<Color>,
+    /// The squares of the luminances of the samples of each pixel, summed, to tell how noisy
+    /// it is. Empty in checkpoints from before they were kept.
+    #[serde(default)]
+    squares: Vec<f64>,
+    /// How many samples each pixel has.
+    samples: Vec<usize>,
+}
+
+impl Accumulation {
+    /// Returns the number of pixels, or 0 before anything was rendered.
+    pub(crate) fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns an image of `pixels` without any samples yet.
+    pub(crate) fn empty(pixels: usize) -> Self {
+        Self {
+            sums: vec![Color::zeros(); pixels],
+            squares: vec![0.0; pixels],
+            samples: vec![0; pixels],
+        }
+    }
+
+    /// Returns the sum of the samples of the pixel at `index`, the sum of the squares of their
+    /// luminances, and how many there are.
+    pub(crate) fn pixel(&self, index: usize) -> (Color, f64, usize) {
+        let squares = self.squares.get(index).copied().unwrap_or_default();
+        (self.sums[index], squares, self.samples[index])
+    }
+
+    pub(crate) fn set_pixel(&mut self, index: usize, sum: Color, squares: f64, samples: usize) {
+        self.sums[index] = sum;
+        if let Some(total) = self.squares.get_mut(index) {
+            *total = squares;
+        }
+        self.samples[index] = samples;
+    }
+
+    /// Returns how much noise is left in the pixel at `index` once displayed: the variance of
+    /// its mean luminance, relative to that luminance since brighter pixels are displayed with
+    /// less contrast. Pixels with too few samples to tell are infinitely noisy, and all of them
+    /// as noisy as each other when the squares were not kept.
+    pub(crate) fn noise(&self, index: usize) -> f64 {
+        let samples = self.samples[index];
+        if samples < 2 || self.squares.len() != self.samples.len() {
+            return INFINITY;
+        }
+
+        let n = samples as f64;
+        let mean = luminance(&self.sums[index]) / n;
+        let variance = (self.squares[index] / n - mean * mean).max(0.0) * n / (n - 1.0);
+
+        variance / n / (mean.max(0.0) + 0.01)
+    }
+
+    /// Returns the fewest samples any pixel has.
+    pub(crate) fn samples_per_pixel(&self) -> usize {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    /// Starts over, for when the scene changed.
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns the RGB8 image data, with each pixel averaged over its own samples and those
+    /// without any black.
+    pub(crate) fn image(&self) -> Vec<u8> {
+        self.sums
+            .iter()
+            .zip(&self.samples)
+            .flat_map(|(sum, &samples)| {
+                let (r, g, b) = clamp_color(sum, samples.max(1));
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    /// Returns the linear color of each pixel, averaged over its own samples, and black for
+    /// those without any.
+    pub(crate) fn colors(&self) -> Vec<Color> {
+        self.sums
+            .iter()
+            .zip(&self.samples)
+            .map(|(sum, &samples)| sum / samples.max(1) as f64)
+            .collect()
+    }
+
+    /// Returns this image of `pixels` with `pass`, a pass still being rendered, added in.
+    fn with_pass(&self, pass: &Accumulation, pixels: usize) -> Accumulation {
+        let mut image = if self.len() == 0 {
+            Accumulation::empty(pixels)
+        } else {
+            self.clone()
+        };
+        image.add(pass.clone());
+
+        image
+    }
+
+    fn add(&mut self, other: Accumulation) {
+        if self.sums.is_empty() {
+            *self = other;
+            return;
+        }
+
+        for (total, sum) in self.sums.iter_mut().zip(other.sums) {
+            *total += sum;
+        }
+        // Squares missing from either side cannot be made up for.
+        if self.squares.len() == other.squares.len() {
+            for (total, squares) in self.squares.iter_mut().zip(other.squares) {
+                *total += squares;
+            }
+        } else {
+            self.squares.clear();
+        }
+        for (total, samples) in self.samples.iter_mut().zip(other.samples) {
+            *total += samples;
+        }
+    }
+}
+
+/// Renders another `settings.samples_per_pixel` for each pixel of `accumulation`, without path
+/// guiding, unless Ctrl-C stops it early.
+pub(crate) fn accumulate<H>(
+    world: &Arc<World<H>>,
+    camera: &Arc<Camera>,
+    settings: RenderSettings,
+    accumulation: &mut Accumulation,
+    pb: &ProgressBar,
+) -> Result<()>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    let pass = render_pass(
+        world,
+        camera,
+        settings,
+        settings.samples_per_pixel,
+        &Arc::new(vec![]),
+        Arc::new(tiles::by_noise(
+            accumulation,
+            settings.image_width,
+            settings.image_height,
+        )),
+        None,
+        None,
+        &mut |_| Ok(()),
+        pb,
+    )?;
+    accumulation.add(pass);
+
+    Ok(())
+}
+
+/// Returns the color of a sample of pixel (`i`, `j`), counted from the bottom left corner of
+/// the image of `settings`, with its random numbers drawn as the current thread draws them.
+pub(crate) fn sample_pixel<H: Hittable>(
+    world: &World<H>,
+    camera: &Camera,
+    settings: &RenderSettings,
+    sampling: &PathSampling,
+    i: u32,
+    j: u32,
+) -> Color {
+    let u = (i as f64 + random_double()) / (settings.image_width - 1) as f64;
+    let v = (j as f64 + random_double()) / (settings.image_height - 1) as f64;
+    let r = camera.get_ray(u, v);
+
+    if settings.spectral {
+        let wavelengths = sample_wavelengths();
+        let radiance = ray_radiance(&r, world, settings.max_depth, sampling, &wavelengths);
+        to_pixel(&wavelengths, &radiance)
+    } else {
+        ray_color(&r, world, settings.max_depth, sampling)
+    }
+}
+
+/// Renders one image with `samples_per_pixel`, less those each pixel already has in `done`
+/// (which may be empty), a tile at a time in the order of `tiles`, following `guide` if there
+/// is one and also training it if its flag is set, and handing the image so far to `on_tile`
+/// after each tile. Pixels stop taking samples once Ctrl-C is pressed or past `deadline`.
+#[allow(clippy::too_many_arguments)]
+fn render_pass<H>(
+    world: &Arc<World<H>>,
+    camera: &Arc<Camera>,
+    settings: RenderSettings,
+    samples_per_pixel: usize,
+    done: &Arc<Vec<usize>>,
+    tiles: Arc<Vec<Tile>>,
+    guide: Option<(Arc<Guide>, bool)>,
+    deadline: Option<Instant>,
+    on_tile: &mut dyn FnMut(&Accumulation) -> Result<()>,
+    pb: &ProgressBar,
+) -> Result<Accumulation>
+where
+    H: Hittable + Send + Sync + 'static,
+{
+    let RenderSettings {
+        image_width,
+        image_height,
+        light_sampling,
+        regularization,
+        sampler,
+        ..
+    } = settings;
+
+    // Dithered samples are the same in every pixel of a pass, and differ between passes.
+    let seed: u64 = rand::random();
+    let pixels = (image_width * image_height) as usize;
+    let mut accumulation = Accumulation::empty(pixels);
+    // Threads take the next tile as soon as they are done with one, so that the tiles are
+    // finished roughly in order.
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = channel();
+    let workers: Vec<_> = (0..nice::threads())
+        .map(|n| {
+            let world = world.clone();
+            let camera = camera.clone();
+            let guide = guide.clone();
+            let done = done.clone();
+            let tiles = tiles.clone();
+            let next = next.clone();
+            let sender = sender.clone();
+            spawn(move || {
+                let occlusion = world.occlusion_cache(&settings);
+                let sampling = PathSampling {
+                    regularization,
+                    lights: light_sampling,
+                    guide: guide.as_ref().map(|(guide, _)| guide.as_ref()),
+                    training: guide.as_ref().is_some_and(|(_, training)| *training),
+                    occlusion: occlusion.as_ref(),
+                };
+
+                while let Some(tile) = {
+                    nice::wait_turn(n);
+                    tiles.get(next.fetch_add(1, AtomicOrdering::Relaxed))
+                } {
+                    let colors: Vec<_> = tile
+                        .pixels()
+                        .map(|(i, row)| {
+                            let j = image_height - 1 - row;
+                            let index = (row * image_width + i) as usize;
+                            let samples = samples_per_pixel
+                                .saturating_sub(done.get(index).copied().unwrap_or_default());
+                            let (pixel_color, squares, taken) = (0..samples)
+                                .take_while(|_| !stopped(deadline))
+                                .map(|sample| {
+                                    let _dither = (sampler == Sampler::BlueNoise)
+                                        .then(|| dither(seed, i as usize, j as usize, sample));
+
+                                    sample_pixel(&world, &camera, &settings, &sampling, i, j)
+                                })
+                                .fold(
+                                    (Color::new(0.0, 0.0, 0.0), 0.0, 0),
+                                    |(pixel_color, squares, taken), color| {
+                                        let y = luminance(&color);
+                                        (pixel_color + color, squares + y * y, taken + 1)
+                                    },
+                                );
+
+                            (index, pixel_color, squares, taken)
+                        })
+                        .collect();
+
+                    sender.send(colors).ok();
+                }
+            })
+        })
+        .collect(); // create workers list
+
+    // The workers hold the only senders left, so that a panic cannot leave this one waiting.
+    drop(sender);
+
+    // Progress is counted in samples per pixel of each row, as if the tiles were rows.
+    let mut total_samples = 0;
+    let mut progress = 0;
+    for _ in 0..tiles.len() {
+        for (index, sum, squares, samples) in receiver.recv()? {
+            accumulation.set_pixel(index, sum, squares, samples);
+            total_samples += samples;
+        }
+
+        let rows = total_samples / image_width as usize;
+        pb.inc((rows - progress) as u64);
+        progress = rows;
+        on_tile(&accumulation)?;
+    }
+
+    // Wait for the workers to let go of the world, so that it can clean up after itself.
+    for handle in workers {
+        handle
+            .join()
+            .map_err(|_| eyre!("a render thread panicked"))?;
+    }
+
+    Ok(accumulation)
+}
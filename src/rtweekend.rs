@@ -2,6 +2,9 @@ use lazy_static::lazy_static;
 use rand::distributions::Uniform;
 use rand::prelude::*;
 
+use crate::sampler;
+use crate::tape;
+
 pub(crate) const INFINITY: f64 = f64::INFINITY;
 
 lazy_static! {
@@ -9,9 +12,15 @@ lazy_static! {
 }
 
 pub(crate) fn random_double() -> f64 {
-    let mut rng = rand::thread_rng();
+    tape::taped(|| {
+        if let Some(value) = sampler::next_dithered() {
+            return value;
+        }
+
+        let mut rng = rand::thread_rng();
 
-    DISTRIBUTION.sample(&mut rng)
+        DISTRIBUTION.sample(&mut rng)
+    })
 }
 
 pub(crate) fn random_double_between(min: f64, max: f64) -> f64 {
@@ -0,0 +1,265 @@
+use std::cell::Cell;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+
+/// Width and height of the blue-noise mask, which tiles the image.
+const MASK_SIZE: usize = 64;
+
+/// Random numbers of each sample that are dithered across pixels; later ones are independent.
+/// Paths draw a varying number of them at each bounce, so those further in rarely line up
+/// between neighbouring pixels anyway.
+const DIMENSIONS: usize = 16;
+
+/// Spread of the Gaussian filter that tells clusters from voids while building the mask.
+const SIGMA: f64 = 1.5;
+
+lazy_static! {
+    static ref MASK: Vec<f64> = void_and_cluster(MASK_SIZE);
+}
+
+thread_local! {
+    static DITHER: Cell<Option<Dither>> = const { Cell::new(None) };
+}
+
+/// How the random numbers of the samples of neighbouring pixels relate to each other.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Sampler {
+    /// Every sample draws its own, so that noise clumps at low sample counts.
+    Random,
+    /// Every pixel draws the same numbers for its `k`th sample, shifted by its value in a
+    /// blue-noise mask (one per dimension), so that errors are spread evenly over the image
+    /// instead, which looks smoother while the image is still noisy.
+    BlueNoise,
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Self::Random),
+            "blue-noise" => Ok(Self::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler `{}`, expected `random` or `blue-noise`",
+                s
+            )),
+        }
+    }
+}
+
+/// The sample that the current thread is drawing random numbers for, see [`Sampler::BlueNoise`].
+#[derive(Clone, Copy)]
+struct Dither {
+    /// Where the pixel falls in the mask.
+    x: usize,
+    y: usize,
+    /// Identifies the sample among those of its pass, the same in every pixel.
+    key: u64,
+    dimension: usize,
+}
+
+/// Dithers the random numbers drawn by the current thread across pixels until dropped, then
+/// goes back to drawing those of the sample drawn before, if any.
+pub(crate) struct DitherGuard {
+    previous: Option<Dither>,
+}
+
+impl Drop for DitherGuard {
+    fn drop(&mut self) {
+        DITHER.with(|dither| dither.set(self.previous));
+    }
+}
+
+/// Makes the random numbers that the current thread draws, until the guard is dropped, those of
+/// sample `sample` of pixel (`i`, `j`) in a pass seeded with `seed`.
+pub(crate) fn dither(seed: u64, i: usize, j: usize, sample: usize) -> DitherGuard {
+    let previous = DITHER.with(|dither| {
+        dither.replace(Some(Dither {
+            x: i % MASK_SIZE,
+            y: j % MASK_SIZE,
+            key: splitmix(seed ^ splitmix(sample as u64)),
+            dimension: 0,
+        }))
+    });
+
+    DitherGuard { previous }
+}
+
+/// Returns the next dithered random number of the current sample, or `None` if it isn't dithered
+/// or has drawn all its dithered dimensions.
+pub(crate) fn next_dithered() -> Option<f64> {
+    DITHER.with(|cell| {
+        let mut dither = cell.get()?;
+        if dither.dimension >= DIMENSIONS {
+            return None;
+        }
+
+        let dimension = dither.dimension;
+        dither.dimension += 1;
+        cell.set(Some(dither));
+
+        // Offset the mask differently for each dimension (by the R2 sequence), so that they
+        // aren't all shifted alike.
+        let (a1, a2) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_3);
+        let dx = ((dimension as f64 + 1.0) * a1).fract() * MASK_SIZE as f64;
+        let dy = ((dimension as f64 + 1.0) * a2).fract() * MASK_SIZE as f64;
+        let x = (dither.x + dx as usize) % MASK_SIZE;
+        let y = (dither.y + dy as usize) % MASK_SIZE;
+
+        let value = to_unit(splitmix(dither.key.wrapping_add(dimension as u64)));
+
+        Some((value + MASK[y * MASK_SIZE + x]).fract())
+    })
+}
+
+pub(crate) fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Maps the top 53 bits of `x` to `0..1`.
+pub(crate) fn to_unit(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Builds a `size` by `size` blue-noise mask, tiling seamlessly, with Ulichney's void and
+/// cluster method: each value is the rank, scaled to `0..1`, at which its texel was added to a
+/// pattern kept as evenly spread as possible.
+fn void_and_cluster(size: usize) -> Vec<f64> {
+    let n = size * size;
+
+    // Toroidal Gaussian weights by offset, so that the mask tiles seamlessly.
+    let weights: Vec<f64> = (0..n)
+        .map(|offset| {
+            let wrap = |d: usize| d.min(size - d) as f64;
+            let (dx, dy) = (wrap(offset % size), wrap(offset / size));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let toggle = |pattern: &mut [bool], energy: &mut [f64], texel: usize| {
+        pattern[texel] = !pattern[texel];
+        let sign = if pattern[texel] { 1.0 } else { -1.0 };
+        let (tx, ty) = (texel % size, texel / size);
+
+        for (other, energy) in energy.iter_mut().enumerate() {
+            let dx = (other % size + size - tx) % size;
+            let dy = (other / size + size - ty) % size;
+            *energy += sign * weights[dy * size + dx];
+        }
+    };
+
+    // The tightest cluster is the set texel with the most energy, and the largest void the
+    // unset texel with the least.
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&texel| pattern[texel])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&texel| !pattern[texel])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+
+    // Start from a tenth of the texels, picked by a fixed hash so that the mask is always the
+    // same, then spread them out until moving the tightest cluster fills the largest void (which
+    // happens long before every texel was moved).
+    let initial = (n / 10).max(1);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&texel| splitmix(texel as u64));
+    for &texel in &order[..initial] {
+        toggle(&mut pattern, &mut energy, texel);
+    }
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy).unwrap();
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy).unwrap();
+        if void == cluster {
+            toggle(&mut pattern, &mut energy, cluster);
+            break;
+        }
+        toggle(&mut pattern, &mut energy, void);
+    }
+
+    let mut rank = vec![0; n];
+
+    // The initial texels are ranked by taking the tightest clusters away, on a copy.
+    let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&removed, &removed_energy).unwrap();
+        toggle(&mut removed, &mut removed_energy, cluster);
+        rank[cluster] = r;
+    }
+
+    // The others by filling the largest voids.
+    for r in initial..n {
+        let void = largest_void(&pattern, &energy).unwrap();
+        toggle(&mut pattern, &mut energy, void);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws the dithered numbers of sample `sample` of pixel (`i`, `j`).
+    fn sequence(i: usize, j: usize, sample: usize) -> Vec<f64> {
+        let _dither = dither(7, i, j, sample);
+
+        std::iter::from_fn(next_dithered).collect()
+    }
+
+    #[test]
+    fn mask_ranks_every_texel_once() {
+        assert_eq!(MASK.len(), MASK_SIZE * MASK_SIZE);
+        assert!(MASK.iter().all(|value| (0.0..1.0).contains(value)));
+
+        let mut ranks: Vec<_> = MASK
+            .iter()
+            .map(|value| (value * MASK.len() as f64) as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert!(ranks.iter().copied().eq(0..MASK.len()));
+    }
+
+    #[test]
+    fn pixels_and_samples_draw_their_own_numbers() {
+        let first = sequence(3, 5, 0);
+        assert_eq!(first.len(), DIMENSIONS);
+        assert!(first.iter().all(|value| (0.0..1.0).contains(value)));
+        assert_eq!(sequence(3, 5, 0), first);
+
+        assert_ne!(sequence(4, 5, 0), first);
+        assert_ne!(sequence(3, 6, 0), first);
+        assert_ne!(sequence(3, 5, 1), first);
+        // The mask tiles the image.
+        assert_eq!(sequence(3 + MASK_SIZE, 5, 0), first);
+    }
+
+    #[test]
+    fn guards_restore_the_sample_drawn_before() {
+        assert_eq!(next_dithered(), None);
+
+        let outer = sequence(1, 2, 0);
+        {
+            let _outer = dither(7, 1, 2, 0);
+            assert_eq!(next_dithered(), Some(outer[0]));
+            {
+                let _inner = dither(7, 8, 9, 3);
+                assert_eq!(next_dithered(), Some(sequence(8, 9, 3)[0]));
+            }
+            // The outer sample picks up where it left off.
+            assert_eq!(next_dithered(), Some(outer[1]));
+        }
+        assert_eq!(next_dithered(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::output::write_atomically;
+use crate::scene_loader::{load_scene, LoadOptions, Scene};
+
+/// Bumped whenever the layout of cached scenes changes.
+const CACHE_VERSION: u32 = 10;
+
+/// A file a cached scene was built from, as it was when the cache was written.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Source {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+impl Source {
+    fn of(path: &Path) -> std::io::Result<Self> {
+        let path = path.canonicalize()?;
+        let metadata = std::fs::metadata(&path)?;
+
+        Ok(Self {
+            path,
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+
+    fn is_unchanged(&self) -> bool {
+        Self::of(&self.path).is_ok_and(|current| current == *self)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Header {
+    version: u32,
+    raytracer: String,
+    sources: Vec<Source>,
+}
+
+pub(crate) fn cache_path(scene_path: &str) -> String {
+    format!("{}.cache", scene_path)
+}
+
+/// Loads a scene from its cache next to the scene file (`scene.yml.cache` for `scene.yml`),
+/// with its meshes and bounding volume hierarchy already built.
+///
+/// The cache is rebuilt whenever it is missing, unreadable, written by another version of the
+/// raytracer, or when the scene file or any file it refers to has changed since.
+pub(crate) fn load_scene_cached(path: &str) -> Result<Scene> {
+    if path == "-" {
+        return Ok(load_scene(path, &LoadOptions::default())?);
+    }
+
+    let cache = cache_path(path);
+    if let Some(scene) = read_cache(&cache) {
+        debug!("read the scene from {}", cache);
+        return Ok(scene);
+    }
+
+    let scene = load_scene(path, &LoadOptions::default())?;
+    match write_cache(&cache, &scene) {
+        Ok(()) => debug!("wrote the scene cache {}", cache),
+        Err(e) => warn!("couldn't write the scene cache {}: {}", cache, e),
+    }
+
+    Ok(scene)
+}
+
+fn read_cache(cache: &str) -> Option<Scene> {
+    let mut reader = BufReader::new(File::open(cache).ok()?);
+    let header: Header = rmp_serde::decode::from_read(&mut reader).ok()?;
+
+    if header.version != CACHE_VERSION
+        || header.raytracer != env!("CARGO_PKG_VERSION")
+        || !header.sources.iter().all(Source::is_unchanged)
+    {
+        return None;
+    }
+
+    let mut scene: Scene = rmp_serde::decode::from_read(&mut reader).ok()?;
+    scene.sources = header
+        .sources
+        .into_iter()
+        .map(|source| source.path)
+        .collect();
+
+    Some(scene)
+}
+
+fn write_cache(cache: &str, scene: &Scene) -> Result<()> {
+    let header = Header {
+        version: CACHE_VERSION,
+        raytracer: env!("CARGO_PKG_VERSION").to_string(),
+        sources: scene
+            .sources
+            .iter()
+            .map(|path| Source::of(path))
+            .collect::<std::io::Result<_>>()?,
+    };
+
+    write_atomically(Path::new(cache), |partial| {
+        let mut writer = BufWriter::new(File::create(partial)?);
+        rmp_serde::encode::write_named(&mut writer, &header)?;
+        rmp_serde::encode::write_named(&mut writer, scene)?;
+        writer.flush()?;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::output::write_atomically;
+use crate::scene_loader::{CameraDef, Color, FogDef, Handedness, IntervalDef, Object, Units, Up};
+
+/// A scene file as it is written, with nothing it refers to loaded and its lengths in its own
+/// units, for tools that edit scenes: move objects, swap their materials or the camera, then
+/// write the file back. Settings the file leaves out stay out, and a file that is only a list
+/// of objects stays one as long as no setting is added to it. Comments are lost, and materials
+/// are written with their defaults spelled out.
+///
+/// The raytracer has no library target, so tools outside of it edit scenes through the `edit`
+/// subcommand, which is built on this.
+#[derive(Default, Deserialize, Serialize)]
+pub(crate) struct SceneDocument {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) camera: Option<CameraDef>,
+    /// Other views of the scene, by name, picked with `--camera`. What they leave out is taken
+    /// from `camera`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cameras: Option<BTreeMap<String, CameraDef>>,
+    /// A uniform color seen in every direction that hits nothing, instead of the sky.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) background: Option<Color>,
+    /// An image seen behind the scene by the camera instead of the background, which still
+    /// lights the scene and shows in reflections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) backplate: Option<String>,
+    /// What a unit of length is in the file, meters by default. Scenes are rendered in meters,
+    /// so that lengths given on the command line are too.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) units: Option<Units>,
+    /// A factor applied to every length of the scene, on top of its units.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) scale: Option<f64>,
+    /// Which axis points up in the file, Y by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) up: Option<Up>,
+    /// Whether the axes of the file are right-handed, by default, or left-handed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) handedness: Option<Handedness>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fog: Option<FogDef>,
+    /// The part of rays along which hits count, in the units of the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) interval: Option<IntervalDef>,
+    /// Names for groups of objects, to link lights to them all at once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sets: Option<BTreeMap<String, Vec<String>>>,
+    /// The lights that only light some objects or are only blocked by some, by name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) light_links: Option<BTreeMap<String, LightLinkDef>>,
+    pub(crate) objects: Vec<NamedObject>,
+    /// Whether the file was only a list of objects.
+    #[serde(skip)]
+    bare: bool,
+}
+
+/// An object of a scene file, which may be given a name to refer to it from the command line.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct NamedObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    #[serde(flatten)]
+    pub(crate) object: Object,
+}
+
+/// What a linked light lights and what casts its shadows, as objects or sets by name. Either
+/// left out means every object.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct LightLinkDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) illuminates: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) shadowed_by: Option<Vec<String>>,
+}
+
+/// A scene file is either a bare list of objects or a document with settings next to them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SceneFile {
+    Objects(Vec<NamedObject>),
+    Document(Box<SceneDocument>),
+}
+
+impl SceneDocument {
+    /// Parses a scene file from its YAML `source`.
+    pub(crate) fn parse(source: &str) -> Result<Self> {
+        Ok(match serde_yaml::from_str(source)? {
+            SceneFile::Objects(objects) => Self {
+                objects,
+                bare: true,
+                ..Self::default()
+            },
+            SceneFile::Document(document) => *document,
+        })
+    }
+
+    /// Reads the scene file at `path`.
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?;
+
+        Self::parse(&source).wrap_err_with(|| format!("parsing {}", path.display()))
+    }
+
+    /// Returns the scene file as YAML.
+    pub(crate) fn to_yaml(&self) -> Result<String> {
+        if self.is_bare() {
+            return Ok(serde_yaml::to_string(&self.objects)?);
+        }
+
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// Writes the scene file to `path`, replacing whatever was there only once it is whole.
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let yaml = self.to_yaml()?;
+
+        write_atomically(path, |partial| Ok(std::fs::write(partial, yaml)?))
+            .wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    /// Returns the objects named `name`, at the top of the file.
+    pub(crate) fn objects_named<'a>(
+        &'a mut self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a mut Object> + 'a {
+        self.objects
+            .iter_mut()
+            .filter(move |named| named.name.as_deref() == Some(name))
+            .map(|named| &mut named.object)
+    }
+
+    fn is_bare(&self) -> bool {
+        self.bare
+            && self.camera.is_none()
+            && self.cameras.is_none()
+            && self.background.is_none()
+            && self.backplate.is_none()
+            && self.units.is_none()
+            && self.scale.is_none()
+            && self.up.is_none()
+            && self.handedness.is_none()
+            && self.fog.is_none()
+            && self.interval.is_none()
+            && self.sets.is_none()
+            && self.light_links.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn documents_are_written_back_as_they_were() {
+        // Numbers are written back as floats.
+        let source = "
+camera:
+  look_from: { x: 0.0, y: 1.0, z: 5.0 }
+units: centimeters
+objects:
+  - name: ball
+    center: { x: 0.0, y: 1.0, z: 0.0 }
+    radius: 1.0
+    material: { albedo: { r: 0.5, g: 0.5, b: 0.5 } }
+  - name: shelf
+    transform: { translate: { x: 1.0, y: 0.0, z: 0.0 }, rotate_y: 90.0 }
+    objects:
+      - obj: shelf.obj
+        material: { albedo: { r: 0.5, g: 0.4, b: 0.3 } }
+";
+        let mut document = SceneDocument::parse(source).unwrap();
+        let yaml = |document: &SceneDocument| -> serde_yaml::Value {
+            serde_yaml::from_str(&document.to_yaml().unwrap()).unwrap()
+        };
+        assert_eq!(
+            yaml(&document),
+            serde_yaml::from_str::<serde_yaml::Value>(source).unwrap()
+        );
+
+        for object in document.objects_named("shelf") {
+            object.translate(&Vec3::new(0.0, 2.0, 0.0));
+        }
+        let written = yaml(&document);
+        let transform = &written["objects"][1]["transform"];
+        assert_eq!(transform["translate"]["y"], serde_yaml::Value::from(2.0));
+        assert_eq!(transform["rotate_y"], serde_yaml::Value::from(90.0));
+
+        // Lists of objects stay lists, until something else is set.
+        let mut bare = SceneDocument::parse("[]").unwrap();
+        assert!(yaml(&bare).is_sequence());
+        bare.scale = Some(2.0);
+        assert!(yaml(&bare).is_mapping());
+    }
+}
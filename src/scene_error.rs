@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use color_eyre::eyre::Report;
+
+/// Why a scene couldn't be loaded, by kind: a file isn't what it should be, couldn't be read or
+/// couldn't be written, or the scene can't be rendered as it is described.
+#[derive(Debug)]
+pub(crate) enum SceneError {
+    /// A file isn't what it should be: the scene file isn't YAML describing a scene, or an OBJ,
+    /// PLY or image file it refers to can't be decoded. The line and column are those of the
+    /// scene file, when it is the one at fault.
+    Parse {
+        path: PathBuf,
+        location: Option<(usize, usize)>,
+        message: String,
+    },
+    /// A file couldn't be read at all.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A file couldn't be written, such as the temporary file of a mesh streamed from disk.
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The scene is well formed but describes something that can't be rendered, such as a
+    /// sphere without a radius or a link to a light that doesn't exist.
+    Invalid(String),
+    /// The scene asks for something the raytracer doesn't do, such as baking a mesh that has
+    /// no texture coordinates.
+    Unsupported(String),
+}
+
+impl SceneError {
+    /// Returns the error of reading the file at `path` from the `report` of whatever read it,
+    /// by its cause: the file couldn't be read, or it isn't what it should be.
+    pub(crate) fn reading(path: impl Into<PathBuf>, report: Report) -> Self {
+        let path = path.into();
+
+        if let Some(e) = report
+            .chain()
+            .find_map(|e| e.downcast_ref::<std::io::Error>())
+        {
+            return Self::Io {
+                path,
+                source: std::io::Error::new(e.kind(), e.to_string()),
+            };
+        }
+        let location = report
+            .chain()
+            .find_map(|e| e.downcast_ref::<serde_yaml::Error>())
+            .and_then(serde_yaml::Error::location)
+            .map(|location| (location.line(), location.column()));
+        let mut messages: Vec<_> = report.chain().map(ToString::to_string).collect();
+        // Some errors repeat what they wrap.
+        messages.dedup();
+        let message = messages.join(": ");
+
+        Self::Parse {
+            path,
+            location,
+            message,
+        }
+    }
+
+    /// Returns the same error, saying it happened in `context`, such as the object at fault.
+    pub(crate) fn within(self, context: impl fmt::Display) -> Self {
+        match self {
+            Self::Parse {
+                path,
+                location,
+                message,
+            } => Self::Parse {
+                path,
+                location,
+                message: format!("{}: {}", context, message),
+            },
+            Self::Invalid(message) => Self::Invalid(format!("{}: {}", context, message)),
+            Self::Unsupported(message) => Self::Unsupported(format!("{}: {}", context, message)),
+            // The path says it all.
+            io @ (Self::Io { .. } | Self::Write { .. }) => io,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { message, .. } => write!(f, "{}", message),
+            Self::Io { path, .. } => write!(f, "reading {}", path.display()),
+            Self::Write { path, .. } => write!(f, "writing {}", path.display()),
+            Self::Invalid(message) | Self::Unsupported(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } | Self::Write { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::eyre::WrapErr;
+
+    #[test]
+    fn tells_unreadable_files_from_malformed_ones() {
+        let missing = std::fs::read("missing.obj")
+            .wrap_err("reading missing.obj")
+            .unwrap_err();
+        match SceneError::reading("missing.obj", missing) {
+            SceneError::Io { path, source } => {
+                assert_eq!(path, PathBuf::from("missing.obj"));
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            e => panic!("expected an I/O error, not {:?}", e),
+        }
+
+        let yaml = serde_yaml::from_str::<Vec<f64>>("- 1\n- [2]\n").unwrap_err();
+        match SceneError::reading("scene.yml", yaml.into()).within("loading scene.yml") {
+            SceneError::Parse {
+                location, message, ..
+            } => {
+                assert_eq!(location, Some((2, 3)));
+                assert!(message.starts_with("loading scene.yml: "));
+            }
+            e => panic!("expected a parse error, not {:?}", e),
+        }
+    }
+}
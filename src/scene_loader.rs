@@ -1,63 +1,219 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::{TryFrom, TryInto};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
-use color_eyre::eyre::Result;
-use serde::Deserialize;
+use nalgebra::{Matrix3, Matrix4, Quaternion, Unit, UnitQuaternion};
+use serde::{Deserialize, Serialize, Serializer};
+use tracing::{debug, warn};
 
+use crate::aabb::Aabb;
+use crate::backplate::Backplate;
+use crate::bvh::{Bvh, Refit};
+use crate::clip::{self, ClipPlane};
+use crate::fog::Fog;
 use crate::hittable::HitRecord;
 use crate::hittable_list::HittableList;
-use crate::material::{Dielectric, Lambertian, Metal, Scatter};
+use crate::info::format_bytes;
+use crate::light::{Light, LightLink, LightLinks, LightTree};
+use crate::material::{
+    Dielectric, DiffuseLight, Fresnel, Lambertian, Material, Metal, Mix, Preview, ShadowCatcher,
+    Sheen, Water,
+};
+use crate::memory_budget::MemoryBudget;
+use crate::mesh::{crease_normals, Backfaces, Mesh, SmoothGeometry};
+use crate::mesh_sequence::MeshSequence;
 use crate::moving_sphere::MovingSphere;
+use crate::obj::{count_obj, load_obj, load_vertices, triangles};
+use crate::ply::{load_ply, load_ply_thinned};
+use crate::point_cloud::{PointCloud, Splat};
+use crate::ray::Interval;
+use crate::rtweekend::degrees_to_radians;
+use crate::sampler::splitmix;
+use crate::scene_document::{LightLinkDef, NamedObject, SceneDocument};
+use crate::scene_error::SceneError;
 use crate::sphere::Sphere;
+use crate::streamed_mesh::{GeometryCache, StreamedMesh, CHUNK_TRIANGLES};
+use crate::texture::Scalar;
+use crate::transform::{placement, Pose, Transform};
+use crate::vec3::is_finite;
 use crate::Hittable;
 use crate::Ray;
 
-#[derive(Clone, Debug, Deserialize)]
+/// Loading a scene fails with a [`SceneError`], which says what kind of failure it was.
+type Result<T, E = SceneError> = std::result::Result<T, E>;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct Point3 {
     x: f64,
     y: f64,
     z: f64,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl From<&Point3> for crate::Point3 {
+    fn from(p: &Point3) -> Self {
+        Self::new(p.x, p.y, p.z)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct Color {
     r: f64,
     g: f64,
     b: f64,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl From<&crate::Point3> for Point3 {
+    fn from(p: &crate::Point3) -> Self {
+        Self {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+        }
+    }
+}
+
+impl From<&Color> for crate::Color {
+    fn from(c: &Color) -> Self {
+        Self::new(c.r, c.g, c.b)
+    }
+}
+
+impl From<&crate::Color> for Color {
+    fn from(c: &crate::Color) -> Self {
+        Self {
+            r: c.x,
+            g: c.y,
+            b: c.z,
+        }
+    }
+}
+
+/// A [`Material`] as written in scene files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
-pub(crate) enum Material {
-    Metal { albedo: Color, fuzz: f64 },
-    Lambertian { albedo: Color },
-    Dielectric { ir: f64 },
+pub(crate) enum MaterialDef {
+    Metal {
+        albedo: Color,
+        fuzz: Scalar,
+    },
+    /// Cloth, with a `sheen_roughness` of 0.3 by default.
+    Sheen {
+        albedo: Color,
+        sheen: Color,
+        sheen_roughness: Option<Scalar>,
+    },
+    Lambertian {
+        albedo: Color,
+    },
+    Dielectric {
+        ir: Scalar,
+        fresnel: Option<Fresnel>,
+    },
+    Water {
+        water: WaterDef,
+    },
+    Light {
+        emit: Color,
+    },
+    Preview {
+        preview: Preview,
+    },
+    ShadowCatcher {
+        shadow_catcher: ShadowCatcher,
+    },
+    Mix {
+        mix: Box<Mix>,
+    },
 }
 
-impl crate::Material for Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord<Self>, scattered: &Ray) -> Scatter {
-        match self {
-            Self::Metal { albedo, fuzz } => {
-                let albedo = crate::Color::new(albedo.r, albedo.g, albedo.b);
-                let material = Metal::new(albedo, *fuzz);
-                let rec = HitRecord::new(*rec.p(), *rec.normal(), material.clone(), *rec.t());
-                material.scatter(r_in, &rec, scattered)
-            }
-            Self::Lambertian { albedo } => {
-                let albedo = crate::Color::new(albedo.r, albedo.g, albedo.b);
-                let material = Lambertian::new(albedo);
-                let rec = HitRecord::new(*rec.p(), *rec.normal(), material.clone(), *rec.t());
-                material.scatter(r_in, &rec, scattered)
+/// A [`Water`] as written in scene files, by default clear water with waves 5 cm high and
+/// 2 m long.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct WaterDef {
+    ir: Option<Scalar>,
+    /// Fraction of each channel absorbed per meter under the surface.
+    absorption: Option<Color>,
+    wave_height: Option<f64>,
+    wavelength: Option<f64>,
+}
+
+impl From<MaterialDef> for Material {
+    fn from(def: MaterialDef) -> Self {
+        match def {
+            MaterialDef::Metal { albedo, fuzz } => Self::Metal(Metal::new((&albedo).into(), fuzz)),
+            MaterialDef::Sheen {
+                albedo,
+                sheen,
+                sheen_roughness,
+            } => Self::Sheen(Sheen::new(
+                (&albedo).into(),
+                (&sheen).into(),
+                sheen_roughness.unwrap_or(Scalar::Constant(0.3)),
+            )),
+            MaterialDef::Lambertian { albedo } => {
+                Self::Lambertian(Lambertian::new((&albedo).into()))
             }
-            Self::Dielectric { ir } => {
-                let material = Dielectric::new(*ir);
-                let rec = HitRecord::new(*rec.p(), *rec.normal(), material.clone(), *rec.t());
-                material.scatter(r_in, &rec, scattered)
+            MaterialDef::Dielectric { ir, fresnel } => {
+                Self::Dielectric(Dielectric::new(ir, fresnel.unwrap_or_default()))
             }
+            MaterialDef::Water { water } => Self::Water(Water::new(
+                water.ir.unwrap_or(Scalar::Constant(1.33)),
+                water
+                    .absorption
+                    .as_ref()
+                    .map_or(crate::Color::new(0.45, 0.06, 0.02), Into::into),
+                water.wave_height.unwrap_or(0.05),
+                water.wavelength.unwrap_or(2.0),
+            )),
+            MaterialDef::Light { emit } => Self::DiffuseLight(DiffuseLight::new((&emit).into())),
+            MaterialDef::Preview { preview } => Self::Preview(preview),
+            MaterialDef::ShadowCatcher { shadow_catcher } => Self::ShadowCatcher(shadow_catcher),
+            MaterialDef::Mix { mix } => Self::Mix(mix),
+        }
+    }
+}
+
+impl From<Material> for MaterialDef {
+    fn from(material: Material) -> Self {
+        match material {
+            Material::Metal(metal) => Self::Metal {
+                albedo: metal.albedo().into(),
+                fuzz: metal.fuzz().clone(),
+            },
+            Material::Sheen(sheen) => Self::Sheen {
+                albedo: sheen.albedo().into(),
+                sheen: sheen.sheen().into(),
+                sheen_roughness: Some(sheen.roughness().clone()),
+            },
+            Material::Lambertian(lambertian) => Self::Lambertian {
+                albedo: lambertian.albedo().into(),
+            },
+            Material::Dielectric(dielectric) => Self::Dielectric {
+                ir: dielectric.ir().clone(),
+                fresnel: Some(dielectric.fresnel()),
+            },
+            Material::Water(water) => Self::Water {
+                water: WaterDef {
+                    ir: Some(water.ir().clone()),
+                    absorption: Some(water.absorption().into()),
+                    wave_height: Some(water.wave_height()),
+                    wavelength: Some(water.wavelength()),
+                },
+            },
+            Material::DiffuseLight(light) => Self::Light {
+                emit: light.emit().into(),
+            },
+            Material::Preview(preview) => Self::Preview { preview },
+            Material::ShadowCatcher(shadow_catcher) => Self::ShadowCatcher { shadow_catcher },
+            Material::Mix(mix) => Self::Mix { mix },
         }
     }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, PartialEq, Serialize)]
 pub(crate) struct StartEndPair<T> {
     start: T,
     end: T,
@@ -75,69 +231,3033 @@ impl<T> StartEndPair<T> {
     pub(crate) fn end(&self) -> &T {
         &self.end
     }
+
+    pub(crate) fn map<U>(&self, f: impl Fn(&T) -> U) -> StartEndPair<U> {
+        StartEndPair::new(f(&self.start), f(&self.end))
+    }
 }
 
-#[derive(Clone, Deserialize)]
+/// A value that is either fixed or moves from `start` to `end` while the shutter is open.
+#[derive(Clone, Deserialize, Debug, Serialize)]
 #[serde(untagged)]
-pub(crate) enum Object {
-    Sphere {
-        center: Point3,
-        radius: f64,
-        material: Material,
-    },
-    MovingSphere {
-        center: StartEndPair<Point3>,
+pub(crate) enum Animated<T> {
+    Moving(StartEndPair<T>),
+    Fixed(T),
+}
+
+impl<T> Animated<T> {
+    fn map<U>(self, f: impl Fn(T) -> U) -> Animated<U> {
+        match self {
+            Self::Moving(pair) => Animated::Moving(StartEndPair::new(f(pair.start), f(pair.end))),
+            Self::Fixed(value) => Animated::Fixed(f(value)),
+        }
+    }
+
+    fn start_end(&self) -> StartEndPair<&T> {
+        match self {
+            Self::Moving(pair) => StartEndPair::new(&pair.start, &pair.end),
+            Self::Fixed(value) => StartEndPair::new(value, value),
+        }
+    }
+}
+
+/// Where a transform puts its objects. Each part is optional, and they apply in the reverse
+/// order of their fields: `matrix` first, then the `rotate` Euler angles, `axis_angle`,
+/// `quaternion`, `rotate_y` and finally `translate`.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct Placement {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translate: Option<Point3>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotate_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quaternion: Option<QuaternionDef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    axis_angle: Option<AxisAngle>,
+    /// Degrees around the X, then Y, then Z axes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotate: Option<Point3>,
+    /// An affine matrix, by rows, which may scale or shear the objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<[[f64; 4]; 4]>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct QuaternionDef {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct AxisAngle {
+    axis: Point3,
+    /// Degrees.
+    angle: f64,
+}
+
+impl TryFrom<&Placement> for Pose {
+    type Error = String;
+
+    fn try_from(p: &Placement) -> Result<Self, Self::Error> {
+        let translate = p
+            .translate
+            .as_ref()
+            .map(crate::Point3::from)
+            .unwrap_or_else(crate::Point3::zeros);
+        let mut rigid = placement(translate, p.rotate_y.unwrap_or(0.0));
+
+        if let Some(q) = &p.quaternion {
+            let q = Quaternion::new(q.w, q.x, q.y, q.z);
+            let q = UnitQuaternion::try_new(q, f64::EPSILON)
+                .ok_or("the quaternion of a transform can't be zero")?;
+            rigid.rotation *= q;
+        }
+
+        if let Some(AxisAngle { axis, angle }) = &p.axis_angle {
+            let axis = Unit::try_new(crate::Point3::from(axis), f64::EPSILON)
+                .ok_or("the axis of a transform can't be zero")?;
+            rigid.rotation *= UnitQuaternion::from_axis_angle(&axis, degrees_to_radians(*angle));
+        }
+
+        if let Some(rotate) = &p.rotate {
+            rigid.rotation *= UnitQuaternion::from_euler_angles(
+                degrees_to_radians(rotate.x),
+                degrees_to_radians(rotate.y),
+                degrees_to_radians(rotate.z),
+            );
+        }
+
+        let matrix = match &p.matrix {
+            Some(rows) => {
+                let matrix = Matrix4::from_fn(|i, j| rows[i][j]);
+                if matrix.row(3) != Matrix4::<f64>::identity().row(3) {
+                    return Err(format!(
+                        "the last row of a transform matrix must be `[0, 0, 0, 1]`, not {:?}",
+                        rows[3]
+                    ));
+                }
+                // Relative to the lengths of its columns, so that small scales stay invertible.
+                let linear = matrix.fixed_slice::<3, 3>(0, 0);
+                let lengths: f64 = linear.column_iter().map(|c| c.norm()).product();
+                if linear.determinant().abs() <= 1e-12 * lengths {
+                    return Err(format!(
+                        "transform matrix {:?} can't be inverted, it flattens objects",
+                        rows
+                    ));
+                }
+                matrix
+            }
+            None => Matrix4::identity(),
+        };
+
+        Ok(Pose { matrix, rigid })
+    }
+}
+
+/// How a group of objects is placed in the scene, optionally moving over `time`.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum TransformDef {
+    Moving {
+        start: Placement,
+        end: Placement,
         time: StartEndPair<f64>,
-        radius: f64,
-        material: Material,
     },
+    Fixed(Placement),
 }
 
-impl Hittable for Object {
-    type Material = Material;
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<Self::Material>> {
+impl From<&Pose> for Placement {
+    fn from(pose: &Pose) -> Self {
+        let translate = pose.rigid.translation.vector;
+        let rotation = pose.rigid.rotation;
+
+        Self {
+            translate: Some((&translate).into()),
+            rotate_y: None,
+            quaternion: Some(QuaternionDef {
+                w: rotation.w,
+                x: rotation.i,
+                y: rotation.j,
+                z: rotation.k,
+            }),
+            axis_angle: None,
+            rotate: None,
+            matrix: (pose.matrix != Matrix4::identity())
+                .then(|| [0, 1, 2, 3].map(|i| [0, 1, 2, 3].map(|j| pose.matrix[(i, j)]))),
+        }
+    }
+}
+
+impl TransformDef {
+    /// Returns the transform followed by a move of `offset`, which is the last thing placements
+    /// do.
+    pub(crate) fn translated(&self, offset: &crate::Vec3) -> Self {
+        let translate = |placement: &Placement| {
+            let translate = placement
+                .translate
+                .as_ref()
+                .map_or_else(crate::Point3::zeros, crate::Point3::from);
+            Placement {
+                translate: Some((&(translate + offset)).into()),
+                ..placement.clone()
+            }
+        };
+
         match self {
-            Self::Sphere {
-                center,
-                radius,
-                material,
-            } => {
-                let center = crate::Point3::new(center.x, center.y, center.z);
-                Sphere::new(center, *radius, material.clone()).hit(r, t_min, t_max)
+            Self::Moving { start, end, time } => Self::Moving {
+                start: translate(start),
+                end: translate(end),
+                time: time.clone(),
+            },
+            Self::Fixed(placement) => Self::Fixed(translate(placement)),
+        }
+    }
+}
+
+impl From<Transform> for TransformDef {
+    fn from(transform: Transform) -> Self {
+        if let Some(def) = transform.def() {
+            return def.clone();
+        }
+
+        Self::Moving {
+            start: transform.start().into(),
+            end: transform.end().into(),
+            time: transform.time().clone(),
+        }
+    }
+}
+
+impl TryFrom<TransformDef> for Transform {
+    type Error = String;
+
+    fn try_from(def: TransformDef) -> Result<Self, Self::Error> {
+        let transform = match &def {
+            TransformDef::Moving { start, end, time } => {
+                Transform::new(start.try_into()?, end.try_into()?, time.clone())
             }
-            Self::MovingSphere {
-                center,
-                time,
-                radius,
-                material,
-            } => {
-                let center = StartEndPair {
-                    start: crate::vec3::Point3::new(center.start.x, center.start.y, center.start.z),
-                    end: crate::vec3::Point3::new(center.end.x, center.end.y, center.end.z),
+            TransformDef::Fixed(placement) => Transform::fixed(placement.try_into()?),
+        };
+
+        Ok(transform.written_as(def))
+    }
+}
+
+/// A [`Fog`] as written in scene files.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct FogDef {
+    density: f64,
+    /// White, scattering all that it stops, by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+    /// How far the fog reaches along each ray, 100 meters by default, or without limit in a
+    /// container.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+    /// How many points along each camera ray gather light, 16 by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<usize>,
+    /// The name of the objects to fill, in place of the whole scene. Those objects aren't
+    /// rendered, they only bound the fog.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+}
+
+impl From<FogDef> for Fog {
+    fn from(def: FogDef) -> Self {
+        let distance = match &def.container {
+            Some(_) => f64::INFINITY,
+            None => 100.0,
+        };
+
+        Fog::new(
+            def.density,
+            def.color
+                .as_ref()
+                .map_or(crate::Color::new(1.0, 1.0, 1.0), Into::into),
+            def.distance.unwrap_or(distance),
+            def.steps.unwrap_or(16).max(1),
+            def.container,
+        )
+    }
+}
+
+/// An [`Interval`] as written in scene files, by default from 0.001 to infinity.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct IntervalDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    t_min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    t_max: Option<f64>,
+}
+
+impl From<IntervalDef> for Interval {
+    fn from(def: IntervalDef) -> Self {
+        let default = Interval::default();
+
+        Interval::new(
+            def.t_min.unwrap_or(default.t_min),
+            def.t_max.unwrap_or(default.t_max),
+        )
+    }
+}
+
+impl From<Interval> for IntervalDef {
+    fn from(interval: Interval) -> Self {
+        Self {
+            t_min: Some(interval.t_min),
+            t_max: Some(interval.t_max),
+        }
+    }
+}
+
+/// A [`ClipPlane`] as written in scene files.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct ClipPlaneDef {
+    point: Point3,
+    /// Points to the side that is kept.
+    normal: Point3,
+}
+
+impl From<ClipPlaneDef> for ClipPlane {
+    fn from(def: ClipPlaneDef) -> Self {
+        ClipPlane::new((&def.point).into(), (&def.normal).into())
+    }
+}
+
+impl From<ClipPlane> for ClipPlaneDef {
+    fn from(plane: ClipPlane) -> Self {
+        Self {
+            point: plane.point().into(),
+            normal: plane.normal().into(),
+        }
+    }
+}
+
+/// Either a single value or a list of them.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+/// A triangle mesh, from OBJ files or with inline `vertices` and `faces`. Several OBJ files,
+/// or several lists of vertices, make the mesh deform from one to the next over `time`.
+///
+/// A `sequence` is instead a directory of OBJ or PLY files, one per frame, numbered in their
+/// names: frame `n` shows from time `n / frame_rate` until the next.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct MeshDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obj: Option<OneOrMany<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<String>,
+    /// Frames of the sequence per unit of time, 24 by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_rate: Option<f64>,
+    /// Whether frames of the sequence deform into the next, when they have the same faces,
+    /// rather than holding until it shows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interpolate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vertices: Option<OneOrMany<Vec<Point3>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    faces: Option<Vec<[usize; 3]>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<StartEndPair<f64>>,
+    /// What a unit of the vertices is, when it isn't that of the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<Units>,
+    /// How the axes of the vertices lie, when not as in the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    up: Option<Up>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handedness: Option<Handedness>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backfaces: Option<Backfaces>,
+    /// Shades the mesh smooth across edges where its faces meet at less than this many degrees,
+    /// and faceted along sharper ones. Meshes are faceted everywhere without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crease_angle: Option<f64>,
+    material: Material,
+}
+
+/// The vertex positions of every frame of a mesh, and its faces.
+type MeshGeometry = (Vec<Vec<crate::Point3>>, Vec<[usize; 3]>);
+
+/// Checks the `frames` and `faces` of a mesh and turns them from `axes` to those of the
+/// renderer, with the vertices multiplied by `scale`. Also returns how many faces were left
+/// out for having no area.
+fn prepare(
+    frames: Vec<Vec<crate::Point3>>,
+    faces: Vec<[usize; 3]>,
+    scale: f64,
+    axes: &Axes,
+) -> Result<(MeshGeometry, usize)> {
+    Mesh::validate(&frames, &faces).map_err(SceneError::Invalid)?;
+    // Modelling tools often leave a few faces without area behind; they can't show, so they
+    // are left out rather than failing the whole mesh.
+    let count = faces.len();
+    let faces: Vec<_> = faces
+        .into_iter()
+        .filter(|&face| !Mesh::is_degenerate(&frames, face))
+        .map(|[a, b, c]| if axes.mirrors() { [a, c, b] } else { [a, b, c] })
+        .collect();
+    let left_out = count - faces.len();
+
+    let frames = frames
+        .into_iter()
+        .map(|frame| frame.into_iter().map(|p| axes.point(&p) * scale).collect())
+        .collect();
+
+    Ok(((frames, faces), left_out))
+}
+
+/// Returns the OBJ and PLY files in `directory` by the frame number in their names (the last
+/// digits in them), in order.
+fn sequence_files(directory: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let io = |source| SceneError::Io {
+        path: directory.to_path_buf(),
+        source,
+    };
+    let entries = std::fs::read_dir(directory).map_err(io)?;
+
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry.map_err(io)?.path();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        if !matches!(extension.as_deref(), Some("obj" | "ply")) {
+            continue;
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let digits: String = stem
+            .chars()
+            .rev()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(char::is_ascii_digit)
+            .collect();
+        let number = digits
+            .chars()
+            .rev()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| {
+                SceneError::Invalid(format!(
+                    "{} has no frame number in its name",
+                    path.display()
+                ))
+            })?;
+        files.push((number, path));
+    }
+
+    files.sort();
+    if let Some(pair) = files.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(SceneError::Invalid(format!(
+            "{} and {} are both frame {}",
+            pair[0].1.display(),
+            pair[1].1.display(),
+            pair[0].0
+        )));
+    }
+    if files.is_empty() {
+        return Err(SceneError::Invalid(format!(
+            "{} has no OBJ or PLY files",
+            directory.display()
+        )));
+    }
+
+    Ok(files)
+}
+
+/// Reads the vertices and faces of an OBJ or PLY file.
+fn read_frame(path: &Path) -> Result<(Vec<crate::Point3>, Vec<[usize; 3]>)> {
+    let is_ply = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ply"));
+
+    if is_ply {
+        let geometry =
+            load_ply(&path.to_string_lossy()).map_err(|e| SceneError::reading(path, e))?;
+        Ok((geometry.vertices, geometry.faces))
+    } else {
+        let geometry =
+            load_obj(&path.to_string_lossy()).map_err(|e| SceneError::reading(path, e))?;
+        Ok((geometry.vertices, geometry.faces))
+    }
+}
+
+impl MeshDef {
+    /// Gives the mesh with `frames` and `faces` the normals its `crease_angle` asks for, if
+    /// any, splitting the vertices on creases.
+    fn shading(
+        &self,
+        frames: Vec<Vec<crate::Point3>>,
+        faces: Vec<[usize; 3]>,
+    ) -> Result<SmoothGeometry> {
+        match self.crease_angle {
+            None => Ok((frames, vec![], faces)),
+            Some(angle) if (0.0..=180.0).contains(&angle) => {
+                Ok(crease_normals(frames, &faces, angle))
+            }
+            Some(angle) => Err(SceneError::Invalid(format!(
+                "the crease angle of a mesh must be from 0 to 180 degrees, not {}",
+                angle
+            ))),
+        }
+    }
+
+    /// Reads the frames and faces of the mesh, with OBJ files relative to `base_dir`, and
+    /// multiplies the vertices by `scale` once turned from `axes` to those of the renderer.
+    fn geometry(
+        &self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        scale: f64,
+        axes: &Axes,
+    ) -> Result<MeshGeometry> {
+        let (frames, faces) = match (&self.obj, &self.vertices, &self.faces) {
+            (Some(paths), None, None) if self.sequence.is_none() => {
+                let mut frames = vec![];
+                let mut faces = None;
+
+                for path in paths.clone().into_vec() {
+                    let path = base_dir.join(path);
+                    let geometry = load_obj(&path.to_string_lossy())
+                        .map_err(|e| SceneError::reading(&path, e))?;
+
+                    match &faces {
+                        None => faces = Some(geometry.faces),
+                        Some(faces) if *faces != geometry.faces => {
+                            return Err(SceneError::Invalid(format!(
+                                "{} doesn't have the same faces as the first frame",
+                                path.display()
+                            )))
+                        }
+                        Some(_) => {}
+                    }
+                    frames.push(geometry.vertices);
+                    sources.push(path);
+                }
+
+                (frames, faces.unwrap_or_default())
+            }
+            (None, Some(vertices), Some(faces)) if self.sequence.is_none() => {
+                let frames = vertices
+                    .clone()
+                    .into_vec()
+                    .iter()
+                    .map(|frame| frame.iter().map(crate::Point3::from).collect())
+                    .collect();
+
+                (frames, faces.clone())
+            }
+            _ => {
+                return Err(SceneError::Invalid(
+                    "a mesh needs either `obj`, `sequence`, or both `vertices` and `faces`"
+                        .to_string(),
+                ))
+            }
+        };
+
+        let ((frames, faces), left_out) = prepare(frames, faces, scale, axes)?;
+        if left_out > 0 {
+            warn!("left out {} faces without area from a mesh", left_out);
+        }
+
+        Ok((frames, faces))
+    }
+
+    /// Returns how many vertices (of every frame, with their normals) and triangles the mesh
+    /// holds, counted from its OBJ files without reading their geometry.
+    fn size(&self, base_dir: &Path) -> Result<(usize, usize)> {
+        let (vertices, triangles, frames) = match (&self.obj, &self.vertices, &self.faces) {
+            (Some(paths), _, _) => {
+                let paths = paths.clone().into_vec();
+                let path = base_dir.join(&paths[0]);
+                let (vertices, triangles) = count_obj(&path.to_string_lossy())
+                    .map_err(|e| SceneError::reading(&path, e))?;
+                (vertices, triangles, paths.len())
+            }
+            (None, Some(vertices), Some(faces)) => {
+                let vertices = vertices.clone().into_vec();
+                (vertices[0].len(), faces.len(), vertices.len())
+            }
+            _ => (0, 0, 0),
+        };
+        let sets = if self.crease_angle.is_some() { 2 } else { 1 };
+
+        Ok((vertices * frames * sets, triangles))
+    }
+
+    /// Reads the mesh of OBJ files `paths`, relative to `base_dir`, as
+    /// [`geometry`](Self::geometry) does, into chunks streamed from disk through `cache`. Only
+    /// the vertex positions are held: the triangles are read one at a time and split into
+    /// chunks on disk.
+    #[allow(clippy::too_many_arguments)]
+    fn stream(
+        &self,
+        paths: &OneOrMany<String>,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        scale: f64,
+        axes: &Axes,
+        time: StartEndPair<f64>,
+        cache: Arc<GeometryCache>,
+    ) -> Result<StreamedMesh> {
+        let paths: Vec<_> = paths
+            .clone()
+            .into_vec()
+            .into_iter()
+            .map(|path| base_dir.join(path))
+            .collect();
+        let frames = paths
+            .iter()
+            .map(|path| {
+                let vertices = load_vertices(&path.to_string_lossy())
+                    .map_err(|e| SceneError::reading(path, e))?;
+                Ok(vertices
+                    .into_iter()
+                    .map(|p| axes.point(&p) * scale)
+                    .collect())
+            })
+            .collect::<Result<Vec<Vec<_>>>>()?;
+        Mesh::validate(&frames, &[]).map_err(SceneError::Invalid)?;
+
+        // The triangles of the first frame, checked against those of the others as they come.
+        let mut frame_triangles = paths
+            .iter()
+            .map(|path| {
+                let triangles =
+                    triangles(&path.to_string_lossy()).map_err(|e| SceneError::reading(path, e))?;
+                Ok(triangles.map(move |face| face.map_err(|e| SceneError::reading(path, e))))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (first, others) = frame_triangles.split_first_mut().unwrap();
+        let mut left_out = 0;
+        let faces = std::iter::from_fn(|| {
+            loop {
+                let face = first.next();
+                for (other, path) in others.iter_mut().zip(&paths[1..]) {
+                    match (other.next(), &face) {
+                        (Some(Err(e)), _) => return Some(Err(e)),
+                        (Some(Ok(other)), Some(Ok(face))) if other == *face => {}
+                        (None, None) | (_, Some(Err(_))) => {}
+                        _ => {
+                            return Some(Err(SceneError::Invalid(format!(
+                                "{} doesn't have the same faces as the first frame",
+                                path.display()
+                            ))))
+                        }
+                    }
+                }
+
+                // Faces without area are left out as when meshes are read whole.
+                return match face? {
+                    Ok(face) if Mesh::is_degenerate(&frames, face) => {
+                        left_out += 1;
+                        continue;
+                    }
+                    Ok([a, b, c]) if axes.mirrors() => Some(Ok([a, c, b])),
+                    face => Some(face),
+                };
+            }
+        });
+        let mesh = StreamedMesh::from_triangles(
+            &frames,
+            faces,
+            time,
+            self.material.clone(),
+            self.backfaces.unwrap_or_default(),
+            cache,
+        )?;
+        if left_out > 0 {
+            warn!("left out {} faces without area from a mesh", left_out);
+        }
+        sources.extend(paths);
+
+        Ok(mesh)
+    }
+
+    /// Reads the frames of the sequence that show over `time` as [`geometry`](Self::geometry)
+    /// does, and builds a mesh of each with the time at which it starts.
+    fn sequence(
+        &self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        scale: f64,
+        axes: &Axes,
+        time: &StartEndPair<f64>,
+    ) -> Result<MeshSequence> {
+        let directory = match (&self.sequence, &self.obj, &self.vertices, &self.faces) {
+            (Some(directory), None, None, None) => base_dir.join(directory),
+            _ => {
+                return Err(SceneError::Invalid(
+                    "a mesh needs either `obj`, `sequence`, or both `vertices` and `faces`"
+                        .to_string(),
+                ))
+            }
+        };
+        let frame_rate = self.frame_rate.unwrap_or(24.0);
+        if frame_rate.is_nan() || frame_rate <= 0.0 {
+            return Err(SceneError::Invalid(
+                "the frame rate of a sequence must be positive".to_string(),
+            ));
+        }
+
+        let files = sequence_files(&directory)?;
+        let starts: Vec<_> = files
+            .iter()
+            .map(|(number, _)| *number as f64 / frame_rate)
+            .collect();
+        // The frame showing when the shutter opens, up to the last that starts before it
+        // closes, and the one after for the last of them to deform into.
+        let first = starts
+            .partition_point(|&start| start <= *time.start())
+            .saturating_sub(1);
+        let last = starts
+            .partition_point(|&start| start <= *time.end())
+            .max(first + 1);
+        let interpolate = self.interpolate.unwrap_or(false);
+        let read = if interpolate {
+            (last + 1).min(files.len())
+        } else {
+            last
+        };
+
+        let geometry = files[first..read]
+            .iter()
+            .map(|(_, path)| {
+                sources.push(path.clone());
+                read_frame(path)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let material = self.material.clone();
+        let backfaces = self.backfaces.unwrap_or_default();
+        let mut left_out = 0;
+        let mut frames = vec![];
+        for index in 0..last - first {
+            let (vertices, faces) = &geometry[index];
+            let start = starts[first + index];
+            let end = starts.get(first + index + 1).copied().unwrap_or(start);
+            let mut positions = vec![vertices.clone()];
+            if let Some((next, next_faces)) = geometry.get(index + 1) {
+                if interpolate && next_faces == faces && next.len() == vertices.len() {
+                    positions.push(next.clone());
+                }
+            }
+
+            let path = &files[first + index].1;
+            let ((positions, faces), dropped) = prepare(positions, faces.clone(), scale, axes)
+                .map_err(|e| e.within(format!("loading {}", path.display())))?;
+            left_out += dropped;
+            let (positions, normals, faces) = self.shading(positions, faces)?;
+            let mesh = Mesh::new(
+                positions,
+                normals,
+                &faces,
+                StartEndPair::new(start, end),
+                material.clone(),
+                backfaces,
+            )
+            .map_err(SceneError::Invalid)?;
+            frames.push((start, mesh));
+        }
+        if left_out > 0 {
+            warn!(
+                "left out {} faces without area from the frames of {}",
+                left_out,
+                directory.display()
+            );
+        }
+
+        Ok(MeshSequence::new(frames))
+    }
+
+    /// Reads the triangles of the first frame of the mesh with their texture coordinates, which
+    /// only OBJ files have, with the positions turned from `axes` and multiplied by `scale`.
+    fn textured_triangles(
+        &self,
+        base_dir: &Path,
+        scale: f64,
+        axes: &Axes,
+    ) -> Result<Vec<TexturedTriangle>> {
+        let path = match &self.obj {
+            Some(paths) => base_dir.join(&paths.clone().into_vec()[0]),
+            None => {
+                return Err(SceneError::Unsupported(
+                    "only meshes read from OBJ files have texture coordinates".to_string(),
+                ))
+            }
+        };
+        let geometry =
+            load_obj(&path.to_string_lossy()).map_err(|e| SceneError::reading(&path, e))?;
+        let texture_faces = geometry.texture_faces.as_ref().ok_or_else(|| {
+            SceneError::Unsupported(format!(
+                "{} has faces without texture coordinates",
+                path.display()
+            ))
+        })?;
+
+        Ok(geometry
+            .faces
+            .iter()
+            .zip(texture_faces)
+            .map(|(&[a, b, c], &[ta, tb, tc])| {
+                let (face, texture_face) = if axes.mirrors() {
+                    ([a, c, b], [ta, tc, tb])
+                } else {
+                    ([a, b, c], [ta, tb, tc])
                 };
-                MovingSphere::new(center, time.clone(), *radius, material.clone())
-                    .hit(r, t_min, t_max)
+                TexturedTriangle {
+                    positions: face.map(|index| axes.point(&geometry.vertices[index]) * scale),
+                    texture_coordinates: texture_face
+                        .map(|index| geometry.texture_coordinates[index]),
+                }
+            })
+            .collect())
+    }
+}
+
+/// A triangle placed in the world, with texture coordinates at its corners.
+pub(crate) struct TexturedTriangle {
+    pub(crate) positions: [crate::Point3; 3],
+    pub(crate) texture_coordinates: [(f64, f64); 3],
+}
+
+/// A mesh as written in the scene file. Its geometry is loaded by [`load_scene`] once the whole
+/// file has been parsed, so that errors (like a missing OBJ file) are reported as such.
+#[derive(Deserialize)]
+#[serde(from = "MeshObjectRepr")]
+pub(crate) struct MeshObject {
+    def: MeshDef,
+    mesh: Option<Mesh>,
+    sequence: Option<MeshSequence>,
+    #[serde(skip)]
+    streamed: Option<StreamedMesh>,
+    /// What the vertices are multiplied by when loading them, see [`Object::convert`].
+    #[serde(skip)]
+    scale: f64,
+    /// What the vertices are turned from when loading them, see [`Object::reorient`].
+    #[serde(skip)]
+    axes: Axes,
+}
+
+impl MeshObject {
+    /// Builds the mesh, streaming it from disk if it is large and `options` allow it, or if it
+    /// would go over their memory budget. Only the frames of sequences that show over `time`
+    /// are read.
+    fn load(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        options: &LoadOptions,
+        time: &StartEndPair<f64>,
+    ) -> Result<()> {
+        if self.def.sequence.is_some() {
+            self.sequence = Some(
+                self.def
+                    .sequence(base_dir, sources, self.scale, &self.axes, time)?,
+            );
+            return Ok(());
+        }
+
+        // Whether to stream the mesh is told from its size before reading it, so that meshes
+        // too large to hold are never read whole.
+        let cache = match (&options.geometry_cache, &options.memory_budget) {
+            (None, None) => None,
+            (cache, budget) => {
+                let (vertices, triangles) = self.def.size(base_dir)?;
+                match (cache, budget) {
+                    (Some(cache), _) if triangles > CHUNK_TRIANGLES => Some(cache.clone()),
+                    (None, Some(budget))
+                        if triangles > CHUNK_TRIANGLES
+                            && !budget.fits(Mesh::memory_for(vertices, triangles)) =>
+                    {
+                        warn!(
+                            "streaming a mesh of {} triangles from disk to fit the memory budget",
+                            triangles
+                        );
+                        Some(budget.streaming())
+                    }
+                    _ => None,
+                }
+            }
+        };
+        let time = self
+            .def
+            .time
+            .clone()
+            .unwrap_or_else(|| StartEndPair::new(0.0, 1.0));
+
+        // Meshes read from OBJ files are streamed a triangle at a time, unless their normals
+        // need every face around each vertex.
+        if let (Some(cache), Some(paths), None) = (&cache, &self.def.obj, self.def.crease_angle) {
+            self.streamed = Some(self.def.stream(
+                paths,
+                base_dir,
+                sources,
+                self.scale,
+                &self.axes,
+                time,
+                cache.clone(),
+            )?);
+            return Ok(());
+        }
+
+        let (frames, faces) = self
+            .def
+            .geometry(base_dir, sources, self.scale, &self.axes)?;
+        let (frames, normals, faces) = self.def.shading(frames, faces)?;
+        let material = self.def.material.clone();
+        let backfaces = self.def.backfaces.unwrap_or_default();
+        match cache {
+            Some(cache) => {
+                self.streamed = Some(StreamedMesh::new(
+                    &frames, &normals, &faces, time, material, backfaces, cache,
+                )?);
+            }
+            None => {
+                self.mesh = Some(
+                    Mesh::new(frames, normals, &faces, time, material, backfaces)
+                        .map_err(SceneError::Invalid)?,
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn material(&self) -> &Material {
+        &self.def.material
+    }
+
+    /// Makes the mesh of `material`, keeping its hierarchy. Streamed meshes keep their old
+    /// material until they are loaded again.
+    fn set_material(&mut self, material: Material) {
+        if let Some(mesh) = &mut self.mesh {
+            *mesh = mesh.with_material(material.clone());
+        }
+        if let Some(sequence) = &mut self.sequence {
+            *sequence = sequence.with_material(material.clone());
+        }
+        self.def.material = material;
+    }
+
+    /// Returns the number of triangles, the bytes they take in memory, and the number of nodes
+    /// in their hierarchy, or nothing if the mesh isn't loaded.
+    pub(crate) fn stats(&self) -> Option<(usize, usize, usize)> {
+        match (&self.streamed, &self.mesh, &self.sequence) {
+            (Some(streamed), _, _) => Some((
+                streamed.triangle_count(),
+                streamed.memory(),
+                streamed.node_count(),
+            )),
+            (None, Some(mesh), _) => {
+                Some((mesh.triangle_count(), mesh.memory(), mesh.node_count()))
             }
+            (None, None, Some(sequence)) => Some((
+                sequence.triangle_count(),
+                sequence.memory(),
+                sequence.node_count(),
+            )),
+            (None, None, None) => None,
+        }
+    }
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        match (&self.streamed, &self.sequence) {
+            (Some(streamed), _) => streamed.hit(r, t_min, t_max),
+            (None, Some(sequence)) => sequence.hit(r, t_min, t_max),
+            (None, None) => self.mesh.as_ref()?.hit(r, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        match (&self.streamed, &self.sequence) {
+            (Some(streamed), _) => streamed.bounding_box(time0, time1),
+            (None, Some(sequence)) => sequence.bounding_box(time0, time1),
+            (None, None) => self.mesh.as_ref()?.bounding_box(time0, time1),
         }
     }
 }
 
-pub(crate) fn load_scene(path: &str) -> Result<HittableList<Object>> {
-    let mut scene_yml;
+/// Scene files only have the definition of a mesh; scene caches have the mesh itself too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MeshObjectRepr {
+    Loaded {
+        def: MeshDef,
+        mesh: Mesh,
+    },
+    Sequence {
+        def: MeshDef,
+        sequence: MeshSequence,
+    },
+    Def(MeshDef),
+}
 
-    if path == "-" {
-        scene_yml = String::new();
+impl Serialize for MeshObject {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Loaded<'a> {
+            def: &'a MeshDef,
+            mesh: &'a Mesh,
+        }
+        #[derive(Serialize)]
+        struct Sequence<'a> {
+            def: &'a MeshDef,
+            sequence: &'a MeshSequence,
+        }
 
-        std::io::stdin().read_to_string(&mut scene_yml)?;
-    } else {
-        scene_yml = std::fs::read_to_string(path)?;
+        match (&self.mesh, &self.sequence) {
+            (Some(mesh), _) => Loaded {
+                def: &self.def,
+                mesh,
+            }
+            .serialize(serializer),
+            (None, Some(sequence)) => Sequence {
+                def: &self.def,
+                sequence,
+            }
+            .serialize(serializer),
+            (None, None) => self.def.serialize(serializer),
+        }
     }
+}
 
-    let scene = serde_yaml::from_str::<Vec<Object>>(&scene_yml)?;
-    let mut world = HittableList::new();
-    for object in scene {
-        world.add(object);
+impl From<MeshObjectRepr> for MeshObject {
+    fn from(repr: MeshObjectRepr) -> Self {
+        match repr {
+            MeshObjectRepr::Loaded { def, mesh } => Self {
+                def,
+                mesh: Some(mesh),
+                sequence: None,
+                streamed: None,
+                scale: 1.0,
+                axes: Axes::default(),
+            },
+            MeshObjectRepr::Sequence { def, sequence } => Self {
+                def,
+                mesh: None,
+                sequence: Some(sequence),
+                streamed: None,
+                scale: 1.0,
+                axes: Axes::default(),
+            },
+            MeshObjectRepr::Def(def) => Self {
+                def,
+                mesh: None,
+                sequence: None,
+                streamed: None,
+                scale: 1.0,
+                axes: Axes::default(),
+            },
+        }
     }
+}
+
+/// A point cloud read from the vertices of a PLY file, such as a LiDAR scan or the particles of
+/// a simulation, with each point drawn as a sphere or, facing along its normal (`nx`, `ny` and
+/// `nz`), a disk. Points have the `radius` of their file, or the one given here, and the color
+/// of their file (`red`, `green` and `blue`), unless the cloud is made of a `material`.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub(crate) struct PointCloudDef {
+    points: String,
+    /// The radius of points whose file has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    radius: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    splat: Option<Splat>,
+    /// What a unit of the points is, when it isn't that of the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<Units>,
+    /// How the axes of the points lie, when not as in the scene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    up: Option<Up>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handedness: Option<Handedness>,
+    /// What every point is made of, instead of diffuse surfaces of the colors of the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    material: Option<Material>,
+}
+
+/// A point cloud as written in the scene file, loaded by [`load_scene`] like a [`MeshObject`].
+#[derive(Deserialize)]
+#[serde(from = "PointCloudObjectRepr")]
+pub(crate) struct PointCloudObject {
+    def: PointCloudDef,
+    cloud: Option<PointCloud>,
+    /// What the points and radii are multiplied by when loading them, see [`Object::convert`].
+    #[serde(skip)]
+    scale: f64,
+    /// What the points are turned from when loading them, see [`Object::reorient`].
+    #[serde(skip)]
+    axes: Axes,
+}
+
+impl PointCloudObject {
+    /// Reads the points, relative to `base_dir`, and builds their hierarchy, thinning them out
+    /// with larger radii if they would go over the `budget`.
+    fn load(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        budget: Option<&MemoryBudget>,
+    ) -> Result<()> {
+        let path = base_dir.join(&self.def.points);
+        // How many points to keep is told from the header, so that those left out are never
+        // held.
+        let mut stride = 1;
+        let geometry = load_ply_thinned(&path.to_string_lossy(), |header| {
+            let memory = PointCloud::memory_for(
+                header.count,
+                header.has(&["nx", "ny", "nz"]),
+                self.def.material.is_none() && header.has(&["red", "green", "blue"]),
+            );
+            if let Some(budget) = budget.filter(|budget| !budget.fits(memory)) {
+                stride = memory.div_ceil(budget.remaining().max(1));
+            }
+            stride
+        })
+        .map_err(|e| SceneError::reading(&path, e))?;
+        sources.push(path.clone());
+
+        let count = geometry.vertices.len();
+        let centers: Vec<_> = geometry
+            .vertices
+            .iter()
+            .map(|p| self.axes.point(p) * self.scale)
+            .collect();
+        let mut radii: Vec<_> = match (geometry.properties.get("radius"), self.def.radius) {
+            (Some(radii), _) => radii.iter().map(|radius| radius * self.scale).collect(),
+            (None, Some(radius)) => vec![radius * self.scale; count],
+            (None, None) => {
+                return Err(SceneError::Invalid(format!(
+                    "{} has no `radius` for its points, and the point cloud doesn't give one",
+                    path.display()
+                )))
+            }
+        };
+        // Disks show both of their sides, so mirrored normals can stay as they are.
+        let normals: Option<Vec<_>> = geometry.vectors(["nx", "ny", "nz"]).map(|normals| {
+            normals
+                .iter()
+                .map(|&[x, y, z]| self.axes.point(&crate::Vec3::new(x, y, z)))
+                .collect()
+        });
+
+        let (palette, materials) = match (
+            &self.def.material,
+            geometry.vectors(["red", "green", "blue"]),
+        ) {
+            (Some(material), _) => (vec![], vec![material.clone()]),
+            (None, Some(colors)) => {
+                // Scans have far fewer colors than points, so points share their materials.
+                let mut entries = HashMap::new();
+                let mut materials = vec![];
+                let palette = colors
+                    .iter()
+                    .map(|color| {
+                        *entries.entry(color.map(f64::to_bits)).or_insert_with(|| {
+                            // Undo the gamma of 2 that colors are written with, as images.
+                            let [r, g, b] = color.map(|c| c * c);
+                            materials.push(Material::Lambertian(Lambertian::new(
+                                crate::Color::new(r, g, b),
+                            )));
+                            materials.len() as u32 - 1
+                        })
+                    })
+                    .collect();
+                (palette, materials)
+            }
+            (None, None) => {
+                return Err(SceneError::Invalid(format!(
+                    "{} has no colors for its points, so the point cloud needs a `material`",
+                    path.display()
+                )))
+            }
+        };
+
+        if stride > 1 {
+            // Points drawn larger cover about as much of the surfaces they sample.
+            let growth = (stride as f64).sqrt();
+            radii.iter_mut().for_each(|r| *r *= growth);
+            warn!(
+                "kept 1 in {} points of {}, {:.1} times as large, to fit the memory budget",
+                stride,
+                path.display(),
+                growth
+            );
+        }
+
+        let cloud = PointCloud::new(
+            centers,
+            radii,
+            normals,
+            palette,
+            materials,
+            self.def.splat.unwrap_or_default(),
+        )
+        .map_err(|e| SceneError::Invalid(format!("loading {}: {}", path.display(), e)))?;
+        self.cloud = Some(cloud);
 
-    Ok(world)
+        Ok(())
+    }
+
+    /// Makes every point of `material`, keeping the hierarchy.
+    fn set_material(&mut self, material: Material) {
+        if let Some(cloud) = &mut self.cloud {
+            *cloud = cloud.with_material(material.clone());
+        }
+        self.def.material = Some(material);
+    }
+
+    /// Returns the number of points, the bytes they take in memory, and the number of nodes
+    /// in their hierarchy, or nothing if the cloud isn't loaded.
+    pub(crate) fn stats(&self) -> Option<(usize, usize, usize)> {
+        let cloud = self.cloud.as_ref()?;
+
+        Some((cloud.point_count(), cloud.memory(), cloud.node_count()))
+    }
+
+    /// Returns what the points are made of, unless they have the colors of their file.
+    pub(crate) fn material(&self) -> Option<&Material> {
+        self.def.material.as_ref()
+    }
+}
+
+/// Scene files only have the definition of a point cloud; scene caches have the points too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PointCloudObjectRepr {
+    Loaded {
+        def: PointCloudDef,
+        cloud: PointCloud,
+    },
+    Def(PointCloudDef),
+}
+
+impl Serialize for PointCloudObject {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Loaded<'a> {
+            def: &'a PointCloudDef,
+            cloud: &'a PointCloud,
+        }
+
+        match &self.cloud {
+            Some(cloud) => Loaded {
+                def: &self.def,
+                cloud,
+            }
+            .serialize(serializer),
+            None => self.def.serialize(serializer),
+        }
+    }
+}
+
+impl From<PointCloudObjectRepr> for PointCloudObject {
+    fn from(repr: PointCloudObjectRepr) -> Self {
+        let (def, cloud) = match repr {
+            PointCloudObjectRepr::Loaded { def, cloud } => (def, Some(cloud)),
+            PointCloudObjectRepr::Def(def) => (def, None),
+        };
+
+        Self {
+            def,
+            cloud,
+            scale: 1.0,
+            axes: Axes::default(),
+        }
+    }
+}
+
+/// A [`Sphere`] as written in scene files.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct SphereDef {
+    center: Point3,
+    radius: f64,
+    material: Material,
+}
+
+impl From<SphereDef> for Sphere {
+    fn from(def: SphereDef) -> Self {
+        Sphere::new((&def.center).into(), def.radius, def.material)
+    }
+}
+
+impl From<Sphere> for SphereDef {
+    fn from(sphere: Sphere) -> Self {
+        Self {
+            center: sphere.center().into(),
+            radius: sphere.radius(),
+            material: sphere.material().clone(),
+        }
+    }
+}
+
+/// A [`MovingSphere`] as written in scene files.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct MovingSphereDef {
+    center: StartEndPair<Point3>,
+    time: StartEndPair<f64>,
+    radius: f64,
+    material: Material,
+}
+
+impl From<MovingSphereDef> for MovingSphere {
+    fn from(def: MovingSphereDef) -> Self {
+        MovingSphere::new(
+            def.center.map(|p| p.into()),
+            def.time,
+            def.radius,
+            def.material,
+        )
+    }
+}
+
+impl From<MovingSphere> for MovingSphereDef {
+    fn from(sphere: MovingSphere) -> Self {
+        Self {
+            center: sphere.centers().map(|p| p.into()),
+            time: sphere.time().clone(),
+            radius: sphere.radius(),
+            material: sphere.material().clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum Object {
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Transformed {
+        transform: Box<Transform>,
+        objects: HittableList<Object>,
+        /// Draws the group's [`crate::attributes::Attribute::Random`]. Groups without one are
+        /// seeded when the scene loads, by their place in it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    /// Objects with the parts on the wrong side of any of the planes cut away.
+    Clipped {
+        clip: Vec<ClipPlane>,
+        objects: HittableList<Object>,
+    },
+    /// Objects lit only by some of the linked lights, or casting only some of their shadows.
+    /// Scene files link lights through `light_links`, which wraps the objects when the scene
+    /// loads.
+    Linked {
+        links: LightLinks,
+        objects: HittableList<Object>,
+    },
+    /// Before meshes, which would take point clouds for meshes without geometry.
+    PointCloud(Box<PointCloudObject>),
+    Mesh(Box<MeshObject>),
+}
+
+impl Object {
+    /// Loads the external data the object refers to, with paths relative to `base_dir`, and
+    /// adds the files it read to `sources`.
+    fn load_resources(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        options: &LoadOptions,
+        time: &StartEndPair<f64>,
+    ) -> Result<()> {
+        match self {
+            Self::Transformed { objects, .. }
+            | Self::Clipped { objects, .. }
+            | Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.load_resources(base_dir, sources, options, time)?;
+                }
+            }
+            Self::Mesh(mesh) => {
+                let budget = options.memory_budget.as_deref();
+                mesh.def.material.load_textures(base_dir, sources, budget)?;
+                mesh.load(base_dir, sources, options, time)?;
+                if let (Some(budget), Some((triangles, memory, _))) = (budget, mesh.stats()) {
+                    budget.take(memory, &format!("a mesh of {} triangles", triangles));
+                }
+            }
+            Self::PointCloud(cloud) => {
+                let budget = options.memory_budget.as_deref();
+                if let Some(material) = &mut cloud.def.material {
+                    material.load_textures(base_dir, sources, budget)?;
+                }
+                cloud.load(base_dir, sources, budget)?;
+                if let (Some(budget), Some((points, memory, _))) = (budget, cloud.stats()) {
+                    budget.take(memory, &format!("a point cloud of {} points", points));
+                }
+            }
+            Self::Sphere { .. } | Self::MovingSphere { .. } => {
+                self.load_textures(base_dir, sources, options.memory_budget.as_deref())?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the textures of the object's materials, down to everything inside transforms,
+    /// downscaling them to fit the `budget` if there is one.
+    fn load_textures(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        budget: Option<&MemoryBudget>,
+    ) -> Result<()> {
+        match self {
+            Self::Sphere(sphere) => {
+                let mut material = sphere.material().clone();
+                material.load_textures(base_dir, sources, budget)?;
+                sphere.set_material(material);
+            }
+            Self::MovingSphere(sphere) => {
+                let mut material = sphere.material().clone();
+                material.load_textures(base_dir, sources, budget)?;
+                sphere.set_material(material);
+            }
+            Self::Transformed { objects, .. }
+            | Self::Clipped { objects, .. }
+            | Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.load_textures(base_dir, sources, budget)?;
+                }
+            }
+            Self::Mesh(mesh) => {
+                let mut material = mesh.material().clone();
+                material.load_textures(base_dir, sources, budget)?;
+                mesh.set_material(material);
+            }
+            Self::PointCloud(cloud) => {
+                if let Some(mut material) = cloud.material().cloned() {
+                    material.load_textures(base_dir, sources, budget)?;
+                    cloud.set_material(material);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the object has parameters that don't describe any shape: spheres need a finite
+    /// center and a finite radius other than 0, and clip planes a finite point and a normal.
+    /// A negative radius makes a hollow sphere, with its normals pointing inward, such as the
+    /// inside of a glass bubble. Point clouds need a positive radius, if they give one. Meshes,
+    /// and the points of clouds, are checked when they load.
+    fn validate(&self) -> Result<()> {
+        let sphere = |centers: &[&crate::Point3], radius: f64| {
+            if radius == 0.0 || !radius.is_finite() {
+                return Err(SceneError::Invalid(format!(
+                    "a sphere has a radius of {}",
+                    radius
+                )));
+            }
+            if !centers.iter().all(|center| is_finite(center)) {
+                return Err(SceneError::Invalid(
+                    "a sphere has an infinite or NaN center".to_string(),
+                ));
+            }
+
+            Ok(())
+        };
+
+        match self {
+            Self::Sphere(s) => sphere(&[s.center()], s.radius()),
+            Self::MovingSphere(s) => {
+                let centers = s.centers();
+                sphere(&[centers.start(), centers.end()], s.radius())
+            }
+            Self::Transformed { objects, .. } | Self::Linked { objects, .. } => {
+                objects.iter().try_for_each(Self::validate)
+            }
+            Self::Clipped { clip, objects } => {
+                if !clip
+                    .iter()
+                    .all(|plane| is_finite(plane.point()) && is_finite(plane.normal()))
+                {
+                    return Err(SceneError::Invalid(
+                        "a clip plane has an infinite or NaN point, or no normal".to_string(),
+                    ));
+                }
+                objects.iter().try_for_each(Self::validate)
+            }
+            Self::PointCloud(cloud) => match cloud.def.radius {
+                Some(radius) if !(radius > 0.0 && radius.is_finite()) => Err(SceneError::Invalid(
+                    format!("a point cloud has a radius of {}", radius),
+                )),
+                _ => Ok(()),
+            },
+            Self::Mesh(_) => Ok(()),
+        }
+    }
+
+    /// Seeds the transform groups in the object that have no seed of their own, from `seed`
+    /// and where they are in it.
+    fn seed(&mut self, seed: u64) {
+        let (objects, seed) = match self {
+            Self::Transformed {
+                objects, seed: own, ..
+            } => {
+                let seed = *own.get_or_insert(seed);
+                (objects, seed)
+            }
+            Self::Clipped { objects, .. } | Self::Linked { objects, .. } => (objects, seed),
+            Self::Sphere(_) | Self::MovingSphere(_) | Self::PointCloud(_) | Self::Mesh(_) => return,
+        };
+
+        for (i, object) in objects.iter_mut().enumerate() {
+            object.seed(splitmix(seed.wrapping_add(i as u64 + 1)));
+        }
+    }
+
+    /// Converts the lengths of the object, given in `units`, to meters, and multiplies them by
+    /// `scale`. Meshes with units of their own are converted from those instead.
+    fn convert(&mut self, units: Units, scale: f64) {
+        let factor = units.meters() * scale;
+
+        match self {
+            Self::Sphere(sphere) => {
+                *sphere = Sphere::new(
+                    sphere.center() * factor,
+                    sphere.radius() * factor,
+                    sphere.material().clone(),
+                )
+            }
+            Self::MovingSphere(sphere) => {
+                *sphere = MovingSphere::new(
+                    sphere.centers().map(|center| center * factor),
+                    sphere.time().clone(),
+                    sphere.radius() * factor,
+                    sphere.material().clone(),
+                )
+            }
+            Self::Transformed {
+                transform, objects, ..
+            } => {
+                **transform = transform.scaled(factor);
+                for object in objects.iter_mut() {
+                    object.convert(units, scale);
+                }
+            }
+            Self::Clipped { clip, objects } => {
+                for plane in clip.iter_mut() {
+                    *plane = plane.scaled(factor);
+                }
+                for object in objects.iter_mut() {
+                    object.convert(units, scale);
+                }
+            }
+            Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.convert(units, scale);
+                }
+            }
+            Self::PointCloud(cloud) => {
+                cloud.scale = cloud.def.units.unwrap_or(units).meters() * scale
+            }
+            Self::Mesh(mesh) => mesh.scale = mesh.def.units.unwrap_or(units).meters() * scale,
+        }
+    }
+
+    /// Turns the object from the axes of its scene file, `axes`, to those of the renderer.
+    /// Meshes with axes of their own are turned from those instead.
+    fn reorient(&mut self, axes: &Axes) {
+        let basis = axes.matrix();
+
+        match self {
+            Self::Sphere(sphere) => {
+                *sphere = Sphere::new(
+                    basis * sphere.center(),
+                    sphere.radius(),
+                    sphere.material().clone(),
+                )
+            }
+            Self::MovingSphere(sphere) => {
+                *sphere = MovingSphere::new(
+                    sphere.centers().map(|center| basis * center),
+                    sphere.time().clone(),
+                    sphere.radius(),
+                    sphere.material().clone(),
+                )
+            }
+            Self::Transformed {
+                transform, objects, ..
+            } => {
+                **transform = transform.reoriented(&basis);
+                for object in objects.iter_mut() {
+                    object.reorient(axes);
+                }
+            }
+            Self::Clipped { clip, objects } => {
+                for plane in clip.iter_mut() {
+                    *plane = ClipPlane::new(basis * plane.point(), basis * plane.normal());
+                }
+                for object in objects.iter_mut() {
+                    object.reorient(axes);
+                }
+            }
+            Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.reorient(axes);
+                }
+            }
+            Self::PointCloud(cloud) => {
+                cloud.axes = Axes {
+                    up: cloud.def.up.unwrap_or(axes.up),
+                    handedness: cloud.def.handedness.unwrap_or(axes.handedness),
+                }
+            }
+            Self::Mesh(mesh) => {
+                mesh.axes = Axes {
+                    up: mesh.def.up.unwrap_or(axes.up),
+                    handedness: mesh.def.handedness.unwrap_or(axes.handedness),
+                }
+            }
+        }
+    }
+
+    /// Makes the whole object of `material`, down to everything inside transforms.
+    pub(crate) fn set_material(&mut self, material: &Material) {
+        match self {
+            Self::Sphere(sphere) => sphere.set_material(material.clone()),
+            Self::MovingSphere(sphere) => sphere.set_material(material.clone()),
+            Self::Transformed { objects, .. }
+            | Self::Clipped { objects, .. }
+            | Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.set_material(material);
+                }
+            }
+            Self::PointCloud(cloud) => cloud.set_material(material.clone()),
+            Self::Mesh(mesh) => mesh.set_material(material.clone()),
+        }
+    }
+
+    /// Moves the object by `offset`, in the units of its scene file. Meshes read from OBJ files
+    /// and point clouds can't move in place, so they are put in a transform group that moves
+    /// them.
+    pub(crate) fn translate(&mut self, offset: &crate::Vec3) {
+        match self {
+            Self::Sphere(sphere) => {
+                *sphere = Sphere::new(
+                    sphere.center() + offset,
+                    sphere.radius(),
+                    sphere.material().clone(),
+                )
+            }
+            Self::MovingSphere(sphere) => {
+                *sphere = MovingSphere::new(
+                    sphere.centers().map(|center| center + offset),
+                    sphere.time().clone(),
+                    sphere.radius(),
+                    sphere.material().clone(),
+                )
+            }
+            Self::Transformed { transform, .. } => **transform = transform.translated(offset),
+            Self::Clipped { clip, objects } => {
+                for plane in clip.iter_mut() {
+                    *plane = ClipPlane::new(plane.point() + offset, *plane.normal());
+                }
+                for object in objects.iter_mut() {
+                    object.translate(offset);
+                }
+            }
+            Self::Linked { objects, .. } => {
+                for object in objects.iter_mut() {
+                    object.translate(offset);
+                }
+            }
+            Self::Mesh(mesh) if mesh.def.obj.is_none() => {
+                if let Some(vertices) = &mut mesh.def.vertices {
+                    let frames = match vertices {
+                        OneOrMany::One(frame) => std::slice::from_mut(frame),
+                        OneOrMany::Many(frames) => frames.as_mut_slice(),
+                    };
+                    for p in frames.iter_mut().flatten() {
+                        *p = (&(crate::Point3::from(&*p) + offset)).into();
+                    }
+                }
+            }
+            Self::Mesh(_) | Self::PointCloud(_) => {
+                // An empty group holds the place of the object while it moves into its own.
+                let empty = Self::Clipped {
+                    clip: vec![],
+                    objects: vec![].into(),
+                };
+                let mesh = std::mem::replace(self, empty);
+                let translate = Placement {
+                    translate: Some(offset.into()),
+                    rotate_y: None,
+                    quaternion: None,
+                    axis_angle: None,
+                    rotate: None,
+                    matrix: None,
+                };
+                let def = TransformDef::Fixed(translate);
+                *self = Self::Transformed {
+                    transform: Box::new(
+                        Transform::fixed(Pose {
+                            matrix: Matrix4::identity(),
+                            rigid: placement(*offset, 0.0),
+                        })
+                        .written_as(def),
+                    ),
+                    objects: vec![mesh].into(),
+                    seed: None,
+                };
+            }
+        }
+    }
+
+    /// Describes the shape of the object, leaving its materials out, for reloading a scene to
+    /// tell which objects haven't moved. Streamed meshes can't be compared, so they have none.
+    fn geometry(&self) -> Option<Vec<u8>> {
+        let encoded = match self {
+            Self::Sphere(sphere) => rmp_serde::to_vec(&(sphere.center(), sphere.radius())),
+            Self::MovingSphere(sphere) => {
+                rmp_serde::to_vec(&(sphere.centers(), sphere.time(), sphere.radius()))
+            }
+            Self::Transformed {
+                transform, objects, ..
+            } => {
+                let objects: Vec<_> = objects
+                    .iter()
+                    .map(Object::geometry)
+                    .collect::<Option<_>>()?;
+                rmp_serde::to_vec(&(transform, objects))
+            }
+            Self::Clipped { clip, objects } => {
+                let objects: Vec<_> = objects
+                    .iter()
+                    .map(Object::geometry)
+                    .collect::<Option<_>>()?;
+                rmp_serde::to_vec(&(clip, objects))
+            }
+            Self::Linked { links, objects } => {
+                let objects: Vec<_> = objects
+                    .iter()
+                    .map(Object::geometry)
+                    .collect::<Option<_>>()?;
+                rmp_serde::to_vec(&(links, objects))
+            }
+            // Points colored by their file would need it read again to take their colors.
+            Self::PointCloud(cloud) => {
+                let def = &cloud.def;
+                rmp_serde::to_vec(&(
+                    &def.points,
+                    def.radius,
+                    def.splat,
+                    cloud.scale,
+                    cloud.axes,
+                    def.material.is_none(),
+                ))
+            }
+            Self::Mesh(mesh) if mesh.streamed.is_some() => return None,
+            Self::Mesh(mesh) => {
+                let def = &mesh.def;
+                rmp_serde::to_vec(&(
+                    &def.obj,
+                    &def.vertices,
+                    &def.faces,
+                    &def.time,
+                    mesh.scale,
+                    mesh.axes,
+                    def.backfaces,
+                    def.crease_angle,
+                ))
+            }
+        };
+
+        encoded.ok()
+    }
+
+    /// Returns whether the object is a mesh sequence or holds one, which only has the frames that
+    /// show while the shutter is open.
+    fn has_sequence(&self) -> bool {
+        match self {
+            Self::Transformed { objects, .. }
+            | Self::Clipped { objects, .. }
+            | Self::Linked { objects, .. } => objects.iter().any(Self::has_sequence),
+            Self::Mesh(mesh) => mesh.sequence.is_some(),
+            Self::Sphere(_) | Self::MovingSphere(_) | Self::PointCloud(_) => false,
+        }
+    }
+
+    /// Describes the shape of a transform group as [`Object::geometry`] does, but leaving its
+    /// placement out, to tell which groups have only moved. Other objects have none.
+    fn shape(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Transformed { objects, .. } => {
+                let objects: Vec<_> = objects
+                    .iter()
+                    .map(Object::geometry)
+                    .collect::<Option<_>>()?;
+                rmp_serde::to_vec(&objects).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Places the transform group as `other` is, which has the same [`Object::shape`].
+    fn take_placement(&mut self, other: &Object) {
+        if let (
+            Self::Transformed { transform, .. },
+            Self::Transformed {
+                transform: placed, ..
+            },
+        ) = (self, other)
+        {
+            *transform = placed.clone();
+        }
+    }
+
+    /// Gives the object the materials of `other`, which has the same [`Object::geometry`].
+    fn take_materials(&mut self, other: &Object) {
+        match (self, other) {
+            (Self::Sphere(sphere), Self::Sphere(other)) => {
+                sphere.set_material(other.material().clone())
+            }
+            (Self::MovingSphere(sphere), Self::MovingSphere(other)) => {
+                sphere.set_material(other.material().clone())
+            }
+            (
+                Self::Transformed { objects, .. },
+                Self::Transformed {
+                    objects: others, ..
+                },
+            )
+            | (
+                Self::Clipped { objects, .. },
+                Self::Clipped {
+                    objects: others, ..
+                },
+            )
+            | (
+                Self::Linked { objects, .. },
+                Self::Linked {
+                    objects: others, ..
+                },
+            ) => {
+                for (object, other) in objects.iter_mut().zip(others.iter()) {
+                    object.take_materials(other);
+                }
+            }
+            (Self::Mesh(mesh), Self::Mesh(other)) => mesh.set_material(other.material().clone()),
+            (Self::PointCloud(cloud), Self::PointCloud(other)) => {
+                if let Some(material) = other.material() {
+                    cloud.set_material(material.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the triangles of the meshes in the object, placed by `placement` as at `time`, to
+    /// `triangles`.
+    fn textured_triangles(
+        &self,
+        base_dir: &Path,
+        placement: &Matrix4<f64>,
+        time: f64,
+        triangles: &mut Vec<TexturedTriangle>,
+    ) -> Result<()> {
+        match self {
+            Self::Transformed {
+                transform, objects, ..
+            } => {
+                let placement = placement * transform.at(time).matrix();
+                for object in objects.iter() {
+                    object.textured_triangles(base_dir, &placement, time, triangles)?;
+                }
+            }
+            // Lightmaps cover whole triangles, cut away or not.
+            Self::Clipped { objects, .. } | Self::Linked { objects, .. } => {
+                for object in objects.iter() {
+                    object.textured_triangles(base_dir, placement, time, triangles)?;
+                }
+            }
+            Self::Mesh(mesh) => triangles.extend(
+                mesh.def
+                    .textured_triangles(base_dir, mesh.scale, &mesh.axes)?
+                    .into_iter()
+                    .map(|triangle| TexturedTriangle {
+                        positions: triangle
+                            .positions
+                            .map(|p| placement.transform_point(&p.into()).coords),
+                        ..triangle
+                    }),
+            ),
+            Self::Sphere(_) | Self::MovingSphere(_) | Self::PointCloud(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the object as a light that paths can aim at, if it is an emitting sphere.
+    /// Emitters of other shapes still light the scene, but only when paths hit them by chance.
+    fn light(&self) -> Option<Light> {
+        let (center, time, radius, material) = match self {
+            Self::Sphere(sphere) => (
+                StartEndPair::new(*sphere.center(), *sphere.center()),
+                StartEndPair::new(0.0, 1.0),
+                sphere.radius(),
+                sphere.material(),
+            ),
+            Self::MovingSphere(sphere) => (
+                sphere.centers().clone(),
+                sphere.time().clone(),
+                sphere.radius(),
+                sphere.material(),
+            ),
+            // Linked lights are spheres on their own, given their link when the scene loads.
+            Self::Linked { links, objects } => {
+                let link = links.light?;
+                let mut objects = objects.iter();
+                return match (objects.next(), objects.next()) {
+                    (Some(light), None) => Some(light.light()?.linked(link)),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+
+        match material {
+            Material::DiffuseLight(_) => Some(Light::new(center, time, radius, material.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        match self {
+            Self::Sphere(sphere) => sphere.hit(r, t_min, t_max),
+            Self::MovingSphere(sphere) => sphere.hit(r, t_min, t_max),
+            Self::Transformed {
+                transform,
+                objects,
+                seed,
+            } => transform.hit(objects, r, t_min, t_max).map(|mut rec| {
+                if let Some(seed) = seed {
+                    rec.set_instance(*seed);
+                }
+                rec
+            }),
+            Self::Clipped { clip, objects } => clip::hit(clip, objects, r, t_min, t_max),
+            Self::Linked { links, objects } => objects.hit(r, t_min, t_max).map(|mut rec| {
+                rec.set_links(*links);
+                rec
+            }),
+            Self::PointCloud(cloud) => cloud.cloud.as_ref()?.hit(r, t_min, t_max),
+            Self::Mesh(mesh) => mesh.hit(r, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        match self {
+            Self::Sphere(sphere) => sphere.bounding_box(time0, time1),
+            Self::MovingSphere(sphere) => sphere.bounding_box(time0, time1),
+            Self::Transformed {
+                transform, objects, ..
+            } => transform.bounding_box(objects, time0, time1),
+            Self::Clipped { objects, .. } | Self::Linked { objects, .. } => {
+                objects.bounding_box(time0, time1)
+            }
+            Self::PointCloud(cloud) => cloud.cloud.as_ref()?.bounding_box(time0, time1),
+            Self::Mesh(mesh) => mesh.bounding_box(time0, time1),
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Debug, Serialize)]
+pub(crate) struct CameraDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) look_from: Option<Animated<Point3>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) look_at: Option<Animated<Point3>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) vup: Option<Point3>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) time: Option<StartEndPair<f64>>,
+    /// Distances along the view direction before and past which the scene is cut away.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) near: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) far: Option<f64>,
+}
+
+/// Where the camera sits and when its shutter is open.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct CameraSettings {
+    pub(crate) look_from: StartEndPair<crate::Point3>,
+    pub(crate) look_at: StartEndPair<crate::Point3>,
+    pub(crate) vup: crate::Vec3,
+    pub(crate) time: StartEndPair<f64>,
+    pub(crate) near: f64,
+    pub(crate) far: f64,
+}
+
+impl CameraDef {
+    /// Returns the camera in the axes of the renderer, given in those of `axes`. The default
+    /// `vup` is the renderer's up already.
+    fn reoriented(self, axes: &Axes) -> Self {
+        let point = |p: Point3| Point3::from(&axes.point(&crate::Point3::from(&p)));
+
+        Self {
+            look_from: self.look_from.map(|p| p.map(point)),
+            look_at: self.look_at.map(|p| p.map(point)),
+            vup: self.vup.map(point),
+            ..self
+        }
+    }
+
+    /// Returns the camera with what it leaves out taken from `base`.
+    fn or(self, base: Option<CameraDef>) -> Self {
+        match base {
+            Some(base) => Self {
+                look_from: self.look_from.or(base.look_from),
+                look_at: self.look_at.or(base.look_at),
+                vup: self.vup.or(base.vup),
+                time: self.time.or(base.time),
+                near: self.near.or(base.near),
+                far: self.far.or(base.far),
+            },
+            None => self,
+        }
+    }
+}
+
+impl From<Option<CameraDef>> for CameraSettings {
+    fn from(def: Option<CameraDef>) -> Self {
+        let def = def.unwrap_or_default();
+        let animated = |value: Option<Animated<Point3>>, default: crate::Point3| match value {
+            Some(value) => value.start_end().map(|p| crate::Point3::from(*p)),
+            None => StartEndPair::new(default, default),
+        };
+
+        Self {
+            look_from: animated(def.look_from, crate::Point3::new(13.0, 2.0, 3.0)),
+            look_at: animated(def.look_at, crate::Point3::new(0.0, 0.0, 0.0)),
+            vup: def
+                .vup
+                .as_ref()
+                .map(crate::Vec3::from)
+                .unwrap_or_else(|| crate::Vec3::new(0.0, 1.0, 0.0)),
+            time: def.time.unwrap_or_else(|| StartEndPair::new(0.0, 1.0)),
+            near: def.near.unwrap_or(0.0),
+            far: def.far.unwrap_or(f64::INFINITY),
+        }
+    }
+}
+
+impl CameraSettings {
+    /// Multiplies the positions of the camera by `factor`, see [`Object::convert`].
+    fn scaled(self, factor: f64) -> Self {
+        Self {
+            look_from: self.look_from.map(|p| p * factor),
+            look_at: self.look_at.map(|p| p * factor),
+            near: self.near * factor,
+            far: self.far * factor,
+            ..self
+        }
+    }
+}
+
+/// What a unit of length is in a scene file.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Units {
+    Meters,
+    Centimeters,
+    /// This many meters.
+    Custom(f64),
+}
+
+impl Units {
+    fn meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.0,
+            Self::Centimeters => 0.01,
+            Self::Custom(meters) => meters,
+        }
+    }
+}
+
+/// Which axis points up in a scene file: `y` as in the renderer and most game engines, or `z`
+/// as in Blender, 3ds Max and most CAD tools.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Up {
+    Y,
+    Z,
+}
+
+/// Whether the axes of a scene file are `right`-handed, as in the renderer, Blender and 3ds
+/// Max, or `left`-handed, as in Unity and some CAD tools.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Handedness {
+    Right,
+    Left,
+}
+
+/// How the axes of a scene file lie, which the scene is turned (and mirrored, for left-handed
+/// files) from when it loads, so that it stands the right way up in the renderer, which is
+/// right-handed with Y up. X stays X in any case.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub(crate) struct Axes {
+    up: Up,
+    handedness: Handedness,
+}
+
+impl Default for Axes {
+    fn default() -> Self {
+        Self {
+            up: Up::Y,
+            handedness: Handedness::Right,
+        }
+    }
+}
+
+impl Axes {
+    /// Returns the matrix that takes points of the file to the renderer.
+    fn matrix(&self) -> Matrix3<f64> {
+        // The file's axis pointing away from the viewer, that left-handed files flip.
+        let away = match self.handedness {
+            Handedness::Right => 1.0,
+            Handedness::Left => -1.0,
+        };
+
+        match self.up {
+            Up::Y => Matrix3::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, away),
+            Up::Z => Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -away, 0.0),
+        }
+    }
+
+    /// Whether the file is mirrored in the renderer, which turns its faces inside out unless
+    /// their corners are put in the other order.
+    fn mirrors(&self) -> bool {
+        self.handedness == Handedness::Left
+    }
+
+    fn point(&self, p: &crate::Point3) -> crate::Point3 {
+        self.matrix() * p
+    }
+}
+
+/// A material to render in place of the one of the objects named `object`, to tell whether
+/// something comes from their geometry or their shading. Written `<object>=<material>`, where
+/// the material is `clay` (plain grey), `normals` or `checker` (see [`Preview`]).
+#[derive(Clone, Debug)]
+pub(crate) struct MaterialOverride {
+    object: String,
+    material: Material,
+}
+
+impl FromStr for MaterialOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (object, material) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid override `{}`, expected `<object>=<material>`", s))?;
+        let material = match material {
+            "clay" => Material::Lambertian(Lambertian::new(crate::Color::new(0.7, 0.7, 0.7))),
+            "normals" => Material::Preview(Preview::Normals),
+            "checker" => Material::Preview(Preview::Checker),
+            _ => {
+                return Err(format!(
+                    "unknown override material `{}`, expected `clay`, `normals` or `checker`",
+                    material
+                ))
+            }
+        };
+
+        Ok(Self {
+            object: object.to_string(),
+            material,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Scene {
+    pub(crate) world: Bvh<Object>,
+    pub(crate) camera: CameraSettings,
+    /// The emitting spheres of `world`, for paths to aim at.
+    pub(crate) lights: LightTree,
+    pub(crate) background: Option<crate::Color>,
+    /// An image seen behind the scene by camera rays that hit nothing.
+    pub(crate) backplate: Option<Backplate>,
+    pub(crate) fog: Option<Fog>,
+    pub(crate) interval: Interval,
+    /// The files the scene was built from: the scene file itself, then everything it refers to.
+    #[serde(skip)]
+    pub(crate) sources: Vec<PathBuf>,
+}
+
+impl Scene {
+    /// Returns whether the scene holds the same things whenever the shutter opens, with only
+    /// their motion to follow, so that another frame of it can be rendered by refitting the
+    /// hierarchy rather than loading it again. Mesh sequences only have the frames that show,
+    /// and fog containers a hierarchy of their own.
+    pub(crate) fn is_rigid(&self) -> bool {
+        !self.world.iter().any(Object::has_sequence)
+            && self.fog.as_ref().and_then(Fog::container).is_none()
+    }
+}
+
+/// How [`load_scene`] builds the scene.
+#[derive(Default)]
+pub(crate) struct LoadOptions {
+    /// When set, meshes too large for a single chunk are streamed from disk through this cache.
+    pub(crate) geometry_cache: Option<Arc<GeometryCache>>,
+    /// Materials replacing those of named objects, applied in order.
+    pub(crate) material_overrides: Vec<MaterialOverride>,
+    /// The scene file itself, for scenes that don't come from a file such as the built-in
+    /// demos, in which case the path only names it.
+    pub(crate) source: Option<String>,
+    /// The view among the scene file's `cameras` to use instead of its `camera`.
+    pub(crate) camera: Option<String>,
+    /// When the shutter opens and closes instead of at the `time` of the camera, to render a
+    /// frame of an animation.
+    pub(crate) time: Option<StartEndPair<f64>>,
+    /// When set, what the scene loads is made smaller rather than going over this budget.
+    pub(crate) memory_budget: Option<Arc<MemoryBudget>>,
+}
+
+/// The settings and objects of a scene file.
+struct SceneParts<T> {
+    camera: CameraSettings,
+    background: Option<Color>,
+    /// Path to the backplate image, relative to the scene file.
+    backplate: Option<String>,
+    fog: Option<Fog>,
+    interval: Interval,
+    sets: BTreeMap<String, Vec<String>>,
+    light_links: BTreeMap<String, LightLinkDef>,
+    objects: Vec<T>,
+    /// The objects that the fog fills, set apart from the others.
+    container: Vec<T>,
+}
+
+/// Reads the scene file at `path` as it is written, with its lengths converted to meters, or
+/// `source` instead if it is given, seen from its `camera` or from the one of its `cameras`
+/// named by `view`.
+fn read_scene_file(
+    path: &str,
+    source: Option<&str>,
+    view: Option<&str>,
+) -> Result<SceneParts<NamedObject>> {
+    let mut scene_yml;
+
+    let io = |source| SceneError::Io {
+        path: PathBuf::from(path),
+        source,
+    };
+    if let Some(source) = source {
+        scene_yml = source.to_string();
+    } else if path == "-" {
+        scene_yml = String::new();
+
+        std::io::stdin()
+            .read_to_string(&mut scene_yml)
+            .map_err(io)?;
+    } else {
+        scene_yml = std::fs::read_to_string(path).map_err(io)?;
+    }
+
+    let SceneDocument {
+        camera,
+        cameras,
+        background,
+        backplate,
+        units,
+        scale,
+        up,
+        handedness,
+        fog,
+        interval,
+        sets,
+        light_links,
+        mut objects,
+        ..
+    } = SceneDocument::parse(&scene_yml)
+        .map_err(|e| SceneError::reading(path, e).within(format!("parsing {}", path)))?;
+    let axes = Axes {
+        up: up.unwrap_or(Up::Y),
+        handedness: handedness.unwrap_or(Handedness::Right),
+    };
+    let camera = match view {
+        Some(view) => {
+            let mut cameras = cameras.unwrap_or_default();
+            match cameras.remove(view) {
+                Some(bookmark) => Some(bookmark.or(camera)),
+                None if cameras.is_empty() => {
+                    return Err(SceneError::Invalid(format!(
+                        "{} has no `cameras` to pick from",
+                        path
+                    )))
+                }
+                None => {
+                    return Err(SceneError::Invalid(format!(
+                        "{} has no camera named `{}` (expected one of {})",
+                        path,
+                        view,
+                        cameras
+                            .keys()
+                            .map(|name| format!("`{}`", name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )))
+                }
+            }
+        }
+        None => camera,
+    };
+
+    let units = units.unwrap_or(Units::Meters);
+    let scale = scale.unwrap_or(1.0);
+    if units.meters() <= 0.0 || scale <= 0.0 {
+        return Err(SceneError::Invalid(format!(
+            "{} has a non-positive unit length or scale",
+            path
+        )));
+    }
+    for (i, named) in objects.iter_mut().enumerate() {
+        named.object.validate().map_err(|e| match &named.name {
+            Some(name) => e.within(format!("`{}` in {} is invalid", name, path)),
+            None => e.within(format!("object {} in {} is invalid", i, path)),
+        })?;
+        named.object.convert(units, scale);
+        named.object.reorient(&axes);
+        // Named objects keep their seeds when others are added or moved around them.
+        let seed = named.name.as_deref().map_or(i as u64, hash_name);
+        named.object.seed(splitmix(seed));
+    }
+    let camera = CameraSettings::from(camera.map(|camera| camera.reoriented(&axes)))
+        .scaled(units.meters() * scale);
+    let fog = fog.map(|fog| Fog::from(fog).scaled(units.meters() * scale));
+    let interval = interval.map_or_else(Interval::default, |interval| {
+        Interval::from(interval).scaled(units.meters() * scale)
+    });
+    if !(interval.t_min >= 0.0 && interval.t_min < interval.t_max) {
+        return Err(SceneError::Invalid(format!(
+            "{} has an empty or negative interval",
+            path
+        )));
+    }
+
+    Ok(SceneParts {
+        camera,
+        background,
+        backplate,
+        fog,
+        interval,
+        sets: sets.unwrap_or_default(),
+        light_links: light_links.unwrap_or_default(),
+        objects,
+        container: vec![],
+    })
+}
+
+/// Hashes `name` with 64-bit FNV-1a, which unlike the standard library's hasher is sure to stay
+/// the same from one build to the next.
+fn hash_name(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Returns the directory that paths in the scene file at `path` are relative to.
+fn base_dir(path: &str) -> &Path {
+    match Path::new(path).parent() {
+        Some(dir) if path != "-" => dir,
+        _ => Path::new(""),
+    }
+}
+
+/// Reads the scene file at `path`, with the objects' materials overridden as `options` say but
+/// nothing they refer to loaded yet.
+fn parse_scene(path: &str, options: &LoadOptions) -> Result<SceneParts<Object>> {
+    let scene = read_scene_file(path, options.source.as_deref(), options.camera.as_deref())?;
+    let mut named = scene.objects;
+
+    for material_override in &options.material_overrides {
+        let mut found = false;
+        for named in &mut named {
+            if named.name.as_ref() == Some(&material_override.object) {
+                named.object.set_material(&material_override.material);
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(SceneError::Invalid(format!(
+                "{} has no object named `{}`",
+                path, material_override.object
+            )));
+        }
+    }
+    link_lights(path, &mut named, &scene.sets, &scene.light_links)?;
+
+    let mut container = vec![];
+    if let Some(name) = scene.fog.as_ref().and_then(Fog::container) {
+        let (inside, others): (Vec<_>, Vec<_>) = named
+            .into_iter()
+            .partition(|named| named.name.as_deref() == Some(name));
+        if inside.is_empty() {
+            return Err(SceneError::Invalid(format!(
+                "{} has no object named `{}` for the fog to fill",
+                path, name
+            )));
+        }
+        named = others;
+        container = inside.into_iter().map(|named| named.object).collect();
+    }
+
+    let mut camera = scene.camera;
+    if let Some(time) = &options.time {
+        camera.time = time.clone();
+    }
+
+    Ok(SceneParts {
+        camera,
+        background: scene.background,
+        backplate: scene.backplate,
+        fog: scene.fog,
+        interval: scene.interval,
+        sets: scene.sets,
+        light_links: scene.light_links,
+        objects: named.into_iter().map(|named| named.object).collect(),
+        container,
+    })
+}
+
+/// Gives each light of `light_links` a bit of its own, and wraps the objects that some of them
+/// don't light, or whose shadows they don't cast, in [`Object::Linked`].
+fn link_lights(
+    path: &str,
+    named: &mut Vec<NamedObject>,
+    sets: &BTreeMap<String, Vec<String>>,
+    light_links: &BTreeMap<String, LightLinkDef>,
+) -> Result<()> {
+    if light_links.is_empty() {
+        return Ok(());
+    }
+    if light_links.len() > 64 {
+        return Err(SceneError::Invalid(format!(
+            "{} links {} lights (at most 64)",
+            path,
+            light_links.len()
+        )));
+    }
+
+    let is_object = |name: &str| named.iter().any(|n| n.name.as_deref() == Some(name));
+    for (set, members) in sets {
+        if let Some(member) = members.iter().find(|member| !is_object(member)) {
+            return Err(SceneError::Invalid(format!(
+                "set `{}` in {} has no object named `{}`",
+                set, path, member
+            )));
+        }
+    }
+    // The names of the objects that `names` pick, directly or through sets.
+    let objects = |names: &Option<Vec<String>>| -> Result<Option<Vec<String>>> {
+        let names = match names {
+            Some(names) => names,
+            None => return Ok(None),
+        };
+        let mut objects = vec![];
+        for name in names {
+            match sets.get(name) {
+                Some(members) => objects.extend(members.iter().cloned()),
+                None if is_object(name) => objects.push(name.clone()),
+                None => {
+                    return Err(SceneError::Invalid(format!(
+                        "{} has no object or set named `{}`",
+                        path, name
+                    )))
+                }
+            }
+        }
+        Ok(Some(objects))
+    };
+
+    let mut links = vec![LightLinks::ALL; named.len()];
+    for (bit, (light, def)) in light_links.iter().enumerate() {
+        let link = LightLink {
+            bit: bit as u8,
+            shadows: def.shadowed_by.is_some(),
+        };
+        let illuminates = objects(&def.illuminates)?;
+        let shadowed_by = objects(&def.shadowed_by)?;
+        let picked = |picks: &Option<Vec<String>>, name: Option<&str>| match picks {
+            Some(picks) => name.is_some_and(|name| picks.iter().any(|pick| pick == name)),
+            None => true,
+        };
+
+        let mut found = false;
+        for (named, links) in named.iter().zip(&mut links) {
+            let name = named.name.as_deref();
+            if !picked(&illuminates, name) {
+                links.lit_by &= !(1 << bit);
+            }
+            if !picked(&shadowed_by, name) {
+                links.casts &= !(1 << bit);
+            }
+            if name == Some(light.as_str()) {
+                if named.object.light().is_none() {
+                    return Err(SceneError::Invalid(format!(
+                        "`{}` in {} is not an emitting sphere to link",
+                        light, path
+                    )));
+                }
+                links.light = Some(link);
+                found = true;
+            }
+        }
+        if !found {
+            return Err(SceneError::Invalid(format!(
+                "{} has no light named `{}` to link",
+                path, light
+            )));
+        }
+    }
+
+    *named = std::mem::take(named)
+        .into_iter()
+        .zip(links)
+        .map(|(named, links)| {
+            if links == LightLinks::ALL {
+                return named;
+            }
+            NamedObject {
+                name: named.name,
+                object: Object::Linked {
+                    links,
+                    objects: vec![named.object].into(),
+                },
+            }
+        })
+        .collect();
+
+    Ok(())
+}
+
+pub(crate) fn load_scene(path: &str, options: &LoadOptions) -> Result<Scene> {
+    let SceneParts {
+        camera,
+        background,
+        backplate,
+        fog,
+        interval,
+        mut objects,
+        mut container,
+        ..
+    } = parse_scene(path, options)?;
+
+    let base_dir = base_dir(path);
+    let mut sources = vec![];
+    if path != "-" && options.source.is_none() {
+        sources.push(PathBuf::from(path));
+    }
+    let backplate = load_backplate(base_dir, backplate.as_deref(), &mut sources)
+        .map_err(|e| e.within(format!("loading {}", path)))?;
+    for object in &mut objects {
+        object
+            .load_resources(base_dir, &mut sources, options, &camera.time)
+            .map_err(|e| e.within(format!("loading {}", path)))?;
+    }
+
+    for object in &mut container {
+        object
+            .load_resources(base_dir, &mut sources, options, &camera.time)
+            .map_err(|e| e.within(format!("loading {}", path)))?;
+    }
+
+    if let Some(budget) = &options.memory_budget {
+        debug!(
+            "loaded {} of the memory budget of {}",
+            format_bytes(budget.used()),
+            format_bytes(budget.limit())
+        );
+    }
+
+    let (start, end) = (*camera.time.start(), *camera.time.end());
+    let fog = match fog {
+        Some(fog) if !container.is_empty() => Some(fog.bounded_by(Bvh::new(container, start, end))),
+        fog => fog,
+    };
+    let lights = LightTree::new(objects.iter().filter_map(Object::light).collect());
+    let world = Bvh::new(objects, start, end);
+
+    Ok(Scene {
+        world,
+        camera,
+        lights,
+        background: background.as_ref().map(crate::Color::from),
+        backplate,
+        fog,
+        interval,
+        sources,
+    })
+}
+
+/// Reads the backplate image at `path`, relative to `base_dir`, if there is one, and adds it to
+/// `sources`.
+fn load_backplate(
+    base_dir: &Path,
+    path: Option<&str>,
+    sources: &mut Vec<PathBuf>,
+) -> Result<Option<Backplate>> {
+    let path = match path {
+        Some(path) => base_dir.join(path),
+        None => return Ok(None),
+    };
+    let backplate = Backplate::load(&path).map_err(|e| SceneError::reading(&path, e))?;
+    sources.push(path);
+
+    Ok(Some(backplate))
+}
+
+/// Reads where the camera of the scene file at `path` is, as `options` pick it, without loading
+/// anything the scene refers to.
+pub(crate) fn camera_settings(path: &str, options: &LoadOptions) -> Result<CameraSettings> {
+    let scene = read_scene_file(path, options.source.as_deref(), options.camera.as_deref())?;
+
+    Ok(scene.camera)
+}
+
+/// Reads the triangles of the meshes named `name` in the scene file at `path`, placed as they
+/// are at `time`, with their texture coordinates. Deforming meshes are read in their first
+/// frame.
+pub(crate) fn textured_triangles(
+    path: &str,
+    name: &str,
+    time: f64,
+) -> Result<Vec<TexturedTriangle>> {
+    let named = read_scene_file(path, None, None)?.objects;
+
+    let mut found = false;
+    let mut triangles = vec![];
+    for named in named {
+        if named.name.as_deref() == Some(name) {
+            found = true;
+            named
+                .object
+                .textured_triangles(base_dir(path), &Matrix4::identity(), time, &mut triangles)
+                .map_err(|e| e.within(format!("loading {}", path)))?;
+        }
+    }
+
+    if !found {
+        return Err(SceneError::Invalid(format!(
+            "{} has no object named `{}`",
+            path, name
+        )));
+    }
+    if triangles.is_empty() {
+        return Err(SceneError::Invalid(format!(
+            "`{}` in {} has no mesh",
+            name, path
+        )));
+    }
+
+    Ok(triangles)
+}
+
+/// The parts of a scene that [`reload_materials`] read again, besides the new materials.
+pub(crate) struct Reload {
+    /// How many transform groups moved, and how the hierarchy followed them, if any did.
+    pub(crate) moved: Option<(usize, Refit)>,
+    pub(crate) camera: CameraSettings,
+    pub(crate) lights: LightTree,
+    pub(crate) background: Option<crate::Color>,
+    pub(crate) backplate: Option<Backplate>,
+    pub(crate) fog: Option<Fog>,
+    pub(crate) interval: Interval,
+}
+
+/// Reads the scene file at `path` again and, if it only changed materials, the camera placement,
+/// the background or where transform groups are, gives the objects of `world` their new
+/// materials and placements without loading them again. Moved groups have the hierarchy of
+/// `world` refitted around them rather than built again, see [`Bvh::refit`]. Returns nothing
+/// when other geometry or the shutter interval (which every bounding box covers) changed too,
+/// when fog fills a container, or when `options` have a memory budget, for the scene to be
+/// loaded again from scratch.
+pub(crate) fn reload_materials(
+    path: &str,
+    options: &LoadOptions,
+    world: &mut Bvh<Object>,
+    camera: &CameraSettings,
+) -> Result<Option<Reload>> {
+    let SceneParts {
+        camera: new_camera,
+        background,
+        backplate,
+        fog,
+        interval,
+        objects,
+        ..
+    } = parse_scene(path, options)?;
+    // Containers are geometry too, which only loading the scene again builds, and budgets
+    // count textures along with everything else.
+    if new_camera.time != camera.time
+        || fog.as_ref().and_then(Fog::container).is_some()
+        || options.memory_budget.is_some()
+    {
+        return Ok(None);
+    }
+
+    // Hierarchies are built stably, so objects of the same shape keep the order of the file.
+    let mut by_geometry: HashMap<Vec<u8>, VecDeque<Object>> = HashMap::new();
+    for object in objects {
+        match object.geometry() {
+            Some(geometry) => by_geometry.entry(geometry).or_default().push_back(object),
+            None => return Ok(None),
+        }
+    }
+
+    let mut pairs = vec![];
+    let mut unmatched = vec![];
+    for object in world.iter_mut() {
+        let other = object
+            .geometry()
+            .and_then(|geometry| by_geometry.get_mut(&geometry)?.pop_front());
+        match other {
+            Some(other) => pairs.push((object, other)),
+            None => unmatched.push(object),
+        }
+    }
+
+    // What is left over may be groups that moved.
+    let mut by_shape: HashMap<Vec<u8>, VecDeque<Object>> = HashMap::new();
+    for object in by_geometry.into_values().flatten() {
+        match object.shape() {
+            Some(shape) => by_shape.entry(shape).or_default().push_back(object),
+            None => return Ok(None),
+        }
+    }
+    let mut moved = vec![];
+    for object in unmatched {
+        let other = object
+            .shape()
+            .and_then(|shape| by_shape.get_mut(&shape)?.pop_front());
+        match other {
+            Some(other) => moved.push((object, other)),
+            None => return Ok(None),
+        }
+    }
+    if by_shape.values().any(|objects| !objects.is_empty()) {
+        return Ok(None);
+    }
+
+    let moved_count = moved.len();
+    for (object, mut other) in pairs.into_iter().chain(moved) {
+        other.load_textures(base_dir(path), &mut vec![], None)?;
+        object.take_placement(&other);
+        object.take_materials(&other);
+    }
+    let moved = match moved_count {
+        0 => None,
+        count => Some((count, world.refit(*camera.time.start(), *camera.time.end()))),
+    };
+
+    Ok(Some(Reload {
+        moved,
+        camera: new_camera,
+        lights: LightTree::new(world.iter().filter_map(Object::light).collect()),
+        background: background.as_ref().map(crate::Color::from),
+        backplate: load_backplate(base_dir(path), backplate.as_deref(), &mut vec![])?,
+        fog,
+        interval,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{luminance, LightSampling, OcclusionCache};
+    use crate::render::{ray_color, PathSampling, Regularization, World};
+    use crate::vec3::{Point3, Vec3};
+
+    /// Returns the mean luminance seen along the ray from `from` towards `to` in a scene.
+    fn mean_luminance(source: &str, from: Point3, to: Point3) -> f64 {
+        let options = LoadOptions {
+            source: Some(source.to_string()),
+            ..LoadOptions::default()
+        };
+        let scene = load_scene("scene.yml", &options).unwrap();
+        let occlusion = OcclusionCache::new(&scene.lights);
+        let world = World::new(
+            scene.world,
+            scene.lights,
+            scene.background,
+            scene.backplate,
+            scene.fog,
+            scene.interval,
+        );
+        let sampling = PathSampling {
+            regularization: Regularization::Off,
+            lights: LightSampling::Tree,
+            guide: None,
+            training: false,
+            occlusion: Some(&occlusion),
+        };
+        let r = Ray::new(from, to - from, Some(0.0));
+        let samples = 4_000;
+
+        (0..samples)
+            .map(|_| luminance(&ray_color(&r, &world, 4, &sampling)))
+            .sum::<f64>()
+            / samples as f64
+    }
+
+    /// Two balls side by side under a light above them, seen from above.
+    fn balls(links: &str) -> String {
+        format!(
+            "
+background: {{ r: 0, g: 0, b: 0 }}
+objects:
+  - name: key
+    center: {{ x: 0, y: 4, z: 0 }}
+    radius: 0.5
+    material: {{ emit: {{ r: 20, g: 20, b: 20 }} }}
+  - name: left
+    center: {{ x: -2, y: 0, z: 0 }}
+    radius: 1
+    material: {{ albedo: {{ r: 0.5, g: 0.5, b: 0.5 }} }}
+  - name: right
+    center: {{ x: 2, y: 0, z: 0 }}
+    radius: 1
+    material: {{ albedo: {{ r: 0.5, g: 0.5, b: 0.5 }} }}
+{}",
+            links
+        )
+    }
+
+    #[test]
+    fn lights_illuminate_only_the_objects_they_list() {
+        let left = |links| {
+            mean_luminance(
+                &balls(links),
+                Point3::new(-2.0, 3.0, 0.0),
+                Point3::new(-2.0, 0.0, 0.0),
+            )
+        };
+        let right = |links| {
+            mean_luminance(
+                &balls(links),
+                Point3::new(2.0, 3.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+            )
+        };
+        let linked = "light_links: { key: { illuminates: [left] } }";
+
+        let unlinked = right("");
+        assert!(unlinked > 0.1, "{}", unlinked);
+        // The left ball gets the light as before, the right one only what bounces off the left.
+        assert!((left(linked) - left("")).abs() < 0.1 * unlinked);
+        assert!(right(linked) < 0.05 * unlinked, "{}", right(linked));
+
+        // Sets list objects too.
+        let set = "sets: { lit: [left] }\nlight_links: { key: { illuminates: [lit] } }";
+        assert!(right(set) < 0.05 * unlinked, "{}", right(set));
+    }
+
+    #[test]
+    fn lights_are_shadowed_only_by_the_objects_they_list() {
+        let floor = |links| {
+            let source = format!(
+                "
+background: {{ r: 0, g: 0, b: 0 }}
+objects:
+  - name: key
+    center: {{ x: 0, y: 4, z: 0 }}
+    radius: 0.5
+    material: {{ emit: {{ r: 20, g: 20, b: 20 }} }}
+  - name: blocker
+    center: {{ x: 0, y: 2, z: 0 }}
+    radius: 0.8
+    material: {{ albedo: {{ r: 0.5, g: 0.5, b: 0.5 }} }}
+  - name: floor
+    center: {{ x: 0, y: -1000, z: 0 }}
+    radius: 1000
+    material: {{ albedo: {{ r: 0.5, g: 0.5, b: 0.5 }} }}
+{}",
+                links
+            );
+            // Under the blocker, which hides the whole light.
+            mean_luminance(
+                &source,
+                Point3::new(0.0, 0.5, 3.0),
+                Point3::new(0.0, 0.0, 0.0),
+            )
+        };
+
+        let shadowed = floor("");
+        let unshadowed = floor("light_links: { key: { shadowed_by: [floor] } }");
+        assert!(unshadowed > 0.1, "{}", unshadowed);
+        assert!(shadowed < 0.05 * unshadowed, "{}", shadowed);
+
+        let listed = floor("light_links: { key: { shadowed_by: [blocker, floor] } }");
+        assert!(listed < 0.05 * unshadowed, "{}", listed);
+    }
+
+    #[test]
+    fn matrices_are_invertible_at_any_scale() {
+        let pose = |rows: [[f64; 4]; 4]| {
+            let placement: Placement =
+                serde_yaml::from_str(&format!("matrix: {:?}", rows)).unwrap();
+            Pose::try_from(&placement)
+        };
+        let scaled = |s| {
+            [
+                [s, 0.0, 0.0, 1.0],
+                [0.0, s, 0.0, 2.0],
+                [0.0, 0.0, s, 3.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        };
+
+        assert!(pose(scaled(1e-6)).is_ok());
+        assert!(pose(scaled(1e6)).is_ok());
+        assert!(pose(scaled(0.0)).is_err());
+        // Columns that are nearly the same flatten objects onto a plane, however long.
+        let flat = [
+            [1e-6, 1e-6, 0.0, 0.0],
+            [0.0, 1e-20, 0.0, 0.0],
+            [0.0, 0.0, 1e-6, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert!(pose(flat).is_err());
+    }
+
+    #[test]
+    fn meshes_streamed_from_obj_files_match_meshes_read_whole() {
+        // A 64 by 64 grid of quads in the plane z = -1, with a face without area at the end.
+        let n = 64;
+        let mut obj = String::new();
+        for j in 0..=n {
+            for i in 0..=n {
+                obj += &format!("v {} {} -1\n", i as f64 / n as f64, j as f64 / n as f64);
+            }
+        }
+        for j in 0..n {
+            for i in 0..n {
+                let corner = j * (n + 1) + i + 1;
+                obj += &format!(
+                    "f {} {} {} {}\n",
+                    corner,
+                    corner + 1,
+                    corner + n + 2,
+                    corner + n + 1
+                );
+            }
+        }
+        obj += "f 1 1 2\n";
+        let path = std::env::temp_dir().join(format!("grid-{}.obj", std::process::id()));
+        std::fs::write(&path, obj).unwrap();
+        assert_eq!(
+            count_obj(&path.to_string_lossy()).unwrap(),
+            (65 * 65, 2 * 64 * 64 + 1)
+        );
+
+        let load = |geometry_cache| {
+            let options = LoadOptions {
+                source: Some(format!(
+                    "objects:\n  - obj: [{0}, {0}]\n    handedness: left\n    material: {{ albedo: {{ r: 0.5, g: 0.5, b: 0.5 }} }}\n",
+                    path.display()
+                )),
+                geometry_cache,
+                ..LoadOptions::default()
+            };
+            load_scene("scene.yml", &options).unwrap()
+        };
+        let whole = load(None);
+        let streamed = load(Some(Arc::new(GeometryCache::new(usize::MAX))));
+        std::fs::remove_file(&path).unwrap();
+
+        for (x, y, z) in [
+            (0.05, 0.05, 5.0),
+            (0.33, 0.71, 5.0),
+            (0.5, 0.5, -5.0),
+            (1.5, 0.5, 5.0),
+        ] {
+            let r = Ray::new(Point3::new(x, y, z), Vec3::new(0.0, 0.0, -z), Some(0.5));
+            let hit = |scene: &Scene| {
+                scene
+                    .world
+                    .hit(&r, 0.001, f64::INFINITY)
+                    .map(|hit| (*hit.p(), *hit.normal()))
+            };
+            assert_eq!(hit(&streamed), hit(&whole));
+            assert_eq!(hit(&whole).is_some(), x < 1.0);
+        }
+    }
+
+    #[test]
+    fn linking_more_than_64_lights_fails() {
+        let objects = |lights: usize| {
+            (0..lights)
+                .map(|i| {
+                    format!(
+                        "  - name: light{}\n    center: {{ x: {}, y: 4, z: 0 }}\n    radius: 0.1\n    material: {{ emit: {{ r: 1, g: 1, b: 1 }} }}\n",
+                        i, i
+                    )
+                })
+                .collect::<String>()
+        };
+        let links = |lights: usize| {
+            (0..lights)
+                .map(|i| format!("  light{}: {{ illuminates: [] }}\n", i))
+                .collect::<String>()
+        };
+        let load = |lights| {
+            let source = format!(
+                "objects:\n{}light_links:\n{}",
+                objects(lights),
+                links(lights)
+            );
+            let options = LoadOptions {
+                source: Some(source),
+                ..LoadOptions::default()
+            };
+            load_scene("scene.yml", &options).map(|_| ())
+        };
+
+        assert!(load(64).is_ok());
+        match load(65) {
+            Err(SceneError::Invalid(message)) => {
+                assert!(message.contains("65 lights (at most 64)"), "{}", message)
+            }
+            Err(e) => panic!("{}", e),
+            Ok(()) => panic!("65 lights were linked"),
+        }
+    }
 }
@@ -0,0 +1,194 @@
+use lazy_static::lazy_static;
+use nalgebra::{Matrix3, Vector3, Vector4};
+
+use crate::rtweekend::random_double;
+use crate::vec3::Color;
+
+/// Shortest wavelength traced in spectral mode, in nanometres.
+pub(crate) const MIN_WAVELENGTH: f64 = 380.0;
+/// Longest wavelength traced in spectral mode, in nanometres.
+pub(crate) const MAX_WAVELENGTH: f64 = 720.0;
+
+/// Wavelengths used to integrate spectra when fitting them.
+const FIT_SAMPLES: usize = 64;
+const FIT_ITERATIONS: usize = 30;
+
+lazy_static! {
+    /// Converts CIE XYZ to linear sRGB.
+    static ref XYZ_TO_RGB: Matrix3<f64> = Matrix3::new(
+        3.2406, -1.5372, -0.4986,
+        -0.9689, 1.8758, 0.0415,
+        0.0557, -0.2040, 1.0570,
+    );
+
+    /// The RGB response of a spectrum that is 1 everywhere, which is made to come out white.
+    static ref WHITE: Color = integrate(|_| 1.0);
+
+    /// Spectra of the three primaries, see [`blend_rgb`].
+    static ref PRIMARIES: [Spectrum; 3] = [
+        Color::new(1.0, 0.0, 0.0),
+        Color::new(0.0, 1.0, 0.0),
+        Color::new(0.0, 0.0, 1.0),
+    ]
+    .map(|primary| Spectrum::from_rgb(&primary));
+
+    /// Weights of `PRIMARIES` by the color they blend to, which isn't quite the identity since
+    /// the primaries are only reached at infinity.
+    static ref RGB_TO_PRIMARIES: Matrix3<f64> = Matrix3::from_columns(
+        &PRIMARIES.map(|primary| to_rgb(|w| primary.at(w)))
+    )
+    .try_inverse()
+    .expect("the spectra of the primaries have independent colors");
+}
+
+fn lobe(wavelength: f64, mean: f64, below: f64, above: f64) -> f64 {
+    let sigma = if wavelength < mean { below } else { above };
+
+    (-0.5 * ((wavelength - mean) / sigma).powi(2)).exp()
+}
+
+/// The CIE 1931 color matching functions, in the multi-lobe fit of Wyman, Sloan and Shirley.
+fn xyz(wavelength: f64) -> Vector3<f64> {
+    let w = wavelength;
+
+    Vector3::new(
+        1.056 * lobe(w, 599.8, 37.9, 31.0) + 0.362 * lobe(w, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(w, 501.1, 20.4, 26.2),
+        0.821 * lobe(w, 568.8, 46.9, 40.5) + 0.286 * lobe(w, 530.9, 16.3, 31.1),
+        1.217 * lobe(w, 437.0, 11.8, 36.0) + 0.681 * lobe(w, 459.0, 26.0, 13.8),
+    )
+}
+
+/// Integrates `spectrum` against the color matching functions, without normalizing to white.
+fn integrate(spectrum: impl Fn(f64) -> f64) -> Color {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / FIT_SAMPLES as f64;
+
+    (0..FIT_SAMPLES)
+        .map(|i| {
+            let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+            *XYZ_TO_RGB * xyz(wavelength) * spectrum(wavelength) * step
+        })
+        .sum()
+}
+
+/// Returns the linear RGB color of `spectrum`, where a spectrum of 1 everywhere is white.
+fn to_rgb(spectrum: impl Fn(f64) -> f64) -> Color {
+    integrate(spectrum).component_div(&WHITE)
+}
+
+/// The wavelengths carried by one path, in nanometres, along with the values of a spectrum at
+/// each of them.
+pub(crate) type Wavelengths = Vector4<f64>;
+
+const PATH_WAVELENGTHS: usize = 4;
+
+/// Picks wavelengths to trace: one uniformly at random, and the others evenly spaced after it.
+/// Nothing bends light differently by wavelength, so they can all share the same path, which
+/// costs nothing and makes the color noise of single-wavelength sampling much finer.
+pub(crate) fn sample_wavelengths() -> Wavelengths {
+    let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+    let hero = random_double() * range;
+
+    Wavelengths::from_fn(|i, _| {
+        MIN_WAVELENGTH + (hero + i as f64 * range / PATH_WAVELENGTHS as f64) % range
+    })
+}
+
+/// Returns the color that `radiance`, sampled at `wavelengths`, contributes to a pixel, such
+/// that on average a spectrum gives its RGB color.
+pub(crate) fn to_pixel(wavelengths: &Wavelengths, radiance: &Wavelengths) -> Color {
+    let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+    let rgb: Color = wavelengths
+        .iter()
+        .zip(radiance.iter())
+        .map(|(&wavelength, &radiance)| *XYZ_TO_RGB * xyz(wavelength) * radiance)
+        .sum();
+
+    (rgb * range / PATH_WAVELENGTHS as f64).component_div(&WHITE)
+}
+
+/// Returns the values at `wavelengths` of a spectrum whose RGB color is `color`, blended from
+/// spectra of the three primaries. Unlike those of [`Spectrum::from_rgb`], it costs next to
+/// nothing, for colors that change from one ray to the next, but may fall outside of `0..=1`.
+pub(crate) fn blend_rgb(color: &Color, wavelengths: &Wavelengths) -> Wavelengths {
+    let weights = *RGB_TO_PRIMARIES * color;
+
+    PRIMARIES
+        .iter()
+        .zip(weights.iter())
+        .map(|(primary, weight)| primary.at_each(wavelengths) * *weight)
+        .sum()
+}
+
+/// A smooth reflectance spectrum, `sigmoid(c0 x² + c1 x + c2)` over the normalized wavelength
+/// `x`, as in Jakob and Hanika's "A Low-Dimensional Function Space for Efficient Spectral
+/// Upsampling". It stays within `0..=1` like a physical reflectance.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Spectrum {
+    coefficients: Vector3<f64>,
+}
+
+impl Spectrum {
+    fn with(coefficients: Vector3<f64>) -> Self {
+        Self { coefficients }
+    }
+
+    /// Fits the spectrum whose linear RGB color is closest to `color`.
+    pub(crate) fn from_rgb(color: &Color) -> Self {
+        // Colors at the very edge of the gamut are only reached at infinity, so aim just
+        // inside it.
+        let target = color.map(|c| c.clamp(1e-4, 1.0 - 1e-4));
+        let residual =
+            |coefficients: &Vector3<f64>| to_rgb(|w| Self::with(*coefficients).at(w)) - target;
+
+        // Levenberg–Marquardt, starting from a flat 50% grey.
+        let mut coefficients = Vector3::zeros();
+        let mut error = residual(&coefficients);
+        let mut damping = 1e-3;
+
+        for _ in 0..FIT_ITERATIONS {
+            let mut jacobian = Matrix3::zeros();
+            for i in 0..3 {
+                let mut nudged = coefficients;
+                nudged[i] += 1e-6;
+                jacobian.set_column(i, &((residual(&nudged) - error) / 1e-6));
+            }
+
+            let normal = jacobian.transpose() * jacobian + Matrix3::identity() * damping;
+            let step = match normal.try_inverse() {
+                Some(inverse) => -(inverse * jacobian.transpose() * error),
+                None => break,
+            };
+
+            let candidate = coefficients + step;
+            let candidate_error = residual(&candidate);
+            if candidate_error.norm_squared() < error.norm_squared() {
+                coefficients = candidate;
+                error = candidate_error;
+                damping *= 0.1;
+            } else {
+                damping *= 10.0;
+            }
+
+            if error.norm() < 1e-6 {
+                break;
+            }
+        }
+
+        Self::with(coefficients)
+    }
+
+    /// Returns the reflectance at each of `wavelengths`.
+    pub(crate) fn at_each(&self, wavelengths: &Wavelengths) -> Wavelengths {
+        wavelengths.map(|wavelength| self.at(wavelength))
+    }
+
+    /// Returns the reflectance at `wavelength`, in nanometres.
+    pub(crate) fn at(&self, wavelength: f64) -> f64 {
+        let x = (wavelength - MIN_WAVELENGTH) / (MAX_WAVELENGTH - MIN_WAVELENGTH);
+        let [c0, c1, c2]: [f64; 3] = self.coefficients.into();
+        let polynomial = (c0 * x + c1) * x + c2;
+
+        0.5 + polynomial / (2.0 * (1.0 + polynomial * polynomial).sqrt())
+    }
+}
@@ -1,31 +1,48 @@
-use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
 
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::{length_squared, Point3};
+use crate::scene_loader::SphereDef;
+use crate::vec3::{length_squared, Point3, Vec3};
 
-#[derive(Debug)]
-pub(crate) struct Sphere<M: Material + Debug> {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "SphereDef", into = "SphereDef")]
+pub(crate) struct Sphere {
     center: Point3,
     radius: f64,
-    material: M,
+    material: Material,
 }
 
-impl<M: Material + Debug> Sphere<M> {
-    pub(crate) fn new(center: Point3, radius: f64, material: M) -> Self {
+impl Sphere {
+    pub(crate) fn new(center: Point3, radius: f64, material: Material) -> Self {
         Self {
             center,
             radius,
             material,
         }
     }
-}
 
-impl<M: Material + Clone + Debug> Hittable for Sphere<M> {
-    type Material = M;
+    pub(crate) fn center(&self) -> &Point3 {
+        &self.center
+    }
+
+    pub(crate) fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub(crate) fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub(crate) fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+}
 
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<M>> {
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = r.origin() - self.center;
         let a = length_squared(r.direction());
         let half_b = oc.dot(r.direction());
@@ -52,10 +69,118 @@ impl<M: Material + Clone + Debug> Hittable for Sphere<M> {
         let t = root;
         let p = r.at(t);
 
-        let mut rec = HitRecord::new(p, (p - self.center) / self.radius, self.material.clone(), t);
+        let mut rec = HitRecord::new(p, (p - self.center) / self.radius, &self.material, t);
         let outward_normal = (rec.p() - self.center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        rec.set_attributes(1.0, 1.0 / self.radius);
 
         Some(rec)
     }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // Hollow spheres have a negative radius, but take the same space.
+        let radius = self.radius.abs();
+        let radius = Vec3::new(radius, radius, radius);
+
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::rtweekend::INFINITY;
+
+    fn sphere() -> Sphere {
+        Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, material())
+    }
+
+    #[test]
+    fn hits_the_near_side_from_outside() {
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        assert_hit(
+            sphere().hit(&r, 0.001, INFINITY),
+            &r,
+            1.5,
+            Vec3::new(0.0, 0.0, 1.0),
+            true,
+        );
+    }
+
+    #[test]
+    fn t_scales_with_the_direction() {
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -3.0), Some(0.0));
+
+        assert_hit(
+            sphere().hit(&r, 0.001, INFINITY),
+            &r,
+            0.5,
+            Vec3::new(0.0, 0.0, 1.0),
+            true,
+        );
+    }
+
+    #[test]
+    fn hits_the_inside_from_the_center() {
+        let r = Ray::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Some(0.0),
+        );
+
+        assert_hit(
+            sphere().hit(&r, 0.001, INFINITY),
+            &r,
+            0.5,
+            Vec3::new(0.0, -1.0, 0.0),
+            false,
+        );
+    }
+
+    #[test]
+    fn takes_the_far_side_past_t_min() {
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        assert_hit(
+            sphere().hit(&r, 2.0, INFINITY),
+            &r,
+            2.5,
+            Vec3::new(0.0, 0.0, 1.0),
+            false,
+        );
+    }
+
+    #[test]
+    fn misses_outside_the_interval_and_beside_it() {
+        let sphere = sphere();
+        let toward = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+        let beside = Ray::new(
+            Point3::new(0.6, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Some(0.0),
+        );
+
+        assert!(sphere.hit(&toward, 0.001, 1.0).is_none());
+        assert!(sphere.hit(&toward, 3.0, INFINITY).is_none());
+        assert!(sphere.hit(&beside, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn hollow_spheres_face_inward() {
+        let hollow = Sphere::new(Point3::new(0.0, 0.0, -2.0), -0.5, material());
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        // From outside, the ray is in the material around the bubble, and meets it from behind.
+        assert_hit(
+            hollow.hit(&r, 0.001, INFINITY),
+            &r,
+            1.5,
+            Vec3::new(0.0, 0.0, 1.0),
+            false,
+        );
+        let bbox = hollow.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bbox.min(), &Point3::new(-0.5, -0.5, -2.5));
+    }
 }
@@ -0,0 +1,188 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static SHADOW_RAYS: AtomicU64 = AtomicU64::new(0);
+static CACHED_SHADOW_RAYS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The allocations made by this thread, for tests to tell them from those of the others.
+    static THREAD_ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Adds `traced` shadow rays, `cached` of which were found blocked by the last thing that
+/// blocked a ray toward the same light.
+pub(crate) fn count_shadow_rays(traced: u64, cached: u64) {
+    SHADOW_RAYS.fetch_add(traced, Ordering::Relaxed);
+    CACHED_SHADOW_RAYS.fetch_add(cached, Ordering::Relaxed);
+}
+
+fn count_allocation(bytes: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+    // Threads being torn down have no counter left.
+    let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+/// The system allocator, counting how many allocations are made and how many bytes they ask for.
+pub(crate) struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Mark {
+    time: Instant,
+    allocations: u64,
+    bytes: u64,
+}
+
+impl Mark {
+    fn now() -> Self {
+        Self {
+            time: Instant::now(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Phase {
+    name: &'static str,
+    elapsed: Duration,
+    allocations: u64,
+    bytes: u64,
+}
+
+/// Time spent and allocations made in each phase of a run, printed with `--stats`.
+pub(crate) struct Stats {
+    last: Mark,
+    phases: Vec<Phase>,
+}
+
+impl Stats {
+    pub(crate) fn start() -> Self {
+        Self {
+            last: Mark::now(),
+            phases: vec![],
+        }
+    }
+
+    /// Ends the phase called `name`, which began when the previous one ended.
+    pub(crate) fn end_phase(&mut self, name: &'static str) {
+        let now = Mark::now();
+        self.phases.push(Phase {
+            name,
+            elapsed: now.time - self.last.time,
+            allocations: now.allocations - self.last.allocations,
+            bytes: now.bytes - self.last.bytes,
+        });
+        self.last = Mark::now();
+    }
+
+    pub(crate) fn print(&self) {
+        for phase in &self.phases {
+            eprintln!(
+                "{:<10} {:>8.2}s {:>10} allocations {:>12} bytes",
+                phase.name,
+                phase.elapsed.as_secs_f64(),
+                phase.allocations,
+                phase.bytes
+            );
+        }
+
+        let shadow_rays = SHADOW_RAYS.load(Ordering::Relaxed);
+        if shadow_rays > 0 {
+            let cached = CACHED_SHADOW_RAYS.load(Ordering::Relaxed);
+            eprintln!(
+                "shadow rays {:>10}, {:.1}% blocked by the last blocker toward their light",
+                shadow_rays,
+                100.0 * cached as f64 / shadow_rays as f64
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demo::DemoScene;
+    use crate::hittable::Hittable;
+    use crate::light::{LightSampling, OcclusionCache};
+    use crate::ray::Ray;
+    use crate::render::{ray_color, ray_radiance, PathSampling, Regularization, World};
+    use crate::scene_loader::{load_scene, LoadOptions};
+    use crate::spectrum::sample_wavelengths;
+    use crate::vec3::{random_unit_vector, Point3};
+
+    fn thread_allocations() -> u64 {
+        THREAD_ALLOCATIONS.with(Cell::get)
+    }
+
+    #[test]
+    fn tracing_rays_does_not_allocate() {
+        let before = thread_allocations();
+        std::hint::black_box(vec![0u8; 16]);
+        assert_eq!(thread_allocations() - before, 1);
+
+        // The furnace has every kind of material, the Cornell box lights and blocks.
+        for demo in [DemoScene::Furnace, DemoScene::Cornell] {
+            let options = LoadOptions {
+                source: Some(demo.scene_file().unwrap()),
+                ..LoadOptions::default()
+            };
+            let scene = load_scene(&demo.to_string(), &options).unwrap();
+            let occlusion = OcclusionCache::new(&scene.lights);
+            let world = World::new(
+                scene.world,
+                scene.lights,
+                scene.background,
+                scene.backplate,
+                scene.fog,
+                scene.interval,
+            );
+            let sampling = PathSampling {
+                regularization: Regularization::After(1),
+                lights: LightSampling::Tree,
+                guide: None,
+                training: false,
+                occlusion: Some(&occlusion),
+            };
+            let origin = Point3::new(0.0, 1.0, 0.5);
+            let trace = || {
+                let r = Ray::new(origin, random_unit_vector(), Some(0.0));
+                world.objects().hit(&r, 0.001, f64::INFINITY);
+                ray_color(&r, &world, 8, &sampling);
+                ray_radiance(&r, &world, 8, &sampling, &sample_wavelengths());
+            };
+
+            // Lazily built spectra allocate once, the first time their material is hit.
+            (0..2_000).for_each(|_| trace());
+            let before = thread_allocations();
+            (0..2_000).for_each(|_| trace());
+
+            assert_eq!(thread_allocations() - before, 0, "tracing {}", demo);
+        }
+    }
+}
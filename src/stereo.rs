@@ -0,0 +1,38 @@
+use std::str::FromStr;
+
+/// How the two eyes of a stereo render are written out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StereoMode {
+    /// A single image twice as wide as requested, left eye on the left.
+    SideBySide,
+    /// Two images, with `-left` and `-right` appended to the output file name.
+    Separate,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(Self::SideBySide),
+            "separate" => Ok(Self::Separate),
+            _ => Err(format!(
+                "unknown stereo mode `{}` (expected `side-by-side` or `separate`)",
+                s
+            )),
+        }
+    }
+}
+
+/// Joins two RGB8 images of `width` by `height` pixels into one of `2 * width` by `height`.
+pub(crate) fn side_by_side(left: &[u8], right: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row_len = width as usize * 3;
+    let mut image_data = Vec::with_capacity(row_len * 2 * height as usize);
+
+    for (left_row, right_row) in left.chunks(row_len).zip(right.chunks(row_len)) {
+        image_data.extend_from_slice(left_row);
+        image_data.extend_from_slice(right_row);
+    }
+
+    image_data
+}
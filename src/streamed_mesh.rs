@@ -0,0 +1,814 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use tracing::warn;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::bvh::Bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::mesh::{Backfaces, Mesh};
+use crate::ray::Ray;
+use crate::scene_error::SceneError;
+use crate::scene_loader::StartEndPair;
+use crate::vec3::{Point3, Vec3};
+
+/// Number of triangles stored together on disk and loaded at once.
+pub(crate) const CHUNK_TRIANGLES: usize = 4096;
+
+static NEXT_STORE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How many shards [`GeometryCache`] splits its chunks over.
+const SHARDS: usize = 16;
+
+type ChunkKey = (usize, usize);
+
+/// Keeps recently used mesh chunks in memory, up to a budget shared by all streamed meshes.
+///
+/// Chunks are spread over shards that hits only read, so that threads tracing resident chunks
+/// don't wait on each other. Eviction is a clock: chunks are passed in the order they were
+/// loaded, and those hit since they were last passed get another round.
+pub(crate) struct GeometryCache {
+    capacity: usize,
+    shards: [RwLock<HashMap<ChunkKey, CacheEntry>>; SHARDS],
+    /// Taken to add chunks, and to evict others to make room for them.
+    eviction: Mutex<Eviction>,
+}
+
+struct CacheEntry {
+    chunk: Arc<Mesh>,
+    size: usize,
+    /// Whether the chunk was hit since the clock last passed it.
+    referenced: AtomicBool,
+}
+
+struct Eviction {
+    used: usize,
+    /// The resident chunks, in the order the clock passes them.
+    clock: VecDeque<ChunkKey>,
+}
+
+impl GeometryCache {
+    /// Creates a cache holding about `capacity` bytes of geometry.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
+            eviction: Mutex::new(Eviction {
+                used: 0,
+                clock: VecDeque::new(),
+            }),
+        }
+    }
+
+    fn shard(&self, (store, index): ChunkKey) -> &RwLock<HashMap<ChunkKey, CacheEntry>> {
+        &self.shards[(store + index) % SHARDS]
+    }
+
+    /// Returns the chunk for `key` if it is resident, marking it as used.
+    fn resident(&self, key: ChunkKey) -> Option<Arc<Mesh>> {
+        let shard = self.shard(key).read().unwrap();
+        let entry = shard.get(&key)?;
+        entry.referenced.store(true, Ordering::Relaxed);
+
+        Some(entry.chunk.clone())
+    }
+
+    /// Returns the chunk for `key`, calling `load` to read it if it isn't resident. Chunks are
+    /// evicted to make room; the latest one is always kept.
+    fn get(
+        &self,
+        key: ChunkKey,
+        size: usize,
+        load: impl FnOnce() -> io::Result<Mesh>,
+    ) -> io::Result<Arc<Mesh>> {
+        if let Some(chunk) = self.resident(key) {
+            return Ok(chunk);
+        }
+
+        // Read without holding a lock, so that other threads keep tracing resident chunks.
+        let chunk = Arc::new(load()?);
+
+        let mut eviction = self.eviction.lock().unwrap();
+        if let Some(chunk) = self.resident(key) {
+            // Another thread loaded it in the meantime.
+            return Ok(chunk);
+        }
+
+        // Chunks hit since they were last passed get a single second chance, so that the
+        // clock stops even while other threads keep hitting them.
+        let mut chances = eviction.clock.len();
+        while eviction.used + size > self.capacity {
+            let oldest = match eviction.clock.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            let mut shard = self.shard(oldest).write().unwrap();
+            if chances > 0 && shard[&oldest].referenced.swap(false, Ordering::Relaxed) {
+                chances -= 1;
+                eviction.clock.push_back(oldest);
+                continue;
+            }
+            if let Some(entry) = shard.remove(&oldest) {
+                eviction.used -= entry.size;
+            }
+        }
+
+        eviction.used += size;
+        eviction.clock.push_back(key);
+        self.shard(key).write().unwrap().insert(
+            key,
+            CacheEntry {
+                chunk: chunk.clone(),
+                size,
+                referenced: AtomicBool::new(false),
+            },
+        );
+
+        Ok(chunk)
+    }
+}
+
+struct ChunkLocation {
+    offset: u64,
+    triangles: usize,
+}
+
+/// The on-disk half of a streamed mesh: a temporary file of triangle soup, one chunk after the
+/// other, each vertex position stored as three little-endian `f64`s for every frame, then as
+/// many normals for smooth meshes.
+struct ChunkStore {
+    id: usize,
+    path: TempPath,
+    file: Mutex<File>,
+    chunks: Vec<ChunkLocation>,
+    frame_count: usize,
+    smooth: bool,
+    time: StartEndPair<f64>,
+    material: Material,
+    backfaces: Backfaces,
+    cache: Arc<GeometryCache>,
+    /// Whether reading a chunk failed, which is only reported once.
+    failed: AtomicBool,
+}
+
+impl ChunkStore {
+    fn chunk(&self, index: usize) -> io::Result<Arc<Mesh>> {
+        let location = &self.chunks[index];
+        // Vertex positions (and normals) for every frame, plus the chunk's own hierarchy and
+        // triangles.
+        let sets = if self.smooth { 2 } else { 1 };
+        let size = location.triangles * (sets * self.frame_count * 3 * 24 + 160);
+
+        self.cache.get((self.id, index), size, || {
+            let vertex_count = location.triangles * 3;
+            let mut bytes = vec![0; sets * vertex_count * self.frame_count * 24];
+            {
+                let mut file = self.file.lock().unwrap();
+                file.seek(SeekFrom::Start(location.offset))?;
+                file.read_exact(&mut bytes)?;
+            }
+
+            let mut values = bytes
+                .chunks(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]));
+            let mut read_frames = |count| -> Vec<Vec<Vec3>> {
+                (0..count)
+                    .map(|_| {
+                        (0..vertex_count)
+                            .map(|_| {
+                                let mut next = || values.next().unwrap();
+                                Point3::new(next(), next(), next())
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            let frames = read_frames(self.frame_count);
+            let normals = read_frames(if self.smooth { self.frame_count } else { 0 });
+            let faces: Vec<_> = (0..location.triangles)
+                .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+                .collect();
+
+            // Only a file changed behind the raytracer's back can hold what it didn't write.
+            Mesh::new(
+                frames,
+                normals,
+                &faces,
+                self.time.clone(),
+                self.material.clone(),
+                self.backfaces,
+            )
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+struct Chunk {
+    store: Arc<ChunkStore>,
+    index: usize,
+    bbox: Aabb,
+}
+
+impl Hittable for Chunk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // The chunk may be evicted as soon as this returns, so refer to the store's material.
+        let chunk = match self.store.chunk(self.index) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                // Rays go through the chunks that can't be read, rather than stopping the render.
+                if !self.store.failed.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "couldn't read mesh chunks from {}, leaving them out: {}",
+                        self.store.path.display(),
+                        e
+                    );
+                }
+                return None;
+            }
+        };
+        let rec = chunk.hit(r, t_min, t_max)?;
+
+        Some(rec.with_material(&self.store.material))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+/// A triangle mesh kept on disk, with only the bounds of its chunks in memory. Chunks are read
+/// back when a ray reaches them and stay in the shared [`GeometryCache`] while they are used.
+pub(crate) struct StreamedMesh {
+    chunks: Bvh<Chunk>,
+}
+
+impl StreamedMesh {
+    /// Splits the mesh into spatially coherent chunks and writes them to a temporary file.
+    /// Arguments are as for [`Mesh::new`], which must have accepted them.
+    pub(crate) fn new(
+        frames: &[Vec<Point3>],
+        normals: &[Vec<Vec3>],
+        faces: &[[usize; 3]],
+        time: StartEndPair<f64>,
+        material: Material,
+        backfaces: Backfaces,
+        cache: Arc<GeometryCache>,
+    ) -> Result<Self, SceneError> {
+        let mut writer = ChunkWriter::create(frames, normals)?;
+        writer.write(faces.to_vec())?;
+
+        writer.finish(time, material, backfaces, cache)
+    }
+
+    /// Like [`new`](Self::new), for a mesh without normals whose triangles are too many to
+    /// hold: `faces` are read once, and split into chunks on disk rather than in memory, so that
+    /// only so many of them are held at once.
+    pub(crate) fn from_triangles(
+        frames: &[Vec<Point3>],
+        faces: impl Iterator<Item = Result<[usize; 3], SceneError>>,
+        time: StartEndPair<f64>,
+        material: Material,
+        backfaces: Backfaces,
+        cache: Arc<GeometryCache>,
+    ) -> Result<Self, SceneError> {
+        let writer = ChunkWriter::create(frames, &[])?;
+
+        writer
+            .spill(faces)?
+            .finish(time, material, backfaces, cache)
+    }
+}
+
+/// How many triangles of a mesh streamed from its file are split into chunks in memory at
+/// once. Larger groups are first halved on disk.
+const IN_MEMORY_TRIANGLES: usize = 64 * CHUNK_TRIANGLES;
+
+/// Bytes a triangle takes in the files that meshes are split in: three little-endian `u64`
+/// vertex indices.
+const FACE_BYTES: u64 = 24;
+
+/// Writes the chunks of a streamed mesh to a temporary file, a group of triangles at a time.
+struct ChunkWriter<'a> {
+    id: usize,
+    frames: &'a [Vec<Point3>],
+    normals: &'a [Vec<Vec3>],
+    path: TempPath,
+    writer: BufWriter<File>,
+    chunks: Vec<ChunkLocation>,
+    bounding_boxes: Vec<Aabb>,
+    offset: u64,
+    /// How many triangles are split into chunks in memory at once.
+    in_memory: usize,
+}
+
+impl<'a> ChunkWriter<'a> {
+    fn create(frames: &'a [Vec<Point3>], normals: &'a [Vec<Vec3>]) -> Result<Self, SceneError> {
+        let id = NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed);
+        let path = TempPath::new(&format!("{}.chunks", id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&*path)
+            .map_err(|source| SceneError::Write {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        Ok(Self {
+            id,
+            frames,
+            normals,
+            path,
+            writer: BufWriter::new(file),
+            chunks: vec![],
+            bounding_boxes: vec![],
+            offset: 0,
+            in_memory: IN_MEMORY_TRIANGLES,
+        })
+    }
+
+    fn bounds(&self, face: [usize; 3]) -> Aabb {
+        Aabb::from_points(
+            self.frames
+                .iter()
+                .flat_map(|frame| face.iter().map(move |&i| frame[i])),
+        )
+        .unwrap()
+    }
+
+    fn writing(&self, source: io::Error) -> SceneError {
+        SceneError::Write {
+            path: self.path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Splits `faces` into chunks and writes them.
+    fn write(&mut self, faces: Vec<[usize; 3]>) -> Result<(), SceneError> {
+        let mut groups = vec![];
+        partition(
+            faces
+                .into_iter()
+                .map(|face| (self.bounds(face), face))
+                .collect(),
+            &mut groups,
+        );
+
+        for group in &groups {
+            for frame in self.frames.iter().chain(self.normals) {
+                for (_, face) in group {
+                    for &i in face {
+                        for value in frame[i].iter() {
+                            self.writer
+                                .write_all(&value.to_le_bytes())
+                                .map_err(|e| self.writing(e))?;
+                        }
+                    }
+                }
+            }
+
+            self.chunks.push(ChunkLocation {
+                offset: self.offset,
+                triangles: group.len(),
+            });
+            self.bounding_boxes.push(
+                group
+                    .iter()
+                    .map(|(bbox, _)| *bbox)
+                    .reduce(|a, b| surrounding_box(&a, &b))
+                    .unwrap(),
+            );
+            self.offset += (group.len() * 3 * (self.frames.len() + self.normals.len()) * 24) as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the `count` triangles from `start` in the first of `files`, whose centroids lie
+    /// in `centroids`. Until they fit in memory, they are halved across the middle of the
+    /// longest axis of their centroids into the second file, which then splits its halves back
+    /// into the first.
+    fn split(
+        &mut self,
+        [from, to]: [&Path; 2],
+        start: u64,
+        count: u64,
+        centroids: Option<Aabb>,
+    ) -> Result<(), SceneError> {
+        let reading = |source| SceneError::Io {
+            path: from.to_path_buf(),
+            source,
+        };
+        let writing = |source| SceneError::Write {
+            path: to.to_path_buf(),
+            source,
+        };
+        let centroids = match centroids {
+            Some(centroids) if count as usize > self.in_memory => centroids,
+            _ => {
+                let faces = read_faces(from, start, count)
+                    .and_then(Iterator::collect::<io::Result<_>>)
+                    .map_err(reading)?;
+                return self.write(faces);
+            }
+        };
+
+        let axis = (centroids.max() - centroids.min()).imax();
+        let middle = centroids.centroid()[axis];
+        let is_left = |face| self.bounds(face).centroid()[axis] < middle;
+        let mut left = 0;
+        for face in read_faces(from, start, count).map_err(reading)? {
+            left += u64::from(is_left(face.map_err(reading)?));
+        }
+        // Triangles whose centroids all lie at one point are halved as they come instead.
+        let by_position = left > 0 && left < count;
+        if !by_position {
+            left = count / 2;
+        }
+
+        let mut halves = [
+            open_at(to, start).map_err(writing)?,
+            open_at(to, start + left).map_err(writing)?,
+        ];
+        let mut bounds = [None, None];
+        for (i, face) in read_faces(from, start, count).map_err(reading)?.enumerate() {
+            let face = face.map_err(reading)?;
+            let half = if by_position {
+                usize::from(!is_left(face))
+            } else {
+                usize::from(i as u64 >= left)
+            };
+            write_face(&mut halves[half], face).map_err(writing)?;
+            bounds[half] = Some(grow(bounds[half], self.bounds(face).centroid()));
+        }
+        for half in &mut halves {
+            half.flush().map_err(writing)?;
+        }
+        drop(halves);
+
+        let [left_bounds, right_bounds] = bounds;
+        self.split([to, from], start, left, left_bounds)?;
+        self.split([to, from], start + left, count - left, right_bounds)
+    }
+
+    /// Writes `faces` to a file as they come, then splits them into chunks from there.
+    fn spill(
+        mut self,
+        faces: impl Iterator<Item = Result<[usize; 3], SceneError>>,
+    ) -> Result<Self, SceneError> {
+        let spill = [
+            TempPath::new(&format!("{}.faces", self.id)),
+            TempPath::new(&format!("{}.split", self.id)),
+        ];
+        let writing = |source| SceneError::Write {
+            path: spill[0].to_path_buf(),
+            source,
+        };
+
+        let mut count = 0;
+        let mut centroids = None;
+        let mut spilled = BufWriter::new(File::create(&*spill[0]).map_err(writing)?);
+        for face in faces {
+            let face = face?;
+            write_face(&mut spilled, face).map_err(writing)?;
+            count += 1;
+            centroids = Some(grow(centroids, self.bounds(face).centroid()));
+        }
+        spilled.flush().map_err(writing)?;
+        drop(spilled);
+        self.split([&spill[0], &spill[1]], 0, count, centroids)?;
+
+        Ok(self)
+    }
+
+    fn finish(
+        self,
+        time: StartEndPair<f64>,
+        material: Material,
+        backfaces: Backfaces,
+        cache: Arc<GeometryCache>,
+    ) -> Result<StreamedMesh, SceneError> {
+        let path = self.path;
+        let file = self.writer.into_inner().map_err(|e| SceneError::Write {
+            path: path.to_path_buf(),
+            source: e.into_error(),
+        })?;
+
+        let store = Arc::new(ChunkStore {
+            id: self.id,
+            path,
+            file: Mutex::new(file),
+            chunks: self.chunks,
+            frame_count: self.frames.len(),
+            smooth: !self.normals.is_empty(),
+            time,
+            material,
+            backfaces,
+            cache,
+            failed: AtomicBool::new(false),
+        });
+        let chunks = self
+            .bounding_boxes
+            .into_iter()
+            .enumerate()
+            .map(|(index, bbox)| Chunk {
+                store: store.clone(),
+                index,
+                bbox,
+            })
+            .collect();
+
+        Ok(StreamedMesh {
+            chunks: Bvh::new(chunks, 0.0, 1.0),
+        })
+    }
+}
+
+/// Returns `bounds` grown to hold `point`.
+fn grow(bounds: Option<Aabb>, point: Point3) -> Aabb {
+    let point = Aabb::new(point, point);
+    match bounds {
+        Some(bounds) => surrounding_box(&bounds, &point),
+        None => point,
+    }
+}
+
+fn write_face(writer: &mut impl Write, face: [usize; 3]) -> io::Result<()> {
+    for index in face {
+        writer.write_all(&(index as u64).to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Opens `path` to write triangles from the one at `start` on.
+fn open_at(path: &Path, start: u64) -> io::Result<BufWriter<File>> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.seek(SeekFrom::Start(start * FACE_BYTES))?;
+
+    Ok(BufWriter::new(file))
+}
+
+/// Reads the `count` triangles from `start` in `path`.
+fn read_faces(
+    path: &Path,
+    start: u64,
+    count: u64,
+) -> io::Result<impl Iterator<Item = io::Result<[usize; 3]>>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start * FACE_BYTES))?;
+    let mut reader = BufReader::new(file);
+
+    Ok((0..count).map(move |_| {
+        let mut bytes = [0; FACE_BYTES as usize];
+        reader.read_exact(&mut bytes)?;
+        let index = |i: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+            u64::from_le_bytes(value) as usize
+        };
+
+        Ok([index(0), index(1), index(2)])
+    }))
+}
+
+/// A file in the temporary directory, removed once dropped.
+struct TempPath(PathBuf);
+
+impl TempPath {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("raytracer-{}-{}", std::process::id(), name)))
+    }
+}
+
+impl std::ops::Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+impl StreamedMesh {
+    pub(crate) fn triangle_count(&self) -> usize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.store.chunks[chunk.index].triangles)
+            .sum()
+    }
+
+    /// Returns the bytes kept in memory for the mesh, which is only the hierarchy of chunks.
+    pub(crate) fn memory(&self) -> usize {
+        self.chunks.memory()
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.chunks.node_count()
+    }
+}
+
+/// Splits `faces` along the longest axis of their centroids until each group fits in a chunk.
+fn partition(mut faces: Vec<(Aabb, [usize; 3])>, groups: &mut Vec<Vec<(Aabb, [usize; 3])>>) {
+    if faces.len() <= CHUNK_TRIANGLES {
+        if !faces.is_empty() {
+            groups.push(faces);
+        }
+
+        return;
+    }
+
+    let centroids = Aabb::from_points(faces.iter().map(|(bbox, _)| bbox.centroid())).unwrap();
+    let axis = (centroids.max() - centroids.min()).imax();
+    faces.sort_by(|(a, _), (b, _)| {
+        a.centroid()[axis]
+            .partial_cmp(&b.centroid()[axis])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let right = faces.split_off(faces.len() / 2);
+    partition(faces, groups);
+    partition(right, groups);
+}
+
+impl Hittable for StreamedMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.chunks.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.chunks.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::material;
+
+    /// A square of `n` by `n` cells in the plane `z = -1`, two triangles each, from the origin
+    /// to `(1, 1)`.
+    fn grid(n: usize) -> (Vec<Point3>, Vec<[usize; 3]>) {
+        let step = 1.0 / n as f64;
+        let vertices = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| Point3::new(i as f64 * step, j as f64 * step, -1.0)))
+            .collect();
+        let faces = (0..n)
+            .flat_map(|j| {
+                (0..n).flat_map(move |i| {
+                    let corner = j * (n + 1) + i;
+                    [
+                        [corner, corner + 1, corner + n + 2],
+                        [corner, corner + n + 2, corner + n + 1],
+                    ]
+                })
+            })
+            .collect();
+
+        (vertices, faces)
+    }
+
+    fn chunk() -> Mesh {
+        let (vertices, faces) = grid(1);
+        Mesh::new(
+            vec![vertices],
+            vec![],
+            &faces,
+            StartEndPair::new(0.0, 1.0),
+            material(),
+            Backfaces::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn evicts_chunks_not_hit_since_the_clock_passed() {
+        let cache = GeometryCache::new(3);
+        for index in 0..3 {
+            cache.get((0, index), 1, || Ok(chunk())).unwrap();
+        }
+
+        // The first chunk is hit again, so the second goes to make room.
+        assert!(cache.resident((0, 0)).is_some());
+        cache.get((0, 3), 1, || Ok(chunk())).unwrap();
+        assert!(cache.resident((0, 0)).is_some());
+        assert!(cache.resident((0, 1)).is_none());
+        assert!(cache.resident((0, 2)).is_some());
+        assert_eq!(cache.eviction.lock().unwrap().used, 3);
+
+        // A chunk larger than the cache still stays, alone.
+        cache.get((1, 0), 5, || Ok(chunk())).unwrap();
+        assert!(cache.resident((1, 0)).is_some());
+        assert_eq!(cache.eviction.lock().unwrap().clock.len(), 1);
+
+        // Chunks that can't be read aren't kept.
+        let failed = cache.get((1, 1), 1, || Err(io::ErrorKind::UnexpectedEof.into()));
+        assert!(failed.is_err());
+        assert!(cache.resident((1, 1)).is_none());
+    }
+
+    #[test]
+    fn meshes_split_on_disk_are_hit_like_meshes_in_memory() {
+        let (vertices, faces) = grid(96);
+        let frames = [vertices];
+        let mesh = Mesh::new(
+            frames.to_vec(),
+            vec![],
+            &faces,
+            StartEndPair::new(0.0, 1.0),
+            material(),
+            Backfaces::default(),
+        )
+        .unwrap();
+        // Few enough triangles are held at once that they are halved on disk twice over.
+        let mut writer = ChunkWriter::create(&frames, &[]).unwrap();
+        writer.in_memory = faces.len() / 3;
+        let streamed = writer
+            .spill(faces.iter().map(|&face| Ok(face)))
+            .unwrap()
+            .finish(
+                StartEndPair::new(0.0, 1.0),
+                material(),
+                Backfaces::default(),
+                Arc::new(GeometryCache::new(usize::MAX)),
+            )
+            .unwrap();
+
+        assert_eq!(streamed.triangle_count(), faces.len());
+        assert!(streamed.chunks.iter().count() >= 4);
+        for (x, y) in [
+            (0.05, 0.05),
+            (0.33, 0.71),
+            (0.5, 0.5),
+            (0.97, 0.02),
+            (1.5, 0.5),
+        ] {
+            let r = Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+            let expected = mesh.hit(&r, 0.001, f64::INFINITY).map(|hit| *hit.p());
+            let hit = streamed.hit(&r, 0.001, f64::INFINITY).map(|hit| *hit.p());
+            assert_eq!(hit, expected);
+        }
+    }
+
+    #[test]
+    fn chunks_that_cant_be_read_are_missed() {
+        let (vertices, faces) = grid(64);
+        let mesh = StreamedMesh::new(
+            &[vertices],
+            &[],
+            &faces,
+            StartEndPair::new(0.0, 1.0),
+            material(),
+            Backfaces::default(),
+            Arc::new(GeometryCache::new(0)),
+        )
+        .unwrap();
+        // The mesh is split in two chunks, one on either side of the diagonal.
+        assert_eq!(mesh.chunks.iter().count(), 2);
+        let ray = |x, y| Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+        let (resident, other) = (ray(0.1, 0.1), ray(0.9, 0.9));
+        assert!(mesh.hit(&resident, 0.001, f64::INFINITY).is_some());
+
+        // As if the temporary file were cut short behind the raytracer's back.
+        let store = &mesh.chunks.iter().next().unwrap().store;
+        OpenOptions::new()
+            .write(true)
+            .open(&*store.path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        let unread = (0..2)
+            .find(|&index| store.cache.resident((store.id, index)).is_none())
+            .unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || store.chunk(unread).is_err())
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+        // The chunk still in memory goes on being hit.
+        assert!(mesh.hit(&other, 0.001, f64::INFINITY).is_none());
+        assert!(mesh.hit(&resident, 0.001, f64::INFINITY).is_some());
+        assert!(store.failed.load(Ordering::Relaxed));
+    }
+}
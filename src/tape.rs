@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use argh::FromArgs;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::output::write_atomically;
+use crate::render::{sample_pixel, PathSampling, RenderSettings, World};
+use crate::sampler::{dither, splitmix, to_unit, Sampler};
+use crate::vec3::Color;
+
+/// Whether any thread ever recorded or replayed, so that renders can skip looking.
+static TAPING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static REEL: RefCell<Option<Reel>> = const { RefCell::new(None) };
+}
+
+/// The random numbers that the current thread draws, while it records or replays them.
+enum Reel {
+    Recording(Vec<f64>),
+    Replaying { draws: Vec<f64>, next: usize },
+}
+
+/// Records or replays the random numbers that the current thread draws, until finished.
+pub(crate) struct ReelGuard;
+
+impl ReelGuard {
+    /// Stops recording or replaying, and returns the numbers recorded, or those replayed,
+    /// with the number of draws made.
+    pub(crate) fn finish(self) -> (Vec<f64>, usize) {
+        match REEL.with(|reel| reel.borrow_mut().take()) {
+            Some(Reel::Recording(draws)) => {
+                let made = draws.len();
+                (draws, made)
+            }
+            Some(Reel::Replaying { draws, next }) => (draws, next),
+            None => (vec![], 0),
+        }
+    }
+}
+
+impl Drop for ReelGuard {
+    fn drop(&mut self) {
+        REEL.with(|reel| reel.borrow_mut().take());
+    }
+}
+
+/// Records the random numbers that the current thread draws until the guard is finished.
+pub(crate) fn record() -> ReelGuard {
+    TAPING.store(true, Ordering::Relaxed);
+    REEL.with(|reel| *reel.borrow_mut() = Some(Reel::Recording(vec![])));
+
+    ReelGuard
+}
+
+/// Makes the current thread draw `draws` in turn until the guard is finished, then numbers
+/// that only depend on how many it drew before, so that replays are the same every time.
+pub(crate) fn replay(draws: Vec<f64>) -> ReelGuard {
+    TAPING.store(true, Ordering::Relaxed);
+    REEL.with(|reel| *reel.borrow_mut() = Some(Reel::Replaying { draws, next: 0 }));
+
+    ReelGuard
+}
+
+/// Returns the next random number of the current thread: the one replayed, or else one of
+/// `draw`, recorded if the thread is recording.
+pub(crate) fn taped(draw: impl FnOnce() -> f64) -> f64 {
+    if !TAPING.load(Ordering::Relaxed) {
+        return draw();
+    }
+
+    REEL.with(|reel| match &mut *reel.borrow_mut() {
+        Some(Reel::Replaying { draws, next }) => {
+            let value = match draws.get(*next) {
+                Some(&value) => value,
+                None => to_unit(splitmix(*next as u64)),
+            };
+            *next += 1;
+            value
+        }
+        Some(Reel::Recording(draws)) => {
+            let value = draw();
+            draws.push(value);
+            value
+        }
+        None => draw(),
+    })
+}
+
+/// Record the random numbers that each sample of a pixel draws, and the color it comes to,
+/// for `replay` to check that the same numbers still come to the same colors.
+///
+/// The pixel is counted from the top left corner of an image as large as `-w` and `-a` make
+/// it, and takes `-s` samples with paths as deep as `-d`, without guiding.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "record")]
+pub(crate) struct Record {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// pixel column, from the left
+    #[argh(positional)]
+    pub(crate) x: u32,
+
+    /// pixel row, from the top
+    #[argh(positional)]
+    pub(crate) y: u32,
+
+    /// file to write the random numbers to
+    #[argh(option)]
+    pub(crate) to: String,
+}
+
+/// Replay the random numbers that `record` wrote for the samples of a pixel, and tell which
+/// samples come to other colors than when they were recorded, failing if any does.
+///
+/// Nothing else is random, so that a sample only changes with the code or the scene: run
+/// this at each step of a bisection to find the change that did it. The scene file, image
+/// size and depth are those recorded, while the other options must be the same as then.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "replay")]
+pub(crate) struct Replay {
+    /// file written by `record`
+    #[argh(positional)]
+    pub(crate) tape: String,
+}
+
+/// What `record` writes.
+#[derive(Deserialize, Serialize)]
+struct Tape {
+    scene_file: String,
+    image_width: u32,
+    image_height: u32,
+    x: u32,
+    y: u32,
+    max_depth: usize,
+    spectral: bool,
+    samples: Vec<TapeSample>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct TapeSample {
+    color: [f64; 3],
+    /// The random numbers the sample drew, in turn.
+    draws: Vec<f64>,
+}
+
+impl Record {
+    pub(crate) fn run<H: Hittable>(
+        &self,
+        world: &World<H>,
+        camera: &Camera,
+        settings: RenderSettings,
+    ) -> Result<()> {
+        let (width, height) = (settings.image_width, settings.image_height);
+        if self.x >= width || self.y >= height {
+            bail!(
+                "pixel ({}, {}) is outside the {}x{} image",
+                self.x,
+                self.y,
+                width,
+                height
+            );
+        }
+
+        let occlusion = world.occlusion_cache(&settings);
+        let sampling = sampling(&settings, occlusion.as_ref());
+        // Rows are traced bottom up, so flip the row.
+        let (i, j) = (self.x, height - 1 - self.y);
+        let seed: u64 = rand::random();
+        let samples = (0..settings.samples_per_pixel)
+            .map(|sample| {
+                let _dither = (settings.sampler == Sampler::BlueNoise)
+                    .then(|| dither(seed, i as usize, j as usize, sample));
+                let reel = record();
+                let color = sample_pixel(world, camera, &settings, &sampling, i, j);
+
+                TapeSample {
+                    color: color.into(),
+                    draws: reel.finish().0,
+                }
+            })
+            .collect();
+
+        let tape = Tape {
+            scene_file: self.scene_file.clone(),
+            image_width: width,
+            image_height: height,
+            x: self.x,
+            y: self.y,
+            max_depth: settings.max_depth,
+            spectral: settings.spectral,
+            samples,
+        };
+        let yaml = serde_yaml::to_string(&tape)?;
+        write_atomically(Path::new(&self.to), |partial| {
+            Ok(std::fs::write(partial, yaml)?)
+        })
+        .wrap_err_with(|| format!("writing {}", self.to))?;
+
+        println!(
+            "recorded {} samples of pixel ({}, {}) to {}",
+            tape.samples.len(),
+            self.x,
+            self.y,
+            self.to
+        );
+
+        Ok(())
+    }
+}
+
+impl Replay {
+    /// Returns the scene file that the tape was recorded for.
+    pub(crate) fn scene_file(&self) -> Result<String> {
+        Ok(self.read()?.scene_file)
+    }
+
+    pub(crate) fn run<H: Hittable>(
+        &self,
+        world: &World<H>,
+        camera: &Camera,
+        settings: RenderSettings,
+    ) -> Result<()> {
+        let tape = self.read()?;
+        let settings = RenderSettings {
+            image_width: tape.image_width,
+            image_height: tape.image_height,
+            max_depth: tape.max_depth,
+            spectral: tape.spectral,
+            ..settings
+        };
+
+        let occlusion = world.occlusion_cache(&settings);
+        let sampling = sampling(&settings, occlusion.as_ref());
+        let (i, j) = (tape.x, tape.image_height - 1 - tape.y);
+        let mut changed = 0;
+        for (index, sample) in tape.samples.iter().enumerate() {
+            let reel = replay(sample.draws.clone());
+            let color = sample_pixel(world, camera, &settings, &sampling, i, j);
+            let made = reel.finish().1;
+
+            let recorded = Color::from(sample.color);
+            if color != recorded || made != sample.draws.len() {
+                changed += 1;
+                println!(
+                    "sample {}: {} after {} draws, was {} after {}",
+                    index,
+                    format_color(&color),
+                    made,
+                    format_color(&recorded),
+                    sample.draws.len()
+                );
+            }
+        }
+
+        if changed > 0 {
+            bail!(
+                "{} of the {} samples of pixel ({}, {}) changed",
+                changed,
+                tape.samples.len(),
+                tape.x,
+                tape.y
+            );
+        }
+        println!(
+            "all {} samples of pixel ({}, {}) are the same",
+            tape.samples.len(),
+            tape.x,
+            tape.y
+        );
+
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Tape> {
+        let source = std::fs::read_to_string(&self.tape)
+            .wrap_err_with(|| format!("reading {}", self.tape))?;
+
+        serde_yaml::from_str(&source).wrap_err_with(|| format!("parsing {}", self.tape))
+    }
+}
+
+/// Paths are traced without guiding, which learns from earlier samples.
+fn sampling<'a>(
+    settings: &RenderSettings,
+    occlusion: Option<&'a crate::light::OcclusionCache>,
+) -> PathSampling<'a> {
+    PathSampling {
+        regularization: settings.regularization,
+        lights: settings.light_sampling,
+        guide: None,
+        training: false,
+        occlusion,
+    }
+}
+
+fn format_color(color: &Color) -> String {
+    format!("({:.6}, {:.6}, {:.6})", color.x, color.y, color.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::random_double;
+
+    #[test]
+    fn replays_draw_what_was_recorded() {
+        let reel = record();
+        let drawn: Vec<f64> = (0..4).map(|_| random_double()).collect();
+        let (recorded, made) = reel.finish();
+        assert_eq!(recorded, drawn);
+        assert_eq!(made, 4);
+
+        // Past the end of the tape, replays still draw the same numbers every time.
+        let replayed = |draws: Vec<f64>| {
+            let reel = replay(draws);
+            let values: Vec<f64> = (0..6).map(|_| random_double()).collect();
+            (values, reel.finish().1)
+        };
+        let (first, made) = replayed(recorded.clone());
+        assert_eq!(first[..4], drawn[..]);
+        assert_eq!(made, 6);
+        assert_eq!(replayed(recorded).0, first);
+
+        // Tapes are written as YAML, which must give the numbers back exactly.
+        let yaml = serde_yaml::to_string(&drawn).unwrap();
+        assert_eq!(serde_yaml::from_str::<Vec<f64>>(&yaml).unwrap(), drawn);
+    }
+}
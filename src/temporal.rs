@@ -0,0 +1,163 @@
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread::spawn;
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::nice;
+use crate::render::{Accumulation, World};
+use crate::vec3::{Point3, Vec3};
+
+/// Smallest cosine between the normals seen at a pixel in two frames for them to be taken for
+/// the same surface.
+const MIN_NORMAL_COSINE: f64 = 0.95;
+/// Largest difference between the depths of a point in two frames, relative to the depth, for
+/// it to be taken for the same one.
+const MAX_DEPTH_ERROR: f64 = 0.01;
+
+/// What the camera sees first through the center of each pixel of a frame, from the top row
+/// down: where, and which way the surface faces there.
+pub(crate) struct GBuffer {
+    camera: Arc<Camera>,
+    width: u32,
+    height: u32,
+    hits: Vec<Option<(Point3, Vec3)>>,
+}
+
+impl GBuffer {
+    pub(crate) fn new<H>(
+        world: &Arc<World<H>>,
+        camera: &Arc<Camera>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self>
+    where
+        H: Hittable + Send + Sync + 'static,
+    {
+        let num_cpus = nice::threads();
+        let workers: Vec<_> = (0..num_cpus)
+            .map(|n| {
+                let world = world.clone();
+                let camera = camera.clone();
+                let (sender, receiver) = channel();
+                let handle = spawn(move || {
+                    let interval = world.interval();
+                    for j in (0..height).filter(|j| *j as usize % num_cpus == n).rev() {
+                        nice::wait_turn(n);
+                        for i in 0..width {
+                            let s = (i as f64 + 0.5) / (width - 1) as f64;
+                            let t = (j as f64 + 0.5) / (height - 1) as f64;
+                            let r = camera.center_ray(s, t);
+                            let (t_min, t_max) = interval.along(&r);
+                            let hit = world
+                                .objects()
+                                .hit(&r, t_min, t_max)
+                                .map(|rec| (*rec.p(), *rec.normal()));
+
+                            sender.send(hit).ok();
+                        }
+                    }
+                });
+                (receiver, handle)
+            })
+            .collect();
+
+        let mut hits = Vec::with_capacity((width * height) as usize);
+        for j in (0..height).rev() {
+            let (worker, _) = &workers[j as usize % num_cpus];
+            for _ in 0..width {
+                hits.push(worker.recv()?);
+            }
+        }
+        for (_, handle) in workers {
+            handle
+                .join()
+                .map_err(|_| eyre!("a reprojection thread panicked"))?;
+        }
+
+        Ok(Self {
+            camera: camera.clone(),
+            width,
+            height,
+            hits,
+        })
+    }
+
+    /// Returns the width and the height of the frame.
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns what the camera sees through each pixel, from the top row down.
+    pub(crate) fn hits(&self) -> &[Option<(Point3, Vec3)>] {
+        &self.hits
+    }
+
+    /// Returns the index of the pixel through which the camera sees `p`, if it is in the frame.
+    fn pixel(&self, p: &Point3) -> Option<usize> {
+        let (s, t) = self.camera.project(p)?;
+        let i = (s * (self.width - 1) as f64 - 0.5).round();
+        let j = (t * (self.height - 1) as f64 - 0.5).round();
+        if i < 0.0 || j < 0.0 || i >= self.width as f64 || j >= self.height as f64 {
+            return None;
+        }
+
+        Some((self.height - 1 - j as u32) as usize * self.width as usize + i as usize)
+    }
+
+    /// Returns the samples of `previous`, rendered for `previous_frame`, that still hold in
+    /// this frame, with at most `max_samples` in each pixel.
+    ///
+    /// A pixel takes the samples of the pixel of the previous frame that saw the same point,
+    /// found by projecting what it sees into the previous camera, as long as that pixel saw it
+    /// too rather than something in front of it, on a surface facing the same way. Pixels that
+    /// found the same one split its samples. Shading that changes with the view, such as
+    /// reflections, still lags behind by the samples reused.
+    pub(crate) fn reproject(
+        &self,
+        previous_frame: &GBuffer,
+        previous: &Accumulation,
+        max_samples: usize,
+    ) -> Accumulation {
+        let camera = &previous_frame.camera;
+        let mut reused = Accumulation::empty(self.hits.len());
+        if max_samples == 0 || previous.len() != previous_frame.hits.len() {
+            return reused;
+        }
+
+        let matches: Vec<_> = self
+            .hits
+            .iter()
+            .enumerate()
+            .filter_map(|(index, hit)| {
+                let (p, normal) = hit.as_ref()?;
+                let before = previous_frame.pixel(p)?;
+                let (p_before, normal_before) = previous_frame.hits[before].as_ref()?;
+
+                let depth = camera.depth(p);
+                let same_point = (depth - camera.depth(p_before)).abs() <= MAX_DEPTH_ERROR * depth;
+                (same_point && normal.dot(normal_before) >= MIN_NORMAL_COSINE)
+                    .then_some((index, before))
+            })
+            .collect();
+
+        // Where the surface is seen at a more grazing angle than before, several pixels see
+        // what one did, and share its samples rather than all repeat the same noise.
+        let mut sharing = vec![0; previous_frame.hits.len()];
+        for &(_, before) in &matches {
+            sharing[before] += 1;
+        }
+        for (index, before) in matches {
+            let (sum, squares, samples) = previous.pixel(before);
+            let kept = samples.min(max_samples) / sharing[before];
+            if kept > 0 {
+                let fraction = kept as f64 / samples as f64;
+                reused.set_pixel(index, sum * fraction, squares * fraction, kept);
+            }
+        }
+
+        reused
+    }
+}
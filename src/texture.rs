@@ -0,0 +1,241 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::hittable::HitRecord;
+use crate::memory_budget::MemoryBudget;
+use crate::scene_error::SceneError;
+use crate::vec3::{Point3, Vec3};
+
+/// A parameter of a material, either the same everywhere or read from a texture at each hit.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum Scalar {
+    Constant(f64),
+    Texture(Box<ScalarTexture>),
+}
+
+impl Scalar {
+    /// Returns the value at `rec`.
+    pub(crate) fn at(&self, rec: &HitRecord) -> f64 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Texture(texture) => texture.at(rec.p(), rec.normal()),
+        }
+    }
+
+    /// Returns the value, if it is the same everywhere.
+    pub(crate) fn constant(&self) -> Option<f64> {
+        match self {
+            Self::Constant(value) => Some(*value),
+            Self::Texture(_) => None,
+        }
+    }
+
+    /// Returns the bytes taken by the image of the texture, if any.
+    pub(crate) fn memory(&self) -> usize {
+        match self {
+            Self::Constant(_) => 0,
+            Self::Texture(texture) => texture
+                .image
+                .as_ref()
+                .map_or(0, |image| image.pixels.capacity()),
+        }
+    }
+
+    /// Reads the image of the texture, if any, relative to `base_dir`, and adds it to `sources`.
+    /// Images are downscaled to fit what is left of the `budget`, if any.
+    pub(crate) fn load(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        budget: Option<&MemoryBudget>,
+    ) -> Result<(), SceneError> {
+        match self {
+            Self::Constant(_) => Ok(()),
+            Self::Texture(texture) => texture.load(base_dir, sources, budget),
+        }
+    }
+}
+
+impl From<f64> for Scalar {
+    fn from(value: f64) -> Self {
+        Self::Constant(value)
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Constant(value) => write!(f, "{}", value),
+            Self::Texture(texture) => write!(
+                f,
+                "{} from {} to {}",
+                texture.texture, texture.from, texture.to
+            ),
+        }
+    }
+}
+
+/// A greyscale image mapping black to `from` and white to `to`, such as a map of smudges on
+/// glass or of rust on metal. Objects have no texture coordinates, so the image is projected in
+/// world space along the three axes and blended by how much the surface faces each of them,
+/// repeating every `size` meters.
+///
+/// Images are data rather than colors, so their values are read as they are, without gamma.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ScalarTexture {
+    /// Path to the image, relative to the scene file.
+    texture: String,
+    from: f64,
+    to: f64,
+    /// 1 meter by default.
+    size: Option<f64>,
+    /// The image, once the scene loads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<Greyscale>,
+}
+
+impl ScalarTexture {
+    fn load(
+        &mut self,
+        base_dir: &Path,
+        sources: &mut Vec<PathBuf>,
+        budget: Option<&MemoryBudget>,
+    ) -> Result<(), SceneError> {
+        let path = base_dir.join(&self.texture);
+        let reading = |e: image::ImageError| SceneError::reading(&path, e.into());
+
+        // The size to fit the budget is told from the dimensions in the file's header, before
+        // the image is decoded.
+        let (width, height) = image::io::Reader::open(&path)
+            .map_err(|e| SceneError::reading(&path, e.into()))?
+            .into_dimensions()
+            .map_err(reading)?;
+        let (mut fit_width, mut fit_height) = (width, height);
+        if let Some(budget) = budget {
+            let bytes = |width: u32, height: u32| width as usize * height as usize;
+            while !budget.fits(bytes(fit_width, fit_height)) && bytes(fit_width, fit_height) > 1 {
+                fit_width = fit_width.div_ceil(2);
+                fit_height = fit_height.div_ceil(2);
+            }
+        }
+
+        let mut image = image::open(&path).map_err(reading)?.to_luma8();
+        if (fit_width, fit_height) != (width, height) {
+            image = image::imageops::resize(&image, fit_width, fit_height, FilterType::Triangle);
+            warn!(
+                "downscaled texture {} from {}x{} to {}x{} to fit the memory budget",
+                path.display(),
+                width,
+                height,
+                fit_width,
+                fit_height
+            );
+        }
+        if let Some(budget) = budget {
+            budget.take(image.len(), &format!("texture {}", path.display()));
+        }
+
+        self.image = Some(Greyscale {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image.into_raw(),
+        });
+        sources.push(path);
+
+        Ok(())
+    }
+
+    fn at(&self, p: &Point3, normal: &Vec3) -> f64 {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return self.from,
+        };
+
+        let size = self.size.unwrap_or(1.0);
+        let p = p / size;
+        // Faces mostly facing an axis take the projection along it, with a short blend between.
+        let weights = normal.map(|n| n.abs().powi(4));
+        let grey = (weights.x * image.at(p.z, p.y)
+            + weights.y * image.at(p.x, p.z)
+            + weights.z * image.at(p.x, p.y))
+            / weights.sum().max(f64::MIN_POSITIVE);
+
+        self.from + (self.to - self.from) * grey
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Greyscale {
+    width: usize,
+    height: usize,
+    /// From the top row down.
+    pixels: Vec<u8>,
+}
+
+impl Greyscale {
+    /// Returns the value at `(u, v)`, from 0 to 1, with the image repeating from its bottom
+    /// left corner at every whole `u` and `v`.
+    fn at(&self, u: f64, v: f64) -> f64 {
+        let x = ((u.rem_euclid(1.0) * self.width as f64) as usize).min(self.width - 1);
+        let y = (((1.0 - v.rem_euclid(1.0)) * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x] as f64 / 255.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_maps_grey_between_its_bounds() {
+        // Black on the left half, white on the right.
+        let texture = ScalarTexture {
+            texture: "halves.png".to_string(),
+            from: 1.2,
+            to: 1.8,
+            size: Some(2.0),
+            image: Some(Greyscale {
+                width: 2,
+                height: 1,
+                pixels: vec![0, 255],
+            }),
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        assert_eq!(texture.at(&Point3::new(0.5, 0.0, 0.0), &up), 1.2);
+        assert_eq!(texture.at(&Point3::new(1.5, 0.0, 0.0), &up), 1.8);
+        // Repeating every `size`.
+        assert_eq!(texture.at(&Point3::new(-0.5, 0.0, 0.0), &up), 1.8);
+
+        let constant: Scalar = serde_yaml::from_str("1.5").unwrap();
+        assert_eq!(constant.constant(), Some(1.5));
+    }
+
+    #[test]
+    fn textures_are_downscaled_to_fit_the_budget() {
+        let name = format!("budget-{}.png", std::process::id());
+        let path = std::env::temp_dir().join(&name);
+        image::GrayImage::new(64, 32).save(&path).unwrap();
+        let mut texture = ScalarTexture {
+            texture: name,
+            from: 0.0,
+            to: 1.0,
+            size: None,
+            image: None,
+        };
+
+        let budget = MemoryBudget::new(600);
+        let loaded = texture.load(&std::env::temp_dir(), &mut vec![], Some(&budget));
+        std::fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        let image = texture.image.unwrap();
+        assert_eq!((image.width, image.height), (32, 16));
+        assert_eq!(budget.used(), 512);
+    }
+}
@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+
+use crate::render::Accumulation;
+
+/// Width and height of the squares of pixels the render threads take one at a time.
+const TILE_SIZE: u32 = 32;
+
+/// A rectangle of pixels, in rows from the top of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Tile {
+    pub(crate) left: u32,
+    pub(crate) top: u32,
+    pub(crate) right: u32,
+    pub(crate) bottom: u32,
+}
+
+impl Tile {
+    /// Returns the column and row of every pixel of the tile, row by row.
+    pub(crate) fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.top..self.bottom).flat_map(move |row| (self.left..self.right).map(move |i| (i, row)))
+    }
+}
+
+/// Returns the tiles covering an image of `width` by `height`, from the top row of tiles down
+/// and from left to right.
+pub(crate) fn sweep(width: u32, height: u32) -> Vec<Tile> {
+    (0..height)
+        .step_by(TILE_SIZE as usize)
+        .flat_map(|top| {
+            (0..width)
+                .step_by(TILE_SIZE as usize)
+                .map(move |left| Tile {
+                    left,
+                    top,
+                    right: (left + TILE_SIZE).min(width),
+                    bottom: (top + TILE_SIZE).min(height),
+                })
+        })
+        .collect()
+}
+
+/// Returns the tiles covering an image of `width` by `height`, the noisiest in `accumulation`
+/// first, so that an interrupted pass leaves the image as clean as it can.
+///
+/// Tiles with pixels that have too few samples to tell come first, in sweep order, as do all
+/// of them if `accumulation` does not know how noisy its pixels are or is still empty.
+pub(crate) fn by_noise(accumulation: &Accumulation, width: u32, height: u32) -> Vec<Tile> {
+    if accumulation.len() != (width * height) as usize {
+        return sweep(width, height);
+    }
+
+    let mut tiles: Vec<_> = sweep(width, height)
+        .into_iter()
+        .map(|tile| {
+            let (sum, count) = tile
+                .pixels()
+                .map(|(i, row)| accumulation.noise((row * width + i) as usize))
+                .fold((0.0, 0), |(sum, count), noise| (sum + noise, count + 1));
+            (tile, sum / count as f64)
+        })
+        .collect();
+    // The sort is stable, which keeps tiles as noisy as each other in sweep order.
+    tiles.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+
+    tiles.into_iter().map(|(tile, _)| tile).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Color;
+
+    #[test]
+    fn sweep_covers_every_pixel_once() {
+        let (width, height) = (70, 40);
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in sweep(width, height) {
+            for (i, row) in tile.pixels() {
+                covered[(row * width + i) as usize] += 1;
+            }
+        }
+
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn noisiest_tiles_come_first() {
+        let (width, height) = (64, 32);
+        let mut accumulation = Accumulation::empty((width * height) as usize);
+        for row in 0..height {
+            for i in 0..width {
+                // Every pixel has a mean of 0.5, from samples spread further apart on the right.
+                let spread = if i < TILE_SIZE { 0.1 } else { 0.4 };
+                let squares =
+                    2.0 * (0.5 - spread) * (0.5 - spread) + 2.0 * (0.5 + spread) * (0.5 + spread);
+                let index = (row * width + i) as usize;
+                accumulation.set_pixel(index, Color::new(2.0, 2.0, 2.0), squares, 4);
+            }
+        }
+
+        let tiles = by_noise(&accumulation, width, height);
+        assert_eq!(tiles[0].left, TILE_SIZE);
+        assert_eq!(tiles[1].left, 0);
+
+        // Without samples, the tiles stay in sweep order.
+        let empty = Accumulation::empty((width * height) as usize);
+        assert_eq!(by_noise(&empty, width, height), sweep(width, height));
+    }
+}
@@ -0,0 +1,414 @@
+use nalgebra::{Isometry3, Matrix3, Matrix4, Rotation3, Translation3, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::rtweekend::degrees_to_radians;
+use crate::scene_loader::{StartEndPair, TransformDef};
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+/// Number of instants sampled across the shutter interval when bounding a moving transform.
+const BOUNDING_STEPS: usize = 16;
+
+/// Where a transform puts objects at some instant: an affine `matrix` first, which may scale
+/// or shear them, then a rigid motion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Pose {
+    pub(crate) matrix: Matrix4<f64>,
+    pub(crate) rigid: Isometry3<f64>,
+}
+
+impl Pose {
+    fn to_homogeneous(self) -> Matrix4<f64> {
+        self.rigid.to_homogeneous() * self.matrix
+    }
+}
+
+/// An invertible affine map from object to world space, with its inverse.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Affine {
+    forward: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+}
+
+impl Affine {
+    /// Returns the map of `forward`, or the identity if it can't be inverted.
+    fn new(forward: Matrix4<f64>) -> Self {
+        match forward.try_inverse() {
+            Some(inverse) => Self { forward, inverse },
+            None => Self {
+                forward: Matrix4::identity(),
+                inverse: Matrix4::identity(),
+            },
+        }
+    }
+
+    pub(crate) fn matrix(&self) -> &Matrix4<f64> {
+        &self.forward
+    }
+
+    pub(crate) fn transform_point(&self, p: &Point3) -> Point3 {
+        self.forward.transform_point(&(*p).into()).coords
+    }
+
+    /// Transforms a normal, which takes the inverse transpose so that it stays perpendicular to
+    /// the surface when the map scales or shears it. Only the linear part is transposed: the
+    /// whole matrix would end up with the translation in its last row, which `transform_vector`
+    /// divides by.
+    fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let linear = self.inverse.fixed_slice::<3, 3>(0, 0);
+
+        unit_vector(&(linear.transpose() * n))
+    }
+
+    fn inverse_transform_point(&self, p: &Point3) -> Point3 {
+        self.inverse.transform_point(&(*p).into()).coords
+    }
+
+    fn inverse_transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.inverse.transform_vector(v)
+    }
+}
+
+/// A transform, optionally moving between two poses over time.
+///
+/// Between `time.start` and `time.end` the translation and the matrices are interpolated
+/// linearly and the rotation spherically; outside of that interval the transform holds its
+/// start or end pose.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "TransformDef", into = "TransformDef")]
+pub(crate) struct Transform {
+    start: Pose,
+    end: Pose,
+    time: StartEndPair<f64>,
+    /// The map at `start`, kept for transforms that don't move.
+    fixed: Affine,
+    /// The transform as the scene file wrote it, to write it back the same way, unless it
+    /// changed since.
+    def: Option<TransformDef>,
+}
+
+impl Transform {
+    pub(crate) fn new(start: Pose, end: Pose, time: StartEndPair<f64>) -> Self {
+        Self {
+            start,
+            end,
+            time,
+            fixed: Affine::new(start.to_homogeneous()),
+            def: None,
+        }
+    }
+
+    /// Returns the transform, remembering that the scene file wrote it as `def`.
+    pub(crate) fn written_as(self, def: TransformDef) -> Self {
+        Self {
+            def: Some(def),
+            ..self
+        }
+    }
+
+    /// Returns how the scene file wrote the transform, if it hasn't changed since.
+    pub(crate) fn def(&self) -> Option<&TransformDef> {
+        self.def.as_ref()
+    }
+
+    pub(crate) fn fixed(pose: Pose) -> Self {
+        Self::new(pose, pose, StartEndPair::new(0.0, 1.0))
+    }
+
+    pub(crate) fn start(&self) -> &Pose {
+        &self.start
+    }
+
+    pub(crate) fn end(&self) -> &Pose {
+        &self.end
+    }
+
+    pub(crate) fn time(&self) -> &StartEndPair<f64> {
+        &self.time
+    }
+
+    /// Returns the transform with the same rotations and matrices, and its translations
+    /// multiplied by `factor`, for objects scaled by `factor` too.
+    pub(crate) fn scaled(&self, factor: f64) -> Self {
+        let scale = |pose: &Pose| {
+            let mut pose = *pose;
+            pose.rigid.translation.vector *= factor;
+            for i in 0..3 {
+                pose.matrix[(i, 3)] *= factor;
+            }
+            pose
+        };
+
+        Self::new(scale(&self.start), scale(&self.end), self.time.clone())
+    }
+
+    /// Returns the transform as seen in axes that `basis` turns or mirrors points into, for
+    /// objects turned by `basis` too, see [`crate::scene_loader::Axes`].
+    pub(crate) fn reoriented(&self, basis: &Matrix3<f64>) -> Self {
+        let homogeneous = basis.to_homogeneous();
+        let reorient = |pose: &Pose| {
+            let rotation =
+                basis * pose.rigid.rotation.to_rotation_matrix().matrix() * basis.transpose();
+            Pose {
+                matrix: homogeneous * pose.matrix * homogeneous.transpose(),
+                rigid: Isometry3::from_parts(
+                    Translation3::from(basis * pose.rigid.translation.vector),
+                    UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(
+                        rotation,
+                    )),
+                ),
+            }
+        };
+
+        Self::new(
+            reorient(&self.start),
+            reorient(&self.end),
+            self.time.clone(),
+        )
+    }
+
+    /// Returns the transform followed by a move of `offset`, all along its motion.
+    pub(crate) fn translated(&self, offset: &Vec3) -> Self {
+        let translate = |pose: &Pose| {
+            let mut pose = *pose;
+            pose.rigid.translation.vector += offset;
+            pose
+        };
+        let moved = Self::new(
+            translate(&self.start),
+            translate(&self.end),
+            self.time.clone(),
+        );
+
+        match &self.def {
+            Some(def) => moved.written_as(def.translated(offset)),
+            None => moved,
+        }
+    }
+
+    fn is_moving(&self) -> bool {
+        self.start != self.end
+    }
+
+    /// Returns the object-to-world map at `time`.
+    pub(crate) fn at(&self, time: f64) -> Affine {
+        if !self.is_moving() {
+            return self.fixed;
+        }
+
+        let duration = self.time.end() - self.time.start();
+        let f = if duration > 0.0 {
+            ((time - self.time.start()) / duration).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let translation = self
+            .start
+            .rigid
+            .translation
+            .vector
+            .lerp(&self.end.rigid.translation.vector, f);
+        let rotation = self.start.rigid.rotation.slerp(&self.end.rigid.rotation, f);
+        let pose = Pose {
+            matrix: self.start.matrix * (1.0 - f) + self.end.matrix * f,
+            rigid: Isometry3::from_parts(Translation3::from(translation), rotation),
+        };
+
+        Affine::new(pose.to_homogeneous())
+    }
+
+    /// Intersects `object`, given in object space, with the world-space ray `r`.
+    pub(crate) fn hit<'a, H: Hittable>(
+        &self,
+        object: &'a H,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord<'a>> {
+        let map = self.at(r.time());
+        let local = Ray::new(
+            map.inverse_transform_point(r.origin()),
+            map.inverse_transform_vector(r.direction()),
+            Some(r.time()),
+        );
+
+        // The direction isn't normalized in object space, so `t` is the same in both spaces.
+        object.hit(&local, t_min, t_max).map(|mut rec| {
+            rec.set_p(map.transform_point(rec.p()));
+            rec.set_normal(map.transform_normal(rec.normal()));
+            rec
+        })
+    }
+
+    /// Bounds `object` over `time0..time1`, sampling the motion at a few instants.
+    pub(crate) fn bounding_box<H: Hittable>(
+        &self,
+        object: &H,
+        time0: f64,
+        time1: f64,
+    ) -> Option<Aabb> {
+        let local = object.bounding_box(time0, time1)?;
+        let steps = if self.is_moving() { BOUNDING_STEPS } else { 1 };
+        let instants = (0..=steps).map(|i| time0 + (time1 - time0) * i as f64 / steps as f64);
+
+        Aabb::from_points(instants.flat_map(|time| {
+            let map = self.at(time);
+            local
+                .corners()
+                .map(move |corner| map.transform_point(&corner))
+                .collect::<Vec<_>>()
+        }))
+    }
+}
+
+/// Returns a placement rotated `rotate_y` degrees around the Y axis, then translated.
+pub(crate) fn placement(translate: Vec3, rotate_y: f64) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::from(translate),
+        UnitQuaternion::from_axis_angle(&Vec3::y_axis(), degrees_to_radians(rotate_y)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_hit, material};
+    use crate::rtweekend::INFINITY;
+    use crate::sphere::Sphere;
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(Point3::zeros(), 1.0, material())
+    }
+
+    #[test]
+    fn hits_a_placed_object() {
+        let transform = Transform::fixed(Pose {
+            matrix: Matrix4::identity(),
+            rigid: placement(Vec3::new(0.0, 0.0, -5.0), 90.0),
+        });
+        let r = Ray::new(Point3::zeros(), Vec3::new(0.0, 0.0, -1.0), Some(0.0));
+
+        let sphere = unit_sphere();
+        let rec = transform.hit(&sphere, &r, 0.001, INFINITY);
+        assert_hit(rec, &r, 4.0, Vec3::new(0.0, 0.0, 1.0), true);
+    }
+
+    #[test]
+    fn keeps_normals_perpendicular_under_scaling() {
+        // An ellipsoid twice as wide along X, hit from above at x = 1 where its surface
+        // `x² / 4 + y² + z² = 1` has the gradient `(x / 4, y, z)`.
+        let transform = Transform::fixed(Pose {
+            matrix: Matrix4::new_nonuniform_scaling(&Vec3::new(2.0, 1.0, 1.0)),
+            rigid: placement(Vec3::new(0.0, 0.0, -5.0), 0.0),
+        });
+        let r = Ray::new(
+            Point3::new(1.0, 5.0, -5.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Some(0.0),
+        );
+        let y = 0.75_f64.sqrt();
+
+        let sphere = unit_sphere();
+        let rec = transform.hit(&sphere, &r, 0.001, INFINITY);
+        assert_hit(
+            rec,
+            &r,
+            5.0 - y,
+            unit_vector(&Vec3::new(0.25, y, 0.0)),
+            true,
+        );
+    }
+
+    #[test]
+    fn normals_of_translated_scaled_spheres_are_unit_and_perpendicular() {
+        // Away from the origin in every axis, so that a translation leaking into normals would
+        // change them wherever they point.
+        let center = Vec3::new(3.0, -2.0, -6.0);
+        let transform = Transform::fixed(Pose {
+            matrix: Matrix4::new_nonuniform_scaling(&Vec3::new(2.0, 0.5, 1.5)),
+            rigid: placement(center, 30.0),
+        });
+        let map = transform.at(0.0);
+        let linear = map.matrix().fixed_slice::<3, 3>(0, 0);
+        let sphere = unit_sphere();
+
+        for i in 0..50 {
+            // Directions spread over the sphere by the golden angle.
+            let z = 1.0 - (i as f64 + 0.5) / 25.0;
+            let phi = i as f64 * 2.399_963;
+            let d = Vec3::new(
+                (1.0 - z * z).sqrt() * phi.cos(),
+                (1.0 - z * z).sqrt() * phi.sin(),
+                z,
+            );
+            let r = Ray::new(center + 20.0 * d, -d, Some(0.0));
+
+            let rec = transform.hit(&sphere, &r, 0.001, INFINITY).unwrap();
+            let normal = rec.normal();
+            assert!(
+                (normal.norm() - 1.0).abs() < 1e-9,
+                "{:?} isn't unit",
+                normal
+            );
+            assert!(
+                normal.dot(&(rec.p() - center)) > 0.0,
+                "{:?} points in",
+                normal
+            );
+
+            // Tangents of the unit sphere at the hit, mapped to the world, stay tangent.
+            let q = map.inverse_transform_point(rec.p());
+            let t1 = q.cross(&Vec3::new(0.3, 0.5, 0.8));
+            for tangent in [t1, q.cross(&t1)] {
+                let tangent = linear * tangent;
+                assert!(
+                    normal.dot(&tangent).abs() < 1e-9 * tangent.norm(),
+                    "{:?} isn't perpendicular to {:?}",
+                    normal,
+                    tangent
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reorients_like_the_objects_it_places() {
+        let transform = Transform::fixed(Pose {
+            matrix: Matrix4::new_nonuniform_scaling(&Vec3::new(2.0, 1.0, 1.0)),
+            rigid: placement(Vec3::new(1.0, 2.0, 3.0), 30.0),
+        });
+        // Z up and left-handed to Y up and right-handed, a mirror.
+        let basis = Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0);
+        let p = Point3::new(0.5, -1.0, 2.0);
+
+        let placed = basis * transform.at(0.0).transform_point(&p);
+        let reoriented = transform
+            .reoriented(&basis)
+            .at(0.0)
+            .transform_point(&(basis * p));
+        assert!((placed - reoriented).norm() < 1e-9);
+    }
+
+    #[test]
+    fn hits_a_moving_object_where_it_is_at_the_ray_time() {
+        let pose = |x| Pose {
+            matrix: Matrix4::identity(),
+            rigid: placement(Vec3::new(x, 0.0, -5.0), 0.0),
+        };
+        let transform = Transform::new(pose(0.0), pose(4.0), StartEndPair::new(0.0, 1.0));
+        let sphere = unit_sphere();
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+
+        let middle = Ray::new(Point3::new(2.0, 0.0, 0.0), direction, Some(0.5));
+        let rec = transform.hit(&sphere, &middle, 0.001, INFINITY);
+        assert_hit(rec, &middle, 4.0, -direction, true);
+
+        let start = Ray::new(Point3::new(2.0, 0.0, 0.0), direction, Some(0.0));
+        assert!(transform
+            .hit(&unit_sphere(), &start, 0.001, INFINITY)
+            .is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use argh::FromArgs;
+
+use crate::vec3::{Point3, Vec3};
+
+/// Orbit the camera around a target point and render one image per frame.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "turntable")]
+pub(crate) struct Turntable {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// number of frames in a full orbit
+    #[argh(option, default = "90")]
+    pub(crate) frames: usize,
+
+    /// distance from the camera to the orbit axis
+    #[argh(option, default = "5.0")]
+    pub(crate) radius: f64,
+
+    /// height of the camera above the target
+    #[argh(option, default = "1.0")]
+    pub(crate) height: f64,
+
+    /// point the camera orbits around and looks at, as `x,y,z`
+    #[argh(
+        option,
+        default = "Point3::new(0.0, 0.0, 0.0)",
+        from_str_fn(parse_point)
+    )]
+    pub(crate) target: Point3,
+
+    /// fraction of the samples of each pixel that may be reused from the previous frame, where
+    /// it saw the same surface, for mostly static scenes (0 renders every frame from scratch)
+    #[argh(option, default = "0.0")]
+    pub(crate) reuse: f64,
+}
+
+impl Turntable {
+    /// Returns the camera position for `frame`, walking the orbit counter-clockwise.
+    pub(crate) fn look_from(&self, frame: usize) -> Point3 {
+        let angle = 2.0 * std::f64::consts::PI * frame as f64 / self.frames as f64;
+
+        self.target
+            + Vec3::new(
+                self.radius * angle.cos(),
+                self.height,
+                self.radius * angle.sin(),
+            )
+    }
+}
+
+pub(crate) fn parse_point(value: &str) -> Result<Point3, String> {
+    let coordinates = value
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid point `{}`: {}", value, e))?;
+
+    match coordinates[..] {
+        [x, y, z] => Ok(Point3::new(x, y, z)),
+        _ => Err(format!("invalid point `{}`: expected `x,y,z`", value)),
+    }
+}
@@ -1,6 +1,6 @@
 use nalgebra::Vector3;
 
-use crate::rtweekend::{random_double, random_double_between};
+use crate::rtweekend::random_double_between;
 
 pub(crate) type Vec3 = Vector3<f64>;
 pub(crate) type Point3 = Vec3;
@@ -18,10 +18,6 @@ pub(crate) fn unit_vector(v: &Vec3) -> Vec3 {
     v / length(v)
 }
 
-pub(crate) fn random_vector() -> Vec3 {
-    Vec3::new(random_double(), random_double(), random_double())
-}
-
 pub(crate) fn random_vector_between(min: f64, max: f64) -> Vec3 {
     Vec3::new(
         random_double_between(min, max),
@@ -46,23 +42,17 @@ pub(crate) fn random_unit_vector() -> Vec3 {
     unit_vector(&random_in_unit_sphere())
 }
 
-pub(crate) fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
-
-    if in_unit_sphere.dot(normal) > 0.0 {
-        // In the same hemisphere as the normal
-        return in_unit_sphere;
-    }
-
-    -in_unit_sphere
-}
-
 pub(crate) fn near_zero(v: &Vec3) -> bool {
     const S: f64 = 1e-8;
 
     v.x.abs() < S && v.y.abs() < S && v.z.abs() < S
 }
 
+/// Returns whether no coordinate of `v` is infinite or NaN.
+pub(crate) fn is_finite(v: &Vec3) -> bool {
+    v.iter().all(|x| x.is_finite())
+}
+
 pub(crate) fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     v - 2.0 * v.dot(n) * n
 }
@@ -90,3 +80,96 @@ pub(crate) fn random_in_unit_disk() -> Vec3 {
         return p;
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const TOLERANCE: f64 = 1e-9;
+
+    /// Vectors with components in `-10..10`.
+    pub(crate) fn vector() -> impl Strategy<Value = Vec3> {
+        [-10.0..10.0, -10.0..10.0, -10.0..10.0].prop_map(|[x, y, z]| Vec3::new(x, y, z))
+    }
+
+    /// Unit vectors, normalized from ones long enough to point somewhere.
+    pub(crate) fn unit() -> impl Strategy<Value = Vec3> {
+        vector()
+            .prop_filter("too short to normalize", |v| length(v) > 1e-3)
+            .prop_map(|v| unit_vector(&v))
+    }
+
+    /// A unit direction and a unit normal facing it, as a ray meets a surface.
+    fn incidence() -> impl Strategy<Value = (Vec3, Vec3)> {
+        (unit(), unit())
+            .prop_filter("grazing", |(v, n)| v.dot(n).abs() > 1e-6)
+            .prop_map(|(v, n)| if v.dot(&n) < 0.0 { (v, n) } else { (v, -n) })
+    }
+
+    proptest! {
+        #[test]
+        fn unit_vectors_have_length_one(v in vector()) {
+            prop_assume!(length(&v) > 1e-3);
+
+            prop_assert!((length(&unit_vector(&v)) - 1.0).abs() < TOLERANCE);
+            prop_assert!(unit_vector(&v).dot(&v) > 0.0);
+        }
+
+        #[test]
+        fn near_zero_needs_every_component_tiny(
+            v in [-1e-8..1e-8, -1e-8..1e-8, -1e-8..1e-8],
+            axis in 0..3_usize,
+            big in 1e-8..10.0,
+        ) {
+            let mut v = Vec3::new(v[0], v[1], v[2]);
+            prop_assert!(near_zero(&v));
+
+            v[axis] = big;
+            prop_assert!(!near_zero(&v));
+            v[axis] = -big;
+            prop_assert!(!near_zero(&v));
+        }
+
+        #[test]
+        fn reflection_mirrors_across_the_surface(v in vector(), n in unit()) {
+            let r = reflect(&v, &n);
+
+            prop_assert!((length(&r) - length(&v)).abs() < TOLERANCE);
+            prop_assert!((r.dot(&n) + v.dot(&n)).abs() < TOLERANCE);
+            prop_assert!(((r - r.dot(&n) * n) - (v - v.dot(&n) * n)).norm() < TOLERANCE);
+            prop_assert!((reflect(&r, &n) - v).norm() < TOLERANCE);
+        }
+
+        #[test]
+        fn refraction_follows_snells_law((v, n) in incidence(), eta in 0.5..2.0) {
+            let cos_i = -v.dot(&n);
+            let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+            prop_assume!(eta * sin_i < 1.0 - 1e-6);
+
+            let r = refract(&v, &n, eta);
+            let tangent = |d: &Vec3| d - d.dot(&n) * n;
+
+            prop_assert!((length(&r) - 1.0).abs() < TOLERANCE);
+            prop_assert!(r.dot(&n) < 0.0, "the ray should go through the surface");
+            prop_assert!((tangent(&r) - eta * tangent(&v)).norm() < TOLERANCE);
+        }
+
+        #[test]
+        fn refraction_is_reversible((v, n) in incidence(), eta in 0.5..2.0) {
+            let cos_i = -v.dot(&n);
+            let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+            prop_assume!(eta * sin_i < 1.0 - 1e-6);
+
+            let back = refract(&-refract(&v, &n, eta), &-n, 1.0 / eta);
+
+            prop_assert!((back + v).norm() < 1e-6);
+        }
+
+        #[test]
+        fn matched_media_dont_bend_rays((v, n) in incidence()) {
+            prop_assert!((refract(&v, &n, 1.0) - v).norm() < TOLERANCE);
+        }
+    }
+}
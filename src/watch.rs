@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use argh::FromArgs;
+
+/// Render a scene pass after pass, writing the image as it improves, and start over whenever
+/// the scene file or a file it refers to changes.
+///
+/// Edits that only change materials, the camera placement or the background are applied to the
+/// scene as it is, without loading meshes or building hierarchies again.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "watch")]
+pub(crate) struct Watch {
+    /// scene file
+    #[argh(positional)]
+    pub(crate) scene_file: String,
+
+    /// stop after this many passes of `-s` samples per pixel (never stops by default)
+    #[argh(option)]
+    pub(crate) passes: Option<usize>,
+}
+
+/// What changed in the files a scene was built from.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Change {
+    None,
+    /// Only the scene file itself, which may only be new materials.
+    SceneFile,
+    /// A file the scene file refers to, like a mesh.
+    Sources,
+}
+
+/// When each file a scene was built from was last modified, if it could be read at all.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Modified(Vec<Option<SystemTime>>);
+
+impl Modified {
+    /// Looks at `sources`, the scene file first.
+    pub(crate) fn of(sources: &[PathBuf]) -> Self {
+        Self(
+            sources
+                .iter()
+                .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+                .collect(),
+        )
+    }
+
+    pub(crate) fn change(&self, now: &Self) -> Change {
+        match self
+            .0
+            .iter()
+            .zip(&now.0)
+            .position(|(then, now)| then != now)
+        {
+            None => Change::None,
+            Some(0) if self.0[1..] == now.0[1..] => Change::SceneFile,
+            Some(_) => Change::Sources,
+        }
+    }
+}